@@ -19,6 +19,7 @@ use protoc_rust::Customize;
 use std::{env, fs, io::Write, path::Path};
 
 const PROTO_DIR_NAME: &str = "protos";
+const ARTIFACTS_DIR_NAME: &str = "artifacts";
 
 fn main() {
     let out_dir = env::var("OUT_DIR").expect("No OUT_DIR env variable");
@@ -63,6 +64,281 @@ fn main() {
         },
     })
         .expect("Error generating rust files from the protos");
+
+    if env::var_os("CARGO_FEATURE_SCHEMA_ARTIFACTS").is_some() {
+        write_schema_artifacts(&proto_src_files);
+    }
+}
+
+/// Copies the proto files and writes a JSON Schema for each of them into
+/// `artifacts/<crate version>/`, so consumers can code against the exact schema of this build.
+fn write_schema_artifacts(proto_src_files: &[String]) {
+    let manifest_dir = env::var("CARGO_MANIFEST_DIR").expect("No CARGO_MANIFEST_DIR env variable");
+    let version = env::var("CARGO_PKG_VERSION").expect("No CARGO_PKG_VERSION env variable");
+    let artifacts_path = Path::new(&manifest_dir)
+        .join(ARTIFACTS_DIR_NAME)
+        .join(&version);
+    fs::create_dir_all(&artifacts_path).expect("Unable to create artifacts dir");
+
+    for proto_file in proto_src_files {
+        let proto_path = Path::new(proto_file);
+        let file_name = proto_path.file_name().expect("Unable to extract filename");
+        let stem = proto_path
+            .file_stem()
+            .expect("Unable to extract stem")
+            .to_str()
+            .expect("Unable to extract filename");
+        fs::copy(proto_path, artifacts_path.join(file_name)).expect("Unable to copy proto file");
+
+        let source = fs::read_to_string(proto_path).expect("Unable to read proto file");
+        let (messages, enums) = parse_proto(&tokenize(&source));
+        let schema = json_schema(stem, &version, &messages, &enums);
+        let mut schema_file = fs::File::create(artifacts_path.join(format!("{}.schema.json", stem)))
+            .expect("Unable to create schema file");
+        schema_file
+            .write_all(schema.as_bytes())
+            .expect("Unable to write schema file");
+    }
+}
+
+struct ProtoMessage {
+    name: String,
+    fields: Vec<ProtoField>,
+}
+
+struct ProtoField {
+    name: String,
+    type_name: String,
+    repeated: bool,
+    /// Value type of a `map<K, V>` field, whose keys are strings in the JSON mapping
+    map_value: Option<String>,
+}
+
+struct ProtoEnum {
+    name: String,
+    values: Vec<String>,
+}
+
+/// Splits a proto file into tokens, dropping comments
+fn tokenize(source: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    for line in source.lines() {
+        let line = match line.find("//") {
+            Some(index) => &line[..index],
+            None => line,
+        };
+        let mut current = String::new();
+        for c in line.chars() {
+            if c.is_whitespace() || "{};=".contains(c) {
+                if !current.is_empty() {
+                    tokens.push(current.clone());
+                    current.clear();
+                }
+                if !c.is_whitespace() {
+                    tokens.push(c.to_string());
+                }
+            } else {
+                current.push(c);
+            }
+        }
+        if !current.is_empty() {
+            tokens.push(current);
+        }
+    }
+    tokens
+}
+
+/// Collects the messages and enums of a proto3 file; nested types are named `Outer.Inner`
+fn parse_proto(tokens: &[String]) -> (Vec<ProtoMessage>, Vec<ProtoEnum>) {
+    let mut messages = Vec::new();
+    let mut enums = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        match tokens[i].as_str() {
+            "message" => i = parse_message(tokens, i + 1, "", &mut messages, &mut enums),
+            "enum" => i = parse_enum(tokens, i + 1, "", &mut enums),
+            _ => i += 1,
+        }
+    }
+    (messages, enums)
+}
+
+fn parse_message(
+    tokens: &[String],
+    mut i: usize,
+    scope: &str,
+    messages: &mut Vec<ProtoMessage>,
+    enums: &mut Vec<ProtoEnum>,
+) -> usize {
+    let name = qualify(scope, &tokens[i]);
+    // skip the name and the opening brace
+    i += 2;
+    let mut fields = Vec::new();
+    while i < tokens.len() && tokens[i] != "}" {
+        match tokens[i].as_str() {
+            "message" => i = parse_message(tokens, i + 1, &name, messages, enums),
+            "enum" => i = parse_enum(tokens, i + 1, &name, enums),
+            "option" | "reserved" => i = skip_statement(tokens, i),
+            _ => {
+                let repeated = tokens[i] == "repeated";
+                if repeated {
+                    i += 1;
+                }
+                // The tokens of `map<K, V>` are split at its whitespace
+                let mut type_name = tokens[i].clone();
+                while type_name.starts_with("map<") && !type_name.ends_with('>') {
+                    i += 1;
+                    type_name.push_str(&tokens[i]);
+                }
+                let map_value = if type_name.starts_with("map<") {
+                    type_name[4..type_name.len() - 1]
+                        .split(',')
+                        .nth(1)
+                        .map(|value| value.trim().to_string())
+                } else {
+                    None
+                };
+                fields.push(ProtoField {
+                    type_name,
+                    name: tokens[i + 1].clone(),
+                    repeated,
+                    map_value,
+                });
+                i = skip_statement(tokens, i);
+            }
+        }
+    }
+    messages.push(ProtoMessage { name, fields });
+    i + 1
+}
+
+fn parse_enum(tokens: &[String], mut i: usize, scope: &str, enums: &mut Vec<ProtoEnum>) -> usize {
+    let name = qualify(scope, &tokens[i]);
+    i += 2;
+    let mut values = Vec::new();
+    while i < tokens.len() && tokens[i] != "}" {
+        if tokens[i] != "option" && tokens[i] != "reserved" {
+            values.push(tokens[i].clone());
+        }
+        i = skip_statement(tokens, i);
+    }
+    enums.push(ProtoEnum { name, values });
+    i + 1
+}
+
+fn skip_statement(tokens: &[String], mut i: usize) -> usize {
+    while i < tokens.len() && tokens[i] != ";" {
+        i += 1;
+    }
+    i + 1
+}
+
+fn qualify(scope: &str, name: &str) -> String {
+    if scope.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", scope, name)
+    }
+}
+
+/// Renders a draft-07 JSON Schema following the proto3 JSON mapping, with one definition
+/// per message and enum. The root of the schema is the `Message` envelope if present.
+fn json_schema(stem: &str, version: &str, messages: &[ProtoMessage], enums: &[ProtoEnum]) -> String {
+    let mut definitions = Vec::new();
+    for message in messages {
+        let properties = message
+            .fields
+            .iter()
+            .map(|field| {
+                if let Some(ref value) = field.map_value {
+                    let schema = field_schema(value, &message.name, messages, enums);
+                    return format!(
+                        r#""{}": {{"type": "object", "additionalProperties": {}}}"#,
+                        field.name, schema
+                    );
+                }
+                let schema = field_schema(&field.type_name, &message.name, messages, enums);
+                if field.repeated {
+                    format!(r#""{}": {{"type": "array", "items": {}}}"#, field.name, schema)
+                } else {
+                    format!(r#""{}": {}"#, field.name, schema)
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        definitions.push(format!(
+            r#"    "{}": {{"type": "object", "properties": {{{}}}}}"#,
+            message.name, properties
+        ));
+    }
+    for proto_enum in enums {
+        let values = proto_enum
+            .values
+            .iter()
+            .map(|value| format!(r#""{}""#, value))
+            .collect::<Vec<_>>()
+            .join(", ");
+        definitions.push(format!(
+            r#"    "{}": {{"type": "string", "enum": [{}]}}"#,
+            proto_enum.name, values
+        ));
+    }
+
+    let root = if messages.iter().any(|message| message.name == "Message") {
+        r##"  "$ref": "#/definitions/Message",
+"##
+    } else {
+        ""
+    };
+
+    format!(
+        r#"{{
+  "$schema": "http://json-schema.org/draft-07/schema#",
+  "$id": "{}-{}",
+{}  "definitions": {{
+{}
+  }}
+}}
+"#,
+        stem,
+        version,
+        root,
+        definitions.join(",\n")
+    )
+}
+
+fn field_schema(
+    type_name: &str,
+    scope: &str,
+    messages: &[ProtoMessage],
+    enums: &[ProtoEnum],
+) -> String {
+    match type_name {
+        "string" => r#"{"type": "string"}"#.to_string(),
+        "bytes" => r#"{"type": "string", "contentEncoding": "base64"}"#.to_string(),
+        "bool" => r#"{"type": "boolean"}"#.to_string(),
+        "int32" | "uint32" | "sint32" | "fixed32" | "sfixed32" => {
+            r#"{"type": "integer"}"#.to_string()
+        }
+        // 64 bit integers are encoded as strings by the proto3 JSON mapping
+        "int64" | "uint64" | "sint64" | "fixed64" | "sfixed64" => {
+            r#"{"type": "string", "pattern": "^-?[0-9]+$"}"#.to_string()
+        }
+        "float" | "double" => r#"{"type": "number"}"#.to_string(),
+        _ => {
+            let scoped = qualify(scope, type_name);
+            let is_defined = |name: &str| {
+                messages.iter().any(|message| message.name == name)
+                    || enums.iter().any(|proto_enum| proto_enum.name == name)
+            };
+            let name = if is_defined(&scoped) {
+                scoped
+            } else {
+                type_name.to_string()
+            };
+            format!(r##"{{"$ref": "#/definitions/{}"}}"##, name)
+        }
+    }
 }
 
 fn glob_simple(pattern: &str) -> Vec<String> {
@@ -0,0 +1,55 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use std::error::Error;
+use std::fmt;
+
+use kafka::error::Error as KafkaError;
+
+#[derive(Debug)]
+pub enum AggregatorError {
+    ConsumerError(KafkaError),
+    StartUpError(String),
+}
+
+impl Error for AggregatorError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            AggregatorError::ConsumerError(err) => Some(err),
+            AggregatorError::StartUpError(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for AggregatorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AggregatorError::ConsumerError(e) => {
+                write!(f, "Unable to consume sibling exports: {}", e)
+            }
+            AggregatorError::StartUpError(msg) => {
+                write!(f, "Unable to start the aggregator: {}", msg)
+            }
+        }
+    }
+}
+
+impl From<KafkaError> for AggregatorError {
+    fn from(err: KafkaError) -> Self {
+        AggregatorError::ConsumerError(err)
+    }
+}
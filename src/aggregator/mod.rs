@@ -0,0 +1,259 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+//! Aggregator mode: merges the exports of sibling exporters into this exporter's sinks.
+//!
+//! Envelopes are consumed from the configured topics and republished in the order they
+//! are consumed, keyed like locally exported messages, so each circuit's merged stream
+//! stays ordered. Consortium members export the same admin events and contract state,
+//! so each message is identified by a digest of its type and contents, and the copies
+//! received from the other members are dropped.
+
+mod error;
+
+pub use error::AggregatorError;
+
+use std::collections::{HashSet, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crypto::digest::Digest;
+use crypto::sha2::Sha256;
+use kafka::consumer::{Consumer, FetchOffset, GroupOffsetStorage};
+use protobuf::{Message as Msg, ProtobufEnum};
+
+use crate::config::DeploymentConfig;
+use crate::proto::pubsub::{
+    CircuitCreated, CircuitPayload, CircuitPayloadDeleted, CircuitRemoved, ContractDeployFailed,
+    Message, Message_MessageType, ProposalAccept, ProposalReady, ProposalReject, ProposalSubmit,
+    ProposalVote,
+};
+use crate::publisher::KafkaPublisher;
+use crate::shutdown::Intake;
+
+/// Sibling exports the aggregator consumes
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AggregatorConfig {
+    /// Kafka broker of the sibling exports, `kafka_url` when not set
+    #[serde(default)]
+    pub kafka_url: Option<String>,
+    pub topics: Vec<String>,
+    #[serde(default = "default_group")]
+    pub group: String,
+    /// Number of message digests remembered to drop duplicates
+    #[serde(default = "default_dedup_window")]
+    pub dedup_window: usize,
+}
+
+/// default consumer group of the aggregator
+fn default_group() -> String {
+    "event-listener-aggregator".to_string()
+}
+
+/// default number of message digests remembered
+fn default_dedup_window() -> usize {
+    100_000
+}
+
+/// Pause after a failed poll before trying again
+const POLL_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+pub struct Aggregator {
+    running: Arc<AtomicBool>,
+    join_handle: thread::JoinHandle<()>,
+}
+
+impl Aggregator {
+    pub fn start(
+        deployment_config: &DeploymentConfig,
+        publisher: KafkaPublisher,
+        intake: Intake,
+    ) -> Result<Self, AggregatorError> {
+        let config = deployment_config.aggregator().cloned().ok_or_else(|| {
+            AggregatorError::StartUpError("no aggregator configuration".to_string())
+        })?;
+        let kafka_url = config
+            .kafka_url
+            .clone()
+            .unwrap_or_else(|| deployment_config.kafka_url().to_string());
+
+        let mut builder = Consumer::from_hosts(vec![kafka_url])
+            .with_group(config.group.clone())
+            .with_fallback_offset(FetchOffset::Earliest)
+            .with_offset_storage(GroupOffsetStorage::Kafka);
+        if let Some(security) = deployment_config.kafka_security() {
+            let security = security
+                .security_config()
+                .map_err(|err| AggregatorError::StartUpError(err.to_string()))?;
+            builder = builder.with_security(security);
+        }
+        for topic in &config.topics {
+            builder = builder.with_topic(topic.clone());
+        }
+        let mut consumer = builder.create()?;
+
+        let running = Arc::new(AtomicBool::new(true));
+        let thread_running = running.clone();
+        let join_handle = thread::Builder::new()
+            .name("Aggregator".into())
+            .spawn(move || {
+                let mut seen = SeenMessages::new(config.dedup_window);
+                while thread_running.load(Ordering::SeqCst) {
+                    let in_flight = match intake.enter() {
+                        Some(in_flight) => in_flight,
+                        None => break,
+                    };
+                    publisher.wait_for_backlog();
+                    if let Err(err) = poll(&mut consumer, &publisher, &mut seen) {
+                        warn!("{}", err);
+                        drop(in_flight);
+                        thread::sleep(POLL_RETRY_DELAY);
+                    }
+                }
+            })
+            .map_err(|err| AggregatorError::StartUpError(err.to_string()))?;
+
+        Ok(Aggregator {
+            running,
+            join_handle,
+        })
+    }
+
+    pub fn shutdown(self) {
+        self.running.store(false, Ordering::SeqCst);
+        if self.join_handle.join().is_err() {
+            error!("Aggregator thread panicked");
+        }
+    }
+}
+
+/// Republishes one batch of consumed envelopes. Offsets are only committed once the
+/// whole batch is published, so a failure redelivers it and duplicates are dropped.
+fn poll(
+    consumer: &mut Consumer,
+    publisher: &KafkaPublisher,
+    seen: &mut SeenMessages,
+) -> Result<(), AggregatorError> {
+    for message_set in consumer.poll()?.iter() {
+        for message in message_set.messages() {
+            let envelope = match protobuf::parse_from_bytes::<Message>(message.value) {
+                Ok(envelope) => envelope,
+                Err(err) => {
+                    warn!(
+                        "Skipping unreadable message at {}:{}@{}: {}",
+                        message_set.topic(),
+                        message_set.partition(),
+                        message.offset,
+                        err
+                    );
+                    continue;
+                }
+            };
+            if !seen.insert(digest(&envelope)) {
+                continue;
+            }
+            let message_type = envelope.get_field_type();
+            let circuit_id = circuit_id(&envelope).unwrap_or_default();
+            if !envelope.get_circuit_management_type().is_empty() {
+                publisher.set_management_type(&circuit_id, envelope.get_circuit_management_type());
+            }
+            if let Err(err) =
+                publisher.republish(&circuit_id, message_type, envelope.get_message().to_vec())
+            {
+                error!(
+                    "Unable to republish {:?} of circuit {}: {}",
+                    message_type, circuit_id, err
+                );
+            }
+        }
+        consumer.consume_messageset(message_set)?;
+    }
+    consumer.commit_consumed()?;
+    Ok(())
+}
+
+/// Identifies a message by its type and contents, whichever exporter sent it
+fn digest(envelope: &Message) -> String {
+    let mut sha = Sha256::new();
+    sha.input(&envelope.get_field_type().value().to_be_bytes());
+    sha.input(envelope.get_message());
+    sha.result_str()
+}
+
+fn circuit_id(envelope: &Message) -> Option<String> {
+    let bytes = envelope.get_message();
+    let circuit_id = match envelope.get_field_type() {
+        Message_MessageType::PROPOSAL_SUBMIT => parse::<ProposalSubmit>(bytes)?.take_circuit_id(),
+        Message_MessageType::PROPOSAL_VOTE => parse::<ProposalVote>(bytes)?.take_circuit_id(),
+        Message_MessageType::PROPOSAL_ACCEPT => parse::<ProposalAccept>(bytes)?.take_circuit_id(),
+        Message_MessageType::PROPOSAL_REJECT => parse::<ProposalReject>(bytes)?.take_circuit_id(),
+        Message_MessageType::PROPOSAL_READY => parse::<ProposalReady>(bytes)?.take_circuit_id(),
+        Message_MessageType::CIRCUIT_CREATED => parse::<CircuitCreated>(bytes)?.take_circuit_id(),
+        Message_MessageType::CIRCUIT_PAYLOAD => parse::<CircuitPayload>(bytes)?.take_circuit_id(),
+        Message_MessageType::CIRCUIT_REMOVED => parse::<CircuitRemoved>(bytes)?.take_circuit_id(),
+        Message_MessageType::CONTRACT_DEPLOY_FAILED => {
+            parse::<ContractDeployFailed>(bytes)?.take_circuit_id()
+        }
+        Message_MessageType::CIRCUIT_PAYLOAD_DELETED => {
+            parse::<CircuitPayloadDeleted>(bytes)?.take_circuit_id()
+        }
+        // Control messages are not tied to a circuit
+        Message_MessageType::CONTROL | Message_MessageType::TYPE_UNKNOWN => return None,
+    };
+    Some(circuit_id)
+}
+
+fn parse<M: Msg>(bytes: &[u8]) -> Option<M> {
+    protobuf::parse_from_bytes::<M>(bytes).ok()
+}
+
+/// The digests of the most recently republished messages
+struct SeenMessages {
+    capacity: usize,
+    order: VecDeque<String>,
+    digests: HashSet<String>,
+}
+
+impl SeenMessages {
+    fn new(capacity: usize) -> Self {
+        SeenMessages {
+            capacity,
+            order: VecDeque::new(),
+            digests: HashSet::new(),
+        }
+    }
+
+    /// Remembers the digest, returns false if it was already known
+    fn insert(&mut self, digest: String) -> bool {
+        if self.capacity == 0 {
+            return true;
+        }
+        if self.digests.contains(&digest) {
+            return false;
+        }
+        if self.order.len() >= self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.digests.remove(&oldest);
+            }
+        }
+        self.order.push_back(digest.clone());
+        self.digests.insert(digest);
+        true
+    }
+}
@@ -0,0 +1,128 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+//! Alerts posted to an incoming webhook, such as a Slack one, when a subscription stops
+//! for good or the webhook sink's endpoint turns slow.
+
+use std::time::Duration;
+
+use futures::{Future, Stream};
+use hyper::{Body, Request};
+use serde_json::Value;
+use splinter::events::Igniter;
+use tokio::runtime::Runtime;
+
+use crate::http;
+use crate::time::now_millis;
+
+/// Posts an alert when a WebSocket subscription exhausts its reconnect limit, which
+/// otherwise only shows in the logs and the status API, or when the webhook sink's
+/// endpoint keeps responding slower than its threshold.
+///
+/// The body carries a `text` field for Slack along with the subscription, the circuit
+/// and the last error for other receivers.
+#[derive(Clone, Default)]
+pub struct Alerter {
+    url: Option<String>,
+}
+
+impl Alerter {
+    pub fn new(url: Option<&str>) -> Self {
+        Alerter {
+            url: url.map(ToOwned::to_owned),
+        }
+    }
+
+    /// Sends the alert on the reactor, failures to deliver it are only logged
+    pub fn reconnect_exhausted(
+        &self,
+        igniter: &Igniter,
+        subscription: &str,
+        circuit_id: Option<&str>,
+        error: &str,
+    ) {
+        let body = json!({
+            "text": format!(
+                "event-listener gave up reconnecting subscription {}: {}",
+                subscription, error
+            ),
+            "subscription": subscription,
+            "circuit_id": circuit_id,
+            "error": error,
+            "at": now_millis(),
+        });
+        if let Some(future) = self.post(body) {
+            if let Err(err) = igniter.send(future) {
+                error!("Unable to send alert: {}", err);
+            }
+        }
+    }
+
+    /// Sends the alert on the runtime of the webhook worker that found its endpoint
+    /// responding slower than its threshold
+    pub fn slow_webhook(&self, runtime: &mut Runtime, endpoint: &str, latency: Duration) {
+        let latency = latency.as_secs() * 1000 + u64::from(latency.subsec_millis());
+        let body = json!({
+            "text": format!(
+                "event-listener webhook {} is slow, its last response took {}ms",
+                endpoint, latency
+            ),
+            "endpoint": endpoint,
+            "latency_ms": latency,
+            "at": now_millis(),
+        });
+        if let Some(future) = self.post(body) {
+            runtime.spawn(future);
+        }
+    }
+
+    /// Builds the request posting the alert, or None when no alert webhook is configured
+    fn post(&self, body: Value) -> Option<Box<dyn Future<Item = (), Error = ()> + Send>> {
+        let url = self.url.as_ref()?;
+        let client = match http::client() {
+            Ok(client) => client,
+            Err(err) => {
+                error!("Unable to create the alert client: {}", err);
+                return None;
+            }
+        };
+        let mut builder = Request::post(url.as_str());
+        builder.header("Content-Type", "application/json");
+        let request = match builder.body(Body::from(body.to_string())) {
+            Ok(request) => request,
+            Err(err) => {
+                error!("Unable to build the alert request: {}", err);
+                return None;
+            }
+        };
+        let future = client
+            .request(request)
+            .and_then(|resp| {
+                let status = resp.status();
+                resp.into_body().concat2().map(move |_| status)
+            })
+            .then(|response| {
+                match response {
+                    Ok(status) if status.is_success() => debug!("Alert delivered"),
+                    Ok(status) => error!("Alert webhook responded with status {}", status),
+                    Err(err) => error!("Unable to deliver alert: {}", err),
+                }
+                Ok::<(), ()>(())
+            });
+        Some(Box::new(future))
+    }
+}
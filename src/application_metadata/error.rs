@@ -23,6 +23,7 @@ use std::fmt;
 pub enum ApplicationMetadataError {
     SerializationError(SerdeError),
     DeserializationError(SerdeError),
+    UnsupportedVersion(u64),
 }
 
 impl Error for ApplicationMetadataError {
@@ -30,6 +31,7 @@ impl Error for ApplicationMetadataError {
         match self {
             ApplicationMetadataError::SerializationError(err) => Some(err),
             ApplicationMetadataError::DeserializationError(err) => Some(err),
+            ApplicationMetadataError::UnsupportedVersion(_) => None,
         }
     }
 }
@@ -43,6 +45,9 @@ impl fmt::Display for ApplicationMetadataError {
             ApplicationMetadataError::DeserializationError(e) => {
                 write!(f, "Failed to deserialize ApplicationMetadata: {}", e)
             }
+            ApplicationMetadataError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported ApplicationMetadata version {}", version)
+            }
         }
     }
 }
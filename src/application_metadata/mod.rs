@@ -19,22 +19,109 @@ mod error;
 
 pub use error::ApplicationMetadataError;
 
+use crate::config::{DeploymentConfig, TransactionProcessor};
+use crate::publisher::OutputFormat;
+
+/// Metadata without a `version` field, as written by gameroom
+const VERSION_1: u64 = 1;
+/// Metadata which may carry the circuit's data export preferences and contract
+const VERSION_2: u64 = 2;
+
+/// The application metadata of a circuit.
+///
+/// Version 1 has the alias, the scabbard admin keys and comments. Version 2 adds the
+/// `data_export` preferences and the `contract`, which are ignored in version 1 metadata.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ApplicationMetadata {
+    #[serde(default = "default_version", skip_serializing_if = "is_version_1")]
+    version: u64,
     alias: String,
     scabbard_admin_keys: Vec<String>,
+    #[serde(default)]
+    comments: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    data_export: Option<ExportPreferences>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    contract: Option<ContractSelection>,
+}
+
+/// The Sabre contract the members of a circuit run, in place of the deployment's contracts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContractSelection {
+    pub name: String,
+    pub version: String,
+    pub prefix: String,
+}
+
+impl ContractSelection {
+    /// Returns the selected contract, with the path of the configured contract of the same
+    /// name and version. The path is empty if no such contract is configured, the contract
+    /// is then exported but cannot be set up by the exporter.
+    pub fn resolve(&self, deployment_config: &DeploymentConfig) -> TransactionProcessor {
+        let path = deployment_config
+            .transaction_processors()
+            .into_iter()
+            .find(|processor| processor.name == self.name && processor.version == self.version)
+            .map(|processor| processor.path)
+            .unwrap_or_default();
+        TransactionProcessor {
+            name: self.name.clone(),
+            version: self.version.clone(),
+            prefix: self.prefix.clone(),
+            path,
+        }
+    }
+}
+
+/// How the members of a circuit want its events exported
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ExportPreferences {
+    /// Topic of the circuit's messages, replacing `kafka_topic` and the topic template
+    #[serde(default)]
+    pub topic: Option<String>,
+    #[serde(default)]
+    pub output_format: Option<OutputFormat>,
+    /// Names of the message types exported for the circuit, every type when unset
+    #[serde(default)]
+    pub message_types: Option<Vec<String>>,
+    /// PEM encoded RSA public key of the consumer the circuit's payloads are encrypted
+    /// for, payloads are exported in the clear when unset
+    #[serde(default)]
+    pub encryption_key: Option<String>,
+}
+
+fn default_version() -> u64 {
+    VERSION_1
+}
+
+fn is_version_1(version: &u64) -> bool {
+    *version == VERSION_1
 }
 
 impl ApplicationMetadata {
     pub fn new(alias: &str, scabbard_admin_keys: &[String]) -> ApplicationMetadata {
         ApplicationMetadata {
+            version: VERSION_1,
             alias: alias.to_string(),
             scabbard_admin_keys: scabbard_admin_keys.to_vec(),
+            comments: None,
+            data_export: None,
+            contract: None,
         }
     }
 
     pub fn from_bytes(bytes: &[u8]) -> Result<ApplicationMetadata, ApplicationMetadataError> {
-        serde_json::from_slice(bytes).map_err(ApplicationMetadataError::DeserializationError)
+        let mut metadata: ApplicationMetadata = serde_json::from_slice(bytes)
+            .map_err(ApplicationMetadataError::DeserializationError)?;
+        match metadata.version {
+            VERSION_1 => {
+                metadata.data_export = None;
+                metadata.contract = None;
+            }
+            VERSION_2 => (),
+            version => return Err(ApplicationMetadataError::UnsupportedVersion(version)),
+        }
+        Ok(metadata)
     }
 
     pub fn to_bytes(&self) -> Result<Vec<u8>, ApplicationMetadataError> {
@@ -48,4 +135,20 @@ impl ApplicationMetadata {
     pub fn scabbard_admin_keys(&self) -> &[String] {
         &self.scabbard_admin_keys
     }
+
+    pub fn comments(&self) -> Option<&str> {
+        self.comments.as_ref().map(String::as_str)
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    pub fn data_export(&self) -> Option<&ExportPreferences> {
+        self.data_export.as_ref()
+    }
+
+    pub fn contract(&self) -> Option<&ContractSelection> {
+        self.contract.as_ref()
+    }
 }
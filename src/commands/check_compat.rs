@@ -0,0 +1,283 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use std::collections::BTreeMap;
+use std::fs;
+
+use futures::{Future, Stream};
+use hyper::{StatusCode, Uri};
+use serde_json::Value;
+use tokio::runtime::Runtime;
+
+use crate::error::EventListenerError;
+use crate::http;
+use crate::publisher::avro_schema;
+use crate::schema::schema_document;
+
+/// Compares the schemas of the exported messages with a previously published version and
+/// prints the changes which would break its consumers.
+///
+/// `against` is a file or an HTTP(S) URL holding either the output of `describe-schema
+/// --format json`, or an Avro schema, as written to a file or as returned by a schema
+/// registry for a subject version. Fails if a breaking change is found, so the command can
+/// gate an upgrade.
+pub fn check_compat(against: &str) -> Result<(), EventListenerError> {
+    let previous = load(against)?;
+    let changes = if previous.get("messages").is_some() {
+        protobuf_changes(&previous, &schema_document())
+    } else {
+        let previous = match previous.get("schema").and_then(Value::as_str) {
+            Some(registered) => serde_json::from_str(registered).map_err(command_error)?,
+            None => previous,
+        };
+        avro_changes(&previous, &avro_schema())
+    };
+
+    if changes.is_empty() {
+        println!("No breaking changes against {}", against);
+        return Ok(());
+    }
+    println!("Breaking changes against {}:", against);
+    for change in &changes {
+        println!("  {}", change);
+    }
+    Err(EventListenerError::CommandError(format!(
+        "{} breaking schema changes",
+        changes.len()
+    )))
+}
+
+fn load(against: &str) -> Result<Value, EventListenerError> {
+    if !against.starts_with("http://") && !against.starts_with("https://") {
+        let contents = fs::read_to_string(against).map_err(|err| {
+            EventListenerError::CommandError(format!("Unable to read {}: {}", against, err))
+        })?;
+        return serde_json::from_str(&contents).map_err(command_error);
+    }
+
+    let uri = against.parse::<Uri>().map_err(command_error)?;
+    let client = http::client().map_err(command_error)?;
+    let mut runtime = Runtime::new().map_err(command_error)?;
+    let (status, body) = runtime
+        .block_on(client.get(uri).and_then(|res| {
+            let status = res.status();
+            res.into_body()
+                .concat2()
+                .map(move |body| (status, body.to_vec()))
+        }))
+        .map_err(|err| {
+            EventListenerError::CommandError(format!("Unable to fetch {}: {}", against, err))
+        })?;
+    if status != StatusCode::OK {
+        return Err(EventListenerError::CommandError(format!(
+            "{} responded with status {}",
+            against, status
+        )));
+    }
+    serde_json::from_slice(&body).map_err(command_error)
+}
+
+/// Fields and enum values are matched by number, as protobuf consumers decode them, and by
+/// name, as consumers of the JSON output read them, so removing, renumbering, renaming or
+/// retyping either breaks some consumer.
+fn protobuf_changes(previous: &Value, current: &Value) -> Vec<String> {
+    let current_messages = by_name(current.get("messages"));
+    let mut changes = vec![];
+    for (name, message) in by_name(previous.get("messages")) {
+        let current_message = match current_messages.get(name.as_str()) {
+            Some(current_message) => current_message,
+            None => {
+                changes.push(format!("message {} was removed", name));
+                continue;
+            }
+        };
+        let fields = by_name(current_message.get("fields"));
+        for (field_name, field) in by_name(message.get("fields")) {
+            let path = format!("{}.{}", name, field_name);
+            match fields.get(field_name.as_str()) {
+                Some(current_field) => {
+                    if current_field["number"] != field["number"] {
+                        changes.push(format!(
+                            "{} was renumbered from {} to {}",
+                            path, field["number"], current_field["number"]
+                        ));
+                    }
+                    if current_field["type"] != field["type"] {
+                        changes.push(format!(
+                            "{} changed type from {} to {}",
+                            path, field["type"], current_field["type"]
+                        ));
+                    }
+                }
+                None => changes.push(format!("{} ({}) was removed", path, field["number"])),
+            }
+        }
+
+        let enums = by_name(current_message.get("enums"));
+        for (enum_name, enum_type) in by_name(message.get("enums")) {
+            let path = format!("{}.{}", name, enum_name);
+            let values = match enums.get(enum_name.as_str()) {
+                Some(current_enum) => by_name(current_enum.get("values")),
+                None => {
+                    changes.push(format!("enum {} was removed", path));
+                    continue;
+                }
+            };
+            for (value_name, value) in by_name(enum_type.get("values")) {
+                match values.get(value_name.as_str()) {
+                    Some(current_value) if current_value["number"] != value["number"] => changes
+                        .push(format!(
+                            "{}.{} was renumbered from {} to {}",
+                            path, value_name, value["number"], current_value["number"]
+                        )),
+                    Some(_) => (),
+                    None => changes.push(format!("{}.{} was removed", path, value_name)),
+                }
+            }
+        }
+    }
+    changes
+}
+
+/// A consumer still reading with the previous schema fails on a field it expects without
+/// a default, on a field whose type changed, or on an enum symbol it does not know
+fn avro_changes(previous: &Value, current: &Value) -> Vec<String> {
+    let mut previous_types = BTreeMap::new();
+    collect_named_types(previous, &mut previous_types);
+    let mut current_types = BTreeMap::new();
+    collect_named_types(current, &mut current_types);
+
+    let mut changes = vec![];
+    for (name, named_type) in previous_types {
+        let current_type = match current_types.get(&name) {
+            Some(current_type) => current_type,
+            None => {
+                changes.push(format!(
+                    "{} {} was removed",
+                    text(named_type.get("type")),
+                    name
+                ));
+                continue;
+            }
+        };
+        if named_type["type"] == "enum" {
+            let symbols = current_type["symbols"]
+                .as_array()
+                .cloned()
+                .unwrap_or_default();
+            for symbol in named_type["symbols"].as_array().into_iter().flatten() {
+                if !symbols.contains(symbol) {
+                    changes.push(format!("{}.{} was removed", name, symbol));
+                }
+            }
+            continue;
+        }
+        let fields = by_name(current_type.get("fields"));
+        for (field_name, field) in by_name(named_type.get("fields")) {
+            let path = format!("{}.{}", name, field_name);
+            match fields.get(field_name.as_str()) {
+                Some(current_field) => {
+                    if type_name(&current_field["type"]) != type_name(&field["type"]) {
+                        changes.push(format!(
+                            "{} changed type from {} to {}",
+                            path,
+                            type_name(&field["type"]),
+                            type_name(&current_field["type"])
+                        ));
+                    }
+                }
+                None if field.get("default").is_none() => {
+                    changes.push(format!("{} was removed and has no default", path))
+                }
+                None => (),
+            }
+        }
+    }
+    changes
+}
+
+/// Collects the records and enums defined anywhere in an Avro schema, by name
+fn collect_named_types<'a>(schema: &'a Value, types: &mut BTreeMap<String, &'a Value>) {
+    match schema {
+        Value::Array(union) => union
+            .iter()
+            .for_each(|branch| collect_named_types(branch, types)),
+        Value::Object(object) => {
+            let kind = object.get("type").and_then(Value::as_str);
+            if kind == Some("record") || kind == Some("enum") {
+                if let Some(name) = object.get("name").and_then(Value::as_str) {
+                    types.insert(name.to_string(), schema);
+                }
+                for field in object
+                    .get("fields")
+                    .and_then(Value::as_array)
+                    .into_iter()
+                    .flatten()
+                {
+                    collect_named_types(&field["type"], types);
+                }
+            } else {
+                for key in &["type", "items", "values"] {
+                    if let Some(nested) = object.get(*key) {
+                        collect_named_types(nested, types);
+                    }
+                }
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Names an Avro type, records and enums by their name so that a change inside a nested
+/// record is reported on the record itself
+fn type_name(avro_type: &Value) -> String {
+    match avro_type {
+        Value::String(name) => name.clone(),
+        Value::Array(union) => format!(
+            "[{}]",
+            union.iter().map(type_name).collect::<Vec<_>>().join(", ")
+        ),
+        Value::Object(object) => match object.get("type").and_then(Value::as_str) {
+            Some("record") | Some("enum") | Some("fixed") => text(object.get("name")),
+            Some("array") => format!("array<{}>", type_name(&avro_type["items"])),
+            Some("map") => format!("map<{}>", type_name(&avro_type["values"])),
+            _ => type_name(&avro_type["type"]),
+        },
+        _ => avro_type.to_string(),
+    }
+}
+
+/// Indexes the elements of an array of named objects by their name
+fn by_name(elements: Option<&Value>) -> BTreeMap<String, &Value> {
+    elements
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .map(|element| (text(element.get("name")), element))
+        .collect()
+}
+
+fn text(value: Option<&Value>) -> String {
+    value
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn command_error<E: std::fmt::Display>(err: E) -> EventListenerError {
+    EventListenerError::CommandError(err.to_string())
+}
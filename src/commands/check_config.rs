@@ -0,0 +1,53 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use crate::config::DataReaderConfigBuilder;
+use crate::error::EventListenerError;
+
+/// Loads and validates the configuration `run` would start with, and prints a summary
+pub fn check_config(builder: DataReaderConfigBuilder) -> Result<(), EventListenerError> {
+    let config = builder.build()?;
+    let deployment_config = config.deployment_config();
+
+    println!("Configuration is valid");
+    println!("  export mode:    {:?}", deployment_config.export_mode());
+    println!("  splinterd:      {}", config.splinterd_url());
+    if deployment_config.kafka_enabled() {
+        println!(
+            "  kafka:          {} topic {}",
+            deployment_config.kafka_url(),
+            deployment_config.kafka_topic()
+        );
+    } else {
+        println!("  kafka:          disabled");
+    }
+    println!(
+        "  wal:            {}{}",
+        deployment_config.wal_dir().unwrap_or("none"),
+        if deployment_config.wal_encryption().is_some() {
+            " (encrypted)"
+        } else {
+            ""
+        }
+    );
+    println!(
+        "  rest api:       {}",
+        config.rest_api_endpoint().unwrap_or("none")
+    );
+    println!("  signing key:    {}", config.key_path().unwrap_or("none"));
+    Ok(())
+}
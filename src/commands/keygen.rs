@@ -0,0 +1,42 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use std::fs;
+use std::path::Path;
+
+use crate::error::EventListenerError;
+use crate::signing;
+
+/// Writes a new signing key to the file and prints its public key, to be listed among the
+/// scabbard admin keys of new circuits.
+///
+/// An existing key file is only replaced when `force` is set.
+pub fn keygen(path: &str, force: bool) -> Result<(), EventListenerError> {
+    if Path::new(path).exists() {
+        if !force {
+            return Err(EventListenerError::KeyFileError(format!(
+                "{}: already exists, use --force to replace it",
+                path
+            )));
+        }
+        fs::remove_file(path)
+            .map_err(|err| EventListenerError::KeyFileError(format!("{}: {}", path, err)))?;
+    }
+    let private_key = signing::load_or_generate(path)?;
+    println!("{}", signing::public_key(&private_key)?);
+    Ok(())
+}
@@ -0,0 +1,30 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+//! The operational commands of the binary, besides `run` which starts the daemon.
+
+mod check_compat;
+mod check_config;
+mod keygen;
+mod status;
+mod wal;
+
+pub use check_compat::check_compat;
+pub use check_config::check_config;
+pub use keygen::keygen;
+pub use status::status;
+pub use wal::{drain, replay};
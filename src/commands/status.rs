@@ -0,0 +1,190 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use futures::{Future, Stream};
+use hyper::{Client, StatusCode, Uri};
+use serde_json::Value;
+use tokio::runtime::Runtime;
+
+use crate::error::EventListenerError;
+use crate::time::now_millis;
+
+/// Prints the status reported by a running daemon's REST API, as tables or, with `json`,
+/// as the JSON document the API returned
+pub fn status(url: &str, json: bool) -> Result<(), EventListenerError> {
+    let status = fetch_status(url)?;
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&status).map_err(command_error)?
+        );
+        return Ok(());
+    }
+
+    let now = now_millis();
+    println!("SUBSCRIPTIONS");
+    print_table(
+        &["NAME", "STATE", "ATTEMPTS", "LAST MESSAGE", "LAST ERROR"],
+        entries(&status, "subscriptions")
+            .map(|(name, subscription)| {
+                let mut state = text(subscription.get("state"));
+                if subscription.get("catching_up").and_then(Value::as_bool) == Some(true) {
+                    state.push_str(" (catching up)");
+                }
+                vec![
+                    name.to_string(),
+                    state,
+                    text(subscription.get("attempts")),
+                    ago(subscription.get("last_message_at"), now),
+                    text(subscription.get("last_error")),
+                ]
+            })
+            .collect(),
+    );
+
+    println!("\nSINKS");
+    print_table(
+        &["NAME", "HEALTHY", "DELIVERED", "FAILED", "LAST ERROR"],
+        entries(&status, "sinks")
+            .map(|(name, sink)| {
+                vec![
+                    name.to_string(),
+                    text(sink.get("healthy")),
+                    text(sink.get("delivered")),
+                    text(sink.get("failed")),
+                    text(sink.get("last_error")),
+                ]
+            })
+            .collect(),
+    );
+
+    println!("\nCONSUMER LAG");
+    print_table(
+        &["GROUP", "TOPIC", "LAG", "CHECKED"],
+        status
+            .get("consumer_lag")
+            .and_then(Value::as_array)
+            .map(|lags| {
+                lags.iter()
+                    .map(|lag| {
+                        vec![
+                            text(lag.get("group")),
+                            text(lag.get("topic")),
+                            text(lag.get("lag")),
+                            ago(lag.get("at"), now),
+                        ]
+                    })
+                    .collect()
+            })
+            .unwrap_or_default(),
+    );
+
+    println!("\nBUFFER");
+    match status.get("wal").filter(|wal| !wal.is_null()) {
+        Some(wal) => println!(
+            "WAL: {} pending, oldest {}",
+            text(wal.get("pending")),
+            wal.get("oldest_pending_age")
+                .and_then(Value::as_u64)
+                .map_or("-".to_string(), |age| format!("{}s", age / 1000))
+        ),
+        None => println!("WAL: not configured"),
+    }
+    Ok(())
+}
+
+fn fetch_status(url: &str) -> Result<Value, EventListenerError> {
+    let uri = format!("{}/status", url.trim_end_matches('/'))
+        .parse::<Uri>()
+        .map_err(command_error)?;
+    let mut runtime = Runtime::new().map_err(command_error)?;
+    let body = runtime.block_on(Client::new().get(uri).and_then(|res| {
+        let status = res.status();
+        res.into_body()
+            .concat2()
+            .map(move |body| (status, body.to_vec()))
+    }));
+    let (status, body) = body.map_err(|err| {
+        EventListenerError::CommandError(format!("Unable to reach the daemon at {}: {}", url, err))
+    })?;
+    if status != StatusCode::OK {
+        return Err(EventListenerError::CommandError(format!(
+            "The daemon at {} responded with status {}",
+            url, status
+        )));
+    }
+    serde_json::from_slice(&body).map_err(command_error)
+}
+
+/// Returns the entries of an object of the status document
+fn entries<'a>(status: &'a Value, field: &str) -> impl Iterator<Item = (&'a String, &'a Value)> {
+    status
+        .get(field)
+        .and_then(Value::as_object)
+        .into_iter()
+        .flat_map(|object| object.iter())
+}
+
+fn text(value: Option<&Value>) -> String {
+    match value {
+        None | Some(Value::Null) => "-".to_string(),
+        Some(Value::String(value)) => value.clone(),
+        Some(value) => value.to_string(),
+    }
+}
+
+/// Formats a time in milliseconds since the UNIX epoch as the time elapsed since
+fn ago(value: Option<&Value>, now: u64) -> String {
+    match value.and_then(Value::as_u64) {
+        Some(at) => format!("{}s ago", now.saturating_sub(at) / 1000),
+        None => "never".to_string(),
+    }
+}
+
+fn print_table(headers: &[&str], rows: Vec<Vec<String>>) {
+    if rows.is_empty() {
+        println!("(none)");
+        return;
+    }
+    let mut widths = headers
+        .iter()
+        .map(|header| header.len())
+        .collect::<Vec<_>>();
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let format_row = |cells: Vec<&str>| {
+        cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
+    };
+    println!("{}", format_row(headers.to_vec()));
+    for row in &rows {
+        println!("{}", format_row(row.iter().map(String::as_str).collect()));
+    }
+}
+
+fn command_error<E: std::fmt::Display>(err: E) -> EventListenerError {
+    EventListenerError::CommandError(err.to_string())
+}
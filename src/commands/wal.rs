@@ -0,0 +1,91 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+//! Delivery of a WAL left behind by a daemon which is not running. The daemon replays its
+//! WAL itself, these commands must not be used on the WAL of a running daemon.
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::config::DeploymentConfig;
+use crate::error::EventListenerError;
+use crate::metrics::Metrics;
+use crate::publisher::{KafkaPublisher, RecentEvents};
+
+/// Time to wait between two replays while draining
+const DRAIN_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Delivers the pending WAL entries once, stopping at the first entry which cannot be
+/// delivered or is over its topic's quota
+pub fn replay(deployment_config: &DeploymentConfig) -> Result<(), EventListenerError> {
+    let publisher = wal_publisher(deployment_config)?;
+    let replayed = publisher.replay_wal()?;
+    println!(
+        "Replayed {} WAL entries, {} still pending",
+        replayed,
+        pending(&publisher)
+    );
+    Ok(())
+}
+
+/// Replays the WAL until it is empty, failing if entries are still pending after the
+/// timeout
+pub fn drain(
+    deployment_config: &DeploymentConfig,
+    timeout: Duration,
+) -> Result<(), EventListenerError> {
+    let publisher = wal_publisher(deployment_config)?;
+    let started = Instant::now();
+    let mut replayed = 0;
+    loop {
+        match publisher.replay_wal() {
+            Ok(count) => replayed += count,
+            Err(err) => warn!("Unable to replay WAL, retrying: {}", err),
+        }
+        let pending = pending(&publisher);
+        if pending == 0 {
+            println!("Drained the WAL, {} entries replayed", replayed);
+            return Ok(());
+        }
+        if started.elapsed() >= timeout {
+            return Err(EventListenerError::CommandError(format!(
+                "{} WAL entries still pending after {:?}, {} replayed",
+                pending, timeout, replayed
+            )));
+        }
+        thread::sleep(DRAIN_RETRY_INTERVAL);
+    }
+}
+
+fn wal_publisher(
+    deployment_config: &DeploymentConfig,
+) -> Result<KafkaPublisher, EventListenerError> {
+    if deployment_config.wal_dir().is_none() {
+        return Err(EventListenerError::CommandError(
+            "the configuration has no wal_dir".to_string(),
+        ));
+    }
+    Ok(KafkaPublisher::new(
+        deployment_config,
+        RecentEvents::new(0),
+        Metrics::new(),
+    )?)
+}
+
+fn pending(publisher: &KafkaPublisher) -> usize {
+    publisher.wal_stats().map_or(0, |stats| stats.pending)
+}
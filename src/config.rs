@@ -16,30 +16,479 @@
  * -----------------------------------------------------------------------------
  */
 
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::process::Command;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+
 use actix_web::Result;
 use futures::{
     future::{self, Either},
     Future, Stream,
 };
-use hyper::{Client as HyperClient, StatusCode, Uri};
+use hyper::{StatusCode, Uri};
+use openssl::sha::sha256;
 use serde_json::Value;
 use splinter::node_registry::Node;
 use tokio::runtime::Runtime;
 
+use crate::aggregator::AggregatorConfig;
 use crate::error::{ConfigurationError, GetNodeError};
+use crate::event_handler::{
+    to_hex, BackfillDepth, CheckpointStoreConfig, DecoderKind, EmptyValuePolicy,
+    StateFilterConfig,
+};
+use crate::http::{self, TlsConfig};
+use crate::publisher::{
+    validate_message_types, validate_topic_map, DualWriteConfig, FileSinkConfig,
+    KafkaProducerConfig, KafkaSecurityConfig, KeyStrategy, OutputFormat, PartitionStrategy,
+    PluginConfig, StateMirrorConfig, WebhookConfig, ENVELOPE_VERSIONS,
+};
+
+/// Which events the exporter subscribes to
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ExportMode {
+    /// Export the admin events and the contract state of every circuit
+    Full,
+    /// Export only the admin events, without subscribing to scabbard
+    AdminOnly,
+    /// Export only the contract state of the circuits listed in `state_subscriptions`,
+    /// without registering for admin events
+    StateOnly,
+    /// Republish the exports of sibling exporters read from the `aggregator` topics,
+    /// without connecting to splinterd
+    Aggregator,
+}
+
+/// How the scabbard events which cannot be parsed are handled
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum EventParsing {
+    /// Close the subscription at the first such event, to surface it in test environments
+    Strict,
+    /// Log and count the event as a circuit error, then carry on with the next one
+    Lenient,
+}
+
+impl Default for EventParsing {
+    fn default() -> Self {
+        EventParsing::Lenient
+    }
+}
+
+/// Whether the exporter sets up the Sabre contracts of a circuit once it is ready
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum DeployMode {
+    /// Submit every setup transaction, failing if a peer already registered the contract
+    Always,
+    /// Read the Sabre state first and submit only the transactions of the missing pieces
+    IfMissing,
+    /// Never set up contracts, they are deployed by other means
+    Never,
+}
+
+impl Default for DeployMode {
+    fn default() -> Self {
+        DeployMode::Always
+    }
+}
+
+/// A scabbard service to read contract state from in the state-only export mode
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StateSubscription {
+    pub circuit_id: String,
+    pub service_id: String,
+    #[serde(default)]
+    pub requester: String,
+    #[serde(default)]
+    pub requester_node_id: String,
+}
+
+impl Default for ExportMode {
+    fn default() -> Self {
+        ExportMode::Full
+    }
+}
+
+/// Kind of token presented to splinterd
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum AuthScheme {
+    /// A Cylinder JWT issued for the exporter
+    Cylinder,
+    /// A Biome access token of a splinterd user
+    Biome,
+}
+
+/// Provides the `Authorization` header of the requests to splinterd
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SplinterdAuth {
+    scheme: AuthScheme,
+    #[serde(default)]
+    token: Option<String>,
+    /// File holding the token, read again for each request so the token can be rotated
+    /// without a restart
+    #[serde(default)]
+    token_file: Option<String>,
+}
+
+impl SplinterdAuth {
+    pub fn authorization(&self) -> Result<String, io::Error> {
+        let token = match (&self.token, &self.token_file) {
+            (Some(token), _) => token.clone(),
+            (None, Some(token_file)) => fs::read_to_string(token_file)?.trim().to_string(),
+            (None, None) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "splinterd_auth has no token",
+                ))
+            }
+        };
+        let scheme = match self.scheme {
+            AuthScheme::Cylinder => "Cylinder",
+            AuthScheme::Biome => "Biome",
+        };
+        Ok(format!("Bearer {}:{}", scheme, token))
+    }
+}
+
+/// Source of the AES-256 key encrypting the WAL entries at rest, as 64 hex digits
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WalEncryptionConfig {
+    #[serde(default)]
+    key: Option<String>,
+    #[serde(default)]
+    key_file: Option<String>,
+    /// Shell command printing the key, e.g. one unwrapping it with a KMS, so that the
+    /// key itself never sits on the node's disk
+    #[serde(default)]
+    key_command: Option<String>,
+}
+
+impl WalEncryptionConfig {
+    pub fn key(&self) -> Result<String, io::Error> {
+        match (&self.key, &self.key_file, &self.key_command) {
+            (Some(key), None, None) => Ok(key.clone()),
+            (None, Some(key_file), None) => fs::read_to_string(key_file),
+            (None, None, Some(key_command)) => {
+                let output = Command::new("sh").arg("-c").arg(key_command).output()?;
+                if !output.status.success() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::Other,
+                        format!(
+                            "wal_encryption.key_command failed with {}: {}",
+                            output.status,
+                            String::from_utf8_lossy(&output.stderr).trim()
+                        ),
+                    ));
+                }
+                String::from_utf8(output.stdout)
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "wal_encryption needs exactly one of key, key_file and key_command",
+            )),
+        }
+    }
+
+    fn sources(&self) -> usize {
+        [&self.key, &self.key_file, &self.key_command]
+            .iter()
+            .filter(|source| source.is_some())
+            .count()
+    }
+}
+
+/// A Sabre smart contract set up on the circuits, whose state is exported
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct TransactionProcessor {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub version: String,
+    /// State address prefix of the contract, its state deltas are exported tagged with
+    /// the contract name
+    pub prefix: String,
+    #[serde(default)]
+    pub path: String,
+}
+
+impl TransactionProcessor {
+    /// Returns whether a state address belongs to the contract's namespace
+    pub fn owns(&self, address: &str) -> bool {
+        !self.prefix.is_empty() && address.starts_with(&self.prefix)
+    }
+}
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct DeploymentConfig {
+    #[serde(default)]
+    export_mode: ExportMode,
+    #[serde(default)]
+    event_parsing: EventParsing,
+    #[serde(default = "default_circuit_management_types")]
+    circuit_management_types: Vec<String>,
+    #[serde(default)]
+    state_subscriptions: Vec<StateSubscription>,
+    #[serde(default)]
+    aggregator: Option<AggregatorConfig>,
+    #[serde(default)]
     tp_name: String,
+    #[serde(default)]
     tp_version: String,
+    #[serde(default)]
     tp_prefix: String,
+    #[serde(default)]
     tp_path: String,
+    #[serde(default)]
+    transaction_processors: Vec<TransactionProcessor>,
+    #[serde(default)]
+    deploy_mode: DeployMode,
+    #[serde(default)]
+    submitter_keys: Vec<String>,
+    #[serde(default)]
+    payload_decoders: BTreeMap<String, DecoderKind>,
+    #[serde(default)]
+    filters: StateFilterConfig,
+    #[serde(default = "default_contract_deploy_attempts")]
+    contract_deploy_attempts: u32,
+    #[serde(default = "default_contract_deploy_timeout")]
+    contract_deploy_timeout: u64,
+    #[serde(default = "default_batch_status_poll_interval")]
+    batch_status_poll_interval: u64,
+    #[serde(default = "default_kafka_enabled")]
+    kafka_enabled: bool,
+    #[serde(default)]
     kafka_topic: String,
+    #[serde(default)]
     kafka_url: String,
+    #[serde(default)]
+    kafka_topic_template: Option<String>,
+    #[serde(default)]
+    topic_map: BTreeMap<String, String>,
+    #[serde(default)]
+    kafka_producer_per_circuit: bool,
+    #[serde(default)]
+    kafka_partitioner: PartitionStrategy,
+    #[serde(default)]
+    kafka_key: KeyStrategy,
+    #[serde(default)]
+    export_deletes: bool,
+    #[serde(default)]
+    empty_values: EmptyValuePolicy,
+    #[serde(default)]
+    state_mirror: Option<StateMirrorConfig>,
+    #[serde(default)]
+    ops_topic: Option<String>,
+    #[serde(default)]
+    kafka_topic_quotas: BTreeMap<String, u64>,
+    #[serde(default)]
+    kafka_producer: KafkaProducerConfig,
+    #[serde(default)]
+    kafka_security: Option<KafkaSecurityConfig>,
+    #[serde(default)]
+    output_format: OutputFormat,
+    #[serde(default)]
+    schema_registry_url: Option<String>,
+    #[serde(default)]
+    consumer_lag_groups: Vec<String>,
+    #[serde(default = "default_consumer_lag_interval")]
+    consumer_lag_interval: u64,
+    #[serde(default = "default_recent_events_capacity")]
+    recent_events_capacity: usize,
+    #[serde(default)]
+    rest_cache: bool,
+    #[serde(default)]
+    wal_dir: Option<String>,
+    #[serde(default)]
+    wal_encryption: Option<WalEncryptionConfig>,
+    #[serde(default = "default_wal_replay_interval")]
+    wal_replay_interval: u64,
+    #[serde(default = "default_heartbeat_interval")]
+    heartbeat_interval: u64,
+    #[serde(default)]
+    wal_max_pending: Option<usize>,
+    #[serde(default)]
+    wal_max_pending_age: Option<u64>,
+    #[serde(default)]
+    wal_pause_intake: bool,
+    #[serde(default = "default_shutdown_drain_timeout")]
+    shutdown_drain_timeout: u64,
+    #[serde(default = "default_state_change_timeout")]
+    state_change_timeout: u64,
+    #[serde(default)]
+    subscription_registry: Option<String>,
+    #[serde(default)]
+    backfill_depth: BackfillDepth,
+    #[serde(default)]
+    checkpoint_store: Option<CheckpointStoreConfig>,
+    #[serde(default = "default_circuit_reconcile_interval")]
+    circuit_reconcile_interval: u64,
+    #[serde(default = "default_envelope_version")]
+    envelope_version: u32,
+    #[serde(default)]
+    envelope_dual_write: Option<DualWriteConfig>,
+    #[serde(default)]
+    dedup: bool,
+    #[serde(default = "default_dedup_capacity")]
+    dedup_capacity: usize,
+    #[serde(default = "default_node_registry_cache_ttl")]
+    node_registry_cache_ttl: u64,
+    #[serde(default = "default_resubscribe_backoff_initial")]
+    resubscribe_backoff_initial: u64,
+    #[serde(default = "default_resubscribe_backoff_max")]
+    resubscribe_backoff_max: u64,
+    /// Named `batch_signing_key` before, still accepted so that existing deployments keep
+    /// their signing identity
+    #[serde(default, alias = "batch_signing_key")]
+    key_path: Option<String>,
+    #[serde(default)]
+    splinterd_tls: Option<TlsConfig>,
+    #[serde(default)]
+    splinterd_auth: Option<SplinterdAuth>,
+    #[serde(default)]
+    alert_webhook_url: Option<String>,
+    #[serde(default)]
+    database_url: Option<String>,
+    #[serde(default)]
+    nats_url: Option<String>,
+    #[serde(default)]
+    nats_subject: Option<String>,
+    #[serde(default)]
+    file_sink: Option<FileSinkConfig>,
+    #[serde(default)]
+    webhook: Option<WebhookConfig>,
+    #[serde(default)]
+    plugins: Vec<PluginConfig>,
+    #[serde(default)]
+    sink_message_types: BTreeMap<String, Vec<String>>,
+    #[serde(default)]
+    transform_wasm: Option<String>,
+    #[serde(default)]
+    delta_compression: bool,
+    #[serde(default = "default_delta_min_size")]
+    delta_min_size: usize,
+    #[serde(default = "default_delta_snapshot_interval")]
+    delta_snapshot_interval: u64,
+    #[serde(default = "default_delta_cache_capacity")]
+    delta_cache_capacity: usize,
+}
+
+/// default management types of the circuits whose admin events are exported
+fn default_circuit_management_types() -> Vec<String> {
+    vec!["consortium".to_string()]
+}
+
+fn default_kafka_enabled() -> bool {
+    true
+}
+
+/// default interval in seconds between consumer group offset inspections
+fn default_consumer_lag_interval() -> u64 {
+    30
+}
+
+/// default number of recently exported events kept in memory for the rest API
+fn default_recent_events_capacity() -> usize {
+    100
+}
+
+/// default interval in seconds between attempts to replay the WAL
+fn default_wal_replay_interval() -> u64 {
+    5
+}
+
+/// default interval in seconds between heartbeat control messages
+fn default_heartbeat_interval() -> u64 {
+    60
+}
+
+/// default interval in seconds between checks of the subscribed circuits against
+/// splinterd's circuit list
+fn default_circuit_reconcile_interval() -> u64 {
+    300
+}
+
+/// default schema version of the envelopes written to the topics
+fn default_envelope_version() -> u32 {
+    1
+}
+
+/// default number of state addresses whose last exported value is remembered
+fn default_dedup_capacity() -> usize {
+    100_000
+}
+
+/// default time in seconds a node registry entry is used to name voters before it is
+/// looked up again
+fn default_node_registry_cache_ttl() -> u64 {
+    3600
+}
+
+/// default time in seconds before reopening a subscription which exhausted its
+/// reconnect limit
+fn default_resubscribe_backoff_initial() -> u64 {
+    5
+}
+
+/// default upper bound in seconds of the backoff between reopenings of a subscription
+fn default_resubscribe_backoff_max() -> u64 {
+    300
+}
+
+/// default time in seconds to wait on shutdown for the events being processed
+fn default_shutdown_drain_timeout() -> u64 {
+    30
+}
+
+/// default time in seconds a state change may take to be published before it is moved to the WAL
+fn default_state_change_timeout() -> u64 {
+    60
+}
+
+/// default size in bytes from which state values are sent as patches
+fn default_delta_min_size() -> usize {
+    64 * 1024
+}
+
+/// default number of messages per key between full snapshots
+fn default_delta_snapshot_interval() -> u64 {
+    20
+}
+
+/// default number of state keys whose previous value is kept for delta compression
+fn default_delta_cache_capacity() -> usize {
+    10000
+}
+
+/// default number of times the set up of a circuit's contracts is attempted
+fn default_contract_deploy_attempts() -> u32 {
+    3
+}
+
+/// default number of seconds to wait for a setup batch to be committed
+fn default_contract_deploy_timeout() -> u64 {
+    300
+}
+
+/// default number of milliseconds between two reads of a setup batch's status
+fn default_batch_status_poll_interval() -> u64 {
+    2000
 }
 
 impl DeploymentConfig {
-    fn from(config_file: Option<String>) -> Result<Self, ConfigurationError> {
+    /// Reads the deployment configuration file, then applies the settings given in the
+    /// environment, named by their paths in the file
+    fn from(
+        config_file: Option<String>,
+        overrides: &[EnvOverride],
+    ) -> Result<Self, ConfigurationError> {
         let file = match config_file {
             Some(file_present) => file_present,
             None => return Err(ConfigurationError::MissingValue("Deployment configuration file is missing".to_string())),
@@ -48,19 +497,221 @@ impl DeploymentConfig {
             Ok(file) => file,
             Err(err) => return Err(ConfigurationError::MissingValue(format!("Invalid deployment configuration {}", err.to_string()))),
         };
-        let resultant: Result<DeploymentConfig, serde_yaml::Error> = serde_yaml::from_reader(f);
+        let resultant: Result<serde_yaml::Value, serde_yaml::Error> = serde_yaml::from_reader(f);
+        let mut document = match resultant {
+            Ok(document) => document,
+            Err(err) => return Err(ConfigurationError::MissingValue(format!("Invalid deployment configuration {}", err.to_string()))),
+        };
+        for env_override in overrides {
+            env_override.apply(&mut document)?;
+        }
+        let resultant: Result<DeploymentConfig, serde_yaml::Error> = serde_yaml::from_value(document);
         let parsed = match resultant {
             Ok(parsed) => parsed,
             Err(err) => return Err(ConfigurationError::MissingValue(format!("Invalid deployment configuration {}", err.to_string()))),
         };
-        Ok(DeploymentConfig {
-            tp_name: parsed.tp_name,
-            tp_version: parsed.tp_version,
-            tp_prefix: parsed.tp_prefix,
-            tp_path: parsed.tp_path,
-            kafka_topic: parsed.kafka_topic,
-            kafka_url: parsed.kafka_url,
-        })
+        // Settings are not checked against the configuration's fields when deserialized,
+        // so a misspelled variable would otherwise be ignored silently
+        if !overrides.is_empty() {
+            let document = serde_yaml::to_value(&parsed).map_err(|err| {
+                ConfigurationError::MissingValue(format!(
+                    "Invalid deployment configuration {}",
+                    err
+                ))
+            })?;
+            let unknown = overrides
+                .iter()
+                .find(|env_override| !env_override.names_setting(&document));
+            if let Some(unknown) = unknown {
+                return Err(ConfigurationError::MissingValue(format!(
+                    "the deployment setting named by {}",
+                    unknown.var
+                )));
+            }
+        }
+        parsed.validate()?;
+        Ok(parsed)
+    }
+
+    /// The Sabre contract settings are only needed when contract state is exported, and
+    /// only the contract prefix when the contract is not set up by the exporter
+    fn validate(&self) -> Result<(), ConfigurationError> {
+        let legacy = [&self.tp_name, &self.tp_version, &self.tp_prefix, &self.tp_path];
+        if !self.transaction_processors.is_empty() && legacy.iter().any(|v| !v.is_empty()) {
+            return Err(ConfigurationError::MissingValue(
+                "only one of transaction_processors and the tp_* settings".to_string(),
+            ));
+        }
+        let required: &[(&str, &String)] = match self.export_mode {
+            _ if !self.transaction_processors.is_empty() => &[],
+            ExportMode::Full => &[
+                ("tp_name", &self.tp_name),
+                ("tp_version", &self.tp_version),
+                ("tp_prefix", &self.tp_prefix),
+                ("tp_path", &self.tp_path),
+            ],
+            ExportMode::StateOnly => &[("tp_prefix", &self.tp_prefix)],
+            ExportMode::AdminOnly | ExportMode::Aggregator => &[],
+        };
+        self.validate_transaction_processors()?;
+        let is_public_key =
+            |key: &String| key.len() == 66 && key.chars().all(|c| c.is_ascii_hexdigit());
+        if !self.submitter_keys.iter().all(is_public_key) {
+            return Err(ConfigurationError::MissingValue(
+                "submitter_keys as compressed secp256k1 public keys in hex".to_string(),
+            ));
+        }
+        validate_message_types(&self.sink_message_types)?;
+        validate_topic_map(&self.topic_map)?;
+        if let Some(ref auth) = self.splinterd_auth {
+            if auth.token.is_none() && auth.token_file.is_none() {
+                return Err(ConfigurationError::MissingValue(
+                    "splinterd_auth.token or splinterd_auth.token_file".to_string(),
+                ));
+            }
+        }
+        if let Some((key_regex, err)) = self.filters.invalid_regex() {
+            return Err(ConfigurationError::MissingValue(format!(
+                "a valid filters key_regex instead of {}: {}",
+                key_regex, err
+            )));
+        }
+        if self.empty_values == EmptyValuePolicy::Delete && !self.export_deletes {
+            return Err(ConfigurationError::MissingValue(
+                "export_deletes enabled to export empty values as deletions".to_string(),
+            ));
+        }
+        if let Some(ref state_mirror) = self.state_mirror {
+            if !state_mirror.is_per_circuit() {
+                return Err(ConfigurationError::MissingValue(
+                    "a {circuit_id} placeholder in state_mirror.topic".to_string(),
+                ));
+            }
+        }
+        if let Some(ref wal_encryption) = self.wal_encryption {
+            if wal_encryption.sources() != 1 {
+                return Err(ConfigurationError::MissingValue(
+                    "exactly one of wal_encryption.key, key_file and key_command".to_string(),
+                ));
+            }
+        }
+        if self.subscription_registry.is_some() && self.checkpoint_store.is_some() {
+            return Err(ConfigurationError::MissingValue(
+                "only one of subscription_registry and checkpoint_store".to_string(),
+            ));
+        }
+        match self.checkpoint_store {
+            Some(CheckpointStoreConfig::Sqlite { .. })
+                if cfg!(not(feature = "sqlite-checkpoints")) =>
+            {
+                return Err(ConfigurationError::MissingValue(
+                    "the sqlite-checkpoints feature, needed by a sqlite checkpoint_store"
+                        .to_string(),
+                ));
+            }
+            Some(CheckpointStoreConfig::Redis { .. })
+                if cfg!(not(feature = "redis-checkpoints")) =>
+            {
+                return Err(ConfigurationError::MissingValue(
+                    "the redis-checkpoints feature, needed by a redis checkpoint_store"
+                        .to_string(),
+                ));
+            }
+            _ => (),
+        }
+        let invalid_management_type = self
+            .circuit_management_types
+            .iter()
+            .any(|management_type| management_type.is_empty() || management_type.contains('/'));
+        if self.circuit_management_types.is_empty() || invalid_management_type {
+            return Err(ConfigurationError::MissingValue(
+                "circuit_management_types, as a list of names without slashes".to_string(),
+            ));
+        }
+        if !ENVELOPE_VERSIONS.contains(&self.envelope_version) {
+            return Err(ConfigurationError::MissingValue(format!(
+                "an envelope_version among {:?}",
+                ENVELOPE_VERSIONS
+            )));
+        }
+        if let Some(ref dual_write) = self.envelope_dual_write {
+            if !ENVELOPE_VERSIONS.contains(&dual_write.version)
+                || dual_write.version == self.envelope_version
+            {
+                return Err(ConfigurationError::MissingValue(format!(
+                    "an envelope_dual_write.version among {:?} other than envelope_version",
+                    ENVELOPE_VERSIONS
+                )));
+            }
+        }
+        if self.resubscribe_backoff_max < self.resubscribe_backoff_initial {
+            return Err(ConfigurationError::MissingValue(
+                "a resubscribe_backoff_max no lower than resubscribe_backoff_initial".to_string(),
+            ));
+        }
+        if cfg!(not(feature = "rustls-tls")) && self.splinterd_tls.is_some() {
+            return Err(ConfigurationError::MissingValue(
+                "the rustls-tls feature, needed by splinterd_tls".to_string(),
+            ));
+        }
+        if self.kafka_enabled {
+            if self.kafka_url.is_empty() {
+                return Err(ConfigurationError::MissingValue("kafka_url".to_string()));
+            }
+            if self.kafka_topic.is_empty() {
+                return Err(ConfigurationError::MissingValue("kafka_topic".to_string()));
+            }
+        }
+        if let Some((name, _)) = required.iter().find(|(_, value)| value.is_empty()) {
+            return Err(ConfigurationError::MissingValue(name.to_string()));
+        }
+        if self.output_format == OutputFormat::Avro && self.schema_registry_url.is_none() {
+            return Err(ConfigurationError::MissingValue(
+                "schema_registry_url".to_string(),
+            ));
+        }
+        if self.export_mode == ExportMode::Aggregator {
+            let aggregator = self
+                .aggregator
+                .as_ref()
+                .filter(|aggregator| !aggregator.topics.is_empty())
+                .ok_or_else(|| ConfigurationError::MissingValue("aggregator.topics".to_string()))?;
+            // Republishing to a consumed topic would feed the aggregator its own output
+            let republished = aggregator.topics.iter().any(|topic| {
+                *topic == self.kafka_topic || self.topic_map.values().any(|mapped| mapped == topic)
+            });
+            if aggregator.kafka_url.is_none() && republished {
+                return Err(ConfigurationError::MissingValue(
+                    "aggregator topics other than the exported topics".to_string(),
+                ));
+            }
+        }
+        if self.export_mode == ExportMode::StateOnly && self.state_subscriptions.is_empty() {
+            return Err(ConfigurationError::MissingValue(
+                "state_subscriptions".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn export_mode(&self) -> ExportMode {
+        self.export_mode
+    }
+
+    pub fn event_parsing(&self) -> EventParsing {
+        self.event_parsing
+    }
+
+    pub fn circuit_management_types(&self) -> &[String] {
+        &self.circuit_management_types
+    }
+
+    pub fn state_subscriptions(&self) -> &[StateSubscription] {
+        &self.state_subscriptions
+    }
+
+    pub fn aggregator(&self) -> Option<&AggregatorConfig> {
+        self.aggregator.as_ref()
     }
 
     pub fn tp_name(&self) -> &str {
@@ -79,6 +730,69 @@ impl DeploymentConfig {
         &self.tp_path
     }
 
+    /// Returns the contracts set up and exported: `transaction_processors`, or else the
+    /// one described by the `tp_*` settings, if any
+    pub fn transaction_processors(&self) -> Vec<TransactionProcessor> {
+        if !self.transaction_processors.is_empty() {
+            return self.transaction_processors.clone();
+        }
+        if self.tp_prefix.is_empty() {
+            return vec![];
+        }
+        vec![TransactionProcessor {
+            name: self.tp_name.clone(),
+            version: self.tp_version.clone(),
+            prefix: self.tp_prefix.clone(),
+            path: self.tp_path.clone(),
+        }]
+    }
+
+    pub fn deploy_mode(&self) -> DeployMode {
+        self.deploy_mode
+    }
+
+    /// Returns the public keys added to the owners of the contracts' namespace registries
+    pub fn submitter_keys(&self) -> &[String] {
+        &self.submitter_keys
+    }
+
+    /// Returns the decoder of the state values under each namespace prefix
+    pub fn payload_decoders(&self) -> &BTreeMap<String, DecoderKind> {
+        &self.payload_decoders
+    }
+
+    pub fn filters(&self) -> &StateFilterConfig {
+        &self.filters
+    }
+
+    pub fn contract_deploy_attempts(&self) -> u32 {
+        self.contract_deploy_attempts
+    }
+
+    pub fn contract_deploy_timeout(&self) -> u64 {
+        self.contract_deploy_timeout
+    }
+
+    pub fn batch_status_poll_interval(&self) -> u64 {
+        self.batch_status_poll_interval
+    }
+
+    /// Returns the contracts of a circuit, the one chosen in its application metadata if
+    /// any, the deployment's otherwise
+    pub fn circuit_contracts(
+        &self,
+        selected: Option<&TransactionProcessor>,
+    ) -> Vec<TransactionProcessor> {
+        match selected {
+            Some(selected) => vec![selected.clone()],
+            None => self.transaction_processors(),
+        }
+    }
+
+    pub fn kafka_enabled(&self) -> bool {
+        self.kafka_enabled
+    }
+
     pub fn kafka_topic(&self) -> &str {
         &self.kafka_topic
     }
@@ -86,11 +800,370 @@ impl DeploymentConfig {
     pub fn kafka_url(&self) -> &str {
         &self.kafka_url
     }
+
+    pub fn kafka_topic_template(&self) -> Option<&str> {
+        self.kafka_topic_template.as_ref().map(String::as_str)
+    }
+
+    pub fn kafka_producer_per_circuit(&self) -> bool {
+        self.kafka_producer_per_circuit
+    }
+
+    pub fn kafka_partitioner(&self) -> PartitionStrategy {
+        self.kafka_partitioner
+    }
+
+    pub fn kafka_key(&self) -> KeyStrategy {
+        self.kafka_key
+    }
+
+    pub fn export_deletes(&self) -> bool {
+        self.export_deletes
+    }
+
+    pub fn empty_values(&self) -> EmptyValuePolicy {
+        self.empty_values
+    }
+
+    pub fn state_mirror(&self) -> Option<&StateMirrorConfig> {
+        self.state_mirror.as_ref()
+    }
+
+    pub fn ops_topic(&self) -> Option<&str> {
+        self.ops_topic.as_ref().map(String::as_str)
+    }
+
+    pub fn topic_map(&self) -> &BTreeMap<String, String> {
+        &self.topic_map
+    }
+
+    pub fn kafka_producer(&self) -> &KafkaProducerConfig {
+        &self.kafka_producer
+    }
+
+    pub fn kafka_security(&self) -> Option<&KafkaSecurityConfig> {
+        self.kafka_security.as_ref()
+    }
+
+    pub fn kafka_topic_quotas(&self) -> &BTreeMap<String, u64> {
+        &self.kafka_topic_quotas
+    }
+
+    pub fn output_format(&self) -> OutputFormat {
+        self.output_format
+    }
+
+    pub fn schema_registry_url(&self) -> Option<&str> {
+        self.schema_registry_url.as_ref().map(String::as_str)
+    }
+
+    pub fn consumer_lag_groups(&self) -> &[String] {
+        &self.consumer_lag_groups
+    }
+
+    pub fn consumer_lag_interval(&self) -> u64 {
+        self.consumer_lag_interval
+    }
+
+    pub fn recent_events_capacity(&self) -> usize {
+        self.recent_events_capacity
+    }
+
+    pub fn rest_cache(&self) -> bool {
+        self.rest_cache
+    }
+
+    pub fn wal_dir(&self) -> Option<&str> {
+        self.wal_dir.as_ref().map(String::as_str)
+    }
+
+    pub fn wal_encryption(&self) -> Option<&WalEncryptionConfig> {
+        self.wal_encryption.as_ref()
+    }
+
+    pub fn wal_replay_interval(&self) -> u64 {
+        self.wal_replay_interval
+    }
+
+    pub fn heartbeat_interval(&self) -> u64 {
+        self.heartbeat_interval
+    }
+
+    pub fn shutdown_drain_timeout(&self) -> u64 {
+        self.shutdown_drain_timeout
+    }
+
+    pub fn state_change_timeout(&self) -> u64 {
+        self.state_change_timeout
+    }
+
+    pub fn backfill_depth(&self) -> BackfillDepth {
+        self.backfill_depth
+    }
+
+    pub fn wal_max_pending(&self) -> Option<usize> {
+        self.wal_max_pending
+    }
+
+    pub fn wal_max_pending_age(&self) -> Option<u64> {
+        self.wal_max_pending_age
+    }
+
+    pub fn wal_pause_intake(&self) -> bool {
+        self.wal_pause_intake
+    }
+
+    /// The store of the subscription registry, `subscription_registry` being a shorthand
+    /// for a file store
+    pub fn checkpoint_store(&self) -> Option<CheckpointStoreConfig> {
+        self.checkpoint_store.clone().or_else(|| {
+            self.subscription_registry
+                .clone()
+                .map(|path| CheckpointStoreConfig::File { path })
+        })
+    }
+
+    pub fn circuit_reconcile_interval(&self) -> u64 {
+        self.circuit_reconcile_interval
+    }
+
+    pub fn envelope_version(&self) -> u32 {
+        self.envelope_version
+    }
+
+    pub fn envelope_dual_write(&self) -> Option<&DualWriteConfig> {
+        self.envelope_dual_write.as_ref()
+    }
+
+    pub fn dedup(&self) -> bool {
+        self.dedup
+    }
+
+    pub fn dedup_capacity(&self) -> usize {
+        self.dedup_capacity
+    }
+
+    pub fn node_registry_cache_ttl(&self) -> u64 {
+        self.node_registry_cache_ttl
+    }
+
+    pub fn resubscribe_backoff_initial(&self) -> u64 {
+        self.resubscribe_backoff_initial
+    }
+
+    pub fn resubscribe_backoff_max(&self) -> u64 {
+        self.resubscribe_backoff_max
+    }
+
+    pub fn splinterd_tls(&self) -> Option<&TlsConfig> {
+        self.splinterd_tls.as_ref()
+    }
+
+    /// The `Authorization` header value of the requests to splinterd, if any
+    pub fn splinterd_authorization(&self) -> Result<Option<String>, io::Error> {
+        match self.splinterd_auth {
+            Some(ref auth) => auth.authorization().map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub fn key_path(&self) -> Option<&str> {
+        self.key_path.as_ref().map(String::as_str)
+    }
+
+    pub fn alert_webhook_url(&self) -> Option<&str> {
+        self.alert_webhook_url.as_ref().map(String::as_str)
+    }
+
+    pub fn database_url(&self) -> Option<&str> {
+        self.database_url.as_ref().map(String::as_str)
+    }
+
+    pub fn nats_url(&self) -> Option<&str> {
+        self.nats_url.as_ref().map(String::as_str)
+    }
+
+    pub fn nats_subject(&self) -> Option<&str> {
+        self.nats_subject.as_ref().map(String::as_str)
+    }
+
+    pub fn file_sink(&self) -> Option<&FileSinkConfig> {
+        self.file_sink.as_ref()
+    }
+
+    pub fn webhook(&self) -> Option<&WebhookConfig> {
+        self.webhook.as_ref()
+    }
+
+    pub fn plugins(&self) -> &[PluginConfig] {
+        &self.plugins
+    }
+
+    pub fn sink_message_types(&self) -> &BTreeMap<String, Vec<String>> {
+        &self.sink_message_types
+    }
+
+    pub fn transform_wasm(&self) -> Option<&str> {
+        self.transform_wasm.as_ref().map(String::as_str)
+    }
+
+    pub fn delta_compression(&self) -> bool {
+        self.delta_compression
+    }
+
+    /// Every listed contract needs the settings the `tp_*` ones would need in the export
+    /// mode, and the contracts' namespaces may not overlap
+    fn validate_transaction_processors(&self) -> Result<(), ConfigurationError> {
+        for (index, processor) in self.transaction_processors.iter().enumerate() {
+            let required: &[(&str, &String)] = match self.export_mode {
+                ExportMode::Full => &[
+                    ("name", &processor.name),
+                    ("version", &processor.version),
+                    ("prefix", &processor.prefix),
+                    ("path", &processor.path),
+                ],
+                ExportMode::StateOnly => &[("prefix", &processor.prefix)],
+                ExportMode::AdminOnly | ExportMode::Aggregator => &[],
+            };
+            if let Some((name, _)) = required.iter().find(|(_, value)| value.is_empty()) {
+                return Err(ConfigurationError::MissingValue(format!(
+                    "transaction_processors[{}].{}",
+                    index, name
+                )));
+            }
+            let overlapping = self.transaction_processors[..index]
+                .iter()
+                .find(|other| other.owns(&processor.prefix) || processor.owns(&other.prefix));
+            if let Some(other) = overlapping {
+                return Err(ConfigurationError::MissingValue(format!(
+                    "transaction_processors[{}].prefix not overlapping {}",
+                    index, other.prefix
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns a hash of the settings shaping the exported stream: the sinks, the message
+    /// filters and routes, and the schema. Exporters of a fleet which publish the same
+    /// stream share it, while their connection details and local paths may differ.
+    pub fn fingerprint(&self) -> String {
+        let settings = json!({
+            "export_mode": self.export_mode,
+            "event_parsing": self.event_parsing,
+            "circuit_management_types": self.circuit_management_types,
+            "kafka_enabled": self.kafka_enabled,
+            "kafka_topic": self.kafka_topic,
+            "kafka_topic_template": self.kafka_topic_template,
+            "kafka_key": self.kafka_key,
+            "export_deletes": self.export_deletes,
+            "empty_values": self.empty_values,
+            "state_mirror": self.state_mirror,
+            "topic_map": self.topic_map,
+            "output_format": self.output_format,
+            "envelope_version": self.envelope_version,
+            "envelope_dual_write": self.envelope_dual_write,
+            "sink_message_types": self.sink_message_types,
+            "nats_subject": self.nats_subject,
+            "file_sink": self.file_sink.is_some(),
+            "webhook": self.webhook.is_some(),
+            "plugins": self.plugins,
+            "transform_wasm": self.transform_wasm,
+            "delta_compression": self.delta_compression,
+            "payload_decoders": self.payload_decoders,
+            "filters": self.filters,
+            "backfill_depth": self.backfill_depth,
+            "dedup": self.dedup,
+            "transaction_processors": self
+                .transaction_processors()
+                .iter()
+                .map(|processor| json!([processor.name, processor.version, processor.prefix]))
+                .collect::<Vec<_>>(),
+        });
+        to_hex(&sha256(settings.to_string().as_bytes()))
+    }
+
+    pub fn delta_min_size(&self) -> usize {
+        self.delta_min_size
+    }
+
+    pub fn delta_snapshot_interval(&self) -> u64 {
+        self.delta_snapshot_interval
+    }
+
+    pub fn delta_cache_capacity(&self) -> usize {
+        self.delta_cache_capacity
+    }
+}
+
+/// Prefix of the environment variables overriding the settings of the daemon
+const ENV_PREFIX: &str = "SPLINTER_EXPORTER_";
+
+/// Environment variables of the daemon settings, the others name deployment settings
+const DAEMON_ENV_VARS: &[&str] = &[
+    "SPLINTERD_URL",
+    "BIND",
+    "KEY_FILE",
+    "CONFIG",
+    "VERBOSITY",
+    "RECONNECT",
+    "RECONNECT_LIMIT",
+    "CONNECTION_TIMEOUT",
+];
+
+/// Short names of deployment settings accepted in the environment
+const ENV_ALIASES: &[(&str, &str)] = &[("TOPIC", "kafka_topic")];
+
+/// Separates the fields of a nested deployment setting in an environment variable name
+const ENV_PATH_SEPARATOR: &str = "__";
+
+/// Deployment settings a reload applies, the others take effect on the next start
+const RELOADABLE_SETTINGS: &[&str] = &[
+    "kafka_topic",
+    "kafka_topic_template",
+    "topic_map",
+    "sink_message_types",
+    "tp_name",
+    "tp_version",
+    "tp_prefix",
+    "tp_path",
+    "transaction_processors",
+];
+
+/// default value if the client should attempt to reconnect if ws connection is lost
+const DEFAULT_RECONNECT: bool = true;
+
+/// default limit for number of consecutives failed reconnection attempts
+const DEFAULT_RECONNECT_LIMIT: u64 = 10;
+
+/// default timeout in seconds if no message is received from server
+const DEFAULT_CONNECTION_TIMEOUT: u64 = 60;
+
+/// Settings of the daemon itself, read from the TOML file given with `--daemon-config`.
+///
+/// What is exported, and where to, stays in the deployment configuration, which this file
+/// may name.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DaemonConfig {
+    splinterd_url: Option<String>,
+    bind: Option<String>,
+    key_file: Option<String>,
+    deployment_config: Option<String>,
+    verbosity: Option<u64>,
+    reconnect: Option<bool>,
+    reconnect_limit: Option<u64>,
+    connection_timeout: Option<u64>,
 }
 
 #[derive(Debug, Clone)]
 pub struct EventListenerConfig {
     splinterd_url: String,
+    rest_api_endpoint: Option<String>,
+    key_file: Option<String>,
+    reconnect: bool,
+    reconnect_limit: u64,
+    connection_timeout: u64,
     deployment_config: DeploymentConfig,
 }
 
@@ -99,62 +1172,432 @@ impl EventListenerConfig {
         &self.splinterd_url
     }
 
+    pub fn rest_api_endpoint(&self) -> Option<&str> {
+        self.rest_api_endpoint.as_ref().map(String::as_str)
+    }
+
+    /// The signing key file, `--key-file` taking precedence over `key_path`
+    pub fn key_path(&self) -> Option<&str> {
+        self.key_file
+            .as_ref()
+            .map(String::as_str)
+            .or_else(|| self.deployment_config.key_path())
+    }
+
+    /// Whether the WebSocket subscriptions reconnect when their connection is lost
+    pub fn reconnect(&self) -> bool {
+        self.reconnect
+    }
+
+    pub fn reconnect_limit(&self) -> u64 {
+        self.reconnect_limit
+    }
+
+    /// Seconds without a message after which a WebSocket connection is considered lost
+    pub fn connection_timeout(&self) -> u64 {
+        self.connection_timeout
+    }
+
     pub fn deployment_config(&self) -> &DeploymentConfig {
         &self.deployment_config
     }
 }
 
+/// A deployment setting given in the environment, e.g. `SPLINTER_EXPORTER_KAFKA_URL` for
+/// `kafka_url` or `SPLINTER_EXPORTER_SPLINTERD_AUTH__TOKEN_FILE` for
+/// `splinterd_auth.token_file`.
+///
+/// The value is read as YAML, so numbers, booleans and flow-style lists and maps can be
+/// given. Strings which would read as another type have to be quoted.
+#[derive(Debug, Clone)]
+struct EnvOverride {
+    var: String,
+    path: Vec<String>,
+    value: serde_yaml::Value,
+}
+
+impl EnvOverride {
+    /// Returns the override named by an environment variable, if it names a deployment
+    /// setting
+    fn from_env(var: String, value: String) -> Option<Self> {
+        if !var.starts_with(ENV_PREFIX) {
+            return None;
+        }
+        let name = &var[ENV_PREFIX.len()..];
+        if DAEMON_ENV_VARS.contains(&name) {
+            return None;
+        }
+        let path = match ENV_ALIASES.iter().find(|(alias, _)| *alias == name) {
+            Some((_, setting)) => vec![setting.to_string()],
+            None => name
+                .split(ENV_PATH_SEPARATOR)
+                .map(str::to_lowercase)
+                .collect(),
+        };
+        let value = if value.is_empty() {
+            serde_yaml::Value::String(value)
+        } else {
+            serde_yaml::from_str(&value).unwrap_or_else(|_| serde_yaml::Value::String(value))
+        };
+        Some(EnvOverride { var, path, value })
+    }
+
+    fn apply(&self, document: &mut serde_yaml::Value) -> Result<(), ConfigurationError> {
+        let mut node = document;
+        for field in &self.path {
+            if node.is_null() {
+                *node = serde_yaml::Value::Mapping(serde_yaml::Mapping::new());
+            }
+            let mapping = match node {
+                serde_yaml::Value::Mapping(mapping) => mapping,
+                _ => {
+                    return Err(ConfigurationError::MissingValue(format!(
+                        "a setting with fields, to apply {}",
+                        self.var
+                    )))
+                }
+            };
+            let key = serde_yaml::Value::String(field.clone());
+            if !mapping.contains_key(&key) {
+                mapping.insert(key.clone(), serde_yaml::Value::Null);
+            }
+            node = mapping.get_mut(&key).expect("the field was just inserted");
+        }
+        *node = self.value.clone();
+        Ok(())
+    }
+
+    fn names_setting(&self, document: &serde_yaml::Value) -> bool {
+        let mut node = document;
+        for field in &self.path {
+            node = match node.get(field.as_str()) {
+                Some(node) => node,
+                None => return false,
+            };
+        }
+        true
+    }
+}
+
+/// The configuration in use, shared by the components reading it as events arrive so that
+/// a reload of the deployment configuration reaches them
+#[derive(Clone)]
+pub struct SharedConfig {
+    config: Arc<RwLock<EventListenerConfig>>,
+}
+
+/// Deployment settings which differ after a reload
+#[derive(Debug, Default)]
+pub struct ReloadedSettings {
+    pub applied: Vec<String>,
+    /// Changed settings which are not reloadable, kept at their current value
+    pub needing_restart: Vec<String>,
+}
+
+impl SharedConfig {
+    pub fn new(config: EventListenerConfig) -> Self {
+        SharedConfig {
+            config: Arc::new(RwLock::new(config)),
+        }
+    }
+
+    pub fn current(&self) -> EventListenerConfig {
+        match self.config.read() {
+            Ok(config) => config.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+
+    /// Swaps in the reloadable settings of a new deployment configuration, returns the
+    /// resulting deployment configuration and the settings which changed
+    pub fn reload(
+        &self,
+        deployment_config: &DeploymentConfig,
+    ) -> Result<(DeploymentConfig, ReloadedSettings), ConfigurationError> {
+        let mut config = self.config.write().map_err(|err| {
+            ConfigurationError::MissingValue(format!("an unlocked configuration: {}", err))
+        })?;
+        let mut current = to_mapping(&config.deployment_config)?;
+        let reloaded = to_mapping(deployment_config)?;
+
+        let mut settings = ReloadedSettings::default();
+        for (key, value) in reloaded {
+            if current.get(&key) == Some(&value) {
+                continue;
+            }
+            let name = key.as_str().unwrap_or_default().to_string();
+            if RELOADABLE_SETTINGS.contains(&name.as_str()) {
+                current.insert(key, value);
+                settings.applied.push(name);
+            } else {
+                settings.needing_restart.push(name);
+            }
+        }
+
+        let merged: DeploymentConfig = serde_yaml::from_value(serde_yaml::Value::Mapping(current))
+            .map_err(|err| {
+                ConfigurationError::MissingValue(format!(
+                    "Invalid deployment configuration {}",
+                    err
+                ))
+            })?;
+        merged.validate()?;
+        config.deployment_config = merged.clone();
+        Ok((merged, settings))
+    }
+}
+
+fn to_mapping(
+    deployment_config: &DeploymentConfig,
+) -> Result<serde_yaml::Mapping, ConfigurationError> {
+    match serde_yaml::to_value(deployment_config) {
+        Ok(serde_yaml::Value::Mapping(mapping)) => Ok(mapping),
+        Ok(_) => Err(ConfigurationError::MissingValue(
+            "Invalid deployment configuration".to_string(),
+        )),
+        Err(err) => Err(ConfigurationError::MissingValue(format!(
+            "Invalid deployment configuration {}",
+            err
+        ))),
+    }
+}
+
+/// Reads the deployment configuration again from the sources it was built from, the file
+/// and the environment overrides taken at startup
+#[derive(Clone)]
+pub struct DeploymentLoader {
+    config_file: Option<String>,
+    overrides: Vec<EnvOverride>,
+}
+
+impl DeploymentLoader {
+    pub fn load(&self) -> Result<DeploymentConfig, ConfigurationError> {
+        DeploymentConfig::from(self.config_file.clone(), &self.overrides)
+    }
+}
+
+/// Builds the configuration of the daemon. Its sources are applied in order, each one
+/// overriding the settings it gives: the defaults, the configuration files, the
+/// environment and the command line.
 pub struct DataReaderConfigBuilder {
     splinterd_url: Option<String>,
+    rest_api_endpoint: Option<String>,
+    key_file: Option<String>,
     config_file: Option<String>,
+    verbosity: u64,
+    reconnect: bool,
+    reconnect_limit: u64,
+    connection_timeout: u64,
+    deployment_overrides: Vec<EnvOverride>,
 }
 
 impl Default for DataReaderConfigBuilder {
     fn default() -> Self {
         Self {
             splinterd_url: Some("http://127.0.0.1:8080".to_owned()),
+            rest_api_endpoint: None,
+            key_file: None,
             config_file: Some("deployment-config.yaml".to_owned()),
+            verbosity: 0,
+            reconnect: DEFAULT_RECONNECT,
+            reconnect_limit: DEFAULT_RECONNECT_LIMIT,
+            connection_timeout: DEFAULT_CONNECTION_TIMEOUT,
+            deployment_overrides: vec![],
         }
     }
 }
 
 impl DataReaderConfigBuilder {
-    pub fn with_cli_args(&mut self, matches: &clap::ArgMatches<'_>) -> Self {
-        Self {
+    pub fn with_daemon_config(&mut self, path: &str) -> Result<Self, ConfigurationError> {
+        let contents = fs::read_to_string(path).map_err(|err| {
+            ConfigurationError::MissingValue(format!("Invalid daemon configuration {}: {}", path, err))
+        })?;
+        let daemon_config: DaemonConfig = toml::from_str(&contents).map_err(|err| {
+            ConfigurationError::MissingValue(format!("Invalid daemon configuration {}: {}", path, err))
+        })?;
+        Ok(Self {
+            splinterd_url: daemon_config.splinterd_url.or_else(|| self.splinterd_url.take()),
+            rest_api_endpoint: daemon_config.bind.or_else(|| self.rest_api_endpoint.take()),
+            key_file: daemon_config.key_file.or_else(|| self.key_file.take()),
+            config_file: daemon_config
+                .deployment_config
+                .or_else(|| self.config_file.take()),
+            verbosity: daemon_config.verbosity.unwrap_or(self.verbosity),
+            reconnect: daemon_config.reconnect.unwrap_or(self.reconnect),
+            reconnect_limit: daemon_config.reconnect_limit.unwrap_or(self.reconnect_limit),
+            connection_timeout: daemon_config
+                .connection_timeout
+                .unwrap_or(self.connection_timeout),
+            deployment_overrides: self.deployment_overrides.split_off(0),
+        })
+    }
+
+    /// Applies the settings given in `SPLINTER_EXPORTER_*` environment variables, those of
+    /// the daemon and those of the deployment configuration alike
+    pub fn with_env(&mut self) -> Result<Self, ConfigurationError> {
+        Ok(Self {
+            splinterd_url: env_var("SPLINTERD_URL").or_else(|| self.splinterd_url.take()),
+            rest_api_endpoint: env_var("BIND").or_else(|| self.rest_api_endpoint.take()),
+            key_file: env_var("KEY_FILE").or_else(|| self.key_file.take()),
+            config_file: env_var("CONFIG").or_else(|| self.config_file.take()),
+            verbosity: parse_env_var("VERBOSITY")?.unwrap_or(self.verbosity),
+            reconnect: parse_env_var("RECONNECT")?.unwrap_or(self.reconnect),
+            reconnect_limit: parse_env_var("RECONNECT_LIMIT")?.unwrap_or(self.reconnect_limit),
+            connection_timeout: parse_env_var("CONNECTION_TIMEOUT")?
+                .unwrap_or(self.connection_timeout),
+            deployment_overrides: env::vars()
+                .filter_map(|(var, value)| EnvOverride::from_env(var, value))
+                .collect(),
+        })
+    }
+
+    pub fn with_cli_args(
+        &mut self,
+        matches: &clap::ArgMatches<'_>,
+    ) -> Result<Self, ConfigurationError> {
+        Ok(Self {
             splinterd_url: matches
                 .value_of("splinterd_url")
                 .map(ToOwned::to_owned)
                 .or_else(|| self.splinterd_url.take()),
+            rest_api_endpoint: matches
+                .value_of("bind")
+                .map(ToOwned::to_owned)
+                .or_else(|| self.rest_api_endpoint.take()),
+            key_file: matches
+                .value_of("key_file")
+                .map(ToOwned::to_owned)
+                .or_else(|| self.key_file.take()),
             config_file: matches
                 .value_of("config")
                 .map(ToOwned::to_owned)
                 .or_else(|| self.config_file.take()),
+            verbosity: match matches.occurrences_of("verbose") {
+                0 => self.verbosity,
+                occurrences => occurrences,
+            },
+            reconnect: self.reconnect && !matches.is_present("no_reconnect"),
+            reconnect_limit: parse_arg(matches, "reconnect_limit")?
+                .unwrap_or(self.reconnect_limit),
+            connection_timeout: parse_arg(matches, "connection_timeout")?
+                .unwrap_or(self.connection_timeout),
+            deployment_overrides: self.deployment_overrides.split_off(0),
+        })
+    }
+
+    /// Number of `-v` given, or the verbosity configured otherwise, known before the
+    /// configuration is built so that its errors can be logged
+    pub fn verbosity(&self) -> u64 {
+        self.verbosity
+    }
+
+    /// Address the REST API is bound to, known before the configuration is built so that
+    /// the `status` command can find it
+    pub fn rest_api_endpoint(&self) -> Option<&str> {
+        self.rest_api_endpoint.as_ref().map(String::as_str)
+    }
+
+    pub fn deployment_loader(&self) -> DeploymentLoader {
+        DeploymentLoader {
+            config_file: self.config_file.clone(),
+            overrides: self.deployment_overrides.clone(),
         }
     }
 
+    /// Builds only the deployment configuration, for the commands not talking to splinterd
+    pub fn build_deployment_config(mut self) -> Result<DeploymentConfig, ConfigurationError> {
+        DeploymentConfig::from(self.config_file.take(), &self.deployment_overrides)
+    }
+
     pub fn build(mut self) -> Result<EventListenerConfig, ConfigurationError> {
         Ok(EventListenerConfig {
-            splinterd_url: self
-                .splinterd_url
-                .take()
-                .ok_or_else(|| ConfigurationError::MissingValue("splinterd_url".to_owned()))?,
-            deployment_config: DeploymentConfig::from(self.config_file.take())?,
+            splinterd_url: splinterd_base_url(
+                self.splinterd_url
+                    .take()
+                    .ok_or_else(|| ConfigurationError::MissingValue("splinterd_url".to_owned()))?,
+            )?,
+            rest_api_endpoint: self.rest_api_endpoint.take(),
+            key_file: self.key_file.take(),
+            reconnect: self.reconnect,
+            reconnect_limit: self.reconnect_limit,
+            connection_timeout: self.connection_timeout,
+            deployment_config: DeploymentConfig::from(
+                self.config_file.take(),
+                &self.deployment_overrides,
+            )?,
         })
     }
 }
 
-pub fn get_node(splinterd_url: &str) -> Result<Node, GetNodeError> {
+/// Checks the splinterd URL, which may carry the base path of a reverse proxy, e.g.
+/// `https://gateway.example.com/splinter/`. The endpoints are appended to it, so any
+/// trailing slash is dropped.
+fn splinterd_base_url(url: String) -> Result<String, ConfigurationError> {
+    let is_valid = url.parse::<Uri>().ok().map_or(false, |uri| {
+        let is_http = uri
+            .scheme_str()
+            .map_or(false, |scheme| scheme == "http" || scheme == "https");
+        is_http && uri.authority_part().is_some() && uri.query().is_none()
+    });
+    if !is_valid {
+        return Err(ConfigurationError::MissingValue(format!(
+            "splinterd_url as an http(s) URL with an optional base path, got {}",
+            url
+        )));
+    }
+    Ok(url.trim_end_matches('/').to_string())
+}
+
+fn parse_arg<T: FromStr>(
+    matches: &clap::ArgMatches<'_>,
+    name: &str,
+) -> Result<Option<T>, ConfigurationError> {
+    match matches.value_of(name) {
+        Some(value) => value.parse().map(Some).map_err(|_| {
+            ConfigurationError::MissingValue(format!(
+                "a valid value for --{}, got {}",
+                name.replace('_', "-"),
+                value
+            ))
+        }),
+        None => Ok(None),
+    }
+}
+
+fn env_var(name: &str) -> Option<String> {
+    env::var(format!("{}{}", ENV_PREFIX, name)).ok()
+}
+
+fn parse_env_var<T: FromStr>(name: &str) -> Result<Option<T>, ConfigurationError> {
+    match env_var(name) {
+        Some(value) => value.parse().map(Some).map_err(|_| {
+            ConfigurationError::MissingValue(format!(
+                "a valid value for {}{}, got {}",
+                ENV_PREFIX, name, value
+            ))
+        }),
+        None => Ok(None),
+    }
+}
+
+pub fn get_node(
+    splinterd_url: &str,
+    deployment_config: &DeploymentConfig,
+) -> Result<Node, GetNodeError> {
     let mut runtime = Runtime::new()
         .map_err(|err| GetNodeError(format!("Failed to get set up runtime: {}", err)))?;
-    let client = HyperClient::new();
+    let client = http::splinterd_client(deployment_config.splinterd_tls())
+        .map_err(|err| GetNodeError(format!("Failed to set up HTTP client: {}", err)))?;
+    let authorization = deployment_config
+        .splinterd_authorization()
+        .map_err(|err| GetNodeError(format!("Failed to read splinterd token: {}", err)))?;
     let splinterd_url = splinterd_url.to_owned();
-    let uri = format!("{}/status", splinterd_url)
-        .parse::<Uri>()
+    let request = http::splinterd_get(&format!("{}/status", splinterd_url), authorization.clone())
         .map_err(|err| GetNodeError(format!("Failed to get set up request: {}", err)))?;
 
     runtime.block_on(
         client
-            .get(uri)
+            .request(request)
             .map_err(|err| {
                 GetNodeError(format!(
                     "Failed to get splinter node metadata: {}",
@@ -195,8 +1638,9 @@ pub fn get_node(splinterd_url: &str) -> Result<Node, GetNodeError> {
                 Ok(node_id)
             })
             .and_then(move |node_id| {
-                let uri = match format!("{}/nodes/{}", splinterd_url, node_id).parse::<Uri>() {
-                        Ok(uri) => uri,
+                let url = format!("{}/nodes/{}", splinterd_url, node_id);
+                let request = match http::splinterd_get(&url, authorization) {
+                        Ok(request) => request,
                         Err(err) => return
                             Either::A(
                                 future::err(GetNodeError(format!(
@@ -206,7 +1650,7 @@ pub fn get_node(splinterd_url: &str) -> Result<Node, GetNodeError> {
                 };
 
                 Either::B(client
-                    .get(uri)
+                    .request(request)
                     .map_err(|err| {
                         GetNodeError(format!(
                             "Failed to get splinter node: {}",
@@ -0,0 +1,62 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug)]
+pub enum DatabaseError {
+    ConnectionError(diesel::r2d2::PoolError),
+    QueryError(diesel::result::Error),
+    MissingProposal(String),
+}
+
+impl Error for DatabaseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            DatabaseError::ConnectionError(err) => Some(err),
+            DatabaseError::QueryError(err) => Some(err),
+            DatabaseError::MissingProposal(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for DatabaseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DatabaseError::ConnectionError(e) => {
+                write!(f, "Unable to connect to the database: {}", e)
+            }
+            DatabaseError::QueryError(e) => write!(f, "Database query failed: {}", e),
+            DatabaseError::MissingProposal(circuit_id) => {
+                write!(f, "No proposal recorded for circuit {}", circuit_id)
+            }
+        }
+    }
+}
+
+impl From<diesel::r2d2::PoolError> for DatabaseError {
+    fn from(err: diesel::r2d2::PoolError) -> Self {
+        DatabaseError::ConnectionError(err)
+    }
+}
+
+impl From<diesel::result::Error> for DatabaseError {
+    fn from(err: diesel::result::Error) -> Self {
+        DatabaseError::QueryError(err)
+    }
+}
@@ -0,0 +1,169 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+mod error;
+mod schema;
+
+pub use error::DatabaseError;
+
+use std::time::SystemTime;
+
+use db_models::models::{
+    Consortium, NewConsortiumMember, NewConsortiumProposal, NewConsortiumService,
+    NewProposalVoteRecord,
+};
+use db_models::schema::{
+    consortium, consortium_member, consortium_proposal, consortium_service, proposal_vote_record,
+};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::r2d2::{ConnectionManager, Pool};
+
+use self::schema::circuit_payload;
+
+#[derive(Insertable)]
+#[table_name = "circuit_payload"]
+struct NewCircuitPayload<'a> {
+    circuit_id: &'a str,
+    state_key: &'a str,
+    data: &'a [u8],
+    created_time: SystemTime,
+}
+
+/// Writes the exported events to the gameroom-style consortium tables.
+///
+/// Like the proposal cache, failures are logged rather than returned so that the
+/// database never holds back the other sinks.
+#[derive(Clone)]
+pub struct DatabaseSink {
+    pool: Pool<ConnectionManager<PgConnection>>,
+}
+
+impl DatabaseSink {
+    pub fn connect(database_url: &str) -> Result<Self, DatabaseError> {
+        let pool = Pool::builder().build(ConnectionManager::<PgConnection>::new(database_url))?;
+        Ok(DatabaseSink { pool })
+    }
+
+    /// Records a new proposal along with its circuit, members and services
+    pub fn add_proposal(
+        &self,
+        proposal: &NewConsortiumProposal,
+        circuit: &Consortium,
+        members: &[NewConsortiumMember],
+        services: &[NewConsortiumService],
+    ) {
+        let result = self.with_connection(|conn| {
+            conn.transaction(|| {
+                diesel::insert_into(consortium::table)
+                    .values(circuit)
+                    .execute(conn)?;
+                diesel::insert_into(consortium_proposal::table)
+                    .values(proposal)
+                    .execute(conn)?;
+                diesel::insert_into(consortium_member::table)
+                    .values(members)
+                    .execute(conn)?;
+                diesel::insert_into(consortium_service::table)
+                    .values(services)
+                    .execute(conn)?;
+                Ok(())
+            })
+        });
+        if let Err(err) = result {
+            error!("Unable to store proposal {}: {}", proposal.circuit_id, err);
+        }
+    }
+
+    pub fn update_status(&self, circuit_id: &str, status: &str, time: SystemTime) {
+        let result = self.with_connection(|conn| {
+            conn.transaction(|| {
+                diesel::update(
+                    consortium_proposal::table
+                        .filter(consortium_proposal::circuit_id.eq(circuit_id)),
+                )
+                .set((
+                    consortium_proposal::status.eq(status),
+                    consortium_proposal::updated_time.eq(time),
+                ))
+                .execute(conn)?;
+                diesel::update(consortium::table.filter(consortium::circuit_id.eq(circuit_id)))
+                    .set((
+                        consortium::status.eq(status),
+                        consortium::updated_time.eq(time),
+                    ))
+                    .execute(conn)?;
+                Ok(())
+            })
+        });
+        if let Err(err) = result {
+            error!("Unable to update proposal {}: {}", circuit_id, err);
+        }
+    }
+
+    /// Records a vote against the stored proposal of the circuit
+    pub fn add_vote(&self, circuit_id: &str, vote: &NewProposalVoteRecord) {
+        let result = self.with_connection(|conn| {
+            let proposal_id = consortium_proposal::table
+                .filter(consortium_proposal::circuit_id.eq(circuit_id))
+                .select(consortium_proposal::id)
+                .first::<i64>(conn)
+                .optional()?
+                .ok_or_else(|| DatabaseError::MissingProposal(circuit_id.to_string()))?;
+            let vote = NewProposalVoteRecord {
+                proposal_id,
+                voter_public_key: vote.voter_public_key.clone(),
+                voter_node_id: vote.voter_node_id.clone(),
+                vote: vote.vote.clone(),
+                created_time: vote.created_time,
+            };
+            diesel::insert_into(proposal_vote_record::table)
+                .values(&vote)
+                .execute(conn)?;
+            Ok(())
+        });
+        if let Err(err) = result {
+            error!("Unable to store vote on {}: {}", circuit_id, err);
+        }
+    }
+
+    pub fn add_payload(&self, circuit_id: &str, state_key: &str, data: &[u8]) {
+        let payload = NewCircuitPayload {
+            circuit_id,
+            state_key,
+            data,
+            created_time: SystemTime::now(),
+        };
+        let result = self.with_connection(|conn| {
+            diesel::insert_into(circuit_payload::table)
+                .values(&payload)
+                .execute(conn)?;
+            Ok(())
+        });
+        if let Err(err) = result {
+            error!("Unable to store payload of {}: {}", circuit_id, err);
+        }
+    }
+
+    fn with_connection<F>(&self, f: F) -> Result<(), DatabaseError>
+    where
+        F: FnOnce(&PgConnection) -> Result<(), DatabaseError>,
+    {
+        let conn = self.pool.get()?;
+        f(&conn)
+    }
+}
@@ -0,0 +1,37 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+// The consortium tables come from db_models; this table holds the contract state
+// exported from the circuits and is created with:
+//
+//   CREATE TABLE IF NOT EXISTS circuit_payload (
+//     id            BIGSERIAL PRIMARY KEY,
+//     circuit_id    TEXT      NOT NULL,
+//     state_key     TEXT      NOT NULL,
+//     data          BYTEA     NOT NULL,
+//     created_time  TIMESTAMP NOT NULL
+//   );
+
+table! {
+    circuit_payload (id) {
+        id -> Int8,
+        circuit_id -> Text,
+        state_key -> Text,
+        data -> Bytea,
+        created_time -> Timestamp,
+    }
+}
@@ -18,7 +18,11 @@ use std::fmt;
 
 use sawtooth_sdk::signing::Error as KeyGenError;
 
+use crate::aggregator::AggregatorError;
+use crate::database::DatabaseError;
 use crate::event_handler::EventHandlerError;
+use crate::publisher::PublisherError;
+use crate::rest_api::RestApiServerError;
 
 #[derive(Debug)]
 pub enum EventListenerError {
@@ -27,6 +31,13 @@ pub enum EventListenerError {
     AppAuthHandlerError(EventHandlerError),
     KeyGenError(KeyGenError),
     GetNodeError(GetNodeError),
+    RestApiError(RestApiServerError),
+    PublisherError(PublisherError),
+    DatabaseError(DatabaseError),
+    SignalHandlerError(String),
+    AggregatorError(AggregatorError),
+    KeyFileError(String),
+    CommandError(String),
 }
 
 impl Error for EventListenerError {
@@ -37,6 +48,13 @@ impl Error for EventListenerError {
             EventListenerError::AppAuthHandlerError(err) => Some(err),
             EventListenerError::KeyGenError(err) => Some(err),
             EventListenerError::GetNodeError(err) => Some(err),
+            EventListenerError::RestApiError(err) => Some(err),
+            EventListenerError::PublisherError(err) => Some(err),
+            EventListenerError::DatabaseError(err) => Some(err),
+            EventListenerError::SignalHandlerError(_) => None,
+            EventListenerError::AggregatorError(err) => Some(err),
+            EventListenerError::KeyFileError(_) => None,
+            EventListenerError::CommandError(_) => None,
         }
     }
 }
@@ -63,6 +81,15 @@ impl fmt::Display for EventListenerError {
                 "an error occurred while getting splinterd node information: {}",
                 e
             ),
+            EventListenerError::RestApiError(e) => write!(f, "The REST API encountered an error: {}", e),
+            EventListenerError::PublisherError(e) => write!(f, "The publisher encountered an error: {}", e),
+            EventListenerError::DatabaseError(e) => write!(f, "The database sink encountered an error: {}", e),
+            EventListenerError::SignalHandlerError(e) => {
+                write!(f, "Unable to set a signal handler: {}", e)
+            }
+            EventListenerError::AggregatorError(e) => write!(f, "The aggregator encountered an error: {}", e),
+            EventListenerError::KeyFileError(e) => write!(f, "Unable to use the signing key file {}", e),
+            EventListenerError::CommandError(e) => write!(f, "The command failed: {}", e),
         }
     }
 }
@@ -79,6 +106,36 @@ impl From<EventHandlerError> for EventListenerError {
     }
 }
 
+impl From<RestApiServerError> for EventListenerError {
+    fn from(err: RestApiServerError) -> EventListenerError {
+        EventListenerError::RestApiError(err)
+    }
+}
+
+impl From<PublisherError> for EventListenerError {
+    fn from(err: PublisherError) -> EventListenerError {
+        EventListenerError::PublisherError(err)
+    }
+}
+
+impl From<DatabaseError> for EventListenerError {
+    fn from(err: DatabaseError) -> EventListenerError {
+        EventListenerError::DatabaseError(err)
+    }
+}
+
+impl From<AggregatorError> for EventListenerError {
+    fn from(err: AggregatorError) -> EventListenerError {
+        EventListenerError::AggregatorError(err)
+    }
+}
+
+impl From<ctrlc::Error> for EventListenerError {
+    fn from(err: ctrlc::Error) -> EventListenerError {
+        EventListenerError::SignalHandlerError(err.to_string())
+    }
+}
+
 impl From<KeyGenError> for EventListenerError {
     fn from(err: KeyGenError) -> EventListenerError {
         EventListenerError::KeyGenError(err)
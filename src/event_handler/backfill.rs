@@ -0,0 +1,161 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use splinter::service::scabbard::StateChangeEvent;
+
+use super::state_delta::SabreProcessor;
+use super::EventHandlerError;
+use crate::status::CATCH_UP_QUIET_PERIOD;
+
+/// How often the end of a replay is checked for
+const WATCH_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How much of the history splinterd replays to a reopened subscription is exported
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum BackfillDepth {
+    /// The whole replay
+    Full,
+    /// None of the replay, the subscription resumes with the changes committed after it
+    /// connects
+    Checkpoint,
+    /// The last change sets of the replay, at most this many
+    Events(usize),
+}
+
+impl Default for BackfillDepth {
+    fn default() -> Self {
+        BackfillDepth::Full
+    }
+}
+
+/// Holds back the replay splinterd sends when a subscription reopens, exporting only
+/// the part the backfill depth allows.
+///
+/// Scabbard events carry neither ids nor timestamps, so the replay is told apart from
+/// the live changes the way the status does: it ends once the stream has been connected
+/// and quiet for the catch-up quiet period. The change sets kept are then exported,
+/// before any later ones.
+#[derive(Clone)]
+pub struct Backfill {
+    state: Arc<Mutex<BackfillState>>,
+}
+
+struct BackfillState {
+    limit: usize,
+    kept: VecDeque<Vec<StateChangeEvent>>,
+    skipped: u64,
+    /// When the stream connected or last sent changes, unset until it connects
+    last_activity: Option<Instant>,
+    replaying: bool,
+}
+
+impl Backfill {
+    /// Starts holding back the replay of a subscription, returns `None` when all of it
+    /// is exported
+    pub fn start(
+        circuit_id: &str,
+        depth: BackfillDepth,
+        processor: Arc<SabreProcessor>,
+    ) -> Result<Option<Self>, EventHandlerError> {
+        let limit = match depth {
+            BackfillDepth::Full => return Ok(None),
+            BackfillDepth::Checkpoint => 0,
+            BackfillDepth::Events(limit) => limit,
+        };
+        let backfill = Backfill {
+            state: Arc::new(Mutex::new(BackfillState {
+                limit,
+                kept: VecDeque::new(),
+                skipped: 0,
+                last_activity: None,
+                replaying: true,
+            })),
+        };
+        let watched = backfill.clone();
+        let watched_circuit = circuit_id.to_string();
+        thread::Builder::new()
+            .name(format!("backfill-{}", circuit_id))
+            .spawn(move || watched.watch(&watched_circuit, &processor))?;
+        Ok(Some(backfill))
+    }
+
+    /// Starts the quiet period, as the stream connects
+    pub fn connected(&self) {
+        let mut state = self.lock();
+        if state.replaying {
+            state.last_activity = Some(Instant::now());
+        }
+    }
+
+    /// Returns the changes to export now, none while they belong to the replay
+    pub fn admit(&self, changes: Vec<StateChangeEvent>) -> Option<Vec<StateChangeEvent>> {
+        let mut state = self.lock();
+        if !state.replaying {
+            return Some(changes);
+        }
+        state.last_activity = Some(Instant::now());
+        state.kept.push_back(changes);
+        if state.kept.len() > state.limit {
+            state.kept.pop_front();
+            state.skipped += 1;
+        }
+        None
+    }
+
+    /// Waits for the end of the replay, then exports the change sets kept. The state
+    /// stays locked meanwhile, so that live changes wait for them.
+    fn watch(&self, circuit_id: &str, processor: &SabreProcessor) {
+        let quiet_period = Duration::from_millis(CATCH_UP_QUIET_PERIOD);
+        loop {
+            thread::sleep(WATCH_INTERVAL);
+            let mut state = self.lock();
+            match state.last_activity {
+                Some(last_activity) if last_activity.elapsed() >= quiet_period => (),
+                _ => continue,
+            }
+            state.replaying = false;
+            info!(
+                "Replay of circuit {} ended, skipped {} change sets and exporting {}",
+                circuit_id,
+                state.skipped,
+                state.kept.len()
+            );
+            for changes in state.kept.drain(..) {
+                for err in processor.handle_state_changes(changes) {
+                    error!(
+                        "An error occurred while handling replayed changes {:?}",
+                        err
+                    );
+                }
+            }
+            return;
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<BackfillState> {
+        match self.state.lock() {
+            Ok(state) => state,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
@@ -0,0 +1,104 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug)]
+pub enum CheckpointError {
+    IoError(std::io::Error),
+    SerializationError(serde_json::Error),
+    ConnectionError(String),
+    QueryError(String),
+    /// The configured store needs a feature the binary was built without
+    UnsupportedStore(&'static str),
+}
+
+impl Error for CheckpointError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CheckpointError::IoError(err) => Some(err),
+            CheckpointError::SerializationError(err) => Some(err),
+            CheckpointError::ConnectionError(_) => None,
+            CheckpointError::QueryError(_) => None,
+            CheckpointError::UnsupportedStore(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for CheckpointError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CheckpointError::IoError(err) => write!(f, "Unable to access checkpoints: {}", err),
+            CheckpointError::SerializationError(err) => {
+                write!(f, "Invalid checkpoints: {}", err)
+            }
+            CheckpointError::ConnectionError(msg) => {
+                write!(f, "Unable to connect to the checkpoint store: {}", msg)
+            }
+            CheckpointError::QueryError(msg) => {
+                write!(f, "Checkpoint store query failed: {}", msg)
+            }
+            CheckpointError::UnsupportedStore(feature) => write!(
+                f,
+                "The checkpoint store needs the {} feature, which this binary lacks",
+                feature
+            ),
+        }
+    }
+}
+
+impl From<std::io::Error> for CheckpointError {
+    fn from(err: std::io::Error) -> Self {
+        CheckpointError::IoError(err)
+    }
+}
+
+impl From<serde_json::Error> for CheckpointError {
+    fn from(err: serde_json::Error) -> Self {
+        CheckpointError::SerializationError(err)
+    }
+}
+
+impl From<diesel::r2d2::PoolError> for CheckpointError {
+    fn from(err: diesel::r2d2::PoolError) -> Self {
+        CheckpointError::ConnectionError(err.to_string())
+    }
+}
+
+impl From<diesel::ConnectionError> for CheckpointError {
+    fn from(err: diesel::ConnectionError) -> Self {
+        CheckpointError::ConnectionError(err.to_string())
+    }
+}
+
+impl From<diesel::result::Error> for CheckpointError {
+    fn from(err: diesel::result::Error) -> Self {
+        CheckpointError::QueryError(err.to_string())
+    }
+}
+
+#[cfg(feature = "redis-checkpoints")]
+impl From<redis::RedisError> for CheckpointError {
+    fn from(err: redis::RedisError) -> Self {
+        if err.is_connection_refusal() || err.is_io_error() {
+            CheckpointError::ConnectionError(err.to_string())
+        } else {
+            CheckpointError::QueryError(err.to_string())
+        }
+    }
+}
@@ -0,0 +1,56 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+
+use super::{CheckpointError, CheckpointStore, ScabbardSubscription};
+
+/// Keeps the subscriptions in a JSON file
+pub struct FileStore {
+    path: PathBuf,
+}
+
+impl FileStore {
+    pub fn new(path: &str) -> Self {
+        FileStore {
+            path: PathBuf::from(path),
+        }
+    }
+}
+
+impl CheckpointStore for FileStore {
+    fn load(&self) -> Result<Vec<ScabbardSubscription>, CheckpointError> {
+        if !self.path.exists() {
+            return Ok(vec![]);
+        }
+        let bytes = fs::read(&self.path)?;
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+
+    fn save(&self, subscriptions: &[&ScabbardSubscription]) -> Result<(), CheckpointError> {
+        let bytes = serde_json::to_vec_pretty(subscriptions)?;
+        // Write to a temporary file first so a crash never leaves a truncated file
+        let tmp_path = self.path.with_extension("tmp");
+        let mut file = fs::File::create(&tmp_path)?;
+        file.write_all(&bytes)?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, &self.path)?;
+        Ok(())
+    }
+}
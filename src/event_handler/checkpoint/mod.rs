@@ -0,0 +1,111 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+//! Stores for the checkpoints the exporter resumes from after a restart: the scabbard
+//! subscriptions it has open.
+//!
+//! A file suits a single container. Exporters deployed as a highly available pair share
+//! a Postgres or Redis store, each one saving the whole list under the same key so that
+//! the survivor reopens the subscriptions of both.
+
+mod error;
+mod file;
+#[cfg(feature = "redis-checkpoints")]
+mod redis_store;
+mod sql;
+
+pub use error::CheckpointError;
+
+use std::sync::Arc;
+
+use super::registry::ScabbardSubscription;
+
+use self::file::FileStore;
+use self::sql::PostgresStore;
+
+/// Persists the recorded subscriptions
+pub trait CheckpointStore: Send + Sync {
+    /// Returns the recorded subscriptions, none if nothing was saved yet
+    fn load(&self) -> Result<Vec<ScabbardSubscription>, CheckpointError>;
+
+    /// Replaces the recorded subscriptions
+    fn save(&self, subscriptions: &[&ScabbardSubscription]) -> Result<(), CheckpointError>;
+}
+
+/// Where the checkpoints are kept, the `type` field naming the backend
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum CheckpointStoreConfig {
+    /// A JSON file, replaced on each save
+    File { path: String },
+    /// A table of a SQLite database, needs the `sqlite-checkpoints` feature
+    Sqlite {
+        path: String,
+        #[serde(default = "default_checkpoint_key")]
+        key: String,
+    },
+    /// A table of a Postgres database
+    Postgres {
+        url: String,
+        #[serde(default = "default_checkpoint_key")]
+        key: String,
+    },
+    /// A Redis key, needs the `redis-checkpoints` feature
+    Redis {
+        url: String,
+        #[serde(default = "default_checkpoint_key")]
+        key: String,
+    },
+}
+
+/// default key the subscriptions are saved under in a shared store
+fn default_checkpoint_key() -> String {
+    "subscriptions".to_string()
+}
+
+pub fn open_store(
+    config: &CheckpointStoreConfig,
+) -> Result<Arc<dyn CheckpointStore>, CheckpointError> {
+    match config {
+        CheckpointStoreConfig::File { path } => Ok(Arc::new(FileStore::new(path))),
+        CheckpointStoreConfig::Sqlite { path, key } => open_sqlite(path, key),
+        CheckpointStoreConfig::Postgres { url, key } => {
+            Ok(Arc::new(PostgresStore::connect(url, key)?))
+        }
+        CheckpointStoreConfig::Redis { url, key } => open_redis(url, key),
+    }
+}
+
+#[cfg(feature = "sqlite-checkpoints")]
+fn open_sqlite(path: &str, key: &str) -> Result<Arc<dyn CheckpointStore>, CheckpointError> {
+    Ok(Arc::new(sql::SqliteStore::open(path, key)?))
+}
+
+#[cfg(not(feature = "sqlite-checkpoints"))]
+fn open_sqlite(_path: &str, _key: &str) -> Result<Arc<dyn CheckpointStore>, CheckpointError> {
+    Err(CheckpointError::UnsupportedStore("sqlite-checkpoints"))
+}
+
+#[cfg(feature = "redis-checkpoints")]
+fn open_redis(url: &str, key: &str) -> Result<Arc<dyn CheckpointStore>, CheckpointError> {
+    Ok(Arc::new(redis_store::RedisStore::open(url, key)?))
+}
+
+#[cfg(not(feature = "redis-checkpoints"))]
+fn open_redis(_url: &str, _key: &str) -> Result<Arc<dyn CheckpointStore>, CheckpointError> {
+    Err(CheckpointError::UnsupportedStore("redis-checkpoints"))
+}
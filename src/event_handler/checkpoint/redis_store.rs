@@ -0,0 +1,60 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use super::{CheckpointError, CheckpointStore, ScabbardSubscription};
+
+/// Keeps the subscriptions, as a JSON document, in a Redis key.
+///
+/// A connection is opened for each load and save, which are rare enough that a dropped
+/// connection is not worth keeping track of.
+pub struct RedisStore {
+    client: redis::Client,
+    key: String,
+}
+
+impl RedisStore {
+    pub fn open(url: &str, key: &str) -> Result<Self, CheckpointError> {
+        let client = redis::Client::open(url)?;
+        // Fail at startup rather than on the first save if the server cannot be reached
+        client.get_connection()?;
+        Ok(RedisStore {
+            client,
+            key: key.to_string(),
+        })
+    }
+}
+
+impl CheckpointStore for RedisStore {
+    fn load(&self) -> Result<Vec<ScabbardSubscription>, CheckpointError> {
+        let mut connection = self.client.get_connection()?;
+        let data: Option<String> = redis::cmd("GET").arg(&self.key).query(&mut connection)?;
+        match data {
+            Some(data) => Ok(serde_json::from_str(&data)?),
+            None => Ok(vec![]),
+        }
+    }
+
+    fn save(&self, subscriptions: &[&ScabbardSubscription]) -> Result<(), CheckpointError> {
+        let data = serde_json::to_string(subscriptions)?;
+        let mut connection = self.client.get_connection()?;
+        redis::cmd("SET")
+            .arg(&self.key)
+            .arg(data)
+            .query::<()>(&mut connection)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,134 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::r2d2::{ConnectionManager, Pool};
+
+use super::{CheckpointError, CheckpointStore, ScabbardSubscription};
+
+/// Created by the stores when they connect, the same statement works for both backends
+const CREATE_TABLE: &str = "CREATE TABLE IF NOT EXISTS subscription_checkpoint (
+    checkpoint_key TEXT PRIMARY KEY,
+    data           TEXT NOT NULL
+)";
+
+table! {
+    subscription_checkpoint (checkpoint_key) {
+        checkpoint_key -> Text,
+        data -> Text,
+    }
+}
+
+/// Keeps the subscriptions, as a JSON document, in a row of a Postgres table
+pub struct PostgresStore {
+    pool: Pool<ConnectionManager<PgConnection>>,
+    key: String,
+}
+
+impl PostgresStore {
+    pub fn connect(database_url: &str, key: &str) -> Result<Self, CheckpointError> {
+        let pool = Pool::builder().build(ConnectionManager::<PgConnection>::new(database_url))?;
+        diesel::sql_query(CREATE_TABLE).execute(&pool.get()?)?;
+        Ok(PostgresStore {
+            pool,
+            key: key.to_string(),
+        })
+    }
+}
+
+impl CheckpointStore for PostgresStore {
+    fn load(&self) -> Result<Vec<ScabbardSubscription>, CheckpointError> {
+        let data = subscription_checkpoint::table
+            .find(&self.key)
+            .select(subscription_checkpoint::data)
+            .first::<String>(&self.pool.get()?)
+            .optional()?;
+        parse(data)
+    }
+
+    fn save(&self, subscriptions: &[&ScabbardSubscription]) -> Result<(), CheckpointError> {
+        let data = serde_json::to_string(subscriptions)?;
+        diesel::insert_into(subscription_checkpoint::table)
+            .values((
+                subscription_checkpoint::checkpoint_key.eq(&self.key),
+                subscription_checkpoint::data.eq(&data),
+            ))
+            .on_conflict(subscription_checkpoint::checkpoint_key)
+            .do_update()
+            .set(subscription_checkpoint::data.eq(&data))
+            .execute(&self.pool.get()?)?;
+        Ok(())
+    }
+}
+
+/// Keeps the subscriptions, as a JSON document, in a row of a SQLite table
+#[cfg(feature = "sqlite-checkpoints")]
+pub struct SqliteStore {
+    connection: std::sync::Mutex<diesel::sqlite::SqliteConnection>,
+    key: String,
+}
+
+#[cfg(feature = "sqlite-checkpoints")]
+impl SqliteStore {
+    pub fn open(path: &str, key: &str) -> Result<Self, CheckpointError> {
+        let connection = diesel::sqlite::SqliteConnection::establish(path)?;
+        diesel::sql_query(CREATE_TABLE).execute(&connection)?;
+        Ok(SqliteStore {
+            connection: std::sync::Mutex::new(connection),
+            key: key.to_string(),
+        })
+    }
+
+    fn connection(
+        &self,
+    ) -> Result<std::sync::MutexGuard<diesel::sqlite::SqliteConnection>, CheckpointError> {
+        self.connection
+            .lock()
+            .map_err(|err| CheckpointError::ConnectionError(err.to_string()))
+    }
+}
+
+#[cfg(feature = "sqlite-checkpoints")]
+impl CheckpointStore for SqliteStore {
+    fn load(&self) -> Result<Vec<ScabbardSubscription>, CheckpointError> {
+        let data = subscription_checkpoint::table
+            .find(&self.key)
+            .select(subscription_checkpoint::data)
+            .first::<String>(&*self.connection()?)
+            .optional()?;
+        parse(data)
+    }
+
+    fn save(&self, subscriptions: &[&ScabbardSubscription]) -> Result<(), CheckpointError> {
+        let data = serde_json::to_string(subscriptions)?;
+        diesel::replace_into(subscription_checkpoint::table)
+            .values((
+                subscription_checkpoint::checkpoint_key.eq(&self.key),
+                subscription_checkpoint::data.eq(&data),
+            ))
+            .execute(&*self.connection()?)?;
+        Ok(())
+    }
+}
+
+fn parse(data: Option<String>) -> Result<Vec<ScabbardSubscription>, CheckpointError> {
+    match data {
+        Some(data) => Ok(serde_json::from_str(&data)?),
+        None => Ok(vec![]),
+    }
+}
@@ -0,0 +1,215 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use std::collections::HashSet;
+
+use futures::{Future, Stream};
+use hyper::StatusCode;
+use serde_json::Value;
+use splinter::node_registry::Node;
+use tokio::runtime::Runtime;
+
+use crate::config::DeploymentConfig;
+use crate::http;
+
+use super::registry::{CircuitMember, RosterService, ScabbardSubscription};
+use super::EventHandlerError;
+
+/// Lists the scabbard services this node runs on the circuits known to splinterd.
+///
+/// Circuits listed this way carry no proposal, so the requester of the returned
+/// subscriptions is left empty, and the endpoints of their members are only known when
+/// splinterd lists them.
+pub fn fetch_node_services(
+    splinterd_url: &str,
+    deployment_config: &DeploymentConfig,
+    node_id: &str,
+) -> Result<Vec<ScabbardSubscription>, EventHandlerError> {
+    Ok(fetch_circuits(splinterd_url, deployment_config)?
+        .iter()
+        .filter_map(|circuit| {
+            let circuit_id = circuit.get("id")?.as_str()?;
+            let roster = circuit.get("roster")?.as_array()?;
+            let service_id = roster
+                .iter()
+                .filter(|service| {
+                    service.get("service_type").and_then(Value::as_str) == Some("scabbard")
+                })
+                .find(|service| {
+                    service
+                        .get("allowed_nodes")
+                        .and_then(Value::as_array)
+                        .map_or(false, |nodes| {
+                            nodes.iter().any(|node| node.as_str() == Some(node_id))
+                        })
+                })?
+                .get("service_id")?
+                .as_str()?;
+            Some(ScabbardSubscription {
+                circuit_id: circuit_id.to_string(),
+                service_id: service_id.to_string(),
+                requester: String::new(),
+                requester_node_id: String::new(),
+                scabbard_admin_keys: vec![],
+                services: roster.iter().filter_map(parse_service).collect(),
+                members: circuit
+                    .get("members")
+                    .and_then(Value::as_array)
+                    .map(|members| members.iter().filter_map(parse_member).collect())
+                    .unwrap_or_default(),
+                management_type: circuit
+                    .get("management_type")
+                    .and_then(Value::as_str)
+                    .unwrap_or_default()
+                    .to_string(),
+                contract: None,
+            })
+        })
+        .collect())
+}
+
+/// Lists the ids of the circuits known to splinterd
+pub fn fetch_circuit_ids(
+    splinterd_url: &str,
+    deployment_config: &DeploymentConfig,
+) -> Result<HashSet<String>, EventHandlerError> {
+    Ok(fetch_circuits(splinterd_url, deployment_config)?
+        .iter()
+        .filter_map(|circuit| Some(circuit.get("id")?.as_str()?.to_string()))
+        .collect())
+}
+
+/// Looks up a node in splinterd's node registry
+pub fn fetch_node(
+    splinterd_url: &str,
+    deployment_config: &DeploymentConfig,
+    node_id: &str,
+) -> Result<Node, EventHandlerError> {
+    let node = fetch_json(
+        &format!("{}/nodes/{}", splinterd_url, node_id),
+        deployment_config,
+        "looking up a node",
+    )?;
+    Ok(serde_json::from_value(node)?)
+}
+
+fn fetch_circuits(
+    splinterd_url: &str,
+    deployment_config: &DeploymentConfig,
+) -> Result<Vec<Value>, EventHandlerError> {
+    let circuits = fetch_json(
+        &format!("{}/admin/circuits", splinterd_url),
+        deployment_config,
+        "listing circuits",
+    )?;
+    Ok(circuits
+        .get("data")
+        .and_then(Value::as_array)
+        .cloned()
+        .unwrap_or_default())
+}
+
+fn fetch_json(
+    url: &str,
+    deployment_config: &DeploymentConfig,
+    action: &'static str,
+) -> Result<Value, EventHandlerError> {
+    let mut runtime = Runtime::new()?;
+    let request = http::splinterd_get(url, deployment_config.splinterd_authorization()?)
+        .map_err(|err| EventHandlerError::InvalidMessageError(err.to_string()))?;
+
+    let body = runtime.block_on(
+        http::splinterd_client(deployment_config.splinterd_tls())?
+            .request(request)
+            .map_err(|err| EventHandlerError::InvalidMessageError(err.to_string()))
+            .and_then(move |resp| {
+                let status = resp.status();
+                resp.into_body()
+                    .concat2()
+                    .map_err(|err| EventHandlerError::InvalidMessageError(err.to_string()))
+                    .and_then(move |body| {
+                        if status == StatusCode::OK {
+                            Ok(body.to_vec())
+                        } else {
+                            Err(EventHandlerError::InvalidMessageError(format!(
+                                "splinterd responded with status {} {}",
+                                status, action
+                            )))
+                        }
+                    })
+            }),
+    )?;
+
+    Ok(serde_json::from_slice(&body)?)
+}
+
+fn parse_service(service: &Value) -> Option<RosterService> {
+    Some(RosterService {
+        service_id: service.get("service_id")?.as_str()?.to_string(),
+        service_type: service.get("service_type")?.as_str()?.to_string(),
+        allowed_nodes: string_list(service.get("allowed_nodes")),
+        arguments: match service.get("arguments") {
+            // Listed either as [key, value] pairs or as an object
+            Some(Value::Array(arguments)) => arguments
+                .iter()
+                .filter_map(|argument| {
+                    let pair = argument.as_array()?;
+                    Some((
+                        pair.get(0)?.as_str()?.to_string(),
+                        pair.get(1)?.as_str()?.to_string(),
+                    ))
+                })
+                .collect(),
+            Some(Value::Object(arguments)) => arguments
+                .iter()
+                .filter_map(|(key, value)| Some((key.clone(), value.as_str()?.to_string())))
+                .collect(),
+            _ => vec![],
+        },
+    })
+}
+
+/// Members are listed by node id, or as nodes with an endpoint by newer splinterd versions
+fn parse_member(member: &Value) -> Option<CircuitMember> {
+    match member {
+        Value::String(node_id) => Some(CircuitMember {
+            node_id: node_id.clone(),
+            endpoint: String::new(),
+        }),
+        Value::Object(_) => Some(CircuitMember {
+            node_id: member.get("node_id")?.as_str()?.to_string(),
+            endpoint: member
+                .get("endpoint")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string(),
+        }),
+        _ => None,
+    }
+}
+
+fn string_list(value: Option<&Value>) -> Vec<String> {
+    value
+        .and_then(Value::as_array)
+        .map(|values| {
+            values
+                .iter()
+                .filter_map(|value| value.as_str().map(ToOwned::to_owned))
+                .collect()
+        })
+        .unwrap_or_default()
+}
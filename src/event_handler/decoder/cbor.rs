@@ -0,0 +1,76 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use serde_cbor::Value as CborValue;
+use serde_json::{Map, Number, Value};
+
+use super::{DecodeError, PayloadDecoder};
+use crate::event_handler::to_hex;
+
+/// Decodes values holding a CBOR map, like the state of the intkey contract.
+///
+/// Byte strings are hex encoded, keys which are not text are written as their JSON
+/// text and tags are dropped. Integers beyond 64 bits are exported as strings.
+pub struct CborMapDecoder;
+
+impl PayloadDecoder for CborMapDecoder {
+    fn name(&self) -> &str {
+        "cbor-map"
+    }
+
+    fn decode(&self, value: &[u8]) -> Result<Value, DecodeError> {
+        match serde_cbor::from_slice(value) {
+            Ok(value @ CborValue::Map(_)) => Ok(to_json(value)),
+            Ok(_) => Err(DecodeError::Cbor("the value is not a map".to_string())),
+            Err(err) => Err(DecodeError::Cbor(err.to_string())),
+        }
+    }
+}
+
+fn to_json(value: CborValue) -> Value {
+    match value {
+        CborValue::Null => Value::Null,
+        CborValue::Bool(value) => Value::Bool(value),
+        CborValue::Integer(value) if (0..=i128::from(u64::max_value())).contains(&value) => {
+            Value::from(value as u64)
+        }
+        CborValue::Integer(value) if (i128::from(i64::min_value())..0).contains(&value) => {
+            Value::from(value as i64)
+        }
+        CborValue::Integer(value) => Value::from(value.to_string()),
+        CborValue::Float(value) => Number::from_f64(value)
+            .map(Value::Number)
+            .unwrap_or(Value::Null),
+        CborValue::Bytes(bytes) => Value::from(to_hex(&bytes)),
+        CborValue::Text(text) => Value::from(text),
+        CborValue::Array(values) => Value::Array(values.into_iter().map(to_json).collect()),
+        CborValue::Map(entries) => Value::Object(
+            entries
+                .into_iter()
+                .map(|(key, value)| {
+                    let key = match key {
+                        CborValue::Text(key) => key,
+                        key => to_json(key).to_string(),
+                    };
+                    (key, to_json(value))
+                })
+                .collect::<Map<_, _>>(),
+        ),
+        CborValue::Tag(_, value) => to_json(*value),
+        _ => Value::Null,
+    }
+}
@@ -0,0 +1,45 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug)]
+pub enum DecodeError {
+    /// The value is not a well formed protobuf message
+    Protobuf(String),
+    /// The value is not a CBOR map
+    Cbor(String),
+}
+
+impl Error for DecodeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            DecodeError::Protobuf(_) => None,
+            DecodeError::Cbor(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::Protobuf(msg) => write!(f, "Invalid protobuf value: {}", msg),
+            DecodeError::Cbor(msg) => write!(f, "Invalid CBOR value: {}", msg),
+        }
+    }
+}
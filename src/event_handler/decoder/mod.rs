@@ -0,0 +1,127 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+//! Decoders turning the state values of a contract into JSON documents exported along
+//! with the raw bytes, so that consumers need not know the contract's serialization.
+//!
+//! A decoder is chosen by the longest configured prefix of the state address. Values a
+//! decoder fails on are exported raw, with the error in the payload's `decode_error`.
+
+mod cbor;
+mod error;
+mod protobuf;
+
+pub use error::DecodeError;
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use serde_json::Value;
+
+use crate::config::DeploymentConfig;
+use crate::metrics::Metrics;
+use crate::proto::pubsub::CircuitPayload;
+
+use self::cbor::CborMapDecoder;
+use self::protobuf::ProtobufDecoder;
+
+/// Counter of the values a decoder failed on, by namespace prefix
+const DECODE_FAILURES_METRIC: &str = "exporter_decode_failures_total";
+
+/// Turns the state values of a namespace into JSON
+pub trait PayloadDecoder: Send + Sync {
+    /// Name of the decoder, reported along with its failures
+    fn name(&self) -> &str;
+
+    fn decode(&self, value: &[u8]) -> Result<Value, DecodeError>;
+}
+
+/// The decoders chosen per namespace prefix in the deployment configuration, each
+/// implementing `PayloadDecoder`
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum DecoderKind {
+    /// Schema-less decoding of protobuf messages, fields named by their numbers
+    Protobuf,
+    /// Values holding a CBOR map
+    CborMap,
+}
+
+impl DecoderKind {
+    fn decoder(self) -> Arc<dyn PayloadDecoder> {
+        match self {
+            DecoderKind::Protobuf => Arc::new(ProtobufDecoder),
+            DecoderKind::CborMap => Arc::new(CborMapDecoder),
+        }
+    }
+}
+
+/// The decoders of the namespaces, shared by the circuits
+#[derive(Clone)]
+pub struct PayloadDecoders {
+    decoders: BTreeMap<String, Arc<dyn PayloadDecoder>>,
+    metrics: Metrics,
+}
+
+impl PayloadDecoders {
+    pub fn new(deployment_config: &DeploymentConfig, metrics: &Metrics) -> Self {
+        PayloadDecoders {
+            decoders: deployment_config
+                .payload_decoders()
+                .iter()
+                .map(|(prefix, kind)| (prefix.clone(), kind.decoder()))
+                .collect(),
+            metrics: metrics.clone(),
+        }
+    }
+
+    /// Sets the decoded value of a payload, or its decode error, when a decoder is
+    /// configured for the namespace of its key. The value is decoded in full even when
+    /// the payload carries a delta.
+    pub fn decode(&self, payload: &mut CircuitPayload, value: &[u8]) {
+        let (prefix, decoder) = match self.decoder(payload.get_key()) {
+            Some(decoder) => decoder,
+            None => return,
+        };
+        match decoder.decode(value) {
+            Ok(decoded) => payload.set_decoded(decoded.to_string()),
+            Err(err) => {
+                warn!(
+                    "Exporting the raw value of {} in circuit {}: {}",
+                    payload.get_key(),
+                    payload.get_circuit_id(),
+                    err
+                );
+                self.metrics.incr_counter(
+                    DECODE_FAILURES_METRIC,
+                    &[("namespace", prefix), ("decoder", decoder.name())],
+                    1,
+                );
+                payload.set_decode_error(err.to_string());
+            }
+        }
+    }
+
+    /// Returns the decoder of the longest prefix of the address
+    fn decoder(&self, address: &str) -> Option<(&str, &dyn PayloadDecoder)> {
+        self.decoders
+            .iter()
+            .filter(|(prefix, _)| address.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(prefix, decoder)| (prefix.as_str(), decoder.as_ref()))
+    }
+}
@@ -0,0 +1,142 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use serde_json::{Map, Value};
+
+use super::{DecodeError, PayloadDecoder};
+use crate::event_handler::to_hex;
+
+/// Nested messages deeper than this are exported as bytes
+const MAX_DEPTH: usize = 32;
+
+const WIRE_VARINT: u64 = 0;
+const WIRE_FIXED64: u64 = 1;
+const WIRE_LENGTH_DELIMITED: u64 = 2;
+const WIRE_FIXED32: u64 = 5;
+
+/// Decodes protobuf messages without their schema, like `protoc --decode_raw`.
+///
+/// Fields are named by their numbers and repeated ones become arrays. A length-delimited
+/// field is taken for a nested message when it parses as one, for a string when it is
+/// UTF-8 and is hex encoded otherwise. Varints are exported unsigned, as the wire format
+/// does not tell signed and zigzag encoded integers apart.
+pub struct ProtobufDecoder;
+
+impl PayloadDecoder for ProtobufDecoder {
+    fn name(&self) -> &str {
+        "protobuf"
+    }
+
+    fn decode(&self, value: &[u8]) -> Result<Value, DecodeError> {
+        decode_message(value, 0).map(Value::Object)
+    }
+}
+
+fn decode_message(bytes: &[u8], depth: usize) -> Result<Map<String, Value>, DecodeError> {
+    let mut fields = Map::new();
+    let mut input = bytes;
+    while !input.is_empty() {
+        let tag = read_varint(&mut input)?;
+        let field_number = tag >> 3;
+        if field_number == 0 {
+            return Err(DecodeError::Protobuf("field number 0".to_string()));
+        }
+        let value = match tag & 7 {
+            WIRE_VARINT => Value::from(read_varint(&mut input)?),
+            WIRE_FIXED64 => {
+                let bytes = take(&mut input, 8)?;
+                let mut fixed = [0; 8];
+                fixed.copy_from_slice(bytes);
+                Value::from(u64::from_le_bytes(fixed))
+            }
+            WIRE_LENGTH_DELIMITED => {
+                let len = read_varint(&mut input)? as usize;
+                length_delimited(take(&mut input, len)?, depth)
+            }
+            WIRE_FIXED32 => {
+                let bytes = take(&mut input, 4)?;
+                let mut fixed = [0; 4];
+                fixed.copy_from_slice(bytes);
+                Value::from(u32::from_le_bytes(fixed))
+            }
+            wire_type => {
+                return Err(DecodeError::Protobuf(format!(
+                    "unsupported wire type {} of field {}",
+                    wire_type, field_number
+                )))
+            }
+        };
+        add_field(&mut fields, field_number.to_string(), value);
+    }
+    Ok(fields)
+}
+
+fn length_delimited(bytes: &[u8], depth: usize) -> Value {
+    if !bytes.is_empty() && depth < MAX_DEPTH {
+        if let Ok(message) = decode_message(bytes, depth + 1) {
+            return Value::Object(message);
+        }
+    }
+    match std::str::from_utf8(bytes) {
+        Ok(string) => Value::from(string),
+        Err(_) => Value::from(to_hex(bytes)),
+    }
+}
+
+/// Adds a field, turning it into an array when it is repeated
+fn add_field(fields: &mut Map<String, Value>, name: String, value: Value) {
+    match fields.get_mut(&name) {
+        Some(Value::Array(values)) => values.push(value),
+        Some(existing) => {
+            let first = existing.take();
+            *existing = Value::Array(vec![first, value]);
+        }
+        None => {
+            fields.insert(name, value);
+        }
+    }
+}
+
+fn read_varint(input: &mut &[u8]) -> Result<u64, DecodeError> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (byte, rest) = input
+            .split_first()
+            .ok_or_else(|| DecodeError::Protobuf("truncated varint".to_string()))?;
+        *input = rest;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(DecodeError::Protobuf(
+        "varint longer than 10 bytes".to_string(),
+    ))
+}
+
+fn take<'a>(input: &mut &'a [u8], len: usize) -> Result<&'a [u8], DecodeError> {
+    if input.len() < len {
+        return Err(DecodeError::Protobuf(format!(
+            "field of {} bytes with {} left",
+            len,
+            input.len()
+        )));
+    }
+    let (taken, rest) = input.split_at(len);
+    *input = rest;
+    Ok(taken)
+}
@@ -0,0 +1,86 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use std::sync::{Arc, Mutex};
+
+use openssl::sha::sha256;
+
+use super::lru::LruCache;
+use crate::config::DeploymentConfig;
+use crate::metrics::Metrics;
+
+type Address = (String, String);
+
+/// Suppresses the state changes which repeat the value last exported for their address,
+/// as a reconnect or the replay of missed events may deliver a change twice.
+///
+/// Only a hash of the last exported value of each address is kept, so a value changing
+/// back to an earlier one is still exported. At most `dedup_capacity` addresses are
+/// remembered, the least recently used being forgotten first.
+#[derive(Clone)]
+pub struct Deduplicator {
+    seen: Arc<Mutex<LruCache<Address, [u8; 32]>>>,
+}
+
+impl Deduplicator {
+    /// Returns `None` unless `dedup` is enabled
+    pub fn new(deployment_config: &DeploymentConfig, metrics: &Metrics) -> Option<Self> {
+        if !deployment_config.dedup() {
+            return None;
+        }
+        Some(Deduplicator {
+            seen: Arc::new(Mutex::new(LruCache::new(
+                "dedup",
+                deployment_config.dedup_capacity(),
+                metrics.clone(),
+            ))),
+        })
+    }
+
+    /// Returns whether the value is the one last exported for the address
+    pub fn is_duplicate(&self, circuit_id: &str, address: &str, value: &[u8]) -> bool {
+        let hash = sha256(value);
+        match self.seen.lock() {
+            Ok(mut seen) => seen
+                .get(&(circuit_id.to_string(), address.to_string()))
+                .map_or(false, |seen_hash| *seen_hash == hash),
+            Err(err) => {
+                error!("Unable to read exported values: {}", err);
+                false
+            }
+        }
+    }
+
+    /// Records the value exported for an address
+    pub fn record(&self, circuit_id: &str, address: &str, value: &[u8]) {
+        let hash = sha256(value);
+        match self.seen.lock() {
+            Ok(mut seen) => seen.insert((circuit_id.to_string(), address.to_string()), hash),
+            Err(err) => error!("Unable to record exported value: {}", err),
+        }
+    }
+
+    /// Forgets the value of a deleted address, so that setting it again is exported
+    pub fn forget(&self, circuit_id: &str, address: &str) {
+        match self.seen.lock() {
+            Ok(mut seen) => {
+                seen.remove(&(circuit_id.to_string(), address.to_string()));
+            }
+            Err(err) => error!("Unable to forget exported value: {}", err),
+        }
+    }
+}
@@ -0,0 +1,144 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use std::sync::{Arc, Mutex};
+
+use super::lru::LruCache;
+use crate::config::DeploymentConfig;
+use crate::metrics::Metrics;
+use crate::proto::pubsub::CircuitPayload_Encoding;
+
+/// A state value ready to be placed in a `CircuitPayload`
+pub struct EncodedValue {
+    pub data: Vec<u8>,
+    pub encoding: CircuitPayload_Encoding,
+    pub sequence: u64,
+}
+
+struct KeyState {
+    value: Vec<u8>,
+    sequence: u64,
+    last_snapshot: u64,
+}
+
+/// Encodes consecutive values of each state key as bsdiff patches against the value
+/// exported before them.
+///
+/// A full value is sent for the first message of a key, whenever the value is smaller
+/// than the configured minimum size, when the patch would not be smaller than the value,
+/// and every `delta_snapshot_interval` messages so consumers can resynchronize.
+///
+/// The previous values of at most `delta_cache_capacity` keys, across all circuits, are
+/// kept. The next value of an evicted key is sent in full with its sequence starting over,
+/// as after a restart.
+#[derive(Clone)]
+pub struct DeltaEncoder {
+    enabled: bool,
+    min_size: usize,
+    snapshot_interval: u64,
+    keys: Arc<Mutex<LruCache<(String, String), KeyState>>>,
+}
+
+impl DeltaEncoder {
+    pub fn new(deployment_config: &DeploymentConfig, metrics: &Metrics) -> Self {
+        DeltaEncoder {
+            enabled: deployment_config.delta_compression(),
+            min_size: deployment_config.delta_min_size(),
+            snapshot_interval: deployment_config.delta_snapshot_interval().max(1),
+            keys: Arc::new(Mutex::new(LruCache::new(
+                "delta",
+                deployment_config.delta_cache_capacity(),
+                metrics.clone(),
+            ))),
+        }
+    }
+
+    pub fn encode(&self, circuit_id: &str, key: &str, value: &[u8]) -> EncodedValue {
+        if !self.enabled {
+            return full(value, 0);
+        }
+        let mut keys = match self.keys.lock() {
+            Ok(keys) => keys,
+            Err(err) => {
+                error!("Unable to access previous state values: {}", err);
+                return full(value, 0);
+            }
+        };
+
+        let address = (circuit_id.to_string(), key.to_string());
+        let previous = keys.take(&address);
+        let sequence = previous.as_ref().map_or(1, |state| state.sequence + 1);
+        let patch = match previous {
+            Some(ref state)
+                if value.len() >= self.min_size
+                    && sequence - state.last_snapshot < self.snapshot_interval =>
+            {
+                diff(&state.value, value).filter(|patch| patch.len() < value.len())
+            }
+            _ => None,
+        };
+
+        let last_snapshot = match (&patch, previous) {
+            (Some(_), Some(state)) => state.last_snapshot,
+            _ => sequence,
+        };
+        keys.insert(
+            address,
+            KeyState {
+                value: value.to_vec(),
+                sequence,
+                last_snapshot,
+            },
+        );
+
+        match patch {
+            Some(data) => EncodedValue {
+                data,
+                encoding: CircuitPayload_Encoding::BSDIFF,
+                sequence,
+            },
+            None => full(value, sequence),
+        }
+    }
+
+    /// Forgets the previous value of a key, deleted or whose last value was not exported,
+    /// the next value is sent in full
+    pub fn remove(&self, circuit_id: &str, key: &str) {
+        if let Ok(mut keys) = self.keys.lock() {
+            keys.remove(&(circuit_id.to_string(), key.to_string()));
+        }
+    }
+}
+
+fn full(value: &[u8], sequence: u64) -> EncodedValue {
+    EncodedValue {
+        data: value.to_vec(),
+        encoding: CircuitPayload_Encoding::FULL,
+        sequence,
+    }
+}
+
+fn diff(old: &[u8], new: &[u8]) -> Option<Vec<u8>> {
+    let mut patch = Vec::new();
+    match bsdiff::diff::diff(old, new, &mut patch) {
+        Ok(()) => Some(patch),
+        Err(err) => {
+            warn!("Unable to compute state delta, sending full value: {}", err);
+            None
+        }
+    }
+}
@@ -0,0 +1,188 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use hyper::Uri;
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+
+use super::EventHandlerError;
+
+/// Characters that must be encoded when used in a URL path segment
+const PATH_SEGMENT: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'/')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+/// Builds the URL of a scabbard service endpoint, e.g. `ws/subscribe` or `batches`.
+///
+/// The circuit and service ids are validated before being used as path segments, so that
+/// a malformed id is rejected rather than addressing a different endpoint.
+pub fn scabbard_url(
+    splinterd_url: &str,
+    circuit_id: &str,
+    service_id: &str,
+    path: &str,
+) -> Result<String, EventHandlerError> {
+    Ok(format!(
+        "{}/scabbard/{}/{}/{}",
+        splinterd_url,
+        path_segment("circuit_id", circuit_id)?,
+        path_segment("service_id", service_id)?,
+        path
+    ))
+}
+
+/// Resolves a link returned by splinterd, a path from its root, against the configured URL.
+///
+/// splinterd does not know about the base path of a reverse proxy in front of it, so the
+/// link is placed under that path, unless it already starts with it.
+pub fn splinterd_link(splinterd_url: &str, link: &str) -> String {
+    let base_path = splinterd_url
+        .parse::<Uri>()
+        .map(|uri| uri.path().trim_end_matches('/').to_string())
+        .unwrap_or_default();
+    let under_base_path = !base_path.is_empty()
+        && link.starts_with(&base_path)
+        && link[base_path.len()..].starts_with('/');
+    let link = if under_base_path {
+        &link[base_path.len()..]
+    } else {
+        link
+    };
+    format!("{}{}", splinterd_url, link)
+}
+
+/// Validates an identifier and percent-encodes it for use as a single URL path segment.
+pub fn path_segment(field: &'static str, value: &str) -> Result<String, EventHandlerError> {
+    validate_id(field, value)?;
+    Ok(utf8_percent_encode(value, PATH_SEGMENT).to_string())
+}
+
+/// Splinter ids are made of ASCII alphanumerics, dashes and underscores
+fn validate_id(field: &'static str, value: &str) -> Result<(), EventHandlerError> {
+    let is_valid = !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if is_valid {
+        Ok(())
+    } else {
+        Err(EventHandlerError::InvalidIdentifierError {
+            field,
+            value: value.to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_rejected(value: &str) {
+        match path_segment("circuit_id", value) {
+            Err(EventHandlerError::InvalidIdentifierError { field, value: id }) => {
+                assert_eq!(field, "circuit_id");
+                assert_eq!(id, value);
+            }
+            other => panic!("{:?} was not rejected: {:?}", value, other),
+        }
+    }
+
+    #[test]
+    fn path_segment_keeps_valid_ids() {
+        assert_eq!(
+            path_segment("circuit_id", "01234-ABCDE").unwrap(),
+            "01234-ABCDE"
+        );
+        assert_eq!(path_segment("service_id", "a0_b1").unwrap(), "a0_b1");
+    }
+
+    #[test]
+    fn path_segment_rejects_reserved_characters() {
+        assert_rejected("01234/ABCDE");
+        assert_rejected("01234?x=1");
+        assert_rejected("01234%2FABCDE");
+        assert_rejected("01234#x");
+        assert_rejected("01234 ABCDE");
+    }
+
+    #[test]
+    fn path_segment_rejects_dot_segments() {
+        assert_rejected("..");
+        assert_rejected(".");
+        assert_rejected("../admin");
+    }
+
+    #[test]
+    fn path_segment_rejects_empty_and_non_ascii_ids() {
+        assert_rejected("");
+        assert_rejected("circuité");
+        assert_rejected("０１２３４");
+    }
+
+    #[test]
+    fn scabbard_url_places_ids_as_segments() {
+        assert_eq!(
+            scabbard_url(
+                "http://127.0.0.1:8080",
+                "01234-ABCDE",
+                "a000",
+                "ws/subscribe"
+            )
+            .unwrap(),
+            "http://127.0.0.1:8080/scabbard/01234-ABCDE/a000/ws/subscribe"
+        );
+        assert!(scabbard_url("http://127.0.0.1:8080", "../admin", "a000", "batches").is_err());
+    }
+
+    #[test]
+    fn splinterd_link_without_base_path() {
+        assert_eq!(
+            splinterd_link(
+                "http://127.0.0.1:8080",
+                "/scabbard/c/s/batch_statuses?ids=1"
+            ),
+            "http://127.0.0.1:8080/scabbard/c/s/batch_statuses?ids=1"
+        );
+    }
+
+    #[test]
+    fn splinterd_link_under_base_path() {
+        let url = "https://gateway.example.com/splinter";
+        assert_eq!(
+            splinterd_link(url, "/scabbard/c/s/batch_statuses?ids=1"),
+            "https://gateway.example.com/splinter/scabbard/c/s/batch_statuses?ids=1"
+        );
+        // A link which already carries the base path is not prefixed twice
+        assert_eq!(
+            splinterd_link(url, "/splinter/scabbard/c/s/batch_statuses?ids=1"),
+            "https://gateway.example.com/splinter/scabbard/c/s/batch_statuses?ids=1"
+        );
+        // Only a whole segment matches the base path
+        assert_eq!(
+            splinterd_link(url, "/splinterd/status"),
+            "https://gateway.example.com/splinter/splinterd/status"
+        );
+    }
+}
@@ -23,13 +23,16 @@ use futures::future;
 use sabre_sdk::protocol::payload::{
     CreateContractActionBuildError, CreateContractRegistryActionBuildError,
     CreateNamespaceRegistryActionBuildError, CreateNamespaceRegistryPermissionActionBuildError,
-    SabrePayloadBuildError,
+    SabrePayloadBuildError, UpdateNamespaceRegistryOwnersActionBuildError,
 };
 use sabre_sdk::protos::ProtoConversionError as SabreProtoConversionError;
 use sawtooth_sdk::signing::Error as SigningError;
 use splinter::events;
 
 use crate::application_metadata::ApplicationMetadataError;
+use crate::publisher::PublisherError;
+
+use super::checkpoint::CheckpointError;
 
 #[derive(Debug)]
 pub enum EventHandlerError {
@@ -41,6 +44,12 @@ pub enum EventHandlerError {
     SawtoothError(String),
     SigningError(String),
     BatchSubmitError(String),
+    PublisherError(PublisherError),
+    CheckpointError(CheckpointError),
+    InvalidIdentifierError {
+        field: &'static str,
+        value: String,
+    },
 }
 
 impl Error for EventHandlerError {
@@ -54,6 +63,9 @@ impl Error for EventHandlerError {
             EventHandlerError::SigningError(_) => None,
             EventHandlerError::BatchSubmitError(_) => None,
             EventHandlerError::WebSocketError(err) => Some(err),
+            EventHandlerError::PublisherError(err) => Some(err),
+            EventHandlerError::CheckpointError(err) => Some(err),
+            EventHandlerError::InvalidIdentifierError { .. } => None,
         }
     }
 }
@@ -85,6 +97,13 @@ impl fmt::Display for EventHandlerError {
                 msg
             ),
             EventHandlerError::WebSocketError(msg) => write!(f, "WebsocketError {}", msg),
+            EventHandlerError::PublisherError(msg) => {
+                write!(f, "An error occurred while publishing a message: {}", msg)
+            }
+            EventHandlerError::CheckpointError(msg) => write!(f, "{}", msg),
+            EventHandlerError::InvalidIdentifierError { field, value } => {
+                write!(f, "Invalid {}: {:?}", field, value)
+            }
         }
     }
 }
@@ -125,6 +144,18 @@ impl From<events::WebSocketError> for EventHandlerError {
     }
 }
 
+impl From<PublisherError> for EventHandlerError {
+    fn from(err: PublisherError) -> Self {
+        EventHandlerError::PublisherError(err)
+    }
+}
+
+impl From<CheckpointError> for EventHandlerError {
+    fn from(err: CheckpointError) -> Self {
+        EventHandlerError::CheckpointError(err)
+    }
+}
+
 macro_rules! impl_from_sabre_errors {
     ($($x:ty),*) => {
         $(
@@ -143,7 +174,8 @@ impl_from_sabre_errors!(
     CreateNamespaceRegistryActionBuildError,
     CreateNamespaceRegistryPermissionActionBuildError,
     SabreProtoConversionError,
-    SabrePayloadBuildError
+    SabrePayloadBuildError,
+    UpdateNamespaceRegistryOwnersActionBuildError
 );
 
 impl From<SigningError> for EventHandlerError {
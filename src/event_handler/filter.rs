@@ -0,0 +1,113 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use regex::Regex;
+use splinter::service::scabbard::StateChangeEvent;
+
+/// Which state changes are exported, by rules on their address and value
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct StateFilterConfig {
+    /// When not empty, only the changes matching one of the rules are exported
+    #[serde(default)]
+    pub include: Vec<FilterRule>,
+    /// The changes matching one of the rules are not exported
+    #[serde(default)]
+    pub exclude: Vec<FilterRule>,
+}
+
+/// Matches the state changes meeting all of its conditions
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FilterRule {
+    #[serde(default)]
+    pub prefix: Option<String>,
+    #[serde(default)]
+    pub key_regex: Option<String>,
+    /// Size in bytes the value must reach, a deletion has none
+    #[serde(default)]
+    pub min_size: Option<usize>,
+}
+
+impl StateFilterConfig {
+    /// Returns the first key regex which does not compile, with its error
+    pub fn invalid_regex(&self) -> Option<(String, String)> {
+        self.include
+            .iter()
+            .chain(self.exclude.iter())
+            .filter_map(|rule| rule.key_regex.as_ref())
+            .find_map(|key_regex| match Regex::new(key_regex) {
+                Ok(_) => None,
+                Err(err) => Some((key_regex.clone(), err.to_string())),
+            })
+    }
+}
+
+/// The compiled filter rules of the deployment
+pub struct StateFilter {
+    include: Vec<Rule>,
+    exclude: Vec<Rule>,
+}
+
+struct Rule {
+    prefix: Option<String>,
+    key_regex: Option<Regex>,
+    min_size: Option<usize>,
+}
+
+impl StateFilter {
+    /// Compiles the rules, those with an invalid regex are left out as the configuration
+    /// is validated when it is loaded
+    pub fn new(config: &StateFilterConfig) -> Self {
+        StateFilter {
+            include: config.include.iter().filter_map(Rule::new).collect(),
+            exclude: config.exclude.iter().filter_map(Rule::new).collect(),
+        }
+    }
+
+    pub fn allows(&self, change: &StateChangeEvent) -> bool {
+        let (key, size) = match change {
+            StateChangeEvent::Set { key, value } => (key, value.len()),
+            StateChangeEvent::Delete { key } => (key, 0),
+        };
+        (self.include.is_empty() || self.include.iter().any(|rule| rule.matches(key, size)))
+            && !self.exclude.iter().any(|rule| rule.matches(key, size))
+    }
+}
+
+impl Rule {
+    fn new(rule: &FilterRule) -> Option<Self> {
+        let key_regex = match rule.key_regex {
+            Some(ref key_regex) => Some(Regex::new(key_regex).ok()?),
+            None => None,
+        };
+        Some(Rule {
+            prefix: rule.prefix.clone(),
+            key_regex,
+            min_size: rule.min_size,
+        })
+    }
+
+    fn matches(&self, key: &str, size: usize) -> bool {
+        self.prefix
+            .as_ref()
+            .map_or(true, |prefix| key.starts_with(prefix.as_str()))
+            && self
+                .key_regex
+                .as_ref()
+                .map_or(true, |key_regex| key_regex.is_match(key))
+            && self.min_size.map_or(true, |min_size| size >= min_size)
+    }
+}
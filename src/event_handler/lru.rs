@@ -0,0 +1,148 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+
+use crate::metrics::Metrics;
+
+const HITS_METRIC: &str = "exporter_cache_hits_total";
+const MISSES_METRIC: &str = "exporter_cache_misses_total";
+const EVICTIONS_METRIC: &str = "exporter_cache_evictions_total";
+const ENTRIES_METRIC: &str = "exporter_cache_entries";
+
+/// A map holding at most `capacity` entries, the least recently used being evicted first.
+///
+/// Lookups, evictions and the number of entries are reported in the metrics, labelled
+/// with the cache's name. Recency is tracked lazily: every use queues the key with a new
+/// generation, and queued keys whose generation is no longer current are skipped.
+pub struct LruCache<K, V> {
+    name: &'static str,
+    capacity: usize,
+    metrics: Metrics,
+    entries: HashMap<K, (V, u64)>,
+    order: VecDeque<(K, u64)>,
+    generation: u64,
+}
+
+impl<K: Eq + Hash + Clone, V> LruCache<K, V> {
+    pub fn new(name: &'static str, capacity: usize, metrics: Metrics) -> Self {
+        LruCache {
+            name,
+            capacity,
+            metrics,
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+            generation: 0,
+        }
+    }
+
+    /// Returns the value of a key, marking it as the most recently used
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        if !self.entries.contains_key(key) {
+            self.count(MISSES_METRIC, 1);
+            return None;
+        }
+        self.count(HITS_METRIC, 1);
+        self.touch(key);
+        self.entries.get(key).map(|(value, _)| value)
+    }
+
+    /// Takes the value of a key out of the cache, for a lookup followed by an update
+    pub fn take(&mut self, key: &K) -> Option<V> {
+        let value = self.remove(key);
+        let metric = if value.is_some() {
+            HITS_METRIC
+        } else {
+            MISSES_METRIC
+        };
+        self.count(metric, 1);
+        value
+    }
+
+    pub fn insert(&mut self, key: K, value: V) {
+        self.generation += 1;
+        self.order.push_back((key.clone(), self.generation));
+        self.entries.insert(key, (value, self.generation));
+        self.evict();
+        self.report_size();
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let value = self.entries.remove(key).map(|(value, _)| value);
+        if value.is_some() {
+            self.report_size();
+        }
+        value
+    }
+
+    fn touch(&mut self, key: &K) {
+        self.generation += 1;
+        let generation = self.generation;
+        if let Some(entry) = self.entries.get_mut(key) {
+            entry.1 = generation;
+        }
+        self.order.push_back((key.clone(), generation));
+        self.compact();
+    }
+
+    fn evict(&mut self) {
+        let mut evicted = 0;
+        while self.entries.len() > self.capacity {
+            match self.order.pop_front() {
+                Some((key, generation)) => {
+                    if self.is_current(&key, generation) {
+                        self.entries.remove(&key);
+                        evicted += 1;
+                    }
+                }
+                None => break,
+            }
+        }
+        if evicted > 0 {
+            self.count(EVICTIONS_METRIC, evicted);
+        }
+        self.compact();
+    }
+
+    /// Drops the stale queued keys, which pile up when the same keys are used over and over
+    fn compact(&mut self) {
+        if self.order.len() > self.capacity.saturating_mul(2) {
+            let entries = &self.entries;
+            self.order.retain(|(key, generation)| {
+                entries.get(key).map(|(_, current)| current) == Some(generation)
+            });
+        }
+    }
+
+    fn is_current(&self, key: &K, generation: u64) -> bool {
+        self.entries.get(key).map(|(_, current)| *current) == Some(generation)
+    }
+
+    fn count(&self, metric: &str, value: u64) {
+        self.metrics
+            .incr_counter(metric, &[("cache", self.name)], value);
+    }
+
+    fn report_size(&self) {
+        self.metrics.set_gauge(
+            ENTRIES_METRIC,
+            &[("cache", self.name)],
+            self.entries.len() as i64,
+        );
+    }
+}
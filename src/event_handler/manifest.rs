@@ -0,0 +1,168 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use std::sync::{Arc, RwLock};
+
+use protobuf::ProtobufEnum;
+use serde_json::Value;
+
+use crate::config::EventListenerConfig;
+use crate::proto::pubsub::{Control, Message_MessageType};
+use crate::publisher::{KafkaPublisher, PublisherError};
+use crate::time::now_millis;
+
+use super::registry::SubscriptionRegistry;
+
+/// Command of the control messages carrying the manifest
+const MANIFEST_COMMAND: &str = "manifest";
+
+/// Describes what the exporter currently exports, so that consumers can find the circuits,
+/// contract namespaces and topics they are interested in: for each subscribed circuit its
+/// scabbard service, namespaces, the topic of each message type and the output format.
+///
+/// The manifest is served by the REST API and published as a `manifest` control message
+/// each time it changes, which is when subscriptions are opened or circuits removed.
+#[derive(Clone, Default)]
+pub struct Manifest {
+    /// The document without its generation time, to tell whether it changed
+    current: Arc<RwLock<(Value, u64)>>,
+}
+
+impl Manifest {
+    pub fn new() -> Self {
+        Manifest::default()
+    }
+
+    /// The manifest as last generated, `null` before the first subscription
+    pub fn document(&self) -> Value {
+        let (mut document, generated_at) = match self.current.read() {
+            Ok(current) => current.clone(),
+            Err(err) => {
+                error!("Unable to read the manifest: {}", err);
+                return Value::Null;
+            }
+        };
+        if let Value::Object(ref mut fields) = document {
+            fields.insert("generated_at".into(), json!(generated_at));
+        }
+        document
+    }
+
+    /// Returns whether a subscribed circuit exports the contract namespace under a prefix
+    pub fn has_namespace(&self, prefix: &str) -> bool {
+        let current = match self.current.read() {
+            Ok(current) => current,
+            Err(err) => {
+                error!("Unable to read the manifest: {}", err);
+                return false;
+            }
+        };
+        current.0["circuits"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|circuit| circuit["namespaces"].as_array())
+            .flatten()
+            .any(|namespace| namespace["prefix"] == prefix)
+    }
+
+    /// Generates the manifest again, publishing it if it changed
+    pub(super) fn refresh(
+        &self,
+        config: &EventListenerConfig,
+        publisher: &KafkaPublisher,
+        registry: &SubscriptionRegistry,
+    ) {
+        let document = generate(config, publisher, registry);
+        match self.current.write() {
+            Ok(mut current) => {
+                if current.0 == document {
+                    return;
+                }
+                *current = (document, now_millis());
+            }
+            Err(err) => {
+                error!("Unable to update the manifest: {}", err);
+                return;
+            }
+        }
+        if let Err(err) = publish(publisher, &self.document()) {
+            warn!("Unable to publish the manifest: {}", err);
+        }
+    }
+}
+
+fn generate(
+    config: &EventListenerConfig,
+    publisher: &KafkaPublisher,
+    registry: &SubscriptionRegistry,
+) -> Value {
+    let deployment_config = config.deployment_config();
+    let circuits = registry
+        .active(deployment_config)
+        .iter()
+        .map(|subscription| {
+            let circuit_id = &subscription.circuit_id;
+            let namespaces = deployment_config
+                .circuit_contracts(subscription.contract.as_ref())
+                .iter()
+                .map(|processor| {
+                    json!({
+                        "contract_name": processor.name,
+                        "contract_version": processor.version,
+                        "prefix": processor.prefix,
+                    })
+                })
+                .collect::<Vec<_>>();
+            let topics = Message_MessageType::values()
+                .iter()
+                .filter(|message_type| **message_type != Message_MessageType::TYPE_UNKNOWN)
+                .map(|message_type| {
+                    (
+                        message_type.descriptor().name().to_string(),
+                        json!(publisher.topic(circuit_id, *message_type)),
+                    )
+                })
+                .collect::<serde_json::Map<_, _>>();
+            json!({
+                "circuit_id": circuit_id,
+                "service_id": subscription.service_id,
+                "management_type": subscription.management_type,
+                "namespaces": namespaces,
+                "topics": topics,
+                "output_format": publisher.output_format(circuit_id),
+            })
+        })
+        .collect::<Vec<_>>();
+    json!({
+        "exporter_version": env!("CARGO_PKG_VERSION"),
+        "export_mode": deployment_config.export_mode(),
+        "config_fingerprint": publisher.config_fingerprint(),
+        "envelope_version": publisher.envelope_version(),
+        "circuits": circuits,
+    })
+}
+
+fn publish(publisher: &KafkaPublisher, document: &Value) -> Result<(), PublisherError> {
+    let issued_at = now_millis();
+    let mut control = Control::new();
+    control.set_command(MANIFEST_COMMAND.to_string());
+    control.set_detail(document.to_string());
+    control.set_effective_at(issued_at);
+    control.set_issued_at(issued_at);
+    publisher.publish("", Message_MessageType::CONTROL, &control)
+}
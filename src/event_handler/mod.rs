@@ -16,13 +16,44 @@
  * -----------------------------------------------------------------------------
  */
 
+mod backfill;
+mod checkpoint;
+mod circuits;
+mod dedup;
+mod decoder;
+mod delta;
+mod endpoint;
 mod error;
+mod filter;
+mod lru;
+mod manifest;
+mod nodes;
+mod pause;
+mod reconcile;
+mod registry;
+pub use backfill::BackfillDepth;
+pub use checkpoint::CheckpointStoreConfig;
+pub use decoder::DecoderKind;
 pub use error::EventHandlerError;
+pub use filter::StateFilterConfig;
+pub use manifest::Manifest;
+pub use pause::NamespacePauses;
+pub use state_delta::EmptyValuePolicy;
 pub mod sabre;
+mod snapshot;
 mod state_delta;
+mod supervisor;
+mod votes;
+mod watchdog;
+
+pub use reconcile::CircuitReconciler;
+pub use supervisor::{SubscriptionSupervisor, SupervisorWorker};
+pub use snapshot::{SnapshotTrigger, SnapshotWorker, Snapshotter};
 
 use std::fmt::Write;
-use std::time::{SystemTime, Duration};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::SystemTime;
 
 use splinter::{
     admin::messages::{
@@ -30,67 +61,244 @@ use splinter::{
     },
     events::{Igniter, WebSocketClient, WebSocketError, WsResponse},
 };
-use state_delta::SabreProcessor;
+use state_delta::{circuit_member, circuit_service, SabreProcessor};
 
+use crate::alert::Alerter;
 use crate::application_metadata::ApplicationMetadata;
 
+use self::backfill::Backfill;
+use self::circuits::fetch_node_services;
+use self::decoder::PayloadDecoders;
+use self::dedup::Deduplicator;
+use self::delta::DeltaEncoder;
+use self::endpoint::scabbard_url;
+use self::nodes::{NodeDetails, NodeDirectory};
+use self::registry::{CircuitMember, RosterService, ScabbardSubscription, SubscriptionRegistry};
 use self::sabre::setup_tp;
+use self::supervisor::DeadSubscription;
+use self::votes::{required_votes, VoteTally};
 use db_models::models::{NewConsortiumProposal, NewConsortiumMember, Consortium, NewConsortiumService, NewProposalVoteRecord};
-use crate::config::EventListenerConfig;
-use kafka::producer::{Producer, RequiredAcks, Record};
-use crate::proto::pubsub::{Message, Message_MessageType, ProposalSubmit, ProposalVote, ProposalAccept, ProposalReject, ProposalReady};
-use protobuf::Message as Msg;
+use crate::config::{EventParsing, ExportMode, SharedConfig};
+use crate::database::DatabaseSink;
+use crate::metrics::Metrics;
+use crate::proposal_cache::ProposalCache;
+use crate::shutdown::Intake;
+use crate::status::{admin_subscription, scabbard_subscription, Status};
+use crate::publisher::{KafkaPublisher, Lifecycle, DATABASE_SINK};
+use crate::proto::pubsub::{CircuitDefinition, Message_MessageType, ProposalSubmit, ProposalVote, ProposalAccept, ProposalReject, ProposalReady};
 
-/// default value if the client should attempt to reconnet if ws connection is lost
-const RECONNECT: bool = true;
+/// row id of a vote's proposal until the database sink looks it up by circuit id
+const UNRESOLVED_PROPOSAL_ROW: i64 = 0;
 
-/// default limit for number of consecutives failed reconnection attempts
-const RECONNECT_LIMIT: u64 = 10;
+/// Destinations of the exported events
+#[derive(Clone)]
+pub struct Sinks {
+    pub publisher: KafkaPublisher,
+    pub proposal_cache: Option<ProposalCache>,
+    pub database: Option<DatabaseSink>,
+}
 
-/// default timeout in seconds if no message is received from server
-const CONNECTION_TIMEOUT: u64 = 60;
+impl Sinks {
+    /// Returns the database sink, if there is one allowed to receive the message type
+    fn database(&self, message_type: Message_MessageType) -> Option<&DatabaseSink> {
+        self.database
+            .as_ref()
+            .filter(|_| self.publisher.sink_allows(DATABASE_SINK, message_type))
+    }
+}
 
+/// State shared by the handlers of the admin and scabbard events
+#[derive(Clone)]
+struct ExportContext {
+    config: SharedConfig,
+    node_id: String,
+    private_key: Option<String>,
+    sinks: Sinks,
+    registry: SubscriptionRegistry,
+    status: Status,
+    intake: Intake,
+    alerter: Alerter,
+    supervisor: SubscriptionSupervisor,
+    nodes: NodeDirectory,
+    dedup: Option<Deduplicator>,
+    delta_encoder: DeltaEncoder,
+    decoders: PayloadDecoders,
+    votes: VoteTally,
+    manifest: Manifest,
+    pauses: NamespacePauses,
+}
+
+/// Background workers started along with the export
+pub struct ExportWorkers {
+    reconciler: Option<CircuitReconciler>,
+    supervisor: Option<SupervisorWorker>,
+}
+
+impl ExportWorkers {
+    pub fn shutdown(self) {
+        if let Some(reconciler) = self.reconciler {
+            reconciler.shutdown();
+        }
+        if let Some(supervisor) = self.supervisor {
+            supervisor.shutdown();
+        }
+    }
+}
+
+/// Starts exporting events, returns the snapshotter of the subscribed circuits and the
+/// workers reconciling and reviving their subscriptions
+#[allow(clippy::too_many_arguments)]
 pub fn run(
-    config: EventListenerConfig,
+    config: SharedConfig,
     node_id: String,
-    private_key: String,
+    private_key: Option<String>,
+    sinks: Sinks,
+    status: Status,
+    intake: Intake,
     igniter: Igniter,
+    metrics: Metrics,
+    manifest: Manifest,
+    pauses: NamespacePauses,
+) -> Result<(Snapshotter, ExportWorkers), EventHandlerError> {
+    let current = config.current();
+    let store = match current.deployment_config().checkpoint_store() {
+        Some(store_config) => Some(checkpoint::open_store(&store_config)?),
+        None => None,
+    };
+    let registry = SubscriptionRegistry::load(store)?;
+    let alerter = Alerter::new(current.deployment_config().alert_webhook_url());
+    let supervisor = SubscriptionSupervisor::new(current.deployment_config(), status.clone());
+    let ctx = ExportContext {
+        config,
+        node_id,
+        private_key,
+        sinks,
+        registry,
+        status,
+        intake,
+        alerter,
+        supervisor,
+        nodes: NodeDirectory::new(),
+        dedup: Deduplicator::new(current.deployment_config(), &metrics),
+        delta_encoder: DeltaEncoder::new(current.deployment_config(), &metrics),
+        decoders: PayloadDecoders::new(current.deployment_config(), &metrics),
+        votes: VoteTally::new(),
+        manifest,
+        pauses,
+    };
+    let snapshotter = Snapshotter::new(
+        current.clone(),
+        ctx.sinks.publisher.clone(),
+        ctx.registry.clone(),
+        ctx.status.clone(),
+        ctx.decoders.clone(),
+    );
+
+    let reconciler = CircuitReconciler::start(
+        current.clone(),
+        ctx.sinks.publisher.clone(),
+        ctx.registry.clone(),
+        ctx.status.clone(),
+        ctx.manifest.clone(),
+    )?;
+
+    let workers = ExportWorkers {
+        supervisor: ctx.supervisor.start(ctx.clone(), igniter.clone())?,
+        reconciler,
+    };
+
+    if current.deployment_config().export_mode() == ExportMode::StateOnly {
+        subscribe_listed(&ctx, &igniter)?;
+        return Ok((snapshotter, workers));
+    }
+    if ctx.private_key.is_some() {
+        resubscribe(&ctx, &igniter);
+    }
+    for management_type in current.deployment_config().circuit_management_types() {
+        open_admin_subscription(&ctx, &igniter, management_type)?;
+    }
+    Ok((snapshotter, workers))
+}
+
+/// Subscribes to the admin service events of the circuits of a management type
+fn open_admin_subscription(
+    ctx: &ExportContext,
+    igniter: &Igniter,
+    management_type: &str,
 ) -> Result<(), EventHandlerError> {
+    let config = ctx.config.current();
+    let subscription_name = admin_subscription(management_type);
+    let message_subscription = subscription_name.clone();
+    let open_subscription = subscription_name.clone();
+    let dead_subscription = DeadSubscription::Admin(management_type.to_string());
+    let status = ctx.status.clone();
+    let open_status = status.clone();
+    let error_status = status.clone();
+    let error_intake = ctx.intake.clone();
+    let error_alerter = ctx.alerter.clone();
+    let error_supervisor = ctx.supervisor.clone();
+    status.connecting(&subscription_name);
 
-    // TODO: Resubscribe to all the earlier circuits
+    let ctx = ctx.clone();
     let mut ws = WebSocketClient::new(
-        &format!("{}/ws/admin/register/consortium", config.splinterd_url()),
-        move |ctx, event| {
-            if let Err(err) = process_admin_event(
-                event,
-                &node_id,
-                &private_key,
-                config.clone(),
-                ctx.igniter(),
-            ) {
+        &format!(
+            "{}/ws/admin/register/{}",
+            config.splinterd_url(),
+            management_type
+        ),
+        move |ws_ctx, event| {
+            let _in_flight = match ctx.intake.enter() {
+                Some(in_flight) => in_flight,
+                None => return WsResponse::Close,
+            };
+            ctx.status.message_received(&message_subscription);
+            ctx.sinks.publisher.wait_for_backlog();
+            let circuit_id = event_proposal(&event).circuit_id.clone();
+            if let Err(err) = process_admin_event(event, &ctx, ws_ctx.igniter()) {
                 error!("Failed to process admin event: {}", err);
+                ctx.status.circuit_error(&circuit_id, &err.to_string());
             }
             WsResponse::Empty
         },
     );
 
-    ws.set_reconnect(RECONNECT);
-    ws.set_reconnect_limit(RECONNECT_LIMIT);
-    ws.set_timeout(CONNECTION_TIMEOUT);
+    ws.set_reconnect(config.reconnect());
+    ws.set_reconnect_limit(config.reconnect_limit());
+    ws.set_timeout(config.connection_timeout());
+
+    ws.on_open(move |_| {
+        open_status.connected(&open_subscription);
+        WsResponse::Empty
+    });
 
     ws.on_error(move |err, ctx| {
         error!("An error occured while listening for admin events {}", err);
+        let message = err.to_string();
+        if error_intake.is_stopped() {
+            error_status.failed(&subscription_name, &message, false);
+            return Ok(());
+        }
         match err {
             WebSocketError::ParserError { .. } => {
                 debug!("Protocol error, closing connection");
+                error_status.failed(&subscription_name, &message, false);
                 Ok(())
             }
             WebSocketError::ReconnectError(_) => {
                 debug!("Failed to reconnect. Closing WebSocket.");
+                let revived = error_supervisor.report(dead_subscription.clone());
+                error_status.failed(&subscription_name, &message, revived);
+                error_alerter.reconnect_exhausted(
+                    ctx.igniter(),
+                    &subscription_name,
+                    None,
+                    &message,
+                );
                 Ok(())
             }
             _ => {
                 debug!("Attempting to restart connection");
+                error_status.failed(&subscription_name, &message, true);
                 ctx.start_ws()
             }
         }
@@ -99,25 +307,92 @@ pub fn run(
     igniter.start_ws(&ws).map_err(EventHandlerError::from)
 }
 
+/// Subscribes to the scabbard services listed in the configuration, for the state-only
+/// export mode. Their contracts are expected to be set up already.
+fn subscribe_listed(ctx: &ExportContext, igniter: &Igniter) -> Result<(), EventHandlerError> {
+    let config = ctx.config.current();
+    for listed in config.deployment_config().state_subscriptions() {
+        let subscription = ScabbardSubscription {
+            circuit_id: listed.circuit_id.clone(),
+            service_id: listed.service_id.clone(),
+            requester: listed.requester.clone(),
+            requester_node_id: listed.requester_node_id.clone(),
+            scabbard_admin_keys: vec![],
+            services: vec![],
+            members: vec![],
+            management_type: String::new(),
+            contract: None,
+        };
+        open_scabbard_subscription(subscription, None, Lifecycle::Subscribed, ctx, igniter)?;
+    }
+    Ok(())
+}
+
+/// Reopens the scabbard subscriptions recorded before a restart, along with those of
+/// circuits splinterd reports for this node which were missed while the exporter was down.
+/// The contracts of these circuits are not set up again.
+fn resubscribe(ctx: &ExportContext, igniter: &Igniter) {
+    let registry = &ctx.registry;
+    let mut subscriptions = registry.subscriptions();
+    let config = ctx.config.current();
+    match fetch_node_services(
+        config.splinterd_url(),
+        config.deployment_config(),
+        &ctx.node_id,
+    ) {
+        Ok(services) => subscriptions.extend(services.into_iter().filter(|service| {
+            !registry.contains(&service.circuit_id, &service.service_id)
+        })),
+        Err(err) => warn!("Unable to list circuits, using recorded subscriptions: {}", err),
+    }
+
+    for subscription in subscriptions {
+        if let Err(err) = registry.record(&subscription) {
+            error!("Unable to record subscription: {}", err);
+        }
+        let subscription_name =
+            scabbard_subscription(&subscription.circuit_id, &subscription.service_id);
+        info!("Resubscribing to {}", subscription_name);
+        match open_scabbard_subscription(subscription, None, Lifecycle::Resubscribed, ctx, igniter)
+        {
+            Ok(()) => ctx.status.catching_up(&subscription_name),
+            Err(err) => error!("Unable to resubscribe: {}", err),
+        }
+    }
+}
+
+/// Returns the proposal an admin event is about
+fn event_proposal(admin_event: &AdminServiceEvent) -> &CircuitProposal {
+    match admin_event {
+        AdminServiceEvent::ProposalSubmitted(proposal)
+        | AdminServiceEvent::CircuitReady(proposal) => proposal,
+        AdminServiceEvent::ProposalVote((proposal, _))
+        | AdminServiceEvent::ProposalAccepted((proposal, _))
+        | AdminServiceEvent::ProposalRejected((proposal, _)) => proposal,
+    }
+}
+
 fn process_admin_event(
     admin_event: AdminServiceEvent,
-    node_id: &str,
-    private_key: &str,
-    config: EventListenerConfig,
+    ctx: &ExportContext,
     igniter: Igniter,
 ) -> Result<(), EventHandlerError> {
+    let node_id = &ctx.node_id;
+    let publisher = &ctx.sinks.publisher;
+    let proposal_cache = ctx.sinks.proposal_cache.as_ref();
 
-    let mut producer =
-        match Producer::from_hosts(vec!(config.deployment_config().kafka_url().to_string()))
-            .with_ack_timeout(Duration::from_secs(5))
-            .with_required_acks(RequiredAcks::One)
-            .create() {
-            Ok(created) => created,
-            Err(err) => return Err(EventHandlerError::InvalidMessageError(err.to_string())),
-    };
-    let topic = config.deployment_config().kafka_topic().to_string();
+    // Remember the management type of the circuit, used to route its messages
+    let circuit_proposal = event_proposal(&admin_event);
+    publisher.set_management_type(
+        &circuit_proposal.circuit_id,
+        &circuit_proposal.circuit.circuit_management_type,
+    );
+    // Honor the export preferences of circuits with version 2 metadata
+    let metadata = ApplicationMetadata::from_bytes(&circuit_proposal.circuit.application_metadata);
+    if let Some(preferences) = metadata.as_ref().ok().and_then(ApplicationMetadata::data_export) {
+        publisher.set_export_preferences(&circuit_proposal.circuit_id, preferences);
+    }
 
-    let url = config.splinterd_url();
     match admin_event {
         AdminServiceEvent::ProposalSubmitted(msg_proposal) => {
             let time = SystemTime::now();
@@ -143,21 +418,20 @@ fn process_admin_event(
             proposal_submit.set_requester(requester);
             proposal_submit.set_requester_node_id(proposal.requester_node_id.clone());
             proposal_submit.set_circuit_id(proposal.circuit_id.clone());
-            let message_bytes = match proposal_submit.write_to_bytes() {
-                Ok(bytes) => bytes,
-                Err(err) => return Err(EventHandlerError::InvalidMessageError(err.to_string())),
-            };
-            let mut message = Message::new();
-            message.set_field_type(Message_MessageType::PROPOSAL_SUBMIT);
-            message.set_message(message_bytes);
-            let to_send_bytes = match message.write_to_bytes() {
-                Ok(bytes) => bytes,
-                Err(err) => return Err(EventHandlerError::InvalidMessageError(err.to_string())),
-            };
-            match producer.send(&Record::from_value(&topic, to_send_bytes)) {
-                Ok(_) => info!("Wrote to Kafka about Proposal Update"),
-                Err(err) => return Err(EventHandlerError::InvalidMessageError(err.to_string())),
+            proposal_submit.set_proposal_id(proposal_id(&msg_proposal));
+            proposal_submit.set_circuit(circuit_definition(&msg_proposal, &consortium));
+            if let Some(proposal_cache) = proposal_cache {
+                proposal_cache.add_proposal(&proposal);
             }
+            if let Some(database) = ctx.sinks.database(Message_MessageType::PROPOSAL_SUBMIT) {
+                database.add_proposal(&proposal, &consortium, &nodes, &services);
+            }
+            publisher.publish(
+                &msg_proposal.circuit_id,
+                Message_MessageType::PROPOSAL_SUBMIT,
+                &proposal_submit,
+            )?;
+            info!("Wrote to Kafka about Proposal Update");
             Ok(())
         }
         AdminServiceEvent::ProposalVote((msg_proposal, signer_public_key)) => {
@@ -169,10 +443,9 @@ fn process_admin_event(
                 .ok_or_else(|| {
                     EventHandlerError::InvalidMessageError("Missing vote from signer".to_string())
                 })?;
-            let proposal_id: i64 = 1234;
             let time = SystemTime::now();
             let vote = NewProposalVoteRecord {
-                proposal_id,
+                proposal_id: UNRESOLVED_PROPOSAL_ROW,
                 voter_public_key: to_hex(&signer_public_key),
                 voter_node_id: vote.voter_node_id.to_string(),
                 vote: "Accept".to_string(),
@@ -182,21 +455,24 @@ fn process_admin_event(
             proposal_vote.set_voter(vote.voter_public_key.clone());
             proposal_vote.set_voter_node_id(vote.voter_node_id.clone());
             proposal_vote.set_circuit_id(msg_proposal.circuit_id.clone());
-            let message_bytes = match proposal_vote.write_to_bytes() {
-                Ok(bytes) => bytes,
-                Err(err) => return Err(EventHandlerError::InvalidMessageError(err.to_string())),
-            };
-            let mut message = Message::new();
-            message.set_field_type(Message_MessageType::PROPOSAL_VOTE);
-            message.set_message(message_bytes);
-            let to_send_bytes = match message.write_to_bytes() {
-                Ok(bytes) => bytes,
-                Err(err) => return Err(EventHandlerError::InvalidMessageError(err.to_string())),
-            };
-            match producer.send(&Record::from_value(&topic, to_send_bytes)) {
-                Ok(_) => info!("Wrote to Kafka about Proposal Update"),
-                Err(err) => return Err(EventHandlerError::InvalidMessageError(err.to_string())),
+            proposal_vote.set_proposal_id(proposal_id(&msg_proposal));
+            let voter_node = voter_node(ctx, &vote.voter_node_id);
+            proposal_vote.set_voter_display_name(voter_node.display_name);
+            proposal_vote.set_voter_node_metadata(voter_node.metadata.into_iter().collect());
+            proposal_vote.set_votes(ctx.votes.ballots(&msg_proposal));
+            proposal_vote.set_required_votes(required_votes(&msg_proposal));
+            if let Some(proposal_cache) = proposal_cache {
+                proposal_cache.add_vote(&msg_proposal.circuit_id, &vote);
+            }
+            if let Some(database) = ctx.sinks.database(Message_MessageType::PROPOSAL_VOTE) {
+                database.add_vote(&msg_proposal.circuit_id, &vote);
             }
+            publisher.publish(
+                &msg_proposal.circuit_id,
+                Message_MessageType::PROPOSAL_VOTE,
+                &proposal_vote,
+            )?;
+            info!("Wrote to Kafka about Proposal Update");
             Ok(())
         }
         AdminServiceEvent::ProposalAccepted((msg_proposal, signer_public_key)) => {
@@ -210,9 +486,8 @@ fn process_admin_event(
                     EventHandlerError::InvalidMessageError("Missing vote from signer".to_string())
                 })?;
 
-            let proposal_id: i64 = 1234;
             let vote = NewProposalVoteRecord {
-                proposal_id,
+                proposal_id: UNRESOLVED_PROPOSAL_ROW,
                 voter_public_key: to_hex(&signer_public_key),
                 voter_node_id: vote.voter_node_id.to_string(),
                 vote: "Accept".to_string(),
@@ -222,26 +497,37 @@ fn process_admin_event(
             proposal_accept.set_voter(vote.voter_public_key.clone());
             proposal_accept.set_voter_node_id(vote.voter_node_id.clone());
             proposal_accept.set_circuit_id(msg_proposal.circuit_id.clone());
-            let message_bytes = match proposal_accept.write_to_bytes() {
-                Ok(bytes) => bytes,
-                Err(err) => return Err(EventHandlerError::InvalidMessageError(err.to_string())),
-            };
-            let mut message = Message::new();
-            message.set_field_type(Message_MessageType::PROPOSAL_ACCEPT);
-            message.set_message(message_bytes);
-            let to_send_bytes = match message.write_to_bytes() {
-                Ok(bytes) => bytes,
-                Err(err) => return Err(EventHandlerError::InvalidMessageError(err.to_string())),
-            };
-            match producer.send(&Record::from_value(&topic, to_send_bytes)) {
-                Ok(_) => info!("Wrote to Kafka about Proposal Update"),
-                Err(err) => return Err(EventHandlerError::InvalidMessageError(err.to_string())),
+            proposal_accept.set_proposal_id(proposal_id(&msg_proposal));
+            let voter_node = voter_node(ctx, &vote.voter_node_id);
+            proposal_accept.set_voter_display_name(voter_node.display_name);
+            proposal_accept.set_voter_node_metadata(voter_node.metadata.into_iter().collect());
+            proposal_accept.set_votes(ctx.votes.ballots(&msg_proposal));
+            proposal_accept.set_required_votes(required_votes(&msg_proposal));
+            ctx.votes.decided(&msg_proposal.circuit_id);
+            if let Some(metadata) = proposal_metadata(&msg_proposal) {
+                proposal_accept.set_alias(metadata.alias().to_string());
+                if let Some(comments) = metadata.comments() {
+                    proposal_accept.set_comments(comments.to_string());
+                }
+            }
+            if let Some(proposal_cache) = proposal_cache {
+                proposal_cache.add_vote(&msg_proposal.circuit_id, &vote);
+                proposal_cache.update_status(&msg_proposal.circuit_id, "Accepted", time);
             }
+            if let Some(database) = ctx.sinks.database(Message_MessageType::PROPOSAL_ACCEPT) {
+                database.add_vote(&msg_proposal.circuit_id, &vote);
+                database.update_status(&msg_proposal.circuit_id, "Accepted", time);
+            }
+            publisher.publish(
+                &msg_proposal.circuit_id,
+                Message_MessageType::PROPOSAL_ACCEPT,
+                &proposal_accept,
+            )?;
+            info!("Wrote to Kafka about Proposal Update");
             Ok(())
         }
         AdminServiceEvent::ProposalRejected((msg_proposal, signer_public_key)) => {
 //            let proposal = get_pending_proposal_with_circuit_id(&pool, &msg_proposal.circuit_id)?;
-            let proposal_id: i64 = 1234;
             let time = SystemTime::now();
             let vote = msg_proposal
                 .votes
@@ -252,7 +538,7 @@ fn process_admin_event(
                 })?;
 
             let vote = NewProposalVoteRecord {
-                proposal_id,
+                proposal_id: UNRESOLVED_PROPOSAL_ROW,
                 voter_public_key: to_hex(&signer_public_key),
                 voter_node_id: vote.voter_node_id.to_string(),
                 vote: "Reject".to_string(),
@@ -262,24 +548,44 @@ fn process_admin_event(
             proposal_reject.set_voter(vote.voter_public_key.clone());
             proposal_reject.set_voter_node_id(vote.voter_node_id.clone());
             proposal_reject.set_circuit_id(msg_proposal.circuit_id.clone());
-            let message_bytes = match proposal_reject.write_to_bytes() {
-                Ok(bytes) => bytes,
-                Err(err) => return Err(EventHandlerError::InvalidMessageError(err.to_string())),
-            };
-            let mut message = Message::new();
-            message.set_field_type(Message_MessageType::PROPOSAL_REJECT);
-            message.set_message(message_bytes);
-            let to_send_bytes = match message.write_to_bytes() {
-                Ok(bytes) => bytes,
-                Err(err) => return Err(EventHandlerError::InvalidMessageError(err.to_string())),
-            };
-            match producer.send(&Record::from_value(&topic, to_send_bytes)) {
-                Ok(_) => info!("Wrote to Kafka about Proposal Update"),
-                Err(err) => return Err(EventHandlerError::InvalidMessageError(err.to_string())),
+            proposal_reject.set_proposal_id(proposal_id(&msg_proposal));
+            let voter_node = voter_node(ctx, &vote.voter_node_id);
+            proposal_reject.set_voter_display_name(voter_node.display_name);
+            proposal_reject.set_voter_node_metadata(voter_node.metadata.into_iter().collect());
+            proposal_reject.set_votes(ctx.votes.ballots(&msg_proposal));
+            proposal_reject.set_required_votes(required_votes(&msg_proposal));
+            ctx.votes.decided(&msg_proposal.circuit_id);
+            if let Some(metadata) = proposal_metadata(&msg_proposal) {
+                proposal_reject.set_alias(metadata.alias().to_string());
+                if let Some(comments) = metadata.comments() {
+                    proposal_reject.set_comments(comments.to_string());
+                }
             }
+            if let Some(proposal_cache) = proposal_cache {
+                proposal_cache.add_vote(&msg_proposal.circuit_id, &vote);
+                proposal_cache.update_status(&msg_proposal.circuit_id, "Rejected", time);
+            }
+            if let Some(database) = ctx.sinks.database(Message_MessageType::PROPOSAL_REJECT) {
+                database.add_vote(&msg_proposal.circuit_id, &vote);
+                database.update_status(&msg_proposal.circuit_id, "Rejected", time);
+            }
+            publisher.publish(
+                &msg_proposal.circuit_id,
+                Message_MessageType::PROPOSAL_REJECT,
+                &proposal_reject,
+            )?;
+            info!("Wrote to Kafka about Proposal Update");
             Ok(())
         }
         AdminServiceEvent::CircuitReady(msg_proposal) => {
+            // Without a key the contract state is not exported
+            let private_key = match ctx.private_key {
+                Some(ref private_key) => private_key,
+                None => {
+                    publish_proposal_ready(&msg_proposal, &ctx.sinks)?;
+                    return Ok(());
+                }
+            };
 
             // Now that the circuit is created, submit the Sabre transactions to run xo
             let service_id = match msg_proposal.circuit.roster.iter().find_map(|service| {
@@ -298,10 +604,16 @@ fn process_admin_event(
                     return Ok(());
                 }
             };
-            let scabbard_admin_keys = match serde_json::from_slice::<ApplicationMetadata>(
+            let config = ctx.config.current();
+            let (scabbard_admin_keys, contract) = match ApplicationMetadata::from_bytes(
                 msg_proposal.circuit.application_metadata.as_slice(),
             ) {
-                Ok(metadata) => metadata.scabbard_admin_keys().to_vec(),
+                Ok(metadata) => (
+                    metadata.scabbard_admin_keys().to_vec(),
+                    metadata
+                        .contract()
+                        .map(|contract| contract.resolve(config.deployment_config())),
+                ),
                 Err(err) => {
                     return Err(EventHandlerError::InvalidMessageError(format!(
                         "unable to parse application metadata: {}",
@@ -310,103 +622,274 @@ fn process_admin_event(
                 }
             };
 
-            let time = SystemTime::now();
-            let requester = to_hex(&msg_proposal.requester);
-            let proposal = parse_proposal(&msg_proposal, time, requester.clone());
-            let mut proposal_ready = ProposalReady::new();
-            proposal_ready.set_requester(requester);
-            proposal_ready.set_requester_node_id(proposal.requester_node_id.clone());
-            proposal_ready.set_circuit_id(proposal.circuit_id.clone());
-            let message_bytes = match proposal_ready.write_to_bytes() {
-                Ok(bytes) => bytes,
-                Err(err) => return Err(EventHandlerError::InvalidMessageError(err.to_string())),
-            };
-            let mut message = Message::new();
-            message.set_field_type(Message_MessageType::PROPOSAL_READY);
-            message.set_message(message_bytes);
-            let to_send_bytes = match message.write_to_bytes() {
-                Ok(bytes) => bytes,
-                Err(err) => return Err(EventHandlerError::InvalidMessageError(err.to_string())),
+            let proposal = publish_proposal_ready(&msg_proposal, &ctx.sinks)?;
+
+            let subscription = ScabbardSubscription {
+                circuit_id: msg_proposal.circuit_id.clone(),
+                service_id,
+                requester: proposal.requester,
+                requester_node_id: proposal.requester_node_id,
+                scabbard_admin_keys,
+                services: roster_services(&msg_proposal.circuit.roster),
+                members: circuit_members(&msg_proposal.circuit.members),
+                management_type: msg_proposal.circuit.circuit_management_type.clone(),
+                contract,
             };
-            match producer.send(&Record::from_value(&topic, to_send_bytes)) {
-                Ok(_) => info!("Wrote to Kafka about Proposal Update"),
-                Err(err) => return Err(EventHandlerError::InvalidMessageError(err.to_string())),
+            if let Err(err) = ctx.registry.record(&subscription) {
+                error!("Unable to record subscription: {}", err);
             }
+            open_scabbard_subscription(
+                subscription,
+                Some(private_key),
+                Lifecycle::Subscribed,
+                ctx,
+                &igniter,
+            )
+        }
+    }
+}
 
-            let processor = SabreProcessor::new(
-                &msg_proposal.circuit_id,
-                &proposal.requester_node_id,
-                &proposal.requester,
-                config.clone(),
-            );
+/// Subscribes to the state changes of a scabbard service.
+///
+/// When a private key is given the Sabre contract is set up each time the connection
+/// opens; subscriptions reopened after a restart pass `None` since their contract exists.
+fn open_scabbard_subscription(
+    subscription: ScabbardSubscription,
+    private_key: Option<&str>,
+    lifecycle: Lifecycle,
+    ctx: &ExportContext,
+    igniter: &Igniter,
+) -> Result<(), EventHandlerError> {
+    // Read when the subscription opens, so that new circuits get reloaded contract settings
+    let config = ctx.config.current();
+    let status = &ctx.status;
+    let url = config.splinterd_url().to_string();
+    let reconnect = config.reconnect();
+    let reconnect_limit = config.reconnect_limit();
+    let connection_timeout = config.connection_timeout();
+    let event_parsing = config.deployment_config().event_parsing();
+    let processor = Arc::new(SabreProcessor::new(
+        &subscription,
+        config.clone(),
+        ctx.sinks.publisher.clone(),
+        ctx.sinks
+            .database(Message_MessageType::CIRCUIT_PAYLOAD)
+            .cloned(),
+        ctx.dedup.clone(),
+        ctx.delta_encoder.clone(),
+        ctx.decoders.clone(),
+        ctx.pauses.clone(),
+    ));
+    // Only reopened subscriptions are sent a history the exporter may have seen
+    let backfill = match private_key {
+        Some(_) => None,
+        None => Backfill::start(
+            &subscription.circuit_id,
+            config.deployment_config().backfill_depth(),
+            processor.clone(),
+        )?,
+    };
+    let open_backfill = backfill.clone();
 
-            let mut xo_ws = WebSocketClient::new(
-                &format!(
-                    "{}/scabbard/{}/{}/ws/subscribe",
-                    url, msg_proposal.circuit_id, service_id
-                ),
-                move |_, changes| {
-                    if let Err(err) = processor.handle_state_changes(changes) {
-                        error!("An error occurred while handling state changes {:?}", err);
-                    }
-                    WsResponse::Empty
-                },
-            );
+    ctx.sinks
+        .publisher
+        .set_service(&subscription.circuit_id, &subscription.service_id);
+    // Known from the recorded subscription when no admin event was seen since a restart
+    if !subscription.management_type.is_empty() {
+        let publisher = &ctx.sinks.publisher;
+        publisher.set_management_type(&subscription.circuit_id, &subscription.management_type);
+    }
+    let subscription_name = scabbard_subscription(&subscription.circuit_id, &subscription.service_id);
+    let message_status = status.clone();
+    let message_subscription = subscription_name.clone();
+    let open_status = status.clone();
+    let open_subscription = subscription_name.clone();
+    let error_status = status.clone();
+    let circuit_id = subscription.circuit_id.clone();
+    let message_circuit_id = circuit_id.clone();
+    let open_circuit_id = circuit_id.clone();
+    let intake = ctx.intake.clone();
+    let error_intake = ctx.intake.clone();
+    let error_alerter = ctx.alerter.clone();
+    let message_registry = ctx.registry.clone();
+    let error_registry = ctx.registry.clone();
+    let error_supervisor = ctx.supervisor.clone();
+    let dead_subscription = DeadSubscription::Scabbard(subscription.clone());
+    let opened_circuit_id = subscription.circuit_id.clone();
+    let opened = json!({ "service_id": subscription.service_id });
+    // Set when the connection restarts past an unparseable frame, whose contracts were
+    // set up when it first opened
+    let skip_setup = Arc::new(AtomicBool::new(false));
+    let error_skip_setup = skip_setup.clone();
+    status.connecting(&subscription_name);
 
-            let url_to_string = url.to_string();
-            let private_key_to_string = private_key.to_string();
-            xo_ws.on_open(move |ctx| {
-                debug!("Starting State Delta Export");
-                let future = match setup_tp(
-                    &private_key_to_string,
-                    scabbard_admin_keys.clone(),
-                    &url_to_string,
-                    &msg_proposal.circuit_id.clone(),
-                    &service_id.clone(),
-                    config.clone(),
-                ) {
-                    Ok(f) => f,
-                    Err(err) => {
-                        error!("{}", err);
-                        return WsResponse::Close;
-                    }
-                };
-
-                if let Err(err) = ctx.igniter().send(future) {
-                    error!("Failed to setup scabbard: {}", err);
-                    WsResponse::Close
-                } else {
-                    WsResponse::Empty
-                }
-            });
-            xo_ws.set_reconnect(RECONNECT);
-            xo_ws.set_reconnect_limit(RECONNECT_LIMIT);
-            xo_ws.set_timeout(CONNECTION_TIMEOUT);
-
-            xo_ws.on_error(move |err, ctx| {
-                error!(
-                    "An error occured while listening for scabbard events {}",
-                    err
-                );
-                match err {
-                    WebSocketError::ParserError { .. } => {
-                        debug!("Protocol error, closing connection");
-                        Ok(())
-                    }
-                    WebSocketError::ReconnectError(_) => {
-                        debug!("Failed to reconnect. Closing WebSocket.");
-                        Ok(())
-                    }
-                    _ => {
-                        debug!("Attempting to restart connection");
-                        ctx.start_ws()
-                    }
-                }
-            });
+    let subscribe_url = scabbard_url(
+        &url,
+        &subscription.circuit_id,
+        &subscription.service_id,
+        "ws/subscribe",
+    )?;
+    let mut xo_ws = WebSocketClient::new(&subscribe_url, move |_, changes| {
+        if message_registry.is_removed(&message_circuit_id) {
+            return WsResponse::Close;
+        }
+        let _in_flight = match intake.enter() {
+            Some(in_flight) => in_flight,
+            None => return WsResponse::Close,
+        };
+        message_status.message_received(&message_subscription);
+        let changes = match backfill {
+            Some(ref backfill) => match backfill.admit(changes) {
+                Some(changes) => changes,
+                None => return WsResponse::Empty,
+            },
+            None => changes,
+        };
+        let errors = processor.handle_state_changes(changes);
+        for err in &errors {
+            error!("An error occurred while handling state changes {:?}", err);
+            message_status.circuit_error(&message_circuit_id, &err.to_string());
+        }
+        // Failures to publish went through the WAL already, they do not close the
+        // subscription in either mode
+        match errors.iter().find(|err| err.is_parse_error()) {
+            Some(err) if event_parsing == EventParsing::Strict => {
+                message_status.failed(&message_subscription, &err.to_string(), false);
+                WsResponse::Close
+            }
+            _ => WsResponse::Empty,
+        }
+    });
+
+    let private_key_to_string = private_key.map(ToOwned::to_owned);
+    let setup_publisher = ctx.sinks.publisher.clone();
+    xo_ws.on_open(move |ctx| {
+        debug!("Starting State Delta Export");
+        open_status.connected(&open_subscription);
+        if let Some(ref backfill) = open_backfill {
+            backfill.connected();
+        }
+        if skip_setup.swap(false, Ordering::SeqCst) {
+            return WsResponse::Empty;
+        }
+        let private_key = match private_key_to_string {
+            Some(ref private_key) => private_key,
+            None => return WsResponse::Empty,
+        };
+        let future = match setup_tp(
+            private_key,
+            subscription.scabbard_admin_keys.clone(),
+            &url,
+            &subscription.circuit_id,
+            &subscription.service_id,
+            config
+                .deployment_config()
+                .circuit_contracts(subscription.contract.as_ref()),
+            config.clone(),
+            setup_publisher.clone(),
+        ) {
+            Ok(f) => f,
+            Err(err) => {
+                error!("{}", err);
+                open_status.circuit_error(&open_circuit_id, &err.to_string());
+                return WsResponse::Close;
+            }
+        };
+
+        if let Err(err) = ctx.igniter().send(future) {
+            error!("Failed to setup scabbard: {}", err);
+            open_status.circuit_error(&open_circuit_id, &err.to_string());
+            WsResponse::Close
+        } else {
+            WsResponse::Empty
+        }
+    });
+    xo_ws.set_reconnect(reconnect);
+    xo_ws.set_reconnect_limit(reconnect_limit);
+    xo_ws.set_timeout(connection_timeout);
 
-            igniter.start_ws(&xo_ws).map_err(EventHandlerError::from)
+    xo_ws.on_error(move |err, ctx| {
+        error!(
+            "An error occured while listening for scabbard events {}",
+            err
+        );
+        let message = err.to_string();
+        if error_intake.is_stopped() {
+            error_status.failed(&subscription_name, &message, false);
+            return Ok(());
+        }
+        if error_registry.is_removed(&circuit_id) {
+            debug!("Circuit {} was removed, closing connection", circuit_id);
+            return Ok(());
+        }
+        error_status.circuit_error(&circuit_id, &message);
+        match err {
+            WebSocketError::ParserError { .. } if event_parsing == EventParsing::Strict => {
+                debug!("Protocol error, closing connection");
+                error_status.failed(&subscription_name, &message, false);
+                Ok(())
+            }
+            // The frame ended the connection, which restarts without setting up the
+            // contracts again
+            WebSocketError::ParserError { .. } => {
+                warn!("Skipping unparseable scabbard event, restarting connection");
+                error_skip_setup.store(true, Ordering::SeqCst);
+                ctx.start_ws()
+            }
+            WebSocketError::ReconnectError(_) => {
+                debug!("Failed to reconnect. Closing WebSocket.");
+                let revived = error_supervisor.report(dead_subscription.clone());
+                error_status.failed(&subscription_name, &message, revived);
+                error_alerter.reconnect_exhausted(
+                    ctx.igniter(),
+                    &subscription_name,
+                    Some(&circuit_id),
+                    &message,
+                );
+                Ok(())
+            }
+            _ => {
+                debug!("Attempting to restart connection");
+                error_status.failed(&subscription_name, &message, true);
+                ctx.start_ws()
+            }
         }
+    });
+
+    igniter.start_ws(&xo_ws).map_err(EventHandlerError::from)?;
+    ctx.sinks
+        .publisher
+        .publish_lifecycle(lifecycle, Some(&opened_circuit_id), opened);
+    ctx.manifest
+        .refresh(&config, &ctx.sinks.publisher, &ctx.registry);
+    Ok(())
+}
+
+fn publish_proposal_ready(
+    msg_proposal: &CircuitProposal,
+    sinks: &Sinks,
+) -> Result<NewConsortiumProposal, EventHandlerError> {
+    let time = SystemTime::now();
+    let requester = to_hex(&msg_proposal.requester);
+    let proposal = parse_proposal(msg_proposal, time, requester.clone());
+    let mut proposal_ready = ProposalReady::new();
+    proposal_ready.set_requester(requester);
+    proposal_ready.set_requester_node_id(proposal.requester_node_id.clone());
+    proposal_ready.set_circuit_id(proposal.circuit_id.clone());
+    proposal_ready.set_proposal_id(proposal_id(msg_proposal));
+    if let Some(ref proposal_cache) = sinks.proposal_cache {
+        proposal_cache.update_status(&msg_proposal.circuit_id, "Ready", time);
+    }
+    if let Some(database) = sinks.database(Message_MessageType::PROPOSAL_READY) {
+        database.update_status(&msg_proposal.circuit_id, "Ready", time);
     }
+    sinks.publisher.publish(
+        &msg_proposal.circuit_id,
+        Message_MessageType::PROPOSAL_READY,
+        &proposal_ready,
+    )?;
+    info!("Wrote to Kafka about Proposal Update");
+    Ok(proposal)
 }
 
 fn parse_proposal(
@@ -426,6 +909,37 @@ fn parse_proposal(
     }
 }
 
+/// Returns the id of a proposal exported with each of its messages.
+///
+/// This is the circuit hash, which splinterd computes over the proposed circuit and lists
+/// with the proposal in its management API. Votes do not change the circuit, so the id
+/// stays the same from submission to readiness.
+fn proposal_id(proposal: &CircuitProposal) -> String {
+    proposal.circuit_hash.to_string()
+}
+
+/// Returns the registry details of a voter's node, empty if they could not be looked up
+fn voter_node(ctx: &ExportContext, voter_node_id: &str) -> NodeDetails {
+    ctx.nodes
+        .lookup(&ctx.config.current(), voter_node_id)
+        .unwrap_or_default()
+}
+
+/// Parses the proposal's application metadata, which gives context to votes on it.
+/// Votes are still exported if the metadata cannot be parsed.
+fn proposal_metadata(proposal: &CircuitProposal) -> Option<ApplicationMetadata> {
+    match ApplicationMetadata::from_bytes(&proposal.circuit.application_metadata) {
+        Ok(metadata) => Some(metadata),
+        Err(err) => {
+            warn!(
+                "Unable to parse application metadata of proposal {}: {}",
+                proposal.circuit_id, err
+            );
+            None
+        }
+    }
+}
+
 fn parse_consortium(
     circuit: &CreateCircuit,
     timestamp: SystemTime,
@@ -446,6 +960,36 @@ fn parse_consortium(
     })
 }
 
+/// Builds the definition of a proposed circuit, with the settings parsed for the database
+fn circuit_definition(proposal: &CircuitProposal, consortium: &Consortium) -> CircuitDefinition {
+    let mut definition = CircuitDefinition::new();
+    definition.set_circuit_id(consortium.circuit_id.clone());
+    definition.set_authorization_type(consortium.authorization_type.clone());
+    definition.set_persistence(consortium.persistence.clone());
+    definition.set_durability(consortium.durability.clone());
+    definition.set_routes(consortium.routes.clone());
+    definition.set_circuit_management_type(consortium.circuit_management_type.clone());
+    definition.set_alias(consortium.alias.clone());
+    if let Some(metadata) = proposal_metadata(proposal) {
+        if let Some(comments) = metadata.comments() {
+            definition.set_comments(comments.to_string());
+        }
+    }
+    definition.set_services(
+        roster_services(&proposal.circuit.roster)
+            .iter()
+            .map(circuit_service)
+            .collect(),
+    );
+    definition.set_members(
+        circuit_members(&proposal.circuit.members)
+            .iter()
+            .map(circuit_member)
+            .collect(),
+    );
+    definition
+}
+
 fn parse_splinter_services(
     circuit_id: &str,
     splinter_services: &[SplinterService],
@@ -494,6 +1038,28 @@ fn parse_splinter_nodes(
         .collect()
 }
 
+fn roster_services(splinter_services: &[SplinterService]) -> Vec<RosterService> {
+    splinter_services
+        .iter()
+        .map(|service| RosterService {
+            service_id: service.service_id.to_string(),
+            service_type: service.service_type.to_string(),
+            allowed_nodes: service.allowed_nodes.clone(),
+            arguments: service.arguments.clone(),
+        })
+        .collect()
+}
+
+fn circuit_members(splinter_nodes: &[SplinterNode]) -> Vec<CircuitMember> {
+    splinter_nodes
+        .iter()
+        .map(|node| CircuitMember {
+            node_id: node.node_id.to_string(),
+            endpoint: node.endpoint.to_string(),
+        })
+        .collect()
+}
+
 pub fn to_hex(bytes: &[u8]) -> String {
     let mut buf = String::new();
     for b in bytes {
@@ -0,0 +1,82 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::config::EventListenerConfig;
+
+use super::circuits::fetch_node;
+
+/// Display name and metadata of a node, as splinterd's node registry lists them
+#[derive(Clone, Default)]
+pub struct NodeDetails {
+    pub display_name: String,
+    pub metadata: BTreeMap<String, String>,
+}
+
+/// Caches the node registry entries of the nodes voting on proposals, so that their votes
+/// can name them. Entries are looked up again once older than `node_registry_cache_ttl`.
+///
+/// A failed lookup is cached like an entry, so that an unreachable registry costs one
+/// request per node and period rather than one per vote.
+#[derive(Clone, Default)]
+pub struct NodeDirectory {
+    nodes: Arc<Mutex<HashMap<String, (Instant, Option<NodeDetails>)>>>,
+}
+
+impl NodeDirectory {
+    pub fn new() -> Self {
+        NodeDirectory::default()
+    }
+
+    /// Returns the details of a node, `None` if the registry does not know it
+    pub fn lookup(&self, config: &EventListenerConfig, node_id: &str) -> Option<NodeDetails> {
+        let deployment_config = config.deployment_config();
+        let ttl = Duration::from_secs(deployment_config.node_registry_cache_ttl());
+        if let Ok(nodes) = self.nodes.lock() {
+            match nodes.get(node_id) {
+                Some((fetched_at, details)) if fetched_at.elapsed() < ttl => {
+                    return details.clone()
+                }
+                _ => (),
+            }
+        }
+
+        let details = match fetch_node(config.splinterd_url(), deployment_config, node_id) {
+            Ok(node) => Some(NodeDetails {
+                display_name: node.display_name,
+                metadata: node.metadata.into_iter().collect(),
+            }),
+            Err(err) => {
+                warn!(
+                    "Unable to look up node {} in the registry: {}",
+                    node_id, err
+                );
+                None
+            }
+        };
+        match self.nodes.lock() {
+            Ok(mut nodes) => {
+                nodes.insert(node_id.to_string(), (Instant::now(), details.clone()));
+            }
+            Err(err) => error!("Unable to cache node details: {}", err),
+        }
+        details
+    }
+}
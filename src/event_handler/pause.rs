@@ -0,0 +1,87 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+
+use crate::time::now_millis;
+
+/// Contract namespaces whose state changes an operator paused the export of, e.g. during
+/// a noisy contract migration, by namespace prefix with the time they were paused.
+///
+/// Changes under a paused namespace are dropped rather than queued. Resuming it exports a
+/// snapshot of the namespace in every subscribed circuit, through the `SnapshotTrigger`,
+/// after which its changes are exported again.
+#[derive(Clone, Default)]
+pub struct NamespacePauses {
+    paused: Arc<RwLock<BTreeMap<String, u64>>>,
+}
+
+impl NamespacePauses {
+    pub fn new() -> Self {
+        NamespacePauses::default()
+    }
+
+    /// Pauses a namespace, returns false if it was already paused
+    pub fn pause(&self, prefix: &str) -> bool {
+        match self.paused.write() {
+            Ok(mut paused) => {
+                if paused.contains_key(prefix) {
+                    return false;
+                }
+                paused.insert(prefix.to_string(), now_millis());
+                true
+            }
+            Err(err) => {
+                error!("Unable to pause namespace {}: {}", prefix, err);
+                false
+            }
+        }
+    }
+
+    /// Resumes a namespace, returns false if it was not paused
+    pub fn resume(&self, prefix: &str) -> bool {
+        match self.paused.write() {
+            Ok(mut paused) => paused.remove(prefix).is_some(),
+            Err(err) => {
+                error!("Unable to resume namespace {}: {}", prefix, err);
+                false
+            }
+        }
+    }
+
+    pub fn is_paused(&self, prefix: &str) -> bool {
+        match self.paused.read() {
+            Ok(paused) => paused.contains_key(prefix),
+            Err(err) => {
+                error!("Unable to read the paused namespaces: {}", err);
+                false
+            }
+        }
+    }
+
+    /// Returns the paused namespaces with the time each was paused
+    pub fn paused(&self) -> BTreeMap<String, u64> {
+        match self.paused.read() {
+            Ok(paused) => paused.clone(),
+            Err(err) => {
+                error!("Unable to read the paused namespaces: {}", err);
+                BTreeMap::new()
+            }
+        }
+    }
+}
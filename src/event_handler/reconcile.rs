@@ -0,0 +1,149 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::config::EventListenerConfig;
+use crate::proto::pubsub::{CircuitRemoved, Message_MessageType};
+use crate::publisher::KafkaPublisher;
+use crate::status::{scabbard_subscription, Status};
+use crate::time::now_millis;
+
+use super::circuits::fetch_circuit_ids;
+use super::manifest::Manifest;
+use super::registry::{ScabbardSubscription, SubscriptionRegistry};
+use super::EventHandlerError;
+
+/// Periodically checks the subscribed circuits against splinterd's circuit list.
+///
+/// A circuit which is no longer listed is marked removed in the registry, which stops its
+/// subscription from reconnecting, and a `CIRCUIT_REMOVED` message is published for it.
+/// Nothing is removed when the circuit list cannot be fetched.
+pub struct CircuitReconciler {
+    running: Arc<AtomicBool>,
+    join_handle: thread::JoinHandle<()>,
+}
+
+impl CircuitReconciler {
+    /// Starts the reconciler, returns `None` if the reconcile interval is 0
+    pub(super) fn start(
+        config: EventListenerConfig,
+        publisher: KafkaPublisher,
+        registry: SubscriptionRegistry,
+        status: Status,
+        manifest: Manifest,
+    ) -> Result<Option<Self>, EventHandlerError> {
+        let interval = config.deployment_config().circuit_reconcile_interval();
+        if interval == 0 {
+            return Ok(None);
+        }
+        let interval = Duration::from_secs(interval);
+
+        let running = Arc::new(AtomicBool::new(true));
+        let thread_running = running.clone();
+        let join_handle = thread::Builder::new()
+            .name("CircuitReconciler".into())
+            .spawn(move || {
+                while thread_running.load(Ordering::SeqCst) {
+                    if reconcile(&config, &publisher, &registry, &status) {
+                        manifest.refresh(&config, &publisher, &registry);
+                    }
+                    let started = Instant::now();
+                    while thread_running.load(Ordering::SeqCst) && started.elapsed() < interval {
+                        thread::sleep(Duration::from_millis(100));
+                    }
+                }
+            })?;
+
+        Ok(Some(CircuitReconciler {
+            running,
+            join_handle,
+        }))
+    }
+
+    pub fn shutdown(self) {
+        self.running.store(false, Ordering::SeqCst);
+        if self.join_handle.join().is_err() {
+            error!("Circuit reconciler thread panicked");
+        }
+    }
+}
+
+/// Returns whether a circuit was removed
+fn reconcile(
+    config: &EventListenerConfig,
+    publisher: &KafkaPublisher,
+    registry: &SubscriptionRegistry,
+    status: &Status,
+) -> bool {
+    let circuit_ids = match fetch_circuit_ids(config.splinterd_url(), config.deployment_config()) {
+        Ok(circuit_ids) => circuit_ids,
+        Err(err) => {
+            warn!("Unable to list circuits, skipping reconciliation: {}", err);
+            return false;
+        }
+    };
+    let mut removed = false;
+
+    for subscription in registry.active(config.deployment_config()) {
+        if circuit_ids.contains(&subscription.circuit_id)
+            || registry.is_removed(&subscription.circuit_id)
+        {
+            continue;
+        }
+        info!(
+            "Circuit {} no longer exists, closing its subscription",
+            subscription.circuit_id
+        );
+        if let Err(err) = registry.remove_circuit(&subscription.circuit_id) {
+            error!("Unable to update subscription registry: {}", err);
+        }
+        removed = true;
+        status.remove(&scabbard_subscription(
+            &subscription.circuit_id,
+            &subscription.service_id,
+        ));
+        if let Err(err) = publish_circuit_removed(publisher, &subscription) {
+            error!(
+                "Unable to publish the removal of circuit {}: {}",
+                subscription.circuit_id, err
+            );
+            status.circuit_error(&subscription.circuit_id, &err.to_string());
+        }
+    }
+    removed
+}
+
+fn publish_circuit_removed(
+    publisher: &KafkaPublisher,
+    subscription: &ScabbardSubscription,
+) -> Result<(), EventHandlerError> {
+    let mut circuit_removed = CircuitRemoved::new();
+    circuit_removed.set_requester(subscription.requester.clone());
+    circuit_removed.set_requester_node_id(subscription.requester_node_id.clone());
+    circuit_removed.set_circuit_id(subscription.circuit_id.clone());
+    circuit_removed.set_detected_at(now_millis());
+    publisher.publish(
+        &subscription.circuit_id,
+        Message_MessageType::CIRCUIT_REMOVED,
+        &circuit_removed,
+    )?;
+    Ok(())
+}
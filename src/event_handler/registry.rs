@@ -0,0 +1,211 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use std::collections::{BTreeMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use crate::config::{DeploymentConfig, ExportMode, TransactionProcessor};
+use crate::status::scabbard_subscription;
+
+use super::checkpoint::CheckpointStore;
+use super::EventHandlerError;
+
+/// A scabbard service the exporter reads contract state from
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ScabbardSubscription {
+    pub circuit_id: String,
+    pub service_id: String,
+    #[serde(default)]
+    pub requester: String,
+    #[serde(default)]
+    pub requester_node_id: String,
+    #[serde(default)]
+    pub scabbard_admin_keys: Vec<String>,
+    #[serde(default)]
+    pub services: Vec<RosterService>,
+    #[serde(default)]
+    pub members: Vec<CircuitMember>,
+    /// empty when the circuit's management type is not known, as in the state-only mode
+    #[serde(default)]
+    pub management_type: String,
+    /// the contract chosen in the circuit's application metadata, the circuit runs the
+    /// deployment's contracts when unset
+    #[serde(default)]
+    pub contract: Option<TransactionProcessor>,
+}
+
+/// A service of the circuit's roster
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct RosterService {
+    pub service_id: String,
+    pub service_type: String,
+    #[serde(default)]
+    pub allowed_nodes: Vec<String>,
+    #[serde(default)]
+    pub arguments: Vec<(String, String)>,
+}
+
+/// A member node of the circuit, the endpoint is empty when it is not known
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct CircuitMember {
+    pub node_id: String,
+    #[serde(default)]
+    pub endpoint: String,
+}
+
+/// Records the scabbard services subscribed to, so that the subscriptions can be opened
+/// again after a restart.
+///
+/// The registry is saved to a checkpoint store when one is configured, and only kept in
+/// memory otherwise. Circuits found deleted from splinterd are remembered until the exporter
+/// stops, so that their subscriptions are not opened again.
+#[derive(Clone)]
+pub struct SubscriptionRegistry {
+    store: Option<Arc<dyn CheckpointStore>>,
+    subscriptions: Arc<Mutex<BTreeMap<String, ScabbardSubscription>>>,
+    removed: Arc<Mutex<HashSet<String>>>,
+}
+
+impl SubscriptionRegistry {
+    pub fn load(store: Option<Arc<dyn CheckpointStore>>) -> Result<Self, EventHandlerError> {
+        let subscriptions = match store {
+            Some(ref store) => store
+                .load()?
+                .into_iter()
+                .map(|subscription| (key(&subscription), subscription))
+                .collect(),
+            None => BTreeMap::new(),
+        };
+        Ok(SubscriptionRegistry {
+            store,
+            subscriptions: Arc::new(Mutex::new(subscriptions)),
+            removed: Arc::new(Mutex::new(HashSet::new())),
+        })
+    }
+
+    pub fn subscriptions(&self) -> Vec<ScabbardSubscription> {
+        match self.subscriptions.lock() {
+            Ok(subscriptions) => subscriptions.values().cloned().collect(),
+            Err(err) => {
+                error!("Unable to read subscription registry: {}", err);
+                vec![]
+            }
+        }
+    }
+
+    /// Returns the subscriptions exported from: those listed in the configuration in the
+    /// state-only mode, the recorded ones otherwise, less those of removed circuits
+    pub fn active(&self, deployment_config: &DeploymentConfig) -> Vec<ScabbardSubscription> {
+        let subscriptions = match deployment_config.export_mode() {
+            ExportMode::StateOnly => deployment_config
+                .state_subscriptions()
+                .iter()
+                .map(|listed| ScabbardSubscription {
+                    circuit_id: listed.circuit_id.clone(),
+                    service_id: listed.service_id.clone(),
+                    requester: listed.requester.clone(),
+                    requester_node_id: listed.requester_node_id.clone(),
+                    scabbard_admin_keys: vec![],
+                    services: vec![],
+                    members: vec![],
+                    management_type: String::new(),
+                    contract: None,
+                })
+                .collect(),
+            ExportMode::Full | ExportMode::AdminOnly | ExportMode::Aggregator => {
+                self.subscriptions()
+            }
+        };
+        subscriptions
+            .into_iter()
+            .filter(|subscription| !self.is_removed(&subscription.circuit_id))
+            .collect()
+    }
+
+    pub fn contains(&self, circuit_id: &str, service_id: &str) -> bool {
+        match self.subscriptions.lock() {
+            Ok(subscriptions) => {
+                subscriptions.contains_key(&scabbard_subscription(circuit_id, service_id))
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Adds the subscription and saves the registry, if a store is configured. A circuit
+    /// created again under the id of a removed one is no longer considered removed.
+    pub fn record(&self, subscription: &ScabbardSubscription) -> Result<(), EventHandlerError> {
+        if let Ok(mut removed) = self.removed.lock() {
+            removed.remove(&subscription.circuit_id);
+        }
+        let mut subscriptions = self.subscriptions.lock().map_err(|err| {
+            EventHandlerError::InvalidMessageError(format!(
+                "Unable to update subscription registry: {}",
+                err
+            ))
+        })?;
+        if subscriptions.get(&key(subscription)) == Some(subscription) {
+            return Ok(());
+        }
+        subscriptions.insert(key(subscription), subscription.clone());
+        self.write(&subscriptions)
+    }
+
+    pub fn is_removed(&self, circuit_id: &str) -> bool {
+        match self.removed.lock() {
+            Ok(removed) => removed.contains(circuit_id),
+            Err(_) => false,
+        }
+    }
+
+    /// Marks a circuit as removed and drops its subscriptions, saving the registry if a
+    /// store is configured
+    pub fn remove_circuit(&self, circuit_id: &str) -> Result<(), EventHandlerError> {
+        match self.removed.lock() {
+            Ok(mut removed) => {
+                removed.insert(circuit_id.to_string());
+            }
+            Err(err) => error!("Unable to record removed circuit: {}", err),
+        }
+
+        let mut subscriptions = self.subscriptions.lock().map_err(|err| {
+            EventHandlerError::InvalidMessageError(format!(
+                "Unable to update subscription registry: {}",
+                err
+            ))
+        })?;
+        let recorded = subscriptions.len();
+        subscriptions.retain(|_, subscription| subscription.circuit_id != circuit_id);
+        if subscriptions.len() == recorded {
+            return Ok(());
+        }
+        self.write(&subscriptions)
+    }
+
+    fn write(
+        &self,
+        subscriptions: &BTreeMap<String, ScabbardSubscription>,
+    ) -> Result<(), EventHandlerError> {
+        if let Some(ref store) = self.store {
+            store.save(&subscriptions.values().collect::<Vec<_>>())?;
+        }
+        Ok(())
+    }
+}
+
+fn key(subscription: &ScabbardSubscription) -> String {
+    scabbard_subscription(&subscription.circuit_id, &subscription.service_id)
+}
@@ -18,31 +18,41 @@
 
 //! This module is based on the Sawtooth Sabre CLI.
 
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::{BufReader, Read};
 use std::path::Path;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use crypto::digest::Digest;
 use crypto::sha2::Sha512;
-use futures::future::{self, Future};
+use futures::future::{self, Future, Loop};
 use futures::stream::Stream;
+use hyper::header::AUTHORIZATION;
 use hyper::{Body, Client, Request, StatusCode};
 use protobuf::Message;
+use rand::Rng;
 use sabre_sdk::protocol::payload::{
     Action, CreateContractActionBuilder, CreateContractRegistryActionBuilder,
     CreateNamespaceRegistryActionBuilder, CreateNamespaceRegistryPermissionActionBuilder,
-    SabrePayloadBuilder,
+    SabrePayloadBuilder, UpdateNamespaceRegistryOwnersActionBuilder,
 };
+use sabre_sdk::protocol::state::NamespaceRegistryList;
 use sabre_sdk::protocol::ADMINISTRATORS_SETTING_ADDRESS;
-use sabre_sdk::protos::IntoBytes as SabreIntoBytes;
+use sabre_sdk::protos::{FromBytes as SabreFromBytes, IntoBytes as SabreIntoBytes};
 use sawtooth_sdk::messages::batch::{Batch, BatchHeader, BatchList};
 use sawtooth_sdk::messages::transaction::{Transaction, TransactionHeader};
 use sawtooth_sdk::signing::secp256k1::Secp256k1PrivateKey;
 use sawtooth_sdk::signing::{create_context, CryptoFactory, Signer};
+use tokio::timer::Delay;
 
+use super::endpoint::{scabbard_url, splinterd_link};
 use super::EventHandlerError;
-use crate::config::{EventListenerConfig, DeploymentConfig};
+use crate::config::{DeployMode, EventListenerConfig, TransactionProcessor};
+use crate::http::{self, Connector};
+use crate::proto::pubsub::{ContractDeployFailed, Message_MessageType, SabreContract};
+use crate::publisher::KafkaPublisher;
+use crate::time::now_millis;
 
 /// The Sawtooth Sabre transaction family name (sabre)
 const SABRE_FAMILY_NAME: &str = "sabre";
@@ -63,19 +73,50 @@ const SMART_PERMISSION_PREFIX: &str = "00ec03";
 
 const PIKE_PREFIX: &str = "cad11d";
 
-/// Create and submit the Sabre transactions to setup the XO smart contract.
+/// Number of times a batch is resubmitted while the scabbard batch queue is full
+const BATCH_SUBMIT_RETRY_LIMIT: u32 = 8;
+
+/// Delay before the first resubmission, doubled for every further attempt
+const BATCH_SUBMIT_BASE_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Upper bound of the delay between resubmissions
+const BATCH_SUBMIT_MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Create and submit the Sabre transactions to setup the circuit's smart contracts.
+///
+/// In the `if-missing` deploy mode the registries and contracts are looked up in the
+/// scabbard state first, and only the transactions of the missing ones are submitted.
+///
+/// The namespace registries of the contracts are owned by the scabbard admins and the
+/// configured submitter keys, so that the applications consuming the exports can manage
+/// the namespaces they submit transactions to without a manual step. In the `if-missing`
+/// deploy mode the submitter keys missing from the owners of an existing registry are
+/// added to them, its other owners are kept.
+///
+/// Once scabbard accepts the batch its status is polled until it is committed. An invalid
+/// or timed out batch fails the attempt, which is retried with backoff up to the configured
+/// number of attempts, after which a `CONTRACT_DEPLOY_FAILED` message is published.
+#[allow(clippy::too_many_arguments)]
 pub fn setup_tp(
     private_key: &str,
     scabbard_admin_keys: Vec<String>,
     splinterd_url: &str,
     circuit_id: &str,
     service_id: &str,
+    processors: Vec<TransactionProcessor>,
     config: EventListenerConfig,
+    publisher: KafkaPublisher,
 ) -> Result<Box<dyn Future<Item = (), Error = ()> + Send + 'static>, EventHandlerError> {
+    let deployment_config = config.deployment_config();
+    let deploy_mode = deployment_config.deploy_mode();
+    if deploy_mode == DeployMode::Never {
+        return Ok(Box::new(future::ok(())));
+    }
+
     let context = create_context("secp256k1")?;
     let factory = CryptoFactory::new(&*context);
-    let private_key = Secp256k1PrivateKey::from_hex(private_key)?;
-    let signer = factory.new_signer(&private_key);
+    let signing_key = Secp256k1PrivateKey::from_hex(private_key)?;
+    let signer = factory.new_signer(&signing_key);
 
     // The node with the first key in the list of scabbard admins is responsible for setting up xo
     let public_key = signer.get_public_key()?.as_hex();
@@ -87,68 +128,671 @@ pub fn setup_tp(
         return Ok(Box::new(future::ok(())));
     }
 
-    let tp_name = config.deployment_config().tp_name();
+    // Contracts chosen by a circuit but not configured here have no code to upload
+    let (processors, missing): (Vec<_>, Vec<_>) = processors
+        .into_iter()
+        .partition(|processor| !processor.path.is_empty());
+    for processor in missing {
+        warn!(
+            "Not setting up contract {} {} on circuit {}, it is not configured",
+            processor.name, processor.version, circuit_id
+        );
+    }
+    if processors.is_empty() {
+        return Ok(Box::new(future::ok(())));
+    }
+
+    let mut namespace_owners = scabbard_admin_keys.clone();
+    for key in deployment_config.submitter_keys() {
+        if !namespace_owners.contains(key) {
+            namespace_owners.push(key.clone());
+        }
+    }
+
+    let setup = ContractSetup {
+        client: http::splinterd_client(deployment_config.splinterd_tls())?,
+        authorization: deployment_config.splinterd_authorization()?,
+        private_key: private_key.to_string(),
+        owners: scabbard_admin_keys,
+        namespace_owners,
+        submitter_keys: deployment_config.submitter_keys().to_vec(),
+        processors,
+        deploy_mode,
+        splinterd_url: splinterd_url.to_string(),
+        circuit_id: circuit_id.to_string(),
+        service_id: service_id.to_string(),
+        attempts: deployment_config.contract_deploy_attempts().max(1),
+        timeout: Duration::from_secs(deployment_config.contract_deploy_timeout()),
+        poll_interval: Duration::from_millis(deployment_config.batch_status_poll_interval()),
+        publisher,
+    };
+
+    Ok(Box::new(future::loop_fn(1, move |attempt| {
+        let setup = setup.clone();
+        setup.attempt().then(move |result| -> RetryFuture {
+            let failure = match result {
+                Ok(()) => return Box::new(future::ok(Loop::Break(()))),
+                Err(failure) => failure,
+            };
+            if attempt >= setup.attempts {
+                error!(
+                    "Failed to set up the contracts of circuit {} after {} attempts: {}",
+                    setup.circuit_id, attempt, failure.reason
+                );
+                if let Err(err) = setup.publish_failure(&failure, attempt) {
+                    error!("Failed to publish the failed contract set up: {}", err);
+                }
+                return Box::new(future::ok(Loop::Break(())));
+            }
+            let delay = batch_submit_backoff(attempt - 1);
+            warn!(
+                "Failed to set up the contracts of circuit {}, retrying in {:?}: {}",
+                setup.circuit_id, delay, failure.reason
+            );
+            Box::new(
+                Delay::new(Instant::now() + delay)
+                    .map(move |_| Loop::Continue(attempt + 1))
+                    .map_err(|err| error!("Failed to wait before retrying the set up: {}", err)),
+            )
+        })
+    })))
+}
+
+type SetupFuture = Box<dyn Future<Item = (), Error = SetupFailure> + Send>;
+type LookupFuture =
+    Box<dyn Future<Item = HashMap<String, Vec<u8>>, Error = EventHandlerError> + Send>;
+type RetryFuture = Box<dyn Future<Item = Loop<(), u32>, Error = ()> + Send>;
+type PollFuture = Box<dyn Future<Item = Loop<(), ()>, Error = SetupFailure> + Send>;
+
+/// What an attempt at setting up the contracts of a circuit needs
+#[derive(Clone)]
+struct ContractSetup {
+    client: Client<Connector>,
+    authorization: Option<String>,
+    private_key: String,
+    owners: Vec<String>,
+    /// Owners of the contracts' namespace registries, the owners and the submitter keys
+    namespace_owners: Vec<String>,
+    submitter_keys: Vec<String>,
+    processors: Vec<TransactionProcessor>,
+    deploy_mode: DeployMode,
+    splinterd_url: String,
+    circuit_id: String,
+    service_id: String,
+    attempts: u32,
+    timeout: Duration,
+    poll_interval: Duration,
+    publisher: KafkaPublisher,
+}
+
+/// Why an attempt at setting up the contracts failed
+struct SetupFailure {
+    /// Id of the batch submitted by the attempt, empty if none was accepted
+    batch_id: String,
+    reason: String,
+}
+
+impl SetupFailure {
+    fn new(batch_id: &str, reason: String) -> Self {
+        SetupFailure {
+            batch_id: batch_id.to_string(),
+            reason,
+        }
+    }
+}
+
+impl From<EventHandlerError> for SetupFailure {
+    fn from(err: EventHandlerError) -> Self {
+        SetupFailure::new("", err.to_string())
+    }
+}
+
+impl ContractSetup {
+    /// Looks up the existing pieces, then submits the batch setting up the others and
+    /// waits for it to be committed
+    fn attempt(&self) -> SetupFuture {
+        let existing: LookupFuture = match self.deploy_mode {
+            DeployMode::IfMissing => match self.scabbard_url("state") {
+                Ok(state_url) => match setup_addresses(&self.processors) {
+                    Ok(addresses) => existing_state(
+                        &self.client,
+                        &state_url,
+                        self.authorization.clone(),
+                        addresses,
+                    ),
+                    Err(err) => Box::new(future::err(err)),
+                },
+                Err(err) => Box::new(future::err(err)),
+            },
+            DeployMode::Always | DeployMode::Never => Box::new(future::ok(HashMap::new())),
+        };
+        let setup = self.clone();
+
+        Box::new(
+            existing
+                .and_then(move |existing| {
+                    let batch = setup_batch(
+                        &setup.private_key,
+                        setup.owners.clone(),
+                        &setup.namespace_owners,
+                        &setup.submitter_keys,
+                        &setup.processors,
+                        &existing,
+                    )?;
+                    Ok((setup, batch))
+                })
+                .from_err()
+                .and_then(|(setup, batch)| -> SetupFuture {
+                    match batch {
+                        Some((batch_id, payload)) => setup.submit(batch_id, payload),
+                        None => {
+                            info!(
+                                "The contracts of circuit {} are already set up",
+                                setup.circuit_id
+                            );
+                            Box::new(future::ok(()))
+                        }
+                    }
+                }),
+        )
+    }
+
+    /// Submits the batch to the scabbard service, resubmitting while its queue is full,
+    /// then waits for the batch to be committed
+    fn submit(self, batch_id: String, payload: Vec<u8>) -> SetupFuture {
+        let url = match self.scabbard_url("batches") {
+            Ok(url) => url,
+            Err(err) => return Box::new(future::err(err.into())),
+        };
+        let client = self.client.clone();
+        let authorization = self.authorization.clone();
+
+        Box::new(
+            future::loop_fn(0, move |attempt| {
+                submit_batch_list(&client, &url, authorization.clone(), payload.clone())
+                    .and_then(move |outcome| retry_if_queue_full(outcome, attempt))
+            })
+            .map_err(SetupFailure::from)
+            .and_then(move |link| self.wait_for_commit(batch_id, link)),
+        )
+    }
+
+    /// Polls the status of a submitted batch, following the link returned by scabbard
+    fn wait_for_commit(self, batch_id: String, link: Option<String>) -> SetupFuture {
+        let url = match link {
+            Some(ref link) if link.starts_with('/') => splinterd_link(&self.splinterd_url, link),
+            _ => match self.scabbard_url(&format!("batch_statuses?ids={}", batch_id)) {
+                Ok(url) => url,
+                Err(err) => {
+                    return Box::new(future::err(SetupFailure::new(&batch_id, err.to_string())))
+                }
+            },
+        };
+        let deadline = Instant::now() + self.timeout;
+
+        Box::new(future::loop_fn((), move |_| {
+            let batch_id = batch_id.clone();
+            let poll_interval = self.poll_interval;
+            let timeout = self.timeout;
+            batch_status(&self.client, &url, self.authorization.clone()).then(
+                move |status| -> PollFuture {
+                    let pending = match status {
+                        Ok(BatchStatus::Committed) => return Box::new(future::ok(Loop::Break(()))),
+                        Ok(BatchStatus::Invalid(reason)) => {
+                            return Box::new(future::err(SetupFailure::new(
+                                &batch_id,
+                                format!("The batch is invalid: {}", reason),
+                            )))
+                        }
+                        Ok(BatchStatus::Pending(status)) => status,
+                        // The status is read again until the deadline
+                        Err(err) => err.to_string(),
+                    };
+                    let next_poll = Instant::now() + poll_interval;
+                    if next_poll >= deadline {
+                        return Box::new(future::err(SetupFailure::new(
+                            &batch_id,
+                            format!(
+                                "The batch was not committed within {:?}, last status: {}",
+                                timeout, pending
+                            ),
+                        )));
+                    }
+                    debug!("Batch {} is not committed yet: {}", batch_id, pending);
+                    Box::new(Delay::new(next_poll).map(|_| Loop::Continue(())).map_err(
+                        move |err| {
+                            SetupFailure::new(
+                                &batch_id,
+                                format!("Failed to wait before polling the batch: {}", err),
+                            )
+                        },
+                    ))
+                },
+            )
+        }))
+    }
+
+    fn scabbard_url(&self, path: &str) -> Result<String, EventHandlerError> {
+        scabbard_url(
+            &self.splinterd_url,
+            &self.circuit_id,
+            &self.service_id,
+            path,
+        )
+    }
+
+    fn publish_failure(
+        &self,
+        failure: &SetupFailure,
+        attempts: u32,
+    ) -> Result<(), EventHandlerError> {
+        let mut deploy_failed = ContractDeployFailed::new();
+        deploy_failed.set_circuit_id(self.circuit_id.clone());
+        deploy_failed.set_service_id(self.service_id.clone());
+        deploy_failed.set_contracts(
+            self.processors
+                .iter()
+                .map(|processor| {
+                    let mut contract = SabreContract::new();
+                    contract.set_name(processor.name.clone());
+                    contract.set_version(processor.version.clone());
+                    contract.set_prefix(processor.prefix.clone());
+                    contract
+                })
+                .collect(),
+        );
+        deploy_failed.set_batch_id(failure.batch_id.clone());
+        deploy_failed.set_reason(failure.reason.clone());
+        deploy_failed.set_attempts(attempts);
+        deploy_failed.set_failed_at(now_millis());
+        self.publisher.publish(
+            &self.circuit_id,
+            Message_MessageType::CONTRACT_DEPLOY_FAILED,
+            &deploy_failed,
+        )?;
+        Ok(())
+    }
+}
+
+/// Signs the batch setting up the contracts, leaving out the pieces whose state address
+/// is among `existing`, with the id of the batch. Returns `None` when every piece exists.
+///
+/// Namespace permissions live in the namespace registry entries, so they are granted
+/// whenever their registry or their contract is created. An existing namespace registry
+/// lacking some of the submitter keys among its owners gets them added.
+fn setup_batch(
+    private_key: &str,
+    owners: Vec<String>,
+    namespace_owners: &[String],
+    submitter_keys: &[String],
+    processors: &[TransactionProcessor],
+    existing: &HashMap<String, Vec<u8>>,
+) -> Result<Option<(String, Vec<u8>)>, EventHandlerError> {
+    let context = create_context("secp256k1")?;
+    let factory = CryptoFactory::new(&*context);
+    let private_key = Secp256k1PrivateKey::from_hex(private_key)?;
+    let signer = factory.new_signer(&private_key);
+
     // Create the transactions and batch them
-    let txns = vec![
-        create_contract_registry_txn(scabbard_admin_keys.clone(), &signer, tp_name)?,
-        upload_contract_txn(&signer, config.deployment_config())?,
-        create_tp_namespace_registry_txn(scabbard_admin_keys.clone(), &signer, config.deployment_config())?,
-        tp_namespace_permissions_txn(&signer, config.deployment_config())?,
-        create_pike_namespace_registry_txn(scabbard_admin_keys, &signer)?,
-        pike_namespace_permissions_txn(&signer, config.deployment_config())?,
-    ];
+    let mut txns = vec![];
+    let mut new_contracts = vec![];
+    for processor in processors {
+        let registry_address = compute_contract_registry_address(&processor.name);
+        let contract_address = compute_contract_address(&processor.name, &processor.version);
+        let namespace_address = compute_namespace_registry_address(&processor.prefix)?;
+        if !existing.contains_key(&registry_address) {
+            txns.push(create_contract_registry_txn(
+                owners.clone(),
+                &signer,
+                &processor.name,
+            )?);
+        }
+        let contract_missing = !existing.contains_key(&contract_address);
+        if contract_missing {
+            txns.push(upload_contract_txn(&signer, processor)?);
+            new_contracts.push(processor);
+        }
+        let registry_owners = match existing.get(&namespace_address) {
+            Some(value) => namespace_registry_owners(value, &processor.prefix)?,
+            None => None,
+        };
+        let namespace_missing = registry_owners.is_none();
+        match registry_owners {
+            None => txns.push(create_tp_namespace_registry_txn(
+                namespace_owners.to_vec(),
+                &signer,
+                processor,
+            )?),
+            Some(mut registry_owners) => {
+                let missing = submitter_keys
+                    .iter()
+                    .filter(|key| !registry_owners.contains(key))
+                    .cloned()
+                    .collect::<Vec<_>>();
+                if !missing.is_empty() {
+                    registry_owners.extend(missing);
+                    txns.push(update_tp_namespace_registry_owners_txn(
+                        registry_owners,
+                        &signer,
+                        processor,
+                    )?);
+                }
+            }
+        }
+        if contract_missing || namespace_missing {
+            txns.push(tp_namespace_permissions_txn(&signer, processor)?);
+        }
+    }
+    let pike_missing = !existing.contains_key(&compute_namespace_registry_address(PIKE_PREFIX)?);
+    if pike_missing {
+        txns.push(create_pike_namespace_registry_txn(owners, &signer)?);
+    }
+    for processor in processors {
+        if pike_missing || new_contracts.contains(&processor) {
+            txns.push(pike_namespace_permissions_txn(&signer, processor)?);
+        }
+    }
+    if txns.is_empty() {
+        return Ok(None);
+    }
+
     let batch = create_batch(txns, &signer)?;
+    let batch_id = batch.get_header_signature().to_string();
     let batch_list = create_batch_list_from_one(batch);
     let payload = batch_list.write_to_bytes().map_err(|err| {
         EventHandlerError::SawtoothError(format!("failed to serialize batch list: {}", err))
     })?;
-    // Submit the batch to the scabbard service
-    let body_stream = futures::stream::once::<_, std::io::Error>(Ok(payload));
-    let req = Request::builder()
-        .uri(format!(
-            "{}/scabbard/{}/{}/batches",
-            splinterd_url, circuit_id, service_id
-        ))
-        .method("POST")
-        .body(Body::wrap_stream(body_stream))
-        .map_err(|err| EventHandlerError::BatchSubmitError(format!("{}", err)))?;
+    Ok(Some((batch_id, payload)))
+}
+
+/// Returns the state addresses written by the set up of the contracts
+fn setup_addresses(processors: &[TransactionProcessor]) -> Result<Vec<String>, EventHandlerError> {
+    let mut addresses = vec![compute_namespace_registry_address(PIKE_PREFIX)?];
+    for processor in processors {
+        addresses.push(compute_contract_registry_address(&processor.name));
+        addresses.push(compute_contract_address(&processor.name, &processor.version));
+        addresses.push(compute_namespace_registry_address(&processor.prefix)?);
+    }
+    Ok(addresses)
+}
 
-    let client = Client::new();
+/// Returns the addresses which are set in a scabbard service's state, with their values
+fn existing_state(
+    client: &Client<Connector>,
+    state_url: &str,
+    authorization: Option<String>,
+    addresses: Vec<String>,
+) -> LookupFuture {
+    let lookups = addresses
+        .into_iter()
+        .map(|address| {
+            let url = format!("{}/{}", state_url, address);
+            state_value(client, &url, authorization.clone()).map(move |value| (address, value))
+        })
+        .collect::<Vec<_>>();
+    Box::new(future::join_all(lookups).map(|lookups| {
+        lookups
+            .into_iter()
+            .filter_map(|(address, value)| Some((address, value?)))
+            .collect()
+    }))
+}
+
+/// Returns the owners of the namespace registry of a prefix, read from the value of its
+/// address, or `None` if it does not hold that registry
+fn namespace_registry_owners(
+    value: &[u8],
+    prefix: &str,
+) -> Result<Option<Vec<String>>, EventHandlerError> {
+    let registries = NamespaceRegistryList::from_bytes(value)?;
+    Ok(registries
+        .registries()
+        .iter()
+        .find(|registry| registry.namespace() == prefix)
+        .map(|registry| registry.owners().to_vec()))
+}
 
-    Ok(Box::new(
+/// Reads the value at a state address, `None` if it is not set. Scabbard returns the
+/// value as a JSON array of bytes.
+fn state_value(
+    client: &Client<Connector>,
+    url: &str,
+    authorization: Option<String>,
+) -> Box<dyn Future<Item = Option<Vec<u8>>, Error = EventHandlerError> + Send> {
+    let mut builder = Request::builder();
+    builder.uri(url).method("GET");
+    if let Some(authorization) = authorization {
+        builder.header(AUTHORIZATION, authorization);
+    }
+    let req = match builder.body(Body::empty()) {
+        Ok(req) => req,
+        Err(err) => {
+            return Box::new(future::err(EventHandlerError::SabreError(format!(
+                "Failed to build state request: {}",
+                err
+            ))))
+        }
+    };
+
+    Box::new(
         client
             .request(req)
+            .and_then(|res| {
+                let status = res.status();
+                res.into_body().concat2().map(move |body| (status, body))
+            })
             .then(|response| match response {
-                Ok(res) => {
-                    let status = res.status();
-                    let body = res
-                        .into_body()
-                        .concat2()
-                        .wait()
-                        .map_err(|err| {
-                            EventHandlerError::BatchSubmitError(format!(
-                                "The client encountered an error {}",
-                                err
-                            ))
-                        })?
-                        .to_vec();
-
-                    match status {
-                        StatusCode::ACCEPTED => Ok(()),
-                        _ => Err(EventHandlerError::BatchSubmitError(format!(
-                            "The server returned an error. Status: {}, {}",
-                            status,
-                            String::from_utf8(body)?
-                        ))),
-                    }
-                }
-                Err(err) => Err(EventHandlerError::BatchSubmitError(format!(
-                    "The client encountered an error {}",
+                Ok((StatusCode::OK, body)) => serde_json::from_slice::<Vec<u8>>(&body)
+                    .map(Some)
+                    .map_err(|err| {
+                        EventHandlerError::SabreError(format!(
+                            "Failed to parse the Sabre state: {}",
+                            err
+                        ))
+                    }),
+                Ok((StatusCode::NOT_FOUND, _)) => Ok(None),
+                Ok((status, _)) => Err(EventHandlerError::SabreError(format!(
+                    "Failed to read the Sabre state. Status: {}",
+                    status
+                ))),
+                Err(err) => Err(EventHandlerError::SabreError(format!(
+                    "Failed to read the Sabre state: {}",
                     err
                 ))),
+            }),
+    )
+}
+
+type SubmitLoopFuture =
+    Box<dyn Future<Item = Loop<Option<String>, u32>, Error = EventHandlerError> + Send>;
+
+/// Schedules a resubmission with backoff while the scabbard batch queue is full
+fn retry_if_queue_full(outcome: SubmitOutcome, attempt: u32) -> SubmitLoopFuture {
+    match outcome {
+        SubmitOutcome::Accepted(link) => Box::new(future::ok(Loop::Break(link))),
+        SubmitOutcome::QueueFull(status) if attempt < BATCH_SUBMIT_RETRY_LIMIT => {
+            let delay = batch_submit_backoff(attempt);
+            warn!(
+                "Scabbard batch queue is full ({}), resubmitting in {:?}",
+                status, delay
+            );
+            Box::new(
+                Delay::new(Instant::now() + delay)
+                    .map(move |_| Loop::Continue(attempt + 1))
+                    .map_err(|err| {
+                        EventHandlerError::BatchSubmitError(format!(
+                            "Failed to wait before resubmitting: {}",
+                            err
+                        ))
+                    }),
+            )
+        }
+        SubmitOutcome::QueueFull(status) => Box::new(future::err(
+            EventHandlerError::BatchSubmitError(format!(
+                "The scabbard batch queue is still full after {} attempts. Status: {}",
+                attempt + 1,
+                status
+            )),
+        )),
+    }
+}
+
+/// Result of a batch submission which did not fail outright
+enum SubmitOutcome {
+    /// The batch was queued, with the link to its status if scabbard returned one
+    Accepted(Option<String>),
+    /// The scabbard batch queue is full, the submission may be retried later
+    QueueFull(StatusCode),
+}
+
+fn submit_batch_list(
+    client: &Client<Connector>,
+    url: &str,
+    authorization: Option<String>,
+    payload: Vec<u8>,
+) -> Box<dyn Future<Item = SubmitOutcome, Error = EventHandlerError> + Send> {
+    let body_stream = futures::stream::once::<_, std::io::Error>(Ok(payload));
+    let mut builder = Request::builder();
+    builder.uri(url).method("POST");
+    if let Some(authorization) = authorization {
+        builder.header(AUTHORIZATION, authorization);
+    }
+    let req = match builder.body(Body::wrap_stream(body_stream)) {
+        Ok(req) => req,
+        Err(err) => {
+            return Box::new(future::err(EventHandlerError::BatchSubmitError(format!(
+                "{}",
+                err
+            ))))
+        }
+    };
+
+    Box::new(client.request(req).then(|response| match response {
+        Ok(res) => {
+            let status = res.status();
+            let body = res
+                .into_body()
+                .concat2()
+                .wait()
+                .map_err(|err| {
+                    EventHandlerError::BatchSubmitError(format!(
+                        "The client encountered an error {}",
+                        err
+                    ))
+                })?
+                .to_vec();
+
+            match status {
+                StatusCode::ACCEPTED => Ok(SubmitOutcome::Accepted(
+                    serde_json::from_slice::<serde_json::Value>(&body)
+                        .ok()
+                        .and_then(|response| response["link"].as_str().map(ToOwned::to_owned)),
+                )),
+                StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE => {
+                    Ok(SubmitOutcome::QueueFull(status))
+                }
+                _ => Err(EventHandlerError::BatchSubmitError(format!(
+                    "The server returned an error. Status: {}, {}",
+                    status,
+                    String::from_utf8(body)?
+                ))),
+            }
+        }
+        Err(err) => Err(EventHandlerError::BatchSubmitError(format!(
+            "The client encountered an error {}",
+            err
+        ))),
+    }))
+}
+
+/// Status of a submitted batch, as reported by scabbard
+enum BatchStatus {
+    Committed,
+    /// The batch was rejected, with the errors of its transactions
+    Invalid(String),
+    /// The batch is not committed yet, with its status
+    Pending(String),
+}
+
+fn batch_status(
+    client: &Client<Connector>,
+    url: &str,
+    authorization: Option<String>,
+) -> Box<dyn Future<Item = BatchStatus, Error = EventHandlerError> + Send> {
+    let mut builder = Request::builder();
+    builder.uri(url).method("GET");
+    if let Some(authorization) = authorization {
+        builder.header(AUTHORIZATION, authorization);
+    }
+    let req = match builder.body(Body::empty()) {
+        Ok(req) => req,
+        Err(err) => {
+            return Box::new(future::err(EventHandlerError::BatchSubmitError(format!(
+                "Failed to build batch status request: {}",
+                err
+            ))))
+        }
+    };
+
+    Box::new(
+        client
+            .request(req)
+            .and_then(|res| {
+                let status = res.status();
+                res.into_body().concat2().map(move |body| (status, body))
             })
-            .map_err(|_| ()),
-    ))
+            .then(|response| {
+                let (status, body) = response.map_err(|err| {
+                    EventHandlerError::BatchSubmitError(format!(
+                        "Failed to read the batch status: {}",
+                        err
+                    ))
+                })?;
+                if status != StatusCode::OK {
+                    return Err(EventHandlerError::BatchSubmitError(format!(
+                        "Failed to read the batch status. Status: {}",
+                        status
+                    )));
+                }
+                let statuses: serde_json::Value = serde_json::from_slice(&body).map_err(|err| {
+                    EventHandlerError::BatchSubmitError(format!(
+                        "Failed to parse the batch status: {}",
+                        err
+                    ))
+                })?;
+                let batch_status = &statuses[0]["status"];
+                match batch_status["statusType"].as_str() {
+                    Some("Committed") => Ok(BatchStatus::Committed),
+                    Some("Invalid") => {
+                        let errors = batch_status["message"]
+                            .as_array()
+                            .map(|messages| {
+                                messages
+                                    .iter()
+                                    .filter_map(|message| message["error_message"].as_str())
+                                    .collect::<Vec<_>>()
+                                    .join(", ")
+                            })
+                            .unwrap_or_default();
+                        Ok(BatchStatus::Invalid(errors))
+                    }
+                    Some(status) => Ok(BatchStatus::Pending(status.to_string())),
+                    None => Err(EventHandlerError::BatchSubmitError(
+                        "The batch status is missing from the response".to_string(),
+                    )),
+                }
+            }),
+    )
+}
+
+/// Exponential backoff with up to a second of random jitter, so that several nodes
+/// retrying against the same scabbard service do not resubmit in lockstep
+fn batch_submit_backoff(attempt: u32) -> Duration {
+    let backoff = BATCH_SUBMIT_BASE_BACKOFF
+        .checked_mul(2u32.saturating_pow(attempt))
+        .unwrap_or(BATCH_SUBMIT_MAX_BACKOFF)
+        .min(BATCH_SUBMIT_MAX_BACKOFF);
+    backoff + Duration::from_millis(rand::thread_rng().gen_range(0, 1000))
 }
 
 fn create_contract_registry_txn(
@@ -172,8 +816,11 @@ fn create_contract_registry_txn(
     create_txn(addresses, payload, signer)
 }
 
-fn upload_contract_txn(signer: &Signer, deploymentConfig: &DeploymentConfig) -> Result<Transaction, EventHandlerError> {
-    let contract_path = Path::new(deploymentConfig.tp_path());
+fn upload_contract_txn(
+    signer: &Signer,
+    processor: &TransactionProcessor,
+) -> Result<Transaction, EventHandlerError> {
+    let contract_path = Path::new(&processor.path);
     let contract_file = File::open(contract_path).map_err(|err| {
         EventHandlerError::SabreError(format!("Failed to load contract: {}", err))
     })?;
@@ -186,11 +833,11 @@ fn upload_contract_txn(signer: &Signer, deploymentConfig: &DeploymentConfig) ->
     let action_addresses = vec![
         SMART_PERMISSION_PREFIX.into(),
         PIKE_PREFIX.into(),
-        deploymentConfig.tp_prefix().to_string(),
+        processor.prefix.clone(),
     ];
     let action = CreateContractActionBuilder::new()
-        .with_name(deploymentConfig.tp_name().to_string())
-        .with_version(deploymentConfig.tp_version().to_string())
+        .with_name(processor.name.clone())
+        .with_version(processor.version.clone())
         .with_inputs(action_addresses.clone())
         .with_outputs(action_addresses)
         .with_contract(contract)
@@ -200,8 +847,8 @@ fn upload_contract_txn(signer: &Signer, deploymentConfig: &DeploymentConfig) ->
         .build()?
         .into_bytes()?;
     let addresses = vec![
-        compute_contract_registry_address(deploymentConfig.tp_name()),
-        compute_contract_address(deploymentConfig.tp_name(), deploymentConfig.tp_version()),
+        compute_contract_registry_address(&processor.name),
+        compute_contract_address(&processor.name, &processor.version),
     ];
 
     create_txn(addresses, payload, signer)
@@ -210,10 +857,10 @@ fn upload_contract_txn(signer: &Signer, deploymentConfig: &DeploymentConfig) ->
 fn create_tp_namespace_registry_txn(
     owners: Vec<String>,
     signer: &Signer,
-    deploymentConfig: &DeploymentConfig,
+    processor: &TransactionProcessor,
 ) -> Result<Transaction, EventHandlerError> {
     let action = CreateNamespaceRegistryActionBuilder::new()
-        .with_namespace(deploymentConfig.tp_prefix().to_string())
+        .with_namespace(processor.prefix.clone())
         .with_owners(owners)
         .build()?;
     let payload = SabrePayloadBuilder::new()
@@ -221,17 +868,42 @@ fn create_tp_namespace_registry_txn(
         .build()?
         .into_bytes()?;
     let addresses = vec![
-        compute_namespace_registry_address(deploymentConfig.tp_prefix())?,
+        compute_namespace_registry_address(&processor.prefix)?,
         ADMINISTRATORS_SETTING_ADDRESS.into(),
     ];
 
     create_txn(addresses, payload, signer)
 }
 
-fn tp_namespace_permissions_txn(signer: &Signer, deploymentConfig: &DeploymentConfig) -> Result<Transaction, EventHandlerError> {
+/// Replaces the owners of an existing namespace registry
+fn update_tp_namespace_registry_owners_txn(
+    owners: Vec<String>,
+    signer: &Signer,
+    processor: &TransactionProcessor,
+) -> Result<Transaction, EventHandlerError> {
+    let action = UpdateNamespaceRegistryOwnersActionBuilder::new()
+        .with_namespace(processor.prefix.clone())
+        .with_owners(owners)
+        .build()?;
+    let payload = SabrePayloadBuilder::new()
+        .with_action(Action::UpdateNamespaceRegistryOwners(action))
+        .build()?
+        .into_bytes()?;
+    let addresses = vec![
+        compute_namespace_registry_address(&processor.prefix)?,
+        ADMINISTRATORS_SETTING_ADDRESS.into(),
+    ];
+
+    create_txn(addresses, payload, signer)
+}
+
+fn tp_namespace_permissions_txn(
+    signer: &Signer,
+    processor: &TransactionProcessor,
+) -> Result<Transaction, EventHandlerError> {
     let action = CreateNamespaceRegistryPermissionActionBuilder::new()
-        .with_namespace(deploymentConfig.tp_prefix().to_string())
-        .with_contract_name(deploymentConfig.tp_name().to_string())
+        .with_namespace(processor.prefix.clone())
+        .with_contract_name(processor.name.clone())
         .with_read(true)
         .with_write(true)
         .build()?;
@@ -240,7 +912,7 @@ fn tp_namespace_permissions_txn(signer: &Signer, deploymentConfig: &DeploymentCo
         .build()?
         .into_bytes()?;
     let addresses = vec![
-        compute_namespace_registry_address(deploymentConfig.tp_prefix())?,
+        compute_namespace_registry_address(&processor.prefix)?,
         ADMINISTRATORS_SETTING_ADDRESS.into(),
     ];
 
@@ -267,10 +939,13 @@ fn create_pike_namespace_registry_txn(
     create_txn(addresses, payload, signer)
 }
 
-fn pike_namespace_permissions_txn(signer: &Signer, deploymentConfig: &DeploymentConfig) -> Result<Transaction, EventHandlerError> {
+fn pike_namespace_permissions_txn(
+    signer: &Signer,
+    processor: &TransactionProcessor,
+) -> Result<Transaction, EventHandlerError> {
     let action = CreateNamespaceRegistryPermissionActionBuilder::new()
         .with_namespace(PIKE_PREFIX.into())
-        .with_contract_name(deploymentConfig.tp_name().to_string())
+        .with_contract_name(processor.name.clone())
         .with_read(true)
         .with_write(false)
         .build()?;
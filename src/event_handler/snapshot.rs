@@ -0,0 +1,383 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use std::collections::BTreeSet;
+use std::mem;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+use futures::{Future, Stream};
+use hyper::StatusCode;
+use serde_json::Value;
+use tokio::runtime::Runtime;
+
+use crate::config::{DeploymentConfig, EventListenerConfig};
+use crate::http;
+use crate::proto::pubsub::{CircuitPayload, CircuitPayload_Origin, Control, Message_MessageType};
+use crate::publisher::KafkaPublisher;
+use crate::status::Status;
+use crate::time::now_millis;
+
+use super::decoder::PayloadDecoders;
+use super::endpoint::scabbard_url;
+use super::registry::{ScabbardSubscription, SubscriptionRegistry};
+use super::EventHandlerError;
+
+#[derive(Default)]
+struct Requests {
+    pending: bool,
+    /// Prefixes of the resumed namespaces to catch up on
+    namespaces: BTreeSet<String>,
+    stopped: bool,
+}
+
+/// What a snapshot covers
+enum Scope {
+    Full,
+    Namespaces(BTreeSet<String>),
+}
+
+/// Requests operator-initiated snapshots, from the REST API or a signal handler, and the
+/// snapshots of resumed namespaces.
+///
+/// Requests made while a snapshot is pending are merged into it, a full snapshot covering
+/// the pending namespaces.
+#[derive(Clone, Default)]
+pub struct SnapshotTrigger {
+    requests: Arc<(Mutex<Requests>, Condvar)>,
+}
+
+impl SnapshotTrigger {
+    pub fn new() -> Self {
+        SnapshotTrigger::default()
+    }
+
+    /// Requests a snapshot, returns false if the snapshot worker has stopped
+    pub fn request(&self) -> bool {
+        self.submit(|requests| requests.pending = true)
+    }
+
+    /// Requests a snapshot of a namespace, returns false if the snapshot worker has stopped
+    pub fn catch_up(&self, prefix: &str) -> bool {
+        self.submit(|requests| {
+            requests.namespaces.insert(prefix.to_string());
+        })
+    }
+
+    fn submit<F: FnOnce(&mut Requests)>(&self, f: F) -> bool {
+        let (ref lock, ref condvar) = *self.requests;
+        match lock.lock() {
+            Ok(mut requests) if !requests.stopped => {
+                f(&mut requests);
+                condvar.notify_one();
+                true
+            }
+            Ok(_) => false,
+            Err(err) => {
+                error!("Unable to request snapshot: {}", err);
+                false
+            }
+        }
+    }
+
+    fn stop(&self) {
+        let (ref lock, ref condvar) = *self.requests;
+        if let Ok(mut requests) = lock.lock() {
+            requests.stopped = true;
+            condvar.notify_one();
+        }
+    }
+
+    /// Waits for requests, returns the scope of the snapshot to take or `None` once stopped
+    fn wait(&self) -> Option<Scope> {
+        let (ref lock, ref condvar) = *self.requests;
+        let mut requests = match lock.lock() {
+            Ok(requests) => requests,
+            Err(_) => return None,
+        };
+        while !requests.pending && requests.namespaces.is_empty() && !requests.stopped {
+            requests = match condvar.wait(requests) {
+                Ok(requests) => requests,
+                Err(_) => return None,
+            };
+        }
+        if requests.stopped {
+            return None;
+        }
+        let namespaces = mem::replace(&mut requests.namespaces, BTreeSet::new());
+        if requests.pending {
+            requests.pending = false;
+            Some(Scope::Full)
+        } else {
+            Some(Scope::Namespaces(namespaces))
+        }
+    }
+}
+
+/// Exports the current contract state of every subscribed circuit.
+///
+/// Each circuit's snapshot is published as `CircuitPayload` messages with the
+/// `OPERATOR_SNAPSHOT` origin, between `snapshot-begin` and `snapshot-end` control
+/// messages naming the circuit.
+#[derive(Clone)]
+pub struct Snapshotter {
+    config: EventListenerConfig,
+    publisher: KafkaPublisher,
+    registry: SubscriptionRegistry,
+    status: Status,
+    decoders: PayloadDecoders,
+}
+
+/// Runs the snapshots requested through a `SnapshotTrigger`
+pub struct SnapshotWorker {
+    trigger: SnapshotTrigger,
+    join_handle: thread::JoinHandle<()>,
+}
+
+impl SnapshotWorker {
+    pub fn shutdown(self) {
+        self.trigger.stop();
+        if self.join_handle.join().is_err() {
+            error!("Snapshot thread panicked");
+        }
+    }
+}
+
+impl Snapshotter {
+    pub(super) fn new(
+        config: EventListenerConfig,
+        publisher: KafkaPublisher,
+        registry: SubscriptionRegistry,
+        status: Status,
+        decoders: PayloadDecoders,
+    ) -> Self {
+        Snapshotter {
+            config,
+            publisher,
+            registry,
+            status,
+            decoders,
+        }
+    }
+
+    pub fn start(self, trigger: SnapshotTrigger) -> Result<SnapshotWorker, EventHandlerError> {
+        let thread_trigger = trigger.clone();
+        let join_handle = thread::Builder::new()
+            .name("Snapshot".into())
+            .spawn(move || {
+                while let Some(scope) = thread_trigger.wait() {
+                    match scope {
+                        Scope::Full => self.export(),
+                        Scope::Namespaces(namespaces) => {
+                            for prefix in namespaces {
+                                self.export_namespace(&prefix);
+                            }
+                        }
+                    }
+                }
+            })?;
+        Ok(SnapshotWorker {
+            trigger,
+            join_handle,
+        })
+    }
+
+    /// Exports a snapshot of each subscribed circuit, a failing circuit does not stop
+    /// the others
+    pub fn export(&self) {
+        let subscriptions = self.registry.active(self.config.deployment_config());
+        info!(
+            "Exporting an operator snapshot of {} circuits",
+            subscriptions.len()
+        );
+        for subscription in subscriptions {
+            match self.export_circuit(&subscription, None) {
+                Ok(entries) => info!(
+                    "Exported a snapshot of {} entries for circuit {}",
+                    entries, subscription.circuit_id
+                ),
+                Err(err) => {
+                    error!(
+                        "Unable to export a snapshot of circuit {}: {}",
+                        subscription.circuit_id, err
+                    );
+                    self.status
+                        .circuit_error(&subscription.circuit_id, &err.to_string());
+                }
+            }
+        }
+    }
+
+    /// Exports a snapshot of a resumed namespace from each subscribed circuit with a
+    /// contract under its prefix
+    fn export_namespace(&self, prefix: &str) {
+        for subscription in self.registry.active(self.config.deployment_config()) {
+            let has_namespace = self
+                .config
+                .deployment_config()
+                .circuit_contracts(subscription.contract.as_ref())
+                .iter()
+                .any(|processor| processor.prefix == prefix);
+            if !has_namespace {
+                continue;
+            }
+            match self.export_circuit(&subscription, Some(prefix)) {
+                Ok(entries) => info!(
+                    "Caught up on {} entries of namespace {} for circuit {}",
+                    entries, prefix, subscription.circuit_id
+                ),
+                Err(err) => {
+                    error!(
+                        "Unable to catch up on namespace {} of circuit {}: {}",
+                        prefix, subscription.circuit_id, err
+                    );
+                    self.status
+                        .circuit_error(&subscription.circuit_id, &err.to_string());
+                }
+            }
+        }
+    }
+
+    /// Exports the state of a circuit's contracts, or of the one under `namespace`
+    fn export_circuit(
+        &self,
+        subscription: &ScabbardSubscription,
+        namespace: Option<&str>,
+    ) -> Result<usize, EventHandlerError> {
+        let circuit_id = &subscription.circuit_id;
+        let mut states = vec![];
+        let read_at = now_millis();
+        let processors = self
+            .config
+            .deployment_config()
+            .circuit_contracts(subscription.contract.as_ref())
+            .into_iter()
+            .filter(|processor| namespace.map_or(true, |prefix| processor.prefix == prefix));
+        for processor in processors {
+            let entries = fetch_state(
+                self.config.splinterd_url(),
+                self.config.deployment_config(),
+                circuit_id,
+                &subscription.service_id,
+                &processor.prefix,
+            )?;
+            states.push((processor, entries));
+        }
+
+        self.publisher.wait_for_backlog();
+        self.publish_control(circuit_id, "snapshot-begin")?;
+        let mut exported = 0;
+        for (processor, entries) in states {
+            // The contract's own entry at the prefix is not contract state
+            let entries = entries
+                .into_iter()
+                .filter(|(address, _)| address != &processor.prefix);
+            for (address, value) in entries {
+                self.publisher.mirror_state(circuit_id, &address, Some(&value[..]))?;
+                let mut circuit_payload = CircuitPayload::new();
+                circuit_payload.set_requester(subscription.requester.clone());
+                circuit_payload.set_requester_node_id(subscription.requester_node_id.clone());
+                circuit_payload.set_circuit_id(circuit_id.clone());
+                circuit_payload.set_service_id(subscription.service_id.clone());
+                circuit_payload.set_received_at(read_at);
+                circuit_payload.set_key(address);
+                circuit_payload.set_contract_name(processor.name.clone());
+                self.decoders.decode(&mut circuit_payload, &value);
+                circuit_payload.set_data(value);
+                circuit_payload.set_origin(CircuitPayload_Origin::OPERATOR_SNAPSHOT);
+                self.publisher.publish(
+                    circuit_id,
+                    Message_MessageType::CIRCUIT_PAYLOAD,
+                    &circuit_payload,
+                )?;
+                exported += 1;
+            }
+        }
+        self.publish_control(circuit_id, "snapshot-end")?;
+        Ok(exported)
+    }
+
+    fn publish_control(&self, circuit_id: &str, command: &str) -> Result<(), EventHandlerError> {
+        let issued_at = now_millis();
+        let mut control = Control::new();
+        control.set_command(command.to_string());
+        control.set_detail(circuit_id.to_string());
+        control.set_effective_at(issued_at);
+        control.set_issued_at(issued_at);
+        self.publisher
+            .publish(circuit_id, Message_MessageType::CONTROL, &control)
+            .map_err(EventHandlerError::from)
+    }
+}
+
+/// Reads the state entries under the prefix from a scabbard service
+fn fetch_state(
+    splinterd_url: &str,
+    deployment_config: &DeploymentConfig,
+    circuit_id: &str,
+    service_id: &str,
+    prefix: &str,
+) -> Result<Vec<(String, Vec<u8>)>, EventHandlerError> {
+    let mut runtime = Runtime::new()?;
+    let url = format!(
+        "{}?prefix={}",
+        scabbard_url(splinterd_url, circuit_id, service_id, "state")?,
+        prefix
+    );
+    let request = http::splinterd_get(&url, deployment_config.splinterd_authorization()?)
+        .map_err(|err| EventHandlerError::InvalidMessageError(err.to_string()))?;
+
+    let body = runtime.block_on(
+        http::splinterd_client(deployment_config.splinterd_tls())?
+            .request(request)
+            .map_err(|err| EventHandlerError::InvalidMessageError(err.to_string()))
+            .and_then(|resp| {
+                let status = resp.status();
+                resp.into_body()
+                    .concat2()
+                    .map_err(|err| EventHandlerError::InvalidMessageError(err.to_string()))
+                    .and_then(move |body| {
+                        if status == StatusCode::OK {
+                            Ok(body.to_vec())
+                        } else {
+                            Err(EventHandlerError::InvalidMessageError(format!(
+                                "scabbard responded with status {} reading state",
+                                status
+                            )))
+                        }
+                    })
+            }),
+    )?;
+
+    let entries: Value = serde_json::from_slice(&body)?;
+    Ok(entries
+        .as_array()
+        .cloned()
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|entry| {
+            let address = entry.get("address")?.as_str()?.to_string();
+            let value = entry
+                .get("value")?
+                .as_array()?
+                .iter()
+                .map(|byte| byte.as_u64().map(|byte| byte as u8))
+                .collect::<Option<Vec<_>>>()?;
+            Some((address, value))
+        })
+        .collect())
+}
@@ -16,124 +16,409 @@
  * -----------------------------------------------------------------------------
  */
 
-use std::{error::Error, fmt, time::SystemTime};
+use std::{error::Error, fmt, time::{Duration, SystemTime}};
+use protobuf::{Message, RepeatedField};
 use splinter::service::scabbard::StateChangeEvent;
-use crate::config::EventListenerConfig;
-use kafka::producer::{Producer, RequiredAcks, Record};
-use crate::proto::pubsub::{Message, Message_MessageType, CircuitCreated, CircuitPayload};
-use protobuf::Message as Msg;
-use std::time::Duration;
+use crate::config::{EventListenerConfig, EventParsing, TransactionProcessor};
+use crate::proto::pubsub::{
+    CircuitCreated, CircuitMember, CircuitPayload, CircuitPayloadDeleted, CircuitService,
+    Message_MessageType, ServiceArgument,
+};
+use crate::database::DatabaseSink;
+use crate::publisher::KafkaPublisher;
+use crate::time::now_millis;
+
+use super::decoder::PayloadDecoders;
+use super::dedup::Deduplicator;
+use super::delta::DeltaEncoder;
+use super::filter::StateFilter;
+use super::pause::NamespacePauses;
+use super::registry::{CircuitMember as RegisteredMember, RosterService, ScabbardSubscription};
+use super::watchdog::{PublishWatchdog, StateMessage};
+
+/// What is exported when a contract sets a key to an empty or whitespace value, which
+/// some contracts write as markers
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum EmptyValuePolicy {
+    /// The value, as any other
+    Export,
+    /// A deletion of the key
+    Delete,
+    /// Nothing
+    Skip,
+}
+
+impl Default for EmptyValuePolicy {
+    fn default() -> Self {
+        EmptyValuePolicy::Export
+    }
+}
 
 pub struct SabreProcessor {
     circuit_id: String,
+    service_id: String,
     node_id: String,
     requester: String,
+    services: Vec<CircuitService>,
+    members: Vec<CircuitMember>,
+    /// The circuit is announced when the first contract's entry at its prefix is set
     contract_address: String,
+    processors: Vec<TransactionProcessor>,
     config: EventListenerConfig,
+    publisher: KafkaPublisher,
+    database: Option<DatabaseSink>,
+    delta_encoder: DeltaEncoder,
+    decoders: PayloadDecoders,
+    filter: StateFilter,
+    watchdog: PublishWatchdog,
+    dedup: Option<Deduplicator>,
+    pauses: NamespacePauses,
 }
 
 impl SabreProcessor {
-    pub fn new(circuit_id: &str, node_id: &str, requester: &str, config: EventListenerConfig) -> Self {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        subscription: &ScabbardSubscription,
+        config: EventListenerConfig,
+        publisher: KafkaPublisher,
+        database: Option<DatabaseSink>,
+        dedup: Option<Deduplicator>,
+        delta_encoder: DeltaEncoder,
+        decoders: PayloadDecoders,
+        pauses: NamespacePauses,
+    ) -> Self {
+        let processors = config
+            .deployment_config()
+            .circuit_contracts(subscription.contract.as_ref());
         SabreProcessor {
-            circuit_id: circuit_id.into(),
-            node_id: node_id.to_string(),
-            requester: requester.to_string(),
-            contract_address: config.deployment_config().tp_prefix().to_string(),
+            circuit_id: subscription.circuit_id.clone(),
+            service_id: subscription.service_id.clone(),
+            node_id: subscription.requester_node_id.clone(),
+            requester: subscription.requester.clone(),
+            services: subscription.services.iter().map(circuit_service).collect(),
+            members: subscription.members.iter().map(circuit_member).collect(),
+            contract_address: processors
+                .first()
+                .map(|processor| processor.prefix.clone())
+                .unwrap_or_default(),
+            processors,
+            delta_encoder,
+            decoders,
+            filter: StateFilter::new(config.deployment_config().filters()),
+            watchdog: PublishWatchdog::new(
+                &subscription.circuit_id,
+                publisher.clone(),
+                Duration::from_secs(config.deployment_config().state_change_timeout()),
+            ),
             config,
+            publisher,
+            database,
+            dedup,
+            pauses,
         }
     }
 
-    pub fn handle_state_changes(
-        &self,
-        changes: Vec<StateChangeEvent>,
-    ) -> Result<(), StateDeltaError> {
-        changes
-            .iter()
-            .try_for_each(|change| self.handle_state_change(change))
+    /// Exports a batch of state changes, returning the errors met. In the strict parsing
+    /// mode the batch stops at the first change which cannot be parsed; failures to
+    /// publish are returned in both modes without stopping it.
+    pub fn handle_state_changes(&self, changes: Vec<StateChangeEvent>) -> Vec<StateDeltaError> {
+        self.publisher.wait_for_backlog();
+        let strict = self.config.deployment_config().event_parsing() == EventParsing::Strict;
+        let mut errors = vec![];
+        for change in &changes {
+            if let Err(err) = self.handle_state_change(change) {
+                let stop = strict && err.is_parse_error();
+                errors.push(err);
+                if stop {
+                    break;
+                }
+            }
+        }
+        errors
     }
 
     fn handle_state_change(&self, change: &StateChangeEvent) -> Result<(), StateDeltaError> {
-
-        let mut producer =
-            match Producer::from_hosts(vec!(self.config.deployment_config().kafka_url().to_string()))
-                .with_ack_timeout(Duration::from_secs(5))
-                .with_required_acks(RequiredAcks::One)
-                .create() {
-                Ok(created) => created,
-                Err(err) => return Err(StateDeltaError::SDError(err.to_string())),
-            };
         debug!("Received state change: {}", change);
-        let topic = self.config.deployment_config().kafka_topic().to_string();
+        if !self.is_exported(change) {
+            debug!("Filtering out state change: {}", change);
+            return Ok(());
+        }
+        if self.is_paused(change) {
+            debug!("Dropping state change of a paused namespace: {}", change);
+            return Ok(());
+        }
+        if let StateChangeEvent::Set { key, value } = change {
+            let is_blank = value.iter().all(u8::is_ascii_whitespace);
+            if is_blank && self.processor(key).is_some() {
+                match self.config.deployment_config().empty_values() {
+                    EmptyValuePolicy::Export => (),
+                    EmptyValuePolicy::Delete => {
+                        debug!("Exporting the empty value of {} as a deletion", key);
+                        let key = key.clone();
+                        return self.handle_state_change(&StateChangeEvent::Delete { key });
+                    }
+                    EmptyValuePolicy::Skip => {
+                        debug!("Skipping the empty value of {}", key);
+                        return Ok(());
+                    }
+                }
+            }
+        }
+        if let Some(ref dedup) = self.dedup {
+            match change {
+                StateChangeEvent::Set { key, value }
+                    if dedup.is_duplicate(&self.circuit_id, key, value) =>
+                {
+                    debug!("Skipping duplicate state change of {}", key);
+                    return Ok(());
+                }
+                StateChangeEvent::Delete { key } => dedup.forget(&self.circuit_id, key),
+                _ => (),
+            }
+        }
+        self.mirror(change)?;
+        let published = self.state_message(change).and_then(|message| match message {
+            Some(message) => {
+                let message_type = message.message_type;
+                self.watchdog.publish(message).map(|()| Some(message_type))
+            }
+            None => Ok(None),
+        });
+        let message_type = match published {
+            Ok(Some(message_type)) => message_type,
+            Ok(None) => return Ok(()),
+            Err(err) => {
+                // Consumers did not receive the value the key's next patch would be
+                // computed against, so that value is sent in full instead
+                if let StateChangeEvent::Set { key, .. } = change {
+                    self.delta_encoder.remove(&self.circuit_id, key);
+                }
+                return Err(err);
+            }
+        };
+        // After the deletion message, so that compaction keeps the tombstone
+        if let StateChangeEvent::Delete { key } = change {
+            self.export_delete(key)?;
+        }
+        if let (Some(dedup), StateChangeEvent::Set { key, value }) = (&self.dedup, change) {
+            dedup.record(&self.circuit_id, key, value);
+        }
+        match message_type {
+            Message_MessageType::CIRCUIT_CREATED => info!("Wrote to Kafka about Circuit Created"),
+            Message_MessageType::CIRCUIT_PAYLOAD_DELETED => {
+                info!("Wrote to Kafka about Circuit Payload Deleted")
+            }
+            _ => info!("Wrote to Kafka about Circuit Payload"),
+        }
+        Ok(())
+    }
+
+    /// Writes the tombstone of a deleted key of the contracts' namespaces, when deletes
+    /// are exported
+    fn export_delete(&self, key: &str) -> Result<(), StateDeltaError> {
+        if !self.config.deployment_config().export_deletes() || self.processor(key).is_none() {
+            return Ok(());
+        }
+        self.publisher
+            .publish_tombstone(&self.circuit_id, key)
+            .map_err(|err| StateDeltaError::SinkError(err.to_string()))
+    }
+
+    /// Applies the deployment's filters, except to the contracts' own entries which
+    /// announce the circuit
+    fn is_exported(&self, change: &StateChangeEvent) -> bool {
+        let key = match change {
+            StateChangeEvent::Set { key, .. } => key,
+            StateChangeEvent::Delete { key } => key,
+        };
+        self.processors
+            .iter()
+            .any(|processor| &processor.prefix == key)
+            || self.filter.allows(change)
+    }
+
+    /// Returns whether a state change is in a paused namespace. The key's last exported
+    /// value is forgotten, so that its first change after the namespace resumes is
+    /// exported in full rather than as a patch or a duplicate.
+    fn is_paused(&self, change: &StateChangeEvent) -> bool {
+        let key = match change {
+            StateChangeEvent::Set { key, .. } => key,
+            StateChangeEvent::Delete { key } => key,
+        };
+        match self.processor(key) {
+            Some(processor)
+                if &processor.prefix != key && self.pauses.is_paused(&processor.prefix) =>
+            {
+                self.delta_encoder.remove(&self.circuit_id, key);
+                if let Some(ref dedup) = self.dedup {
+                    dedup.forget(&self.circuit_id, key);
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Writes a state change of the contracts' namespaces to the circuit's state mirror.
+    /// The contracts' own entries at their prefixes are not contract state.
+    fn mirror(&self, change: &StateChangeEvent) -> Result<(), StateDeltaError> {
+        let (key, value) = match change {
+            StateChangeEvent::Set { key, value } => (key, Some(value.as_slice())),
+            StateChangeEvent::Delete { key } => (key, None),
+        };
+        match self.processor(key) {
+            Some(processor) if &processor.prefix != key => self
+                .publisher
+                .mirror_state(&self.circuit_id, key, value)
+                .map_err(|err| StateDeltaError::SinkError(err.to_string())),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the contract whose namespace holds a state address
+    fn processor(&self, address: &str) -> Option<&TransactionProcessor> {
+        self.processors.iter().find(|processor| processor.owns(address))
+    }
+
+    /// Builds the message to export for a state change, if it is exported
+    fn state_message(
+        &self,
+        change: &StateChangeEvent,
+    ) -> Result<Option<StateMessage>, StateDeltaError> {
         match change {
-            StateChangeEvent::Set { key, .. } if key == &self.contract_address => {
+            StateChangeEvent::Set { key, value } if key == &self.contract_address => {
                 debug!("TP contract created successfully");
                 let time = SystemTime::now();
                 let mut circuit_created = CircuitCreated::new();
                 circuit_created.set_requester(self.requester.clone());
                 circuit_created.set_requester_node_id(self.node_id.clone());
                 circuit_created.set_circuit_id(self.circuit_id.clone());
-                let message_bytes = match circuit_created.write_to_bytes() {
-                    Ok(bytes) => bytes,
-                    Err(err) => return Err(StateDeltaError::SDError(err.to_string())),
-                };
-                let mut message = Message::new();
-                message.set_field_type(Message_MessageType::CIRCUIT_CREATED);
-                message.set_message(message_bytes);
-                let to_send_bytes = match message.write_to_bytes() {
-                    Ok(bytes) => bytes,
-                    Err(err) => return Err(StateDeltaError::SDError(err.to_string())),
-                };
-                match producer.send(&Record::from_value(&topic, to_send_bytes)) {
-                    Ok(_) => info!("Wrote to Kafka about Circuit Created"),
-                    Err(err) => return Err(StateDeltaError::SDError(err.to_string())),
-                }
-                Ok(())
+                circuit_created.set_services(RepeatedField::from_vec(self.services.clone()));
+                circuit_created.set_members(RepeatedField::from_vec(self.members.clone()));
+                Ok(Some(StateMessage {
+                    key: key.clone(),
+                    size: value.len(),
+                    message_type: Message_MessageType::CIRCUIT_CREATED,
+                    message: serialize(&circuit_created)?,
+                }))
             }
-            StateChangeEvent::Set { key, value } if &key[..6] == self.config.deployment_config().tp_prefix() => {
-                let time = SystemTime::now();
+            StateChangeEvent::Set { key, value } if self.processor(key).is_some() => {
                 let mut circuit_payload = CircuitPayload::new();
                 circuit_payload.set_requester(self.requester.clone());
                 circuit_payload.set_requester_node_id(self.node_id.clone());
                 circuit_payload.set_circuit_id(self.circuit_id.clone());
-                circuit_payload.set_data(value.to_vec());
-                let message_bytes = match circuit_payload.write_to_bytes() {
-                    Ok(bytes) => bytes,
-                    Err(err) => return Err(StateDeltaError::SDError(err.to_string())),
-                };
-                let mut message = Message::new();
-                message.set_field_type(Message_MessageType::CIRCUIT_PAYLOAD);
-                message.set_message(message_bytes);
-                let to_send_bytes = match message.write_to_bytes() {
-                    Ok(bytes) => bytes,
-                    Err(err) => return Err(StateDeltaError::SDError(err.to_string())),
-                };
-                match producer.send(&Record::from_value(&topic, to_send_bytes)) {
-                    Ok(_) => info!("Wrote to Kafka about Circuit Payload"),
-                    Err(err) => return Err(StateDeltaError::SDError(err.to_string())),
+                circuit_payload.set_service_id(self.service_id.clone());
+                circuit_payload.set_received_at(now_millis());
+                if let Some(ref database) = self.database {
+                    database.add_payload(&self.circuit_id, key, value);
+                }
+                let encoded = self.delta_encoder.encode(&self.circuit_id, key, value);
+                circuit_payload.set_key(key.clone());
+                if let Some(processor) = self.processor(key) {
+                    circuit_payload.set_contract_name(processor.name.clone());
                 }
-                Ok(())
+                self.decoders.decode(&mut circuit_payload, value);
+                circuit_payload.set_data(encoded.data);
+                circuit_payload.set_encoding(encoded.encoding);
+                circuit_payload.set_sequence(encoded.sequence);
+                Ok(Some(StateMessage {
+                    key: key.clone(),
+                    size: value.len(),
+                    message_type: Message_MessageType::CIRCUIT_PAYLOAD,
+                    message: serialize(&circuit_payload)?,
+                }))
             }
-            StateChangeEvent::Delete { .. } => {
-                debug!("Delete state skipping...");
-                Ok(())
+            StateChangeEvent::Delete { key } => {
+                self.delta_encoder.remove(&self.circuit_id, key);
+                let processor = match self.processor(key) {
+                    Some(processor) if self.config.deployment_config().export_deletes() => {
+                        processor
+                    }
+                    _ => {
+                        debug!("Delete state skipping...");
+                        return Ok(None);
+                    }
+                };
+                let mut deleted = CircuitPayloadDeleted::new();
+                deleted.set_requester(self.requester.clone());
+                deleted.set_requester_node_id(self.node_id.clone());
+                deleted.set_circuit_id(self.circuit_id.clone());
+                deleted.set_service_id(self.service_id.clone());
+                deleted.set_key(key.clone());
+                deleted.set_contract_name(processor.name.clone());
+                deleted.set_detected_at(now_millis());
+                Ok(Some(StateMessage {
+                    key: key.clone(),
+                    size: 0,
+                    message_type: Message_MessageType::CIRCUIT_PAYLOAD_DELETED,
+                    message: serialize(&deleted)?,
+                }))
             }
             _ => {
                 debug!("Unrecognized state change skipping...");
-                Ok(())
+                Ok(None)
             }
         }
     }
 }
 
+pub(super) fn circuit_service(service: &RosterService) -> CircuitService {
+    let mut circuit_service = CircuitService::new();
+    circuit_service.set_service_id(service.service_id.clone());
+    circuit_service.set_service_type(service.service_type.clone());
+    circuit_service.set_allowed_nodes(RepeatedField::from_vec(service.allowed_nodes.clone()));
+    circuit_service.set_arguments(
+        service
+            .arguments
+            .iter()
+            .map(|(key, value)| {
+                let mut argument = ServiceArgument::new();
+                argument.set_key(key.clone());
+                argument.set_value(value.clone());
+                argument
+            })
+            .collect(),
+    );
+    circuit_service
+}
+
+pub(super) fn circuit_member(member: &RegisteredMember) -> CircuitMember {
+    let mut circuit_member = CircuitMember::new();
+    circuit_member.set_node_id(member.node_id.clone());
+    circuit_member.set_endpoint(member.endpoint.clone());
+    circuit_member
+}
+
+fn serialize<M: Message>(message: &M) -> Result<Vec<u8>, StateDeltaError> {
+    message
+        .write_to_bytes()
+        .map_err(|err| StateDeltaError::ParseError(err.to_string()))
+}
+
 #[derive(Debug)]
 pub enum StateDeltaError {
-    SDError(String),
+    /// A state change which cannot be turned into a message, the only kind of error
+    /// which closes the subscription in the strict parsing mode
+    ParseError(String),
+    /// A message which could not be handed to the sinks or the WAL
+    SinkError(String),
+}
+
+impl StateDeltaError {
+    pub fn is_parse_error(&self) -> bool {
+        match self {
+            StateDeltaError::ParseError(_) => true,
+            StateDeltaError::SinkError(_) => false,
+        }
+    }
 }
 
 impl Error for StateDeltaError {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
         match self {
-            StateDeltaError::SDError(_) => None,
+            StateDeltaError::ParseError(_) => None,
+            StateDeltaError::SinkError(_) => None,
         }
     }
 }
@@ -141,9 +426,12 @@ impl Error for StateDeltaError {
 impl fmt::Display for StateDeltaError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            StateDeltaError::SDError(err) => {
+            StateDeltaError::ParseError(err) => {
                 write!(f, "Failed to parse tp payload: {}", err)
             }
+            StateDeltaError::SinkError(err) => {
+                write!(f, "Failed to export tp payload: {}", err)
+            }
         }
     }
 }
@@ -0,0 +1,256 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use std::collections::HashMap;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+use splinter::events::Igniter;
+
+use crate::config::DeploymentConfig;
+use crate::publisher::Lifecycle;
+use crate::status::{admin_subscription, scabbard_subscription, Status};
+use crate::time::now_millis;
+
+use super::registry::ScabbardSubscription;
+use super::{
+    open_admin_subscription, open_scabbard_subscription, EventHandlerError, ExportContext,
+};
+
+/// Doublings after which the backoff stops growing, whatever the maximum
+const MAX_DOUBLINGS: u32 = 16;
+
+/// A subscription closed after exhausting its reconnect limit
+#[derive(Clone)]
+pub(super) enum DeadSubscription {
+    /// the admin subscription of a circuit management type
+    Admin(String),
+    /// reopened without setting up its contract again
+    Scabbard(ScabbardSubscription),
+}
+
+impl DeadSubscription {
+    fn name(&self) -> String {
+        match self {
+            DeadSubscription::Admin(management_type) => admin_subscription(management_type),
+            DeadSubscription::Scabbard(subscription) => {
+                scabbard_subscription(&subscription.circuit_id, &subscription.service_id)
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct Queue {
+    pending: Vec<(Instant, DeadSubscription)>,
+    stopped: bool,
+}
+
+/// Revivals of a subscription since it last connected
+struct Revivals {
+    count: u32,
+    last_at: u64,
+}
+
+/// Reopens the subscriptions which exhausted their reconnect limit, which would otherwise
+/// stay closed while the daemon keeps running.
+///
+/// A subscription is reopened after a backoff which doubles with each revival that does
+/// not get it connected, up to `resubscribe_backoff_max`, plus up to a quarter of it as
+/// jitter so that the subscriptions of a restarted splinterd do not all come back at once.
+#[derive(Clone)]
+pub struct SubscriptionSupervisor {
+    initial: Duration,
+    max: Duration,
+    status: Status,
+    queue: Arc<(Mutex<Queue>, Condvar)>,
+    revivals: Arc<Mutex<HashMap<String, Revivals>>>,
+}
+
+/// Runs the revivals reported to a `SubscriptionSupervisor`
+pub struct SupervisorWorker {
+    supervisor: SubscriptionSupervisor,
+    join_handle: thread::JoinHandle<()>,
+}
+
+impl SupervisorWorker {
+    pub fn shutdown(self) {
+        self.supervisor.stop();
+        if self.join_handle.join().is_err() {
+            error!("Subscription supervisor thread panicked");
+        }
+    }
+}
+
+impl SubscriptionSupervisor {
+    pub(super) fn new(deployment_config: &DeploymentConfig, status: Status) -> Self {
+        SubscriptionSupervisor {
+            initial: Duration::from_secs(deployment_config.resubscribe_backoff_initial()),
+            max: Duration::from_secs(deployment_config.resubscribe_backoff_max()),
+            status,
+            queue: Arc::new((Mutex::new(Queue::default()), Condvar::new())),
+            revivals: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Starts reviving subscriptions, returns `None` if revivals are disabled
+    pub(super) fn start(
+        &self,
+        ctx: ExportContext,
+        igniter: Igniter,
+    ) -> Result<Option<SupervisorWorker>, EventHandlerError> {
+        if self.initial == Duration::from_secs(0) {
+            return Ok(None);
+        }
+        let supervisor = self.clone();
+        let join_handle = thread::Builder::new()
+            .name("SubscriptionSupervisor".into())
+            .spawn(move || {
+                while let Some(dead) = supervisor.next_due() {
+                    supervisor.revive(dead, &ctx, &igniter);
+                }
+            })?;
+        Ok(Some(SupervisorWorker {
+            supervisor: self.clone(),
+            join_handle,
+        }))
+    }
+
+    /// Schedules the revival of a subscription, returns false if it stays closed
+    pub(super) fn report(&self, dead: DeadSubscription) -> bool {
+        if self.initial == Duration::from_secs(0) {
+            return false;
+        }
+        let delay = self.backoff(&dead.name());
+        let (ref lock, ref condvar) = *self.queue;
+        match lock.lock() {
+            Ok(mut queue) if !queue.stopped => {
+                info!(
+                    "Reopening subscription {} in {}s",
+                    dead.name(),
+                    delay.as_secs()
+                );
+                queue.pending.push((Instant::now() + delay, dead));
+                condvar.notify_one();
+                true
+            }
+            Ok(_) => false,
+            Err(err) => {
+                error!("Unable to schedule subscription revival: {}", err);
+                false
+            }
+        }
+    }
+
+    /// Returns the backoff of the next revival, started over if the subscription
+    /// connected since the previous one
+    fn backoff(&self, name: &str) -> Duration {
+        let last_connected_at = self
+            .status
+            .subscriptions()
+            .get(name)
+            .and_then(|status| status.last_connected_at());
+        let count = match self.revivals.lock() {
+            Ok(mut revivals) => {
+                let revival = revivals.entry(name.to_string()).or_insert(Revivals {
+                    count: 0,
+                    last_at: 0,
+                });
+                if last_connected_at.map_or(false, |connected_at| connected_at > revival.last_at) {
+                    revival.count = 0;
+                }
+                revival.count += 1;
+                revival.last_at = now_millis();
+                revival.count
+            }
+            Err(err) => {
+                error!("Unable to read subscription revivals: {}", err);
+                1
+            }
+        };
+
+        let delay = self
+            .initial
+            .checked_mul(1 << (count - 1).min(MAX_DOUBLINGS))
+            .unwrap_or(self.max)
+            .min(self.max);
+        let jitter_millis = rand::thread_rng().gen_range(0, delay.as_millis() as u64 / 4 + 1);
+        delay + Duration::from_millis(jitter_millis)
+    }
+
+    /// Waits for the next revival due, returns `None` once stopped
+    fn next_due(&self) -> Option<DeadSubscription> {
+        let (ref lock, ref condvar) = *self.queue;
+        let mut queue = lock.lock().ok()?;
+        loop {
+            if queue.stopped {
+                return None;
+            }
+            let now = Instant::now();
+            let next = queue
+                .pending
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, (due, _))| *due)
+                .map(|(index, (due, _))| (index, *due));
+            queue = match next {
+                Some((index, due)) if due <= now => return Some(queue.pending.remove(index).1),
+                Some((_, due)) => condvar.wait_timeout(queue, due - now).ok()?.0,
+                None => condvar.wait(queue).ok()?,
+            };
+        }
+    }
+
+    fn revive(&self, dead: DeadSubscription, ctx: &ExportContext, igniter: &Igniter) {
+        if ctx.intake.is_stopped() {
+            return;
+        }
+        let name = dead.name();
+        let result = match dead {
+            DeadSubscription::Admin(ref management_type) => {
+                open_admin_subscription(ctx, igniter, management_type)
+            }
+            DeadSubscription::Scabbard(ref subscription) => {
+                if ctx.registry.is_removed(&subscription.circuit_id) {
+                    return;
+                }
+                open_scabbard_subscription(
+                    subscription.clone(),
+                    None,
+                    Lifecycle::Resubscribed,
+                    ctx,
+                    igniter,
+                )
+            }
+        };
+        if let Err(err) = result {
+            error!("Unable to reopen subscription {}: {}", name, err);
+            let revived = self.report(dead);
+            self.status.failed(&name, &err.to_string(), revived);
+        }
+    }
+
+    fn stop(&self) {
+        let (ref lock, ref condvar) = *self.queue;
+        if let Ok(mut queue) = lock.lock() {
+            queue.stopped = true;
+            condvar.notify_one();
+        }
+    }
+}
@@ -0,0 +1,89 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use protobuf::RepeatedField;
+use splinter::admin::messages::{CircuitProposal, Vote};
+
+use crate::proto::pubsub::{Ballot, Ballot_Vote};
+use crate::time::now_millis;
+
+use super::to_hex;
+
+/// Tallies the votes of pending proposals for their exported messages.
+///
+/// splinterd lists the votes of a proposal without timestamps, so each vote is stamped
+/// with the time the exporter first saw it, per circuit and voter key. Votes cast while
+/// the exporter was down are stamped when the next event of their proposal arrives.
+#[derive(Clone, Default)]
+pub struct VoteTally {
+    seen: Arc<Mutex<HashMap<String, HashMap<Vec<u8>, u64>>>>,
+}
+
+impl VoteTally {
+    pub fn new() -> Self {
+        VoteTally::default()
+    }
+
+    /// The ballots of all the votes cast on the proposal so far
+    pub fn ballots(&self, proposal: &CircuitProposal) -> RepeatedField<Ballot> {
+        let now = now_millis();
+        let mut seen = match self.seen.lock() {
+            Ok(seen) => seen,
+            Err(err) => {
+                error!("Unable to look up vote times: {}", err);
+                return RepeatedField::new();
+            }
+        };
+        let seen = seen.entry(proposal.circuit_id.clone()).or_default();
+        proposal
+            .votes
+            .iter()
+            .map(|record| {
+                let mut ballot = Ballot::new();
+                ballot.set_voter(to_hex(&record.public_key));
+                ballot.set_voter_node_id(record.voter_node_id.clone());
+                ballot.set_vote(match record.vote {
+                    Vote::Accept => Ballot_Vote::ACCEPT,
+                    Vote::Reject => Ballot_Vote::REJECT,
+                });
+                ballot.set_observed_at(*seen.entry(record.public_key.clone()).or_insert(now));
+                ballot
+            })
+            .collect()
+    }
+
+    /// Forgets the vote times of a circuit whose proposal was decided
+    pub fn decided(&self, circuit_id: &str) {
+        if let Ok(mut seen) = self.seen.lock() {
+            seen.remove(circuit_id);
+        }
+    }
+}
+
+/// Number of accepting votes the proposal needs: one from each member node other than the
+/// requester's
+pub fn required_votes(proposal: &CircuitProposal) -> u32 {
+    proposal
+        .circuit
+        .members
+        .iter()
+        .filter(|member| member.node_id != proposal.requester_node_id)
+        .count() as u32
+}
@@ -0,0 +1,131 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use super::state_delta::StateDeltaError;
+use crate::proto::pubsub::Message_MessageType;
+use crate::publisher::{KafkaPublisher, PublisherError};
+
+/// A message built from a state change, ready to be published
+pub struct StateMessage {
+    pub key: String,
+    /// Size in bytes of the state value
+    pub size: usize,
+    pub message_type: Message_MessageType,
+    pub message: Vec<u8>,
+}
+
+struct Job {
+    message_type: Message_MessageType,
+    message: Vec<u8>,
+    done: Sender<Result<(), PublisherError>>,
+}
+
+/// Publishes the messages of a circuit on a worker thread, so that a message which takes
+/// too long to reach the sinks does not block the WebSocket thread.
+///
+/// A message still being published after the timeout is appended to the WAL instead and
+/// the worker is abandoned: it finishes the stuck publish on its own and a new worker
+/// takes the next messages. The message is delivered twice if the stuck publish
+/// eventually succeeds.
+pub struct PublishWatchdog {
+    circuit_id: String,
+    publisher: KafkaPublisher,
+    timeout: Duration,
+    worker: Mutex<Option<Sender<Job>>>,
+}
+
+impl PublishWatchdog {
+    pub fn new(circuit_id: &str, publisher: KafkaPublisher, timeout: Duration) -> Self {
+        PublishWatchdog {
+            circuit_id: circuit_id.to_string(),
+            publisher,
+            timeout,
+            worker: Mutex::new(None),
+        }
+    }
+
+    pub fn publish(&self, message: StateMessage) -> Result<(), StateDeltaError> {
+        let mut worker = self
+            .worker
+            .lock()
+            .map_err(|err| StateDeltaError::SinkError(err.to_string()))?;
+        if worker.is_none() {
+            *worker = Some(self.spawn_worker()?);
+        }
+        let (done_tx, done_rx) = mpsc::channel();
+        let job = Job {
+            message_type: message.message_type,
+            message: message.message.clone(),
+            done: done_tx,
+        };
+        let sent = worker.as_ref().map_or(false, |jobs| jobs.send(job).is_ok());
+        if !sent {
+            *worker = None;
+            return Err(StateDeltaError::SinkError(
+                "publish worker went away".to_string(),
+            ));
+        }
+
+        match done_rx.recv_timeout(self.timeout) {
+            Ok(result) => result.map_err(|err| StateDeltaError::SinkError(err.to_string())),
+            Err(RecvTimeoutError::Timeout) => {
+                warn!(
+                    "Publishing {:?} of key {} ({} bytes) in circuit {} took more than {:?}, \
+                     moving it to the WAL",
+                    message.message_type, message.key, message.size, self.circuit_id, self.timeout
+                );
+                *worker = None;
+                self.publisher
+                    .defer(&self.circuit_id, message.message_type, message.message)
+                    .map_err(|err| StateDeltaError::SinkError(err.to_string()))
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                *worker = None;
+                Err(StateDeltaError::SinkError(format!(
+                    "publish worker stopped while publishing key {}",
+                    message.key
+                )))
+            }
+        }
+    }
+
+    fn spawn_worker(&self) -> Result<Sender<Job>, StateDeltaError> {
+        let (jobs_tx, jobs_rx) = mpsc::channel();
+        let circuit_id = self.circuit_id.clone();
+        let publisher = self.publisher.clone();
+        thread::Builder::new()
+            .name(format!("publish-{}", self.circuit_id))
+            .spawn(move || run_worker(&circuit_id, &publisher, jobs_rx))
+            .map_err(|err| {
+                StateDeltaError::SinkError(format!("Unable to start publish worker: {}", err))
+            })?;
+        Ok(jobs_tx)
+    }
+}
+
+fn run_worker(circuit_id: &str, publisher: &KafkaPublisher, jobs: Receiver<Job>) {
+    for job in jobs {
+        let result = publisher.republish(circuit_id, job.message_type, job.message);
+        // The watchdog no longer waits for a publish it gave up on
+        let _ = job.done.send(result);
+    }
+}
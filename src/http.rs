@@ -0,0 +1,238 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+//! The HTTP client used for the requests to splinterd and the webhook sink.
+//!
+//! With the `rustls-tls` feature the client also speaks HTTPS through rustls, and with
+//! the `trust-dns` feature names are resolved by trust-dns instead of the system
+//! resolver, so neither needs OpenSSL or a libc resolver on the target. The requests to
+//! a splinterd behind HTTPS can trust a private CA and present a client certificate.
+
+#[cfg(feature = "rustls-tls")]
+use std::fs::File;
+use std::io;
+#[cfg(feature = "rustls-tls")]
+use std::io::BufReader;
+
+use hyper::client::HttpConnector;
+use hyper::header::AUTHORIZATION;
+use hyper::{Body, Client, Request};
+#[cfg(feature = "rustls-tls")]
+use rustls::internal::pemfile;
+
+#[cfg(not(feature = "trust-dns"))]
+type Resolver = hyper::client::connect::dns::GaiResolver;
+#[cfg(feature = "trust-dns")]
+type Resolver = trust_dns::TrustDnsResolver;
+
+#[cfg(not(feature = "rustls-tls"))]
+pub type Connector = HttpConnector<Resolver>;
+#[cfg(feature = "rustls-tls")]
+pub type Connector = hyper_rustls::HttpsConnector<HttpConnector<Resolver>>;
+
+/// Number of threads resolving names with the system resolver
+#[cfg(not(feature = "trust-dns"))]
+const DNS_THREADS: usize = 4;
+
+/// TLS settings of the requests to splinterd, read from the `splinterd_tls` section
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TlsConfig {
+    /// PEM file of the CA certificates trusted for splinterd, the bundled web roots
+    /// when unset
+    #[serde(default)]
+    pub ca_cert: Option<String>,
+    /// PEM file of the client certificate chain presented to splinterd
+    #[serde(default)]
+    pub client_cert: Option<String>,
+    /// PEM file of the client certificate's PKCS#8 or RSA private key
+    #[serde(default)]
+    pub client_key: Option<String>,
+}
+
+pub fn client() -> Result<Client<Connector>, io::Error> {
+    Ok(Client::builder().build(connector(http_connector()?)))
+}
+
+/// Builds a GET request to splinterd carrying the `Authorization` header when given
+pub fn splinterd_get(
+    url: &str,
+    authorization: Option<String>,
+) -> Result<Request<Body>, hyper::http::Error> {
+    let mut builder = Request::get(url);
+    if let Some(authorization) = authorization {
+        builder.header(AUTHORIZATION, authorization);
+    }
+    builder.body(Body::empty())
+}
+
+/// The client for the requests to splinterd, using the TLS settings when given
+pub fn splinterd_client(tls: Option<&TlsConfig>) -> Result<Client<Connector>, io::Error> {
+    match tls {
+        Some(tls) => Ok(Client::builder().build(tls_connector(http_connector()?, tls)?)),
+        None => client(),
+    }
+}
+
+#[cfg(not(feature = "trust-dns"))]
+fn http_connector() -> Result<HttpConnector<Resolver>, io::Error> {
+    Ok(HttpConnector::new(DNS_THREADS))
+}
+
+#[cfg(feature = "trust-dns")]
+fn http_connector() -> Result<HttpConnector<Resolver>, io::Error> {
+    Ok(HttpConnector::new_with_resolver(
+        trust_dns::TrustDnsResolver::from_system_conf()?,
+    ))
+}
+
+#[cfg(not(feature = "rustls-tls"))]
+fn connector(http: HttpConnector<Resolver>) -> Connector {
+    http
+}
+
+#[cfg(feature = "rustls-tls")]
+fn connector(mut http: HttpConnector<Resolver>) -> Connector {
+    http.enforce_http(false);
+    let mut tls = rustls::ClientConfig::new();
+    tls.root_store
+        .add_server_trust_anchors(&webpki_roots::TLS_SERVER_ROOTS);
+    hyper_rustls::HttpsConnector::from((http, tls))
+}
+
+#[cfg(not(feature = "rustls-tls"))]
+fn tls_connector(_http: HttpConnector<Resolver>, _tls: &TlsConfig) -> Result<Connector, io::Error> {
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "splinterd_tls requires the rustls-tls feature",
+    ))
+}
+
+#[cfg(feature = "rustls-tls")]
+fn tls_connector(
+    mut http: HttpConnector<Resolver>,
+    tls: &TlsConfig,
+) -> Result<Connector, io::Error> {
+    http.enforce_http(false);
+    let mut config = rustls::ClientConfig::new();
+    match tls.ca_cert {
+        Some(ref ca_cert) => {
+            let (added, _) = config
+                .root_store
+                .add_pem_file(&mut BufReader::new(File::open(ca_cert)?))
+                .map_err(|_| invalid_pem(ca_cert))?;
+            if added == 0 {
+                return Err(invalid_pem(ca_cert));
+            }
+        }
+        None => config
+            .root_store
+            .add_server_trust_anchors(&webpki_roots::TLS_SERVER_ROOTS),
+    }
+    match (&tls.client_cert, &tls.client_key) {
+        (Some(client_cert), Some(client_key)) => {
+            let certs = pemfile::certs(&mut BufReader::new(File::open(client_cert)?))
+                .map_err(|_| invalid_pem(client_cert))?;
+            let key = read_private_key(client_key)?;
+            config.set_single_client_cert(certs, key);
+        }
+        (None, None) => (),
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "client_cert and client_key must be set together",
+            ))
+        }
+    }
+    Ok(hyper_rustls::HttpsConnector::from((http, config)))
+}
+
+/// Reads the first PKCS#8 key of the file, or its first RSA key
+#[cfg(feature = "rustls-tls")]
+fn read_private_key(path: &str) -> Result<rustls::PrivateKey, io::Error> {
+    let pkcs8 = pemfile::pkcs8_private_keys(&mut BufReader::new(File::open(path)?))
+        .map_err(|_| invalid_pem(path))?;
+    let mut keys = if pkcs8.is_empty() {
+        pemfile::rsa_private_keys(&mut BufReader::new(File::open(path)?))
+            .map_err(|_| invalid_pem(path))?
+    } else {
+        pkcs8
+    };
+    if keys.is_empty() {
+        return Err(invalid_pem(path));
+    }
+    Ok(keys.remove(0))
+}
+
+#[cfg(feature = "rustls-tls")]
+fn invalid_pem(path: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("no usable PEM entries in {}", path),
+    )
+}
+
+#[cfg(feature = "trust-dns")]
+mod trust_dns {
+    use std::io;
+    use std::net::IpAddr;
+    use std::sync::Arc;
+    use std::vec;
+
+    use futures::future::{self, FutureResult};
+    use hyper::client::connect::dns::{Name, Resolve};
+    use trust_dns_resolver::config::{ResolverConfig, ResolverOpts};
+    use trust_dns_resolver::Resolver;
+
+    /// Resolves names with trust-dns, falling back to public name servers when there is
+    /// no system configuration to read.
+    ///
+    /// Lookups block the connecting thread, which is acceptable for the handful of
+    /// requests the exporter makes.
+    #[derive(Clone)]
+    pub struct TrustDnsResolver {
+        resolver: Arc<Resolver>,
+    }
+
+    impl TrustDnsResolver {
+        pub fn from_system_conf() -> Result<Self, io::Error> {
+            let resolver = match Resolver::from_system_conf() {
+                Ok(resolver) => resolver,
+                Err(err) => {
+                    warn!("Unable to read the system DNS configuration: {}", err);
+                    Resolver::new(ResolverConfig::default(), ResolverOpts::default())?
+                }
+            };
+            Ok(TrustDnsResolver {
+                resolver: Arc::new(resolver),
+            })
+        }
+    }
+
+    impl Resolve for TrustDnsResolver {
+        type Addrs = vec::IntoIter<IpAddr>;
+        type Future = FutureResult<Self::Addrs, io::Error>;
+
+        fn resolve(&self, name: Name) -> Self::Future {
+            future::result(
+                self.resolver
+                    .lookup_ip(name.as_str())
+                    .map(|lookup| lookup.iter().collect::<Vec<_>>().into_iter())
+                    .map_err(|err| io::Error::new(io::ErrorKind::Other, err.to_string())),
+            )
+        }
+    }
+}
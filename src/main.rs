@@ -13,9 +13,10 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-#[macro_use]
 extern crate clap;
 #[macro_use]
+extern crate diesel;
+#[macro_use]
 extern crate log;
 #[macro_use]
 extern crate serde_derive;
@@ -26,25 +27,59 @@ extern crate db_models;
 extern crate splinter;
 extern crate kafka;
 
+mod aggregator;
+mod alert;
 mod application_metadata;
+mod commands;
 mod event_handler;
 mod config;
+mod database;
 mod error;
+mod http;
+mod metrics;
+mod proposal_cache;
 mod proto;
+mod publisher;
+mod rest_api;
+mod schema;
+mod shutdown;
+mod signing;
+mod status;
+mod time;
 
+use std::sync::mpsc;
 use std::thread;
+use std::time::{Duration, Instant};
 
+use clap::{App, Arg, ArgMatches, SubCommand};
 use flexi_logger::{style, DeferredNow, LogSpecBuilder, Logger};
 use log::Record;
-use sawtooth_sdk::signing::create_context;
+use signal_hook::iterator::Signals;
+use signal_hook::{SIGHUP, SIGUSR2};
 use splinter::events::Reactor;
 
-use crate::config::{get_node, DataReaderConfigBuilder};
-use crate::error::EventListenerError;
+use crate::aggregator::Aggregator;
+use crate::config::{
+    get_node, DataReaderConfigBuilder, DeploymentLoader, ExportMode, SharedConfig,
+};
+use crate::database::DatabaseSink;
+use crate::error::{ConfigurationError, EventListenerError};
+use crate::event_handler::{Manifest, NamespacePauses, Sinks, SnapshotTrigger};
+use crate::metrics::Metrics;
+use crate::proposal_cache::ProposalCache;
+use crate::publisher::{
+    ConsumerLagMonitor, Heartbeat, KafkaPublisher, Lifecycle, RecentEvents, WalReplayer,
+};
+use crate::schema::{describe_schema, SchemaFormat};
+use crate::shutdown::Intake;
+use crate::status::Status;
 
 const APP_NAME: &str = env!("CARGO_PKG_NAME");
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
+// port the status command queries when no --url is given and no bind address is configured
+const DEFAULT_REST_API_PORT: u16 = 8000;
+
 // format for logs
 pub fn log_format(
     w: &mut dyn std::io::Write,
@@ -63,19 +98,245 @@ pub fn log_format(
     )
 }
 
+/// Arguments of the daemon, accepted by `run` and, as it is the default, without a command
+fn daemon_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        verbose_arg(),
+        config_arg(),
+        daemon_config_arg(),
+        Arg::with_name("splinterd_url")
+            .long("splinterd-url")
+            .takes_value(true)
+            .help("connection endpoint to SplinterD rest API"),
+        Arg::with_name("bind")
+            .short("b")
+            .long("bind")
+            .takes_value(true)
+            .help("connection endpoint for the event listener rest API serving metrics"),
+        key_file_arg(),
+        Arg::with_name("no_reconnect")
+            .long("no-reconnect")
+            .help("close the WebSocket subscriptions when their connection is lost instead of reconnecting"),
+        Arg::with_name("reconnect_limit")
+            .long("reconnect-limit")
+            .takes_value(true)
+            .help("number of consecutive failed reconnection attempts after which a subscription is closed"),
+        Arg::with_name("connection_timeout")
+            .long("connection-timeout")
+            .takes_value(true)
+            .help("seconds without a message after which a WebSocket connection is considered lost"),
+    ]
+}
+
+fn verbose_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("verbose")
+        .short("v")
+        .multiple(true)
+        .help("Log verbosely")
+}
+
+fn config_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("config")
+        .short("c")
+        .long("config")
+        .takes_value(true)
+        .help("config file to be used for the event listener service")
+}
+
+fn daemon_config_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("daemon_config")
+        .long("daemon-config")
+        .takes_value(true)
+        .help("TOML file with the settings of the daemon, overridden by SPLINTER_EXPORTER_* environment variables and the command line")
+}
+
+fn key_file_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("key_file")
+        .long("key-file")
+        .takes_value(true)
+        .help("file holding the key signing Sabre batches, generated if missing")
+}
+
 fn run() -> Result<(), EventListenerError> {
-    let matches = clap_app!(myapp =>
-        (name: APP_NAME)
-        (version: VERSION)
-        (author: "Cargill Incorporated, Walmart Inc.")
-        (about: "Daemon Package for Listening to events on Splinter")
-        (@arg verbose: -v +multiple "Log verbosely")
-        (@arg config: -c --config +takes_value "config file to be used for the event listener service")
-        (@arg splinterd_url: --("splinterd-url") +takes_value "connection endpoint to SplinterD rest API")
-    )
-    .get_matches();
+    let matches = App::new(APP_NAME)
+        .version(VERSION)
+        .author("Cargill Incorporated, Walmart Inc.")
+        .about("Daemon Package for Listening to events on Splinter")
+        .args(&daemon_args())
+        .subcommand(
+            SubCommand::with_name("run")
+                .about("Run the daemon, the default when no command is given")
+                .args(&daemon_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("keygen")
+                .about("Generate the key signing Sabre batches and print its public key")
+                .arg(key_file_arg().required(true))
+                .arg(
+                    Arg::with_name("force")
+                        .long("force")
+                        .help("Replace the key file if it exists"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("check-config")
+                .about("Validate the configuration the daemon would run with")
+                .args(&daemon_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("replay")
+                .about("Deliver the entries pending in the WAL of a stopped daemon once")
+                .arg(verbose_arg())
+                .arg(config_arg())
+                .arg(daemon_config_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("drain")
+                .about("Replay the WAL of a stopped daemon until it is empty")
+                .arg(verbose_arg())
+                .arg(config_arg())
+                .arg(daemon_config_arg())
+                .arg(
+                    Arg::with_name("timeout")
+                        .long("timeout")
+                        .takes_value(true)
+                        .default_value("300")
+                        .help("seconds to wait for the WAL to be drained"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("status")
+                .about("Print the status of a running daemon")
+                .arg(daemon_config_arg())
+                .arg(
+                    Arg::with_name("url")
+                        .long("url")
+                        .takes_value(true)
+                        .help("URL of the daemon's rest API, http://localhost on the port of the configured bind address by default"),
+                )
+                .arg(
+                    Arg::with_name("json")
+                        .long("json")
+                        .help("Print the status as JSON"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("describe-schema")
+                .about("Print the exported message types and their fields")
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .takes_value(true)
+                        .possible_values(&["markdown", "json"])
+                        .default_value("markdown"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("check-compat")
+                .about("Report breaking changes against previously published message schemas")
+                .arg(
+                    Arg::with_name("against")
+                        .long("against")
+                        .takes_value(true)
+                        .required(true)
+                        .help(
+                            "file or URL of the describe-schema JSON output, an Avro schema \
+                             or a schema registry subject version",
+                        ),
+                ),
+        )
+        .get_matches();
 
-    let log_level = match matches.occurrences_of("verbose") {
+    match matches.subcommand() {
+        ("run", Some(run_matches)) => run_daemon(run_matches),
+        ("keygen", Some(keygen_matches)) => commands::keygen(
+            keygen_matches
+                .value_of("key_file")
+                .ok_or_else(|| ConfigurationError::MissingValue("key_file".to_string()))?,
+            keygen_matches.is_present("force"),
+        ),
+        ("check-config", Some(check_matches)) => {
+            let builder = configure(check_matches)?;
+            commands::check_config(builder)
+        }
+        ("replay", Some(replay_matches)) => {
+            let builder = configure(replay_matches)?;
+            commands::replay(&builder.build_deployment_config()?)
+        }
+        ("drain", Some(drain_matches)) => {
+            let builder = configure(drain_matches)?;
+            let timeout = drain_matches
+                .value_of("timeout")
+                .and_then(|timeout| timeout.parse().ok())
+                .ok_or_else(|| {
+                    ConfigurationError::MissingValue("timeout in seconds".to_string())
+                })?;
+            commands::drain(
+                &builder.build_deployment_config()?,
+                Duration::from_secs(timeout),
+            )
+        }
+        ("status", Some(status_matches)) => {
+            let url = match status_matches.value_of("url") {
+                Some(url) => url.to_string(),
+                None => local_rest_api_url(&config_builder(status_matches)?),
+            };
+            commands::status(&url, status_matches.is_present("json"))
+        }
+        ("describe-schema", Some(describe_matches)) => {
+            let format = match describe_matches.value_of("format") {
+                Some("json") => SchemaFormat::Json,
+                _ => SchemaFormat::Markdown,
+            };
+            println!("{}", describe_schema(format));
+            Ok(())
+        }
+        ("check-compat", Some(compat_matches)) => commands::check_compat(
+            compat_matches
+                .value_of("against")
+                .ok_or_else(|| ConfigurationError::MissingValue("against".to_string()))?,
+        ),
+        _ => run_daemon(&matches),
+    }
+}
+
+/// Merges the daemon configuration file, the environment and the command line, in order
+/// of precedence
+fn config_builder(matches: &ArgMatches<'_>) -> Result<DataReaderConfigBuilder, EventListenerError> {
+    let mut builder = DataReaderConfigBuilder::default();
+    if let Some(path) = matches.value_of("daemon_config") {
+        builder = builder.with_daemon_config(path)?;
+    }
+    Ok(builder.with_env()?.with_cli_args(matches)?)
+}
+
+/// The REST API of a daemon on this host, on the port of its bind address if configured
+fn local_rest_api_url(builder: &DataReaderConfigBuilder) -> String {
+    let port = builder
+        .rest_api_endpoint()
+        .and_then(|bind| bind.rsplit(':').next())
+        .and_then(|port| port.parse::<u16>().ok())
+        .unwrap_or(DEFAULT_REST_API_PORT);
+    format!("http://localhost:{}", port)
+}
+
+/// Merges the configuration sources and starts the logger at the verbosity they give
+fn configure(matches: &ArgMatches<'_>) -> Result<DataReaderConfigBuilder, EventListenerError> {
+    match config_builder(matches) {
+        Ok(builder) => {
+            start_logger(builder.verbosity())?;
+            Ok(builder)
+        }
+        Err(err) => {
+            // Started anyway, so that the error is logged
+            start_logger(matches.occurrences_of("verbose"))?;
+            Err(err)
+        }
+    }
+}
+
+fn start_logger(verbosity: u64) -> Result<(), EventListenerError> {
+    let log_level = match verbosity {
         0 => log::LevelFilter::Warn,
         1 => log::LevelFilter::Info,
         2 => log::LevelFilter::Debug,
@@ -91,27 +352,263 @@ fn run() -> Result<(), EventListenerError> {
     Logger::with(log_spec_builder.build())
         .format(log_format)
         .start()?;
-    let config = DataReaderConfigBuilder::default()
-        .with_cli_args(&matches)
-        .build()?;
+    Ok(())
+}
 
-    // Generate a public/private key pair
-    let context = create_context("secp256k1")?;
-    let private_key = context.new_random_private_key()?;
-    let _public_key = context.get_public_key(&*private_key)?;
+/// Applies the reloadable settings of the deployment configuration, the current
+/// configuration is kept when the new one is invalid
+fn reload_deployment_config(
+    loader: &DeploymentLoader,
+    config: &SharedConfig,
+    publisher: &KafkaPublisher,
+) {
+    let reloaded = loader
+        .load()
+        .and_then(|deployment_config| config.reload(&deployment_config));
+    let (deployment_config, settings) = match reloaded {
+        Ok(reloaded) => reloaded,
+        Err(err) => {
+            error!(
+                "Unable to reload the deployment configuration, keeping the current one: {}",
+                err
+            );
+            return;
+        }
+    };
+    publisher.reload(&deployment_config);
+    if settings.applied.is_empty() {
+        info!("No reloadable setting changed");
+    } else {
+        info!("Reloaded {}", settings.applied.join(", "));
+    }
+    if !settings.needing_restart.is_empty() {
+        warn!(
+            "Changes to {} take effect after a restart",
+            settings.needing_restart.join(", ")
+        );
+    }
+}
 
-    // Get splinterd node information
-    let node = get_node(config.splinterd_url())?;
+fn run_daemon(matches: &ArgMatches<'_>) -> Result<(), EventListenerError> {
+    let builder = configure(matches)?;
+    let deployment_loader = builder.deployment_loader();
+    let config = builder.build()?;
+    let shared_config = SharedConfig::new(config.clone());
 
-    let reactor = Reactor::new();
+    // The key signing the Sabre batches which set up the contract on new circuits
+    let private_key = match config.deployment_config().export_mode() {
+        ExportMode::Full => match config.key_path() {
+            Some(path) => Some(signing::load_or_generate(path)?),
+            None => {
+                warn!("No key file configured, the signing identity changes on every restart");
+                Some(signing::generate()?)
+            }
+        },
+        ExportMode::AdminOnly | ExportMode::StateOnly | ExportMode::Aggregator => None,
+    };
 
-    event_handler::run(
-        config,
-        node.identity.clone(),
-        private_key.as_hex(),
-        reactor.igniter(),
+    let metrics = Metrics::new();
+    let status = Status::new();
+    let recent_events = RecentEvents::new(config.deployment_config().recent_events_capacity());
+    let publisher = KafkaPublisher::new(
+        config.deployment_config(),
+        recent_events.clone(),
+        metrics.clone(),
     )?;
+    let proposal_cache = if config.deployment_config().rest_cache() {
+        Some(ProposalCache::new())
+    } else {
+        None
+    };
+    let snapshot_trigger = SnapshotTrigger::new();
+    let manifest = Manifest::new();
+    let pauses = NamespacePauses::new();
+    let database = match config.deployment_config().database_url() {
+        Some(database_url) => Some(DatabaseSink::connect(database_url)?),
+        None => None,
+    };
+    let rest_api = match config.rest_api_endpoint() {
+        Some(bind_url) => Some(rest_api::run(
+            bind_url,
+            metrics.clone(),
+            publisher.clone(),
+            recent_events,
+            proposal_cache.clone(),
+            status.clone(),
+            snapshot_trigger.clone(),
+            manifest.clone(),
+            pauses.clone(),
+            shared_config.clone(),
+        )?),
+        None => None,
+    };
+    let lag_monitor =
+        ConsumerLagMonitor::start(config.deployment_config(), metrics.clone(), status.clone())?;
+    let wal_replayer = WalReplayer::start(
+        publisher.clone(),
+        Duration::from_secs(config.deployment_config().wal_replay_interval()),
+    )?;
+    let heartbeat = Heartbeat::start(
+        publisher.clone(),
+        Duration::from_secs(config.deployment_config().heartbeat_interval()),
+    )?;
+
+    let drain_timeout = Duration::from_secs(config.deployment_config().shutdown_drain_timeout());
+
+    let reactor = Reactor::new();
+    let intake = Intake::new();
+
+    let (shutdown_tx, shutdown_rx) = mpsc::channel();
+    ctrlc::set_handler(move || {
+        if shutdown_tx.send(()).is_err() {
+            error!("Shutdown already in progress");
+        }
+    })?;
+
+    let signal_trigger = snapshot_trigger.clone();
+    let signals = Signals::new(&[SIGUSR2])
+        .map_err(|err| EventListenerError::SignalHandlerError(err.to_string()))?;
+    thread::Builder::new()
+        .name("SnapshotSignal".into())
+        .spawn(move || {
+            for _ in signals.forever() {
+                info!("Received SIGUSR2, requesting a snapshot");
+                signal_trigger.request();
+            }
+        })
+        .map_err(|err| EventListenerError::SignalHandlerError(err.to_string()))?;
+
+    let reload_config = shared_config.clone();
+    let reload_publisher = publisher.clone();
+    let reload_signals = Signals::new(&[SIGHUP])
+        .map_err(|err| EventListenerError::SignalHandlerError(err.to_string()))?;
+    thread::Builder::new()
+        .name("ConfigReload".into())
+        .spawn(move || {
+            for _ in reload_signals.forever() {
+                info!("Received SIGHUP, reloading the deployment configuration");
+                reload_deployment_config(&deployment_loader, &reload_config, &reload_publisher);
+            }
+        })
+        .map_err(|err| EventListenerError::SignalHandlerError(err.to_string()))?;
+
+    let export_mode = config.deployment_config().export_mode();
+    let (snapshot_worker, export_workers, aggregator) = match export_mode {
+        ExportMode::Aggregator => {
+            let aggregator =
+                Aggregator::start(config.deployment_config(), publisher.clone(), intake.clone())?;
+            (None, None, Some(aggregator))
+        }
+        ExportMode::Full | ExportMode::AdminOnly | ExportMode::StateOnly => {
+            // Get splinterd node information
+            let node = get_node(
+                config.splinterd_url(),
+                config.deployment_config(),
+            )?;
+            publisher.set_node_id(&node.identity);
+            let (snapshotter, export_workers) = event_handler::run(
+                shared_config,
+                node.identity.clone(),
+                private_key,
+                Sinks {
+                    publisher: publisher.clone(),
+                    proposal_cache,
+                    database,
+                },
+                status,
+                intake.clone(),
+                reactor.igniter(),
+                metrics,
+                manifest,
+                pauses,
+            )?;
+            (Some(snapshotter.start(snapshot_trigger)?), Some(export_workers), None)
+        }
+    };
+
+    publisher.publish_lifecycle(
+        Lifecycle::Started,
+        None,
+        json!({ "export_mode": export_mode }),
+    );
+
+    if shutdown_rx.recv().is_err() {
+        error!("Shutdown signal handler went away");
+    }
+    let shutdown_started = Instant::now();
+    publisher.publish_lifecycle(
+        Lifecycle::Draining,
+        None,
+        json!({ "drain_timeout": drain_timeout.as_secs() }),
+    );
+
+    // The stages run in order: nothing new is taken in, the queued events are delivered,
+    // the sinks flushed and the WAL synced before the connections are torn down
+    info!("Shutdown: stopping event intake");
+    intake.stop();
+    if let Some(snapshot_worker) = snapshot_worker {
+        snapshot_worker.shutdown();
+    }
+    if let Some(export_workers) = export_workers {
+        export_workers.shutdown();
+    }
+    if let Some(aggregator) = aggregator {
+        aggregator.shutdown();
+    }
+
+    if let Some(heartbeat) = heartbeat {
+        heartbeat.shutdown();
+    }
+
+    info!("Shutdown: draining queued events");
+    if let Err(in_flight) = intake.drain(drain_timeout) {
+        warn!(
+            "{} events still being processed after {:?}",
+            in_flight, drain_timeout
+        );
+    }
+    if let Some(wal_replayer) = wal_replayer {
+        wal_replayer.shutdown();
+    }
+    let flushed = match publisher.replay_wal() {
+        Ok(replayed) => replayed,
+        Err(err) => {
+            warn!("Unable to replay WAL: {}", err);
+            0
+        }
+    };
 
+    // Stopped before the sinks are closed, as its handlers publish through them
+    if let Some((rest_api_shutdown_handle, rest_api_join_handle)) = rest_api {
+        if let Err(err) = rest_api_shutdown_handle.shutdown() {
+            error!("Unable to cleanly shutdown the REST API: {}", err);
+        }
+        let _ = rest_api_join_handle.join();
+    }
+
+    let persisted = publisher.wal_stats().map_or(0, |stats| stats.pending);
+    publisher.publish_lifecycle(
+        Lifecycle::ShutdownComplete,
+        None,
+        json!({
+            "duration_ms": shutdown_started.elapsed().as_millis() as u64,
+            "flushed": flushed,
+            "persisted": persisted,
+        }),
+    );
+
+    info!("Shutdown: flushing and closing sinks");
+    publisher.close();
+    if let Some(lag_monitor) = lag_monitor {
+        lag_monitor.shutdown();
+    }
+
+    info!("Shutdown: syncing checkpoints");
+    if let Err(err) = publisher.sync_wal() {
+        error!("Unable to sync WAL: {}", err);
+    }
+
+    info!("Shutdown: stopping reactor");
     if let Err(err) = reactor.shutdown() {
         error!(
             "Unable to cleanly shutdown application authorization handler reactor: {}",
@@ -119,6 +616,13 @@ fn run() -> Result<(), EventListenerError> {
         );
     }
 
+    info!(
+        "Shutdown complete in {:?}: {} events flushed from the WAL, {} events persisted to the WAL",
+        shutdown_started.elapsed(),
+        flushed,
+        persisted
+    );
+
     Ok(())
 }
 
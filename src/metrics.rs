@@ -0,0 +1,149 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+
+/// Upper bounds of the histogram buckets, in seconds
+const BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Observations of a histogram series, counted in the bucket of their upper bound
+#[derive(Clone, Default)]
+struct Histogram {
+    buckets: [u64; 11],
+    sum: f64,
+    count: u64,
+}
+
+/// A minimal registry of gauges, counters and histograms, rendered in the Prometheus
+/// text format.
+#[derive(Clone, Default)]
+pub struct Metrics {
+    gauges: Arc<Mutex<BTreeMap<String, i64>>>,
+    counters: Arc<Mutex<BTreeMap<String, u64>>>,
+    /// By metric name and rendered labels, as the `le` label is added to each bucket
+    histograms: Arc<Mutex<BTreeMap<(String, String), Histogram>>>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Metrics::default()
+    }
+
+    pub fn set_gauge(&self, name: &str, labels: &[(&str, &str)], value: i64) {
+        match self.gauges.lock() {
+            Ok(mut gauges) => {
+                gauges.insert(series(name, labels), value);
+            }
+            Err(err) => error!("Unable to update gauge {}: {}", name, err),
+        }
+    }
+
+    pub fn incr_counter(&self, name: &str, labels: &[(&str, &str)], value: u64) {
+        match self.counters.lock() {
+            Ok(mut counters) => {
+                *counters.entry(series(name, labels)).or_insert(0) += value;
+            }
+            Err(err) => error!("Unable to update counter {}: {}", name, err),
+        }
+    }
+
+    /// Records an observation, e.g. a latency in seconds, in a histogram
+    pub fn observe(&self, name: &str, labels: &[(&str, &str)], value: f64) {
+        match self.histograms.lock() {
+            Ok(mut histograms) => {
+                let histogram = histograms
+                    .entry((name.to_string(), label_list(labels)))
+                    .or_insert_with(Histogram::default);
+                if let Some(bucket) = BUCKETS.iter().position(|bound| value <= *bound) {
+                    histogram.buckets[bucket] += 1;
+                }
+                histogram.sum += value;
+                histogram.count += 1;
+            }
+            Err(err) => error!("Unable to update histogram {}: {}", name, err),
+        }
+    }
+
+    /// Renders all the series in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut buf = String::new();
+        if let Ok(counters) = self.counters.lock() {
+            for (series, value) in counters.iter() {
+                writeln!(&mut buf, "{} {}", series, value).expect("Unable to write to string");
+            }
+        }
+        if let Ok(gauges) = self.gauges.lock() {
+            for (series, value) in gauges.iter() {
+                writeln!(&mut buf, "{} {}", series, value).expect("Unable to write to string");
+            }
+        }
+        if let Ok(histograms) = self.histograms.lock() {
+            for ((name, labels), histogram) in histograms.iter() {
+                render_histogram(&mut buf, name, labels, histogram);
+            }
+        }
+        buf
+    }
+}
+
+/// Renders the cumulative buckets, the sum and the count of a histogram series
+fn render_histogram(buf: &mut String, name: &str, labels: &str, histogram: &Histogram) {
+    let separator = if labels.is_empty() { "" } else { "," };
+    let mut cumulative = 0;
+    for (bound, count) in BUCKETS.iter().zip(histogram.buckets.iter()) {
+        cumulative += count;
+        writeln!(
+            buf,
+            "{}_bucket{{{}{}le=\"{}\"}} {}",
+            name, labels, separator, bound, cumulative
+        )
+        .expect("Unable to write to string");
+    }
+    writeln!(
+        buf,
+        "{}_bucket{{{}{}le=\"+Inf\"}} {}",
+        name, labels, separator, histogram.count
+    )
+    .expect("Unable to write to string");
+    let labels = if labels.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", labels)
+    };
+    writeln!(buf, "{}_sum{} {}", name, labels, histogram.sum).expect("Unable to write to string");
+    writeln!(buf, "{}_count{} {}", name, labels, histogram.count)
+        .expect("Unable to write to string");
+}
+
+fn series(name: &str, labels: &[(&str, &str)]) -> String {
+    if labels.is_empty() {
+        return name.to_string();
+    }
+    format!("{}{{{}}}", name, label_list(labels))
+}
+
+fn label_list(labels: &[(&str, &str)]) -> String {
+    labels
+        .iter()
+        .map(|(key, value)| format!("{}=\"{}\"", key, value.replace('"', "\\\"")))
+        .collect::<Vec<_>>()
+        .join(",")
+}
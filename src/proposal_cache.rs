@@ -0,0 +1,136 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use db_models::models::{NewConsortiumProposal, NewProposalVoteRecord};
+
+use crate::time::to_millis;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CachedProposal {
+    circuit_id: String,
+    circuit_hash: String,
+    proposal_type: String,
+    requester: String,
+    requester_node_id: String,
+    status: String,
+    created_time: u64,
+    updated_time: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CachedVote {
+    voter_public_key: String,
+    voter_node_id: String,
+    vote: String,
+    created_time: u64,
+}
+
+/// A queryable in-memory view of the proposals and votes seen on the admin event stream,
+/// served by the rest API for dashboards that do not run a separate consumer.
+#[derive(Clone, Default)]
+pub struct ProposalCache {
+    proposals: Arc<Mutex<BTreeMap<String, CachedProposal>>>,
+    votes: Arc<Mutex<BTreeMap<String, Vec<CachedVote>>>>,
+}
+
+impl ProposalCache {
+    pub fn new() -> Self {
+        ProposalCache::default()
+    }
+
+    pub fn add_proposal(&self, proposal: &NewConsortiumProposal) {
+        let cached = CachedProposal {
+            circuit_id: proposal.circuit_id.clone(),
+            circuit_hash: proposal.circuit_hash.clone(),
+            proposal_type: proposal.proposal_type.clone(),
+            requester: proposal.requester.clone(),
+            requester_node_id: proposal.requester_node_id.clone(),
+            status: proposal.status.clone(),
+            created_time: to_millis(proposal.created_time),
+            updated_time: to_millis(proposal.updated_time),
+        };
+        match self.proposals.lock() {
+            Ok(mut proposals) => {
+                proposals.insert(cached.circuit_id.clone(), cached);
+            }
+            Err(err) => error!("Unable to cache proposal: {}", err),
+        }
+    }
+
+    pub fn update_status(&self, circuit_id: &str, status: &str, time: SystemTime) {
+        match self.proposals.lock() {
+            Ok(mut proposals) => {
+                if let Some(proposal) = proposals.get_mut(circuit_id) {
+                    proposal.status = status.to_string();
+                    proposal.updated_time = to_millis(time);
+                }
+            }
+            Err(err) => error!("Unable to update cached proposal: {}", err),
+        }
+    }
+
+    pub fn add_vote(&self, circuit_id: &str, vote: &NewProposalVoteRecord) {
+        let cached = CachedVote {
+            voter_public_key: vote.voter_public_key.clone(),
+            voter_node_id: vote.voter_node_id.clone(),
+            vote: vote.vote.clone(),
+            created_time: to_millis(vote.created_time),
+        };
+        match self.votes.lock() {
+            Ok(mut votes) => votes
+                .entry(circuit_id.to_string())
+                .or_insert_with(Vec::new)
+                .push(cached),
+            Err(err) => error!("Unable to cache vote: {}", err),
+        }
+    }
+
+    pub fn list_proposals(&self) -> Vec<CachedProposal> {
+        match self.proposals.lock() {
+            Ok(proposals) => proposals.values().cloned().collect(),
+            Err(err) => {
+                error!("Unable to read cached proposals: {}", err);
+                Vec::new()
+            }
+        }
+    }
+
+    /// Returns the votes recorded for a circuit, or `None` if its proposal is not known
+    pub fn list_votes(&self, circuit_id: &str) -> Option<Vec<CachedVote>> {
+        let is_known = match self.proposals.lock() {
+            Ok(proposals) => proposals.contains_key(circuit_id),
+            Err(err) => {
+                error!("Unable to read cached proposals: {}", err);
+                false
+            }
+        };
+        if !is_known {
+            return None;
+        }
+        match self.votes.lock() {
+            Ok(votes) => Some(votes.get(circuit_id).cloned().unwrap_or_default()),
+            Err(err) => {
+                error!("Unable to read cached votes: {}", err);
+                None
+            }
+        }
+    }
+}
@@ -0,0 +1,670 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+//! Avro encoding of the exported messages, in the Confluent wire format.
+//!
+//! Every topic carries a single `Event` record whose `message` field is a union of the
+//! message records, indexed like `Message.MessageType` with `null` for `TYPE_UNKNOWN`.
+//! The schema is registered under the `<topic>-value` subject on first use, and each
+//! record is prefixed with the magic byte and the id the registry assigned to it.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use futures::{Future, Stream};
+use hyper::{Body, Client, Request, StatusCode};
+use protobuf::{Message as Msg, ProtobufEnum};
+use serde_json::Value;
+use tokio::runtime::Runtime;
+
+use super::PublisherError;
+use crate::http::{self, Connector};
+use crate::proto::pubsub::{
+    Ballot, CircuitCreated, CircuitMember, CircuitPayload, CircuitPayloadDeleted, CircuitRemoved,
+    CircuitService, ContractDeployFailed, Control, Message, Message_MessageType, ProposalAccept,
+    ProposalReady, ProposalReject, ProposalSubmit, ProposalVote,
+};
+
+/// First byte of every message in the Confluent wire format
+const MAGIC_BYTE: u8 = 0;
+
+const NAMESPACE: &str = "splinter.exporter";
+
+/// Encodes envelopes as Avro, registering the schema with a Confluent Schema Registry
+pub struct AvroEncoder {
+    registry_url: String,
+    schema: String,
+    client: Client<Connector>,
+    runtime: Mutex<Runtime>,
+    /// schema ids by subject
+    schema_ids: Mutex<HashMap<String, u32>>,
+}
+
+impl AvroEncoder {
+    pub fn new(registry_url: &str) -> Result<Self, PublisherError> {
+        Ok(AvroEncoder {
+            registry_url: registry_url.trim_end_matches('/').to_string(),
+            schema: schema().to_string(),
+            client: http::client().map_err(registry_error)?,
+            runtime: Mutex::new(Runtime::new().map_err(registry_error)?),
+            schema_ids: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn encode(&self, topic: &str, envelope: &Message) -> Result<Vec<u8>, PublisherError> {
+        let schema_id = self.schema_id(&format!("{}-value", topic))?;
+        let mut buf = vec![MAGIC_BYTE];
+        buf.extend_from_slice(&schema_id.to_be_bytes());
+        write_event(&mut buf, envelope)?;
+        Ok(buf)
+    }
+
+    fn schema_id(&self, subject: &str) -> Result<u32, PublisherError> {
+        let mut schema_ids = self
+            .schema_ids
+            .lock()
+            .map_err(|err| PublisherError::LockPoisoned(err.to_string()))?;
+        if let Some(schema_id) = schema_ids.get(subject) {
+            return Ok(*schema_id);
+        }
+        let schema_id = self.register(subject)?;
+        info!("Registered schema {} for subject {}", schema_id, subject);
+        schema_ids.insert(subject.to_string(), schema_id);
+        Ok(schema_id)
+    }
+
+    /// Registers the schema under the subject, returns the id assigned by the registry.
+    /// Registering a schema the subject already has returns its existing id.
+    fn register(&self, subject: &str) -> Result<u32, PublisherError> {
+        let body = json!({ "schema": self.schema }).to_string();
+        let uri = format!("{}/subjects/{}/versions", self.registry_url, subject);
+        let request = Request::post(uri.as_str())
+            .header("Content-Type", "application/vnd.schemaregistry.v1+json")
+        .body(Body::from(body))
+        .map_err(registry_error)?;
+
+        let mut runtime = self
+            .runtime
+            .lock()
+            .map_err(|err| PublisherError::LockPoisoned(err.to_string()))?;
+        let (status, body) = runtime
+            .block_on(self.client.request(request).and_then(|resp| {
+                let status = resp.status();
+                resp.into_body()
+                    .concat2()
+                    .map(move |body| (status, body.to_vec()))
+            }))
+            .map_err(registry_error)?;
+
+        if status != StatusCode::OK {
+            return Err(PublisherError::SchemaRegistryError(format!(
+                "registry responded with status {} registering {}: {}",
+                status,
+                subject,
+                String::from_utf8_lossy(&body)
+            )));
+        }
+        serde_json::from_slice::<Value>(&body)
+            .ok()
+            .and_then(|response| response.get("id").and_then(Value::as_u64))
+            .map(|id| id as u32)
+            .ok_or_else(|| {
+                PublisherError::SchemaRegistryError(format!(
+                    "registry returned no schema id for {}",
+                    subject
+                ))
+            })
+    }
+}
+
+fn registry_error<E: std::fmt::Display>(err: E) -> PublisherError {
+    PublisherError::SchemaRegistryError(err.to_string())
+}
+
+/// Returns the schema of the `Event` record
+pub fn schema() -> Value {
+    let message_types = Message_MessageType::values()
+        .iter()
+        .map(|value| value.descriptor().name())
+        .collect::<Vec<_>>();
+    let parties = |name: &str, party: &str| {
+        record(
+            name,
+            vec![
+                string_field(party),
+                string_field(&format!("{}_node_id", party)),
+                string_field("circuit_id"),
+                proposal_id_field(),
+            ],
+        )
+    };
+    let submit = || {
+        record(
+            "ProposalSubmit",
+            vec![
+                string_field("requester"),
+                string_field("requester_node_id"),
+                string_field("circuit_id"),
+                proposal_id_field(),
+                json!({
+                    "name": "circuit",
+                    "type": [
+                        "null",
+                        record(
+                            "CircuitDefinition",
+                            vec![
+                                string_field("circuit_id"),
+                                string_field("authorization_type"),
+                                string_field("persistence"),
+                                string_field("durability"),
+                                string_field("routes"),
+                                string_field("circuit_management_type"),
+                                string_field("alias"),
+                                string_field("comments"),
+                                array_field(
+                                    "services",
+                                    record(
+                                        "CircuitService",
+                                        vec![
+                                            string_field("service_id"),
+                                            string_field("service_type"),
+                                            array_field("allowed_nodes", json!("string")),
+                                            array_field(
+                                                "arguments",
+                                                record(
+                                                    "ServiceArgument",
+                                                    vec![
+                                                        string_field("key"),
+                                                        string_field("value"),
+                                                    ],
+                                                ),
+                                            ),
+                                        ],
+                                    ),
+                                ),
+                                array_field(
+                                    "members",
+                                    record(
+                                        "CircuitMember",
+                                        vec![string_field("node_id"), string_field("endpoint")],
+                                    ),
+                                ),
+                            ],
+                        ),
+                    ],
+                    "default": null,
+                }),
+            ],
+        )
+    };
+    let vote = || {
+        record(
+            "ProposalVote",
+            vec![
+                string_field("voter"),
+                string_field("voter_node_id"),
+                string_field("circuit_id"),
+                proposal_id_field(),
+                voter_display_name_field(),
+                voter_node_metadata_field(),
+                array_field(
+                    "votes",
+                    record(
+                        "Ballot",
+                        vec![
+                            string_field("voter"),
+                            string_field("voter_node_id"),
+                            json!({
+                                "name": "vote",
+                                "type": {
+                                    "type": "enum",
+                                    "name": "Vote",
+                                    "symbols": ["ACCEPT", "REJECT"],
+                                },
+                            }),
+                            json!({ "name": "observed_at", "type": "long" }),
+                        ],
+                    ),
+                ),
+                required_votes_field(),
+            ],
+        )
+    };
+    let decision = |name: &str| {
+        record(
+            name,
+            vec![
+                string_field("voter"),
+                string_field("voter_node_id"),
+                string_field("circuit_id"),
+                string_field("alias"),
+                string_field("comments"),
+                proposal_id_field(),
+                voter_display_name_field(),
+                voter_node_metadata_field(),
+                // Defined by ProposalVote, which comes first in the union
+                array_field("votes", json!("Ballot")),
+                required_votes_field(),
+            ],
+        )
+    };
+
+    record(
+        "Event",
+        vec![
+            json!({
+                "name": "type",
+                "type": {
+                    "type": "enum",
+                    "name": "MessageType",
+                    "symbols": message_types,
+                },
+            }),
+            json!({
+                "name": "message",
+                "type": [
+                    "null",
+                    submit(),
+                    vote(),
+                    decision("ProposalAccept"),
+                    decision("ProposalReject"),
+                    parties("ProposalReady", "requester"),
+                    record(
+                        "CircuitCreated",
+                        vec![
+                            string_field("requester"),
+                            string_field("requester_node_id"),
+                            string_field("circuit_id"),
+                            // Defined by ProposalSubmit's circuit definition
+                            array_field("services", json!("CircuitService")),
+                            array_field("members", json!("CircuitMember")),
+                        ],
+                    ),
+                    record(
+                        "CircuitPayload",
+                        vec![
+                            string_field("requester"),
+                            string_field("requester_node_id"),
+                            string_field("circuit_id"),
+                            json!({ "name": "data", "type": "bytes" }),
+                            string_field("key"),
+                            json!({
+                                "name": "encoding",
+                                "type": {
+                                    "type": "enum",
+                                    "name": "Encoding",
+                                    "symbols": ["FULL", "BSDIFF"],
+                                },
+                            }),
+                            json!({ "name": "sequence", "type": "long" }),
+                            json!({
+                                "name": "origin",
+                                "type": {
+                                    "type": "enum",
+                                    "name": "Origin",
+                                    "symbols": ["STATE_CHANGE", "OPERATOR_SNAPSHOT"],
+                                },
+                                "default": "STATE_CHANGE",
+                            }),
+                            json!({ "name": "contract_name", "type": "string", "default": "" }),
+                            json!({ "name": "decoded", "type": "string", "default": "" }),
+                            json!({ "name": "decode_error", "type": "string", "default": "" }),
+                            json!({ "name": "service_id", "type": "string", "default": "" }),
+                            json!({ "name": "received_at", "type": "long", "default": 0 }),
+                        ],
+                    ),
+                    record(
+                        "Control",
+                        vec![
+                            string_field("command"),
+                            string_field("detail"),
+                            json!({ "name": "effective_at", "type": "long" }),
+                            json!({ "name": "issued_at", "type": "long" }),
+                        ],
+                    ),
+                    record(
+                        "CircuitRemoved",
+                        vec![
+                            string_field("requester"),
+                            string_field("requester_node_id"),
+                            string_field("circuit_id"),
+                            json!({ "name": "detected_at", "type": "long" }),
+                        ],
+                    ),
+                    record(
+                        "ContractDeployFailed",
+                        vec![
+                            string_field("circuit_id"),
+                            string_field("service_id"),
+                            array_field(
+                                "contracts",
+                                record(
+                                    "SabreContract",
+                                    vec![
+                                        string_field("name"),
+                                        string_field("version"),
+                                        string_field("prefix"),
+                                    ],
+                                ),
+                            ),
+                            string_field("batch_id"),
+                            string_field("reason"),
+                            json!({ "name": "attempts", "type": "long" }),
+                            json!({ "name": "failed_at", "type": "long" }),
+                        ],
+                    ),
+                    record(
+                        "CircuitPayloadDeleted",
+                        vec![
+                            string_field("requester"),
+                            string_field("requester_node_id"),
+                            string_field("circuit_id"),
+                            string_field("service_id"),
+                            string_field("key"),
+                            string_field("contract_name"),
+                            json!({ "name": "detected_at", "type": "long" }),
+                        ],
+                    ),
+                ],
+            }),
+            string_field("run_id"),
+            json!({ "name": "run_started_at", "type": "long" }),
+            json!({ "name": "circuit_management_type", "type": "string", "default": "" }),
+            json!({ "name": "sequence", "type": "long", "default": 0 }),
+            json!({ "name": "exporter_node_id", "type": "string", "default": "" }),
+            json!({ "name": "exporter_version", "type": "string", "default": "" }),
+        ],
+    )
+}
+
+fn record(name: &str, fields: Vec<Value>) -> Value {
+    json!({
+        "type": "record",
+        "name": name,
+        "namespace": NAMESPACE,
+        "fields": fields,
+    })
+}
+
+fn string_field(name: &str) -> Value {
+    json!({ "name": name, "type": "string" })
+}
+
+/// The proposal id, empty for records written before the field existed
+fn proposal_id_field() -> Value {
+    json!({ "name": "proposal_id", "type": "string", "default": "" })
+}
+
+/// The voter node's display name, empty for records written before the field existed
+fn voter_display_name_field() -> Value {
+    json!({ "name": "voter_display_name", "type": "string", "default": "" })
+}
+
+/// The voter node's registry metadata, empty for records written before the field existed
+fn voter_node_metadata_field() -> Value {
+    json!({
+        "name": "voter_node_metadata",
+        "type": { "type": "map", "values": "string" },
+        "default": {},
+    })
+}
+
+/// The accepting votes a proposal needs, zero for records written before the field existed
+fn required_votes_field() -> Value {
+    json!({ "name": "required_votes", "type": "int", "default": 0 })
+}
+
+/// An array field, empty for records written before the field existed
+fn array_field(name: &str, items: Value) -> Value {
+    json!({
+        "name": name,
+        "type": { "type": "array", "items": items },
+        "default": [],
+    })
+}
+
+/// Writes the `Event` record, the fields in the order of the schema
+fn write_event(buf: &mut Vec<u8>, envelope: &Message) -> Result<(), PublisherError> {
+    let message_type = envelope.get_field_type();
+    write_long(buf, i64::from(message_type.value()));
+
+    // The union branch index is the message type number
+    write_long(buf, i64::from(message_type.value()));
+    let bytes = envelope.get_message();
+    match message_type {
+        Message_MessageType::TYPE_UNKNOWN => (),
+        Message_MessageType::PROPOSAL_SUBMIT => {
+            let submit = parse::<ProposalSubmit>(bytes)?;
+            write_string(buf, submit.get_requester());
+            write_string(buf, submit.get_requester_node_id());
+            write_string(buf, submit.get_circuit_id());
+            write_string(buf, submit.get_proposal_id());
+            // The union branch of the definition, null for proposals exported without one
+            if submit.has_circuit() {
+                write_long(buf, 1);
+                let definition = submit.get_circuit();
+                write_string(buf, definition.get_circuit_id());
+                write_string(buf, definition.get_authorization_type());
+                write_string(buf, definition.get_persistence());
+                write_string(buf, definition.get_durability());
+                write_string(buf, definition.get_routes());
+                write_string(buf, definition.get_circuit_management_type());
+                write_string(buf, definition.get_alias());
+                write_string(buf, definition.get_comments());
+                write_services(buf, definition.get_services());
+                write_members(buf, definition.get_members());
+            } else {
+                write_long(buf, 0);
+            }
+        }
+        Message_MessageType::PROPOSAL_VOTE => {
+            let vote = parse::<ProposalVote>(bytes)?;
+            write_string(buf, vote.get_voter());
+            write_string(buf, vote.get_voter_node_id());
+            write_string(buf, vote.get_circuit_id());
+            write_string(buf, vote.get_proposal_id());
+            write_string(buf, vote.get_voter_display_name());
+            write_string_map(buf, vote.get_voter_node_metadata());
+            write_ballots(buf, vote.get_votes());
+            write_long(buf, i64::from(vote.get_required_votes()));
+        }
+        Message_MessageType::PROPOSAL_ACCEPT => {
+            let accept = parse::<ProposalAccept>(bytes)?;
+            write_string(buf, accept.get_voter());
+            write_string(buf, accept.get_voter_node_id());
+            write_string(buf, accept.get_circuit_id());
+            write_string(buf, accept.get_alias());
+            write_string(buf, accept.get_comments());
+            write_string(buf, accept.get_proposal_id());
+            write_string(buf, accept.get_voter_display_name());
+            write_string_map(buf, accept.get_voter_node_metadata());
+            write_ballots(buf, accept.get_votes());
+            write_long(buf, i64::from(accept.get_required_votes()));
+        }
+        Message_MessageType::PROPOSAL_REJECT => {
+            let reject = parse::<ProposalReject>(bytes)?;
+            write_string(buf, reject.get_voter());
+            write_string(buf, reject.get_voter_node_id());
+            write_string(buf, reject.get_circuit_id());
+            write_string(buf, reject.get_alias());
+            write_string(buf, reject.get_comments());
+            write_string(buf, reject.get_proposal_id());
+            write_string(buf, reject.get_voter_display_name());
+            write_string_map(buf, reject.get_voter_node_metadata());
+            write_ballots(buf, reject.get_votes());
+            write_long(buf, i64::from(reject.get_required_votes()));
+        }
+        Message_MessageType::PROPOSAL_READY => {
+            let ready = parse::<ProposalReady>(bytes)?;
+            write_string(buf, ready.get_requester());
+            write_string(buf, ready.get_requester_node_id());
+            write_string(buf, ready.get_circuit_id());
+            write_string(buf, ready.get_proposal_id());
+        }
+        Message_MessageType::CIRCUIT_CREATED => {
+            let created = parse::<CircuitCreated>(bytes)?;
+            write_string(buf, created.get_requester());
+            write_string(buf, created.get_requester_node_id());
+            write_string(buf, created.get_circuit_id());
+            write_services(buf, created.get_services());
+            write_members(buf, created.get_members());
+        }
+        Message_MessageType::CIRCUIT_PAYLOAD => {
+            let payload = parse::<CircuitPayload>(bytes)?;
+            // The schema has no place for the wrapped key, without which the data is lost
+            if payload.has_encryption() {
+                return Err(PublisherError::EncryptionError(
+                    "encrypted payloads cannot be written as Avro, use protobuf or json".into(),
+                ));
+            }
+            write_string(buf, payload.get_requester());
+            write_string(buf, payload.get_requester_node_id());
+            write_string(buf, payload.get_circuit_id());
+            write_bytes(buf, payload.get_data());
+            write_string(buf, payload.get_key());
+            write_long(buf, i64::from(payload.get_encoding().value()));
+            write_long(buf, payload.get_sequence() as i64);
+            write_long(buf, i64::from(payload.get_origin().value()));
+            write_string(buf, payload.get_contract_name());
+            write_string(buf, payload.get_decoded());
+            write_string(buf, payload.get_decode_error());
+            write_string(buf, payload.get_service_id());
+            write_long(buf, payload.get_received_at() as i64);
+        }
+        Message_MessageType::CONTROL => {
+            let control = parse::<Control>(bytes)?;
+            write_string(buf, control.get_command());
+            write_string(buf, control.get_detail());
+            write_long(buf, control.get_effective_at() as i64);
+            write_long(buf, control.get_issued_at() as i64);
+        }
+        Message_MessageType::CIRCUIT_REMOVED => {
+            let removed = parse::<CircuitRemoved>(bytes)?;
+            write_string(buf, removed.get_requester());
+            write_string(buf, removed.get_requester_node_id());
+            write_string(buf, removed.get_circuit_id());
+            write_long(buf, removed.get_detected_at() as i64);
+        }
+        Message_MessageType::CONTRACT_DEPLOY_FAILED => {
+            let failed = parse::<ContractDeployFailed>(bytes)?;
+            write_string(buf, failed.get_circuit_id());
+            write_string(buf, failed.get_service_id());
+            write_array(buf, failed.get_contracts(), |buf, contract| {
+                write_string(buf, contract.get_name());
+                write_string(buf, contract.get_version());
+                write_string(buf, contract.get_prefix());
+            });
+            write_string(buf, failed.get_batch_id());
+            write_string(buf, failed.get_reason());
+            write_long(buf, i64::from(failed.get_attempts()));
+            write_long(buf, failed.get_failed_at() as i64);
+        }
+        Message_MessageType::CIRCUIT_PAYLOAD_DELETED => {
+            let deleted = parse::<CircuitPayloadDeleted>(bytes)?;
+            write_string(buf, deleted.get_requester());
+            write_string(buf, deleted.get_requester_node_id());
+            write_string(buf, deleted.get_circuit_id());
+            write_string(buf, deleted.get_service_id());
+            write_string(buf, deleted.get_key());
+            write_string(buf, deleted.get_contract_name());
+            write_long(buf, deleted.get_detected_at() as i64);
+        }
+    }
+
+    write_string(buf, envelope.get_run_id());
+    write_long(buf, envelope.get_run_started_at() as i64);
+    write_string(buf, envelope.get_circuit_management_type());
+    write_long(buf, envelope.get_sequence() as i64);
+    write_string(buf, envelope.get_exporter_node_id());
+    write_string(buf, envelope.get_exporter_version());
+    Ok(())
+}
+
+fn write_services(buf: &mut Vec<u8>, services: &[CircuitService]) {
+    write_array(buf, services, |buf, service| {
+        write_string(buf, service.get_service_id());
+        write_string(buf, service.get_service_type());
+        write_array(buf, service.get_allowed_nodes(), |buf, node| {
+            write_string(buf, node)
+        });
+        write_array(buf, service.get_arguments(), |buf, argument| {
+            write_string(buf, argument.get_key());
+            write_string(buf, argument.get_value());
+        });
+    });
+}
+
+fn write_members(buf: &mut Vec<u8>, members: &[CircuitMember]) {
+    write_array(buf, members, |buf, member| {
+        write_string(buf, member.get_node_id());
+        write_string(buf, member.get_endpoint());
+    });
+}
+
+fn write_ballots(buf: &mut Vec<u8>, ballots: &[Ballot]) {
+    write_array(buf, ballots, |buf, ballot| {
+        write_string(buf, ballot.get_voter());
+        write_string(buf, ballot.get_voter_node_id());
+        write_long(buf, i64::from(ballot.get_vote().value()));
+        write_long(buf, ballot.get_observed_at() as i64);
+    });
+}
+
+fn parse<M: Msg>(bytes: &[u8]) -> Result<M, PublisherError> {
+    protobuf::parse_from_bytes::<M>(bytes).map_err(PublisherError::SerializationError)
+}
+
+/// Avro ints and longs are zig-zag encoded varints
+fn write_long(buf: &mut Vec<u8>, value: i64) {
+    let mut value = ((value << 1) ^ (value >> 63)) as u64;
+    while value >= 0x80 {
+        buf.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn write_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    write_long(buf, bytes.len() as i64);
+    buf.extend_from_slice(bytes);
+}
+
+fn write_string(buf: &mut Vec<u8>, value: &str) {
+    write_bytes(buf, value.as_bytes());
+}
+
+/// Maps are written like arrays of key and value pairs, in key order to keep records
+/// of the same event identical
+fn write_string_map(buf: &mut Vec<u8>, map: &HashMap<String, String>) {
+    let mut entries = map.iter().collect::<Vec<_>>();
+    entries.sort();
+    write_array(buf, &entries, |buf, (key, value)| {
+        write_string(buf, key);
+        write_string(buf, value);
+    });
+}
+
+/// Arrays are written as a single block: the item count, the items and the empty block
+fn write_array<T, F: Fn(&mut Vec<u8>, &T)>(buf: &mut Vec<u8>, items: &[T], write_item: F) {
+    if !items.is_empty() {
+        write_long(buf, items.len() as i64);
+        for item in items {
+            write_item(buf, item);
+        }
+    }
+    write_long(buf, 0);
+}
@@ -0,0 +1,195 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use openssl::error::ErrorStack;
+use openssl::pkey::{PKey, Public};
+use openssl::rand::rand_bytes;
+use openssl::rsa::{Padding, Rsa};
+use openssl::sha::sha256;
+use openssl::symm::{decrypt_aead, encrypt_aead, Cipher};
+
+use super::PublisherError;
+use crate::event_handler::to_hex;
+use crate::proto::pubsub::{CircuitPayload, PayloadEncryption};
+
+/// Length in bytes of the AES-256 data keys
+const DATA_KEY_LEN: usize = 32;
+/// Length in bytes of the GCM nonces
+const IV_LEN: usize = 12;
+/// Length in bytes of the GCM authentication tags
+const TAG_LEN: usize = 16;
+/// First byte of the WAL entries encrypted at rest, which neither the keyed nor the
+/// older entries start with
+const SEALED_ENTRY_MARKER: u8 = 0xfe;
+/// Length in bytes of the key id stored in sealed WAL entries
+const KEY_ID_LEN: usize = 8;
+
+/// Encrypts the data of a circuit's payloads for the consumer whose RSA public key the
+/// circuit's application metadata designates.
+///
+/// Each payload gets a fresh data key, wrapped with RSA-OAEP, so only the holder of the
+/// matching private key can read it even on a shared broker.
+pub struct PayloadEncryptor {
+    key: Rsa<Public>,
+    key_id: String,
+}
+
+impl PayloadEncryptor {
+    /// Reads a PEM encoded RSA public key, in SubjectPublicKeyInfo or PKCS#1 form
+    pub fn from_pem(pem: &str) -> Result<Self, PublisherError> {
+        let key = match PKey::public_key_from_pem(pem.as_bytes()) {
+            Ok(key) => key.rsa(),
+            Err(_) => Rsa::public_key_from_pem_pkcs1(pem.as_bytes()),
+        }
+        .map_err(encryption_error)?;
+        let key_id = to_hex(&sha256(&key.public_key_to_der().map_err(encryption_error)?));
+        Ok(PayloadEncryptor { key, key_id })
+    }
+
+    pub fn encrypt(&self, payload: &mut CircuitPayload) -> Result<(), PublisherError> {
+        let mut data_key = [0; DATA_KEY_LEN];
+        let mut iv = [0; IV_LEN];
+        rand_bytes(&mut data_key).map_err(encryption_error)?;
+        rand_bytes(&mut iv).map_err(encryption_error)?;
+
+        let mut tag = [0; TAG_LEN];
+        let ciphertext = encrypt_aead(
+            Cipher::aes_256_gcm(),
+            &data_key,
+            Some(&iv),
+            payload.get_circuit_id().as_bytes(),
+            payload.get_data(),
+            &mut tag,
+        )
+        .map_err(encryption_error)?;
+
+        let mut wrapped_key = vec![0; self.key.size() as usize];
+        let wrapped_len = self
+            .key
+            .public_encrypt(&data_key, &mut wrapped_key, Padding::PKCS1_OAEP)
+            .map_err(encryption_error)?;
+        wrapped_key.truncate(wrapped_len);
+
+        let mut encryption = PayloadEncryption::new();
+        encryption.set_wrapped_key(wrapped_key);
+        encryption.set_iv(iv.to_vec());
+        encryption.set_tag(tag.to_vec());
+        encryption.set_key_id(self.key_id.clone());
+        payload.set_data(ciphertext);
+        // The decoded value would give the plaintext away
+        payload.clear_decoded();
+        payload.set_encryption(encryption);
+        Ok(())
+    }
+}
+
+/// Encrypts the WAL entries at rest with AES-256-GCM, as they hold undelivered payloads
+/// on disks which may be shared with other tenants of the node.
+///
+/// A sealed entry is the marker, the id of the key, the nonce, the tag and the
+/// ciphertext. The id lets the WAL refuse entries sealed with another key upfront,
+/// instead of quarantining each of them as corrupted.
+#[derive(Clone)]
+pub struct WalCipher {
+    key: Vec<u8>,
+    key_id: Vec<u8>,
+}
+
+impl WalCipher {
+    /// Reads a key given as 64 hex digits
+    pub fn from_hex(hex: &str) -> Result<Self, PublisherError> {
+        let key = parse_hex(hex.trim())
+            .filter(|key| key.len() == DATA_KEY_LEN)
+            .ok_or_else(|| {
+                PublisherError::EncryptionError(
+                    "the WAL encryption key must be 64 hex digits".to_string(),
+                )
+            })?;
+        let key_id = sha256(&key)[..KEY_ID_LEN].to_vec();
+        Ok(WalCipher { key, key_id })
+    }
+
+    pub fn key_id(&self) -> String {
+        to_hex(&self.key_id)
+    }
+
+    pub fn seal(&self, entry: &[u8]) -> Result<Vec<u8>, PublisherError> {
+        let mut iv = [0; IV_LEN];
+        rand_bytes(&mut iv).map_err(encryption_error)?;
+        let mut header = vec![SEALED_ENTRY_MARKER];
+        header.extend_from_slice(&self.key_id);
+
+        let mut tag = [0; TAG_LEN];
+        let ciphertext = encrypt_aead(
+            Cipher::aes_256_gcm(),
+            &self.key,
+            Some(&iv),
+            &header,
+            entry,
+            &mut tag,
+        )
+        .map_err(encryption_error)?;
+
+        let mut sealed = header;
+        sealed.extend_from_slice(&iv);
+        sealed.extend_from_slice(&tag);
+        sealed.extend_from_slice(&ciphertext);
+        Ok(sealed)
+    }
+
+    /// Returns the entry a sealed one holds, `None` if it was tampered with or truncated
+    pub fn open(&self, sealed: &[u8]) -> Option<Vec<u8>> {
+        let header_len = 1 + KEY_ID_LEN;
+        if sealed.len() < header_len + IV_LEN + TAG_LEN {
+            return None;
+        }
+        let (header, rest) = sealed.split_at(header_len);
+        let (iv, rest) = rest.split_at(IV_LEN);
+        let (tag, ciphertext) = rest.split_at(TAG_LEN);
+        decrypt_aead(
+            Cipher::aes_256_gcm(),
+            &self.key,
+            Some(iv),
+            header,
+            ciphertext,
+            tag,
+        )
+        .ok()
+    }
+}
+
+/// Returns the id of the key a WAL entry was sealed with, `None` if it is in the clear
+pub fn sealed_key_id(entry: &[u8]) -> Option<String> {
+    if entry.first() != Some(&SEALED_ENTRY_MARKER) || entry.len() <= KEY_ID_LEN {
+        return None;
+    }
+    Some(to_hex(&entry[1..=KEY_ID_LEN]))
+}
+
+fn parse_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+fn encryption_error(err: ErrorStack) -> PublisherError {
+    PublisherError::EncryptionError(err.to_string())
+}
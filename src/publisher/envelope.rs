@@ -0,0 +1,150 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+
+use protobuf::Message as Msg;
+use uuid::Uuid;
+
+use super::PublisherError;
+use crate::proto::pubsub::{EnvelopeV2, Message, Message_MessageType};
+use crate::time::now_millis;
+
+/// Envelope schema versions the exporter can write
+pub const ENVELOPE_VERSIONS: &[u32] = &[1, 2];
+
+/// Writes a second envelope version alongside `envelope_version` for an overlap window,
+/// so consumers can move from one to the other without a flag day
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DualWriteConfig {
+    pub version: u32,
+    /// appended to the topic of each message for the second version, which shares the
+    /// topic when empty
+    #[serde(default)]
+    pub topic_suffix: String,
+    /// end of the overlap window, in seconds since the UNIX epoch
+    pub until: u64,
+}
+
+impl DualWriteConfig {
+    pub fn is_active(&self) -> bool {
+        now_millis() / 1000 < self.until
+    }
+}
+
+/// Converts an envelope into version 2
+pub fn to_v2(circuit_id: &str, envelope: &Message) -> EnvelopeV2 {
+    let mut v2 = EnvelopeV2::new();
+    v2.set_schema_version(2);
+    v2.set_field_type(envelope.get_field_type());
+    v2.set_circuit_id(circuit_id.to_string());
+    v2.set_message(envelope.get_message().to_vec());
+    v2.set_run_id(envelope.get_run_id().to_string());
+    v2.set_run_started_at(envelope.get_run_started_at());
+    v2.set_published_at(now_millis());
+    v2.set_circuit_management_type(envelope.get_circuit_management_type().to_string());
+    v2.set_sequence(envelope.get_sequence());
+    v2.set_exporter_node_id(envelope.get_exporter_node_id().to_string());
+    v2.set_exporter_version(envelope.get_exporter_version().to_string());
+    v2
+}
+
+/// Wraps messages in the `Message` envelope, stamping each one with the identity of the
+/// current exporter run so consumers can detect restarts and tell replays apart.
+#[derive(Clone)]
+pub struct EnvelopeBuilder {
+    run_id: String,
+    run_started_at: u64,
+    /// Known once the exporter has queried its splinterd node
+    node_id: Arc<RwLock<String>>,
+}
+
+impl Default for EnvelopeBuilder {
+    fn default() -> Self {
+        EnvelopeBuilder {
+            run_id: Uuid::new_v4().to_string(),
+            run_started_at: now_millis(),
+            node_id: Arc::new(RwLock::new(String::new())),
+        }
+    }
+}
+
+impl EnvelopeBuilder {
+    pub fn new() -> Self {
+        EnvelopeBuilder::default()
+    }
+
+    pub fn build<M: Msg>(
+        &self,
+        message_type: Message_MessageType,
+        message: &M,
+    ) -> Result<Message, PublisherError> {
+        let message = message
+            .write_to_bytes()
+            .map_err(PublisherError::SerializationError)?;
+        Ok(self.build_serialized(message_type, message))
+    }
+
+    /// Identifies the exporter process
+    pub fn run_id(&self) -> &str {
+        &self.run_id
+    }
+
+    pub fn set_node_id(&self, node_id: &str) {
+        match self.node_id.write() {
+            Ok(mut current) => *current = node_id.to_string(),
+            Err(err) => error!("Unable to record the exporter node id: {}", err),
+        }
+    }
+
+    /// Wraps a message which is already serialized
+    pub fn build_serialized(&self, message_type: Message_MessageType, message: Vec<u8>) -> Message {
+        let mut envelope = Message::new();
+        envelope.set_field_type(message_type);
+        envelope.set_message(message);
+        envelope.set_run_id(self.run_id.clone());
+        envelope.set_run_started_at(self.run_started_at);
+        if let Ok(node_id) = self.node_id.read() {
+            envelope.set_exporter_node_id(node_id.clone());
+        }
+        envelope.set_exporter_version(env!("CARGO_PKG_VERSION").to_string());
+        envelope
+    }
+}
+
+/// Numbers the envelopes of each circuit in the order they are published
+#[derive(Clone, Default)]
+pub struct Sequencer {
+    next: Arc<Mutex<HashMap<String, u64>>>,
+}
+
+impl Sequencer {
+    pub fn new() -> Self {
+        Sequencer::default()
+    }
+
+    pub fn next(&self, circuit_id: &str) -> Result<u64, PublisherError> {
+        let mut next = self
+            .next
+            .lock()
+            .map_err(|err| PublisherError::LockPoisoned(err.to_string()))?;
+        let sequence = next.entry(circuit_id.to_string()).or_insert(0);
+        *sequence += 1;
+        Ok(*sequence)
+    }
+}
@@ -0,0 +1,100 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use std::error::Error;
+use std::fmt;
+
+use kafka::error::Error as KafkaError;
+use protobuf::ProtobufError;
+
+#[derive(Debug)]
+pub enum PublisherError {
+    ProducerCreationError(KafkaError),
+    SendError(KafkaError),
+    LockPoisoned(String),
+    LagMonitorError(String),
+    SerializationError(ProtobufError),
+    WalError(String),
+    PluginError(String),
+    TransformError(String),
+    NatsError(String),
+    FileSinkError(String),
+    WebhookError(String),
+    SchemaRegistryError(String),
+    MessageTooLarge(String),
+    KafkaSecurityError(String),
+    EncryptionError(String),
+    HeartbeatError(String),
+}
+
+impl Error for PublisherError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PublisherError::ProducerCreationError(err) => Some(err),
+            PublisherError::SendError(err) => Some(err),
+            PublisherError::LockPoisoned(_) => None,
+            PublisherError::LagMonitorError(_) => None,
+            PublisherError::SerializationError(err) => Some(err),
+            PublisherError::WalError(_) => None,
+            PublisherError::PluginError(_) => None,
+            PublisherError::TransformError(_) => None,
+            PublisherError::NatsError(_) => None,
+            PublisherError::FileSinkError(_) => None,
+            PublisherError::WebhookError(_) => None,
+            PublisherError::SchemaRegistryError(_) => None,
+            PublisherError::MessageTooLarge(_) => None,
+            PublisherError::KafkaSecurityError(_) => None,
+            PublisherError::EncryptionError(_) => None,
+            PublisherError::HeartbeatError(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for PublisherError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PublisherError::ProducerCreationError(e) => {
+                write!(f, "Failed to create Kafka producer: {}", e)
+            }
+            PublisherError::SendError(e) => write!(f, "Failed to write to Kafka: {}", e),
+            PublisherError::LockPoisoned(msg) => write!(f, "Producer lock poisoned: {}", msg),
+            PublisherError::LagMonitorError(msg) => {
+                write!(f, "Consumer lag monitor error: {}", msg)
+            }
+            PublisherError::SerializationError(e) => {
+                write!(f, "Failed to serialize message: {}", e)
+            }
+            PublisherError::WalError(msg) => write!(f, "WAL error: {}", msg),
+            PublisherError::PluginError(msg) => write!(f, "Sink plugin error: {}", msg),
+            PublisherError::TransformError(msg) => write!(f, "WASM transform error: {}", msg),
+            PublisherError::NatsError(msg) => write!(f, "Failed to publish to NATS: {}", msg),
+            PublisherError::FileSinkError(msg) => write!(f, "Failed to write event file: {}", msg),
+            PublisherError::WebhookError(msg) => write!(f, "Failed to deliver webhook: {}", msg),
+            PublisherError::SchemaRegistryError(msg) => {
+                write!(f, "Schema registry error: {}", msg)
+            }
+            PublisherError::MessageTooLarge(msg) => write!(f, "Message too large: {}", msg),
+            PublisherError::KafkaSecurityError(msg) => {
+                write!(f, "Invalid Kafka TLS settings: {}", msg)
+            }
+            PublisherError::EncryptionError(msg) => {
+                write!(f, "Failed to encrypt payload: {}", msg)
+            }
+            PublisherError::HeartbeatError(msg) => write!(f, "Heartbeat error: {}", msg),
+        }
+    }
+}
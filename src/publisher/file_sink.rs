@@ -0,0 +1,188 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::filter::FILE_SINK;
+use super::sink::Sink;
+use super::PublisherError;
+use crate::time::now_millis;
+
+/// When written files are flushed to disk
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum FsyncPolicy {
+    /// Leave flushing to the operating system
+    Never,
+    /// Sync after every message
+    Always,
+    /// Sync when a file is rotated
+    Rotate,
+}
+
+impl Default for FsyncPolicy {
+    fn default() -> Self {
+        FsyncPolicy::Rotate
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FileSinkConfig {
+    pub dir: String,
+    #[serde(default = "default_max_bytes")]
+    pub max_bytes: u64,
+    #[serde(default = "default_max_age")]
+    pub max_age: u64,
+    #[serde(default)]
+    pub fsync: FsyncPolicy,
+}
+
+/// default size in bytes after which a file is rotated
+fn default_max_bytes() -> u64 {
+    100 * 1024 * 1024
+}
+
+/// default age in seconds after which a file is rotated
+fn default_max_age() -> u64 {
+    3600
+}
+
+/// Appends messages to local files, for environments without a network sink.
+///
+/// Each message is written as its varint length followed by the serialized envelope, the
+/// framing read by `parseDelimitedFrom` in the protobuf libraries. Files are named after
+/// the time they were opened and rotated by size and age.
+pub struct FileSink {
+    dir: PathBuf,
+    max_bytes: u64,
+    max_age: Duration,
+    fsync: FsyncPolicy,
+    current: Mutex<Option<CurrentFile>>,
+}
+
+struct CurrentFile {
+    file: File,
+    written: u64,
+    opened_at: Instant,
+}
+
+impl FileSink {
+    pub fn new(config: &FileSinkConfig) -> Result<Self, PublisherError> {
+        let dir = PathBuf::from(&config.dir);
+        fs::create_dir_all(&dir).map_err(|err| file_error(&dir, err))?;
+        Ok(FileSink {
+            dir,
+            max_bytes: config.max_bytes,
+            max_age: Duration::from_secs(config.max_age),
+            fsync: config.fsync,
+            current: Mutex::new(None),
+        })
+    }
+
+    fn open(&self) -> Result<CurrentFile, PublisherError> {
+        let path = self.dir.join(format!("events-{:020}.pb", now_millis()));
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|err| file_error(&path, err))?;
+        Ok(CurrentFile {
+            file,
+            written: 0,
+            opened_at: Instant::now(),
+        })
+    }
+
+    fn needs_rotation(&self, current: &CurrentFile) -> bool {
+        current.written >= self.max_bytes || current.opened_at.elapsed() >= self.max_age
+    }
+}
+
+impl Sink for FileSink {
+    fn name(&self) -> &str {
+        FILE_SINK
+    }
+
+    fn publish(&self, _circuit_id: &str, _topic: &str, bytes: &[u8]) -> Result<(), PublisherError> {
+        let mut current = self
+            .current
+            .lock()
+            .map_err(|err| PublisherError::LockPoisoned(err.to_string()))?;
+
+        if let Some(ref open) = *current {
+            if self.needs_rotation(open) {
+                if self.fsync == FsyncPolicy::Rotate {
+                    open.file
+                        .sync_all()
+                        .map_err(|err| file_error(&self.dir, err))?;
+                }
+                *current = None;
+            }
+        }
+        if current.is_none() {
+            *current = Some(self.open()?);
+        }
+        let open = current.as_mut().expect("file was just opened");
+
+        let mut record = length_prefix(bytes.len());
+        record.extend_from_slice(bytes);
+        open.file
+            .write_all(&record)
+            .map_err(|err| file_error(&self.dir, err))?;
+        open.written += record.len() as u64;
+        if self.fsync == FsyncPolicy::Always {
+            open.file
+                .sync_data()
+                .map_err(|err| file_error(&self.dir, err))?;
+        }
+        Ok(())
+    }
+
+    fn flush(&self) -> Result<(), PublisherError> {
+        let current = self
+            .current
+            .lock()
+            .map_err(|err| PublisherError::LockPoisoned(err.to_string()))?;
+        match *current {
+            Some(ref open) => open.file.sync_all().map_err(|err| file_error(&self.dir, err)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Encodes the length as a protobuf varint
+fn length_prefix(len: usize) -> Vec<u8> {
+    let mut value = len as u64;
+    let mut prefix = Vec::new();
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            prefix.push(byte);
+            return prefix;
+        }
+        prefix.push(byte | 0x80);
+    }
+}
+
+fn file_error(path: &std::path::Path, err: std::io::Error) -> PublisherError {
+    PublisherError::FileSinkError(format!("{}: {}", path.display(), err))
+}
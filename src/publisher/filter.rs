@@ -0,0 +1,102 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use std::collections::{BTreeMap, HashSet};
+use std::sync::{Arc, RwLock};
+
+use protobuf::ProtobufEnum;
+
+use crate::error::ConfigurationError;
+use crate::proto::pubsub::Message_MessageType;
+
+/// Name of the Kafka sink in `sink_message_types`
+pub const KAFKA_SINK: &str = "kafka";
+/// Name of the database sink in `sink_message_types`
+pub const DATABASE_SINK: &str = "database";
+/// Name of the NATS sink in `sink_message_types`
+pub const NATS_SINK: &str = "nats";
+/// Name of the file sink in `sink_message_types`
+pub const FILE_SINK: &str = "file";
+/// Name of the webhook sink in `sink_message_types`
+pub const WEBHOOK_SINK: &str = "webhook";
+
+/// Restricts the message types each sink receives.
+///
+/// Sinks without an entry receive every message type.
+#[derive(Clone, Default)]
+pub struct SinkFilter {
+    allowed: Arc<RwLock<BTreeMap<String, HashSet<String>>>>,
+}
+
+impl SinkFilter {
+    pub fn new(sink_message_types: &BTreeMap<String, Vec<String>>) -> Self {
+        SinkFilter {
+            allowed: Arc::new(RwLock::new(allowed_types(sink_message_types))),
+        }
+    }
+
+    /// Replaces the message types each sink receives
+    pub fn reload(&self, sink_message_types: &BTreeMap<String, Vec<String>>) {
+        match self.allowed.write() {
+            Ok(mut allowed) => *allowed = allowed_types(sink_message_types),
+            Err(err) => error!("Unable to reload sink message types: {}", err),
+        }
+    }
+
+    pub fn allows(&self, sink: &str, message_type: Message_MessageType) -> bool {
+        let allowed = match self.allowed.read() {
+            Ok(allowed) => allowed,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        match allowed.get(sink) {
+            Some(types) => types.contains(&type_name(message_type)),
+            None => true,
+        }
+    }
+}
+
+fn allowed_types(
+    sink_message_types: &BTreeMap<String, Vec<String>>,
+) -> BTreeMap<String, HashSet<String>> {
+    sink_message_types
+        .iter()
+        .map(|(sink, types)| (sink.clone(), types.iter().cloned().collect()))
+        .collect()
+}
+
+/// Checks that the configured message types exist
+pub fn validate_message_types(
+    sink_message_types: &BTreeMap<String, Vec<String>>,
+) -> Result<(), ConfigurationError> {
+    let known = Message_MessageType::values()
+        .iter()
+        .map(|message_type| type_name(*message_type))
+        .collect::<HashSet<_>>();
+    for (sink, types) in sink_message_types {
+        if let Some(unknown) = types.iter().find(|name| !known.contains(*name)) {
+            return Err(ConfigurationError::MissingValue(format!(
+                "sink_message_types.{}: unknown message type {}",
+                sink, unknown
+            )));
+        }
+    }
+    Ok(())
+}
+
+fn type_name(message_type: Message_MessageType) -> String {
+    message_type.descriptor().name().to_string()
+}
@@ -0,0 +1,88 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use crate::time::now_millis;
+
+/// Outcome of the deliveries to one sink
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SinkStatus {
+    /// false while the latest delivery failed
+    healthy: bool,
+    delivered: u64,
+    failed: u64,
+    /// number of failures since the last successful delivery
+    consecutive_failures: u64,
+    last_delivered_at: Option<u64>,
+    last_error: Option<String>,
+    last_error_at: Option<u64>,
+}
+
+/// Tracks the deliveries to each sink, Kafka included, for the status API
+#[derive(Clone, Default)]
+pub struct SinkHealth {
+    sinks: Arc<Mutex<BTreeMap<String, SinkStatus>>>,
+}
+
+impl SinkHealth {
+    pub fn new() -> Self {
+        SinkHealth::default()
+    }
+
+    pub fn delivered(&self, sink: &str) {
+        self.update(sink, |status| {
+            status.healthy = true;
+            status.delivered += 1;
+            status.consecutive_failures = 0;
+            status.last_delivered_at = Some(now_millis());
+        });
+    }
+
+    pub fn failed(&self, sink: &str, error: &str) {
+        self.update(sink, |status| {
+            status.healthy = false;
+            status.failed += 1;
+            status.consecutive_failures += 1;
+            status.last_error = Some(error.to_string());
+            status.last_error_at = Some(now_millis());
+        });
+    }
+
+    pub fn sinks(&self) -> BTreeMap<String, SinkStatus> {
+        match self.sinks.lock() {
+            Ok(sinks) => sinks.clone(),
+            Err(err) => {
+                error!("Unable to read sink health: {}", err);
+                BTreeMap::new()
+            }
+        }
+    }
+
+    fn update<F>(&self, sink: &str, f: F)
+    where
+        F: FnOnce(&mut SinkStatus),
+    {
+        match self.sinks.lock() {
+            Ok(mut sinks) => f(sinks
+                .entry(sink.to_string())
+                .or_insert_with(SinkStatus::default)),
+            Err(err) => error!("Unable to update sink health: {}", err),
+        }
+    }
+}
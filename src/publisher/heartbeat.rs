@@ -0,0 +1,92 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::{KafkaPublisher, PublisherError};
+use crate::proto::pubsub::{Control, Message_MessageType};
+use crate::time::now_millis;
+
+/// Command of the control messages published by the heartbeat
+const HEARTBEAT_COMMAND: &str = "heartbeat";
+
+/// Periodically publishes a control message carrying the fingerprint of the exporter's
+/// configuration, so consumers can tell which exporters of a fleet have drifted apart
+pub struct Heartbeat {
+    running: Arc<AtomicBool>,
+    join_handle: thread::JoinHandle<()>,
+}
+
+impl Heartbeat {
+    /// Starts the heartbeat, returns `None` if the interval is zero.
+    pub fn start(
+        publisher: KafkaPublisher,
+        interval: Duration,
+    ) -> Result<Option<Self>, PublisherError> {
+        if interval == Duration::from_secs(0) {
+            return Ok(None);
+        }
+
+        let running = Arc::new(AtomicBool::new(true));
+        let thread_running = running.clone();
+        let join_handle = thread::Builder::new()
+            .name("Heartbeat".into())
+            .spawn(move || {
+                while thread_running.load(Ordering::SeqCst) {
+                    if let Err(err) = beat(&publisher) {
+                        warn!("Unable to publish heartbeat: {}", err);
+                    }
+                    let started = Instant::now();
+                    while thread_running.load(Ordering::SeqCst) && started.elapsed() < interval {
+                        thread::sleep(Duration::from_millis(100));
+                    }
+                }
+            })
+            .map_err(|err| {
+                PublisherError::HeartbeatError(format!("Unable to start thread: {}", err))
+            })?;
+
+        Ok(Some(Heartbeat {
+            running,
+            join_handle,
+        }))
+    }
+
+    pub fn shutdown(self) {
+        self.running.store(false, Ordering::SeqCst);
+        if self.join_handle.join().is_err() {
+            error!("Heartbeat thread panicked");
+        }
+    }
+}
+
+fn beat(publisher: &KafkaPublisher) -> Result<(), PublisherError> {
+    let detail = json!({
+        "config_fingerprint": publisher.config_fingerprint(),
+        "exporter_version": env!("CARGO_PKG_VERSION"),
+    });
+    let issued_at = now_millis();
+    let mut control = Control::new();
+    control.set_command(HEARTBEAT_COMMAND.to_string());
+    control.set_detail(detail.to_string());
+    control.set_effective_at(issued_at);
+    control.set_issued_at(issued_at);
+    publisher.publish("", Message_MessageType::CONTROL, &control)
+}
@@ -0,0 +1,166 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use protobuf::Message as Msg;
+
+use crate::proto::pubsub::{
+    CircuitCreated, CircuitPayload, CircuitPayloadDeleted, CircuitRemoved, Message,
+    Message_MessageType, ProposalReady, ProposalSubmit,
+};
+
+/// What the Kafka record key of a message is derived from
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum KeyStrategy {
+    /// The circuit id, keeping each circuit's messages in order
+    Circuit,
+    /// The public key of the circuit's requester, keeping each requester's circuits on
+    /// one partition
+    Requester,
+    /// The circuit id and the id of the scabbard service the state is read from
+    Service,
+    /// The circuit id and the state address of circuit payloads and their deletions, so
+    /// that a compacted topic keeps the latest payload of each address. Other messages
+    /// use the circuit id
+    StateKey,
+}
+
+impl Default for KeyStrategy {
+    fn default() -> Self {
+        KeyStrategy::Circuit
+    }
+}
+
+/// Chooses the record key of each message.
+///
+/// Votes carry no requester and admin events no service, so the requester and service
+/// of a circuit are remembered from its earlier messages. Until they are known the
+/// circuit id is used.
+#[derive(Clone)]
+pub struct RecordKeys {
+    strategy: KeyStrategy,
+    requesters: Arc<RwLock<HashMap<String, String>>>,
+    services: Arc<RwLock<HashMap<String, String>>>,
+}
+
+impl RecordKeys {
+    pub fn new(strategy: KeyStrategy) -> Self {
+        RecordKeys {
+            strategy,
+            requesters: Arc::new(RwLock::new(HashMap::new())),
+            services: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    pub fn strategy(&self) -> KeyStrategy {
+        self.strategy
+    }
+
+    pub fn set_service(&self, circuit_id: &str, service_id: &str) {
+        match self.services.write() {
+            Ok(mut services) => {
+                services.insert(circuit_id.to_string(), service_id.to_string());
+            }
+            Err(err) => error!("Unable to record circuit service: {}", err),
+        }
+    }
+
+    pub fn key(&self, circuit_id: &str, envelope: &Message) -> String {
+        match self.strategy {
+            KeyStrategy::Circuit => circuit_id.to_string(),
+            KeyStrategy::Requester => {
+                if let Some(requester) = requester(envelope) {
+                    if let Ok(mut requesters) = self.requesters.write() {
+                        requesters.insert(circuit_id.to_string(), requester);
+                    }
+                }
+                self.requesters
+                    .read()
+                    .ok()
+                    .and_then(|requesters| requesters.get(circuit_id).cloned())
+                    .unwrap_or_else(|| circuit_id.to_string())
+            }
+            KeyStrategy::Service => {
+                match self
+                    .services
+                    .read()
+                    .ok()
+                    .and_then(|services| services.get(circuit_id).cloned())
+                {
+                    Some(service_id) => format!("{}::{}", circuit_id, service_id),
+                    None => circuit_id.to_string(),
+                }
+            }
+            KeyStrategy::StateKey => match state_address(envelope) {
+                Some(address) => state_key(circuit_id, &address),
+                None => circuit_id.to_string(),
+            },
+        }
+    }
+}
+
+/// Returns the record key of a state address's payloads in the `state-key` layout
+pub fn state_key(circuit_id: &str, address: &str) -> String {
+    format!("{}::{}", circuit_id, address)
+}
+
+/// Returns the state address of a circuit payload or of its deletion
+fn state_address(envelope: &Message) -> Option<String> {
+    let bytes = envelope.get_message();
+    let address = match envelope.get_field_type() {
+        Message_MessageType::CIRCUIT_PAYLOAD => parse::<CircuitPayload>(bytes)?.take_key(),
+        Message_MessageType::CIRCUIT_PAYLOAD_DELETED => {
+            parse::<CircuitPayloadDeleted>(bytes)?.take_key()
+        }
+        _ => return None,
+    };
+    Some(address).filter(|address| !address.is_empty())
+}
+
+/// Returns the requester named in the message, if it has one
+fn requester(envelope: &Message) -> Option<String> {
+    let bytes = envelope.get_message();
+    let requester = match envelope.get_field_type() {
+        Message_MessageType::PROPOSAL_SUBMIT => {
+            parse::<ProposalSubmit>(bytes)?.get_requester().to_string()
+        }
+        Message_MessageType::PROPOSAL_READY => {
+            parse::<ProposalReady>(bytes)?.get_requester().to_string()
+        }
+        Message_MessageType::CIRCUIT_CREATED => {
+            parse::<CircuitCreated>(bytes)?.get_requester().to_string()
+        }
+        Message_MessageType::CIRCUIT_PAYLOAD => {
+            parse::<CircuitPayload>(bytes)?.get_requester().to_string()
+        }
+        Message_MessageType::CIRCUIT_REMOVED => {
+            parse::<CircuitRemoved>(bytes)?.get_requester().to_string()
+        }
+        Message_MessageType::CIRCUIT_PAYLOAD_DELETED => parse::<CircuitPayloadDeleted>(bytes)?
+            .get_requester()
+            .to_string(),
+        _ => return None,
+    };
+    Some(requester).filter(|requester| !requester.is_empty())
+}
+
+fn parse<M: Msg>(bytes: &[u8]) -> Option<M> {
+    protobuf::parse_from_bytes::<M>(bytes).ok()
+}
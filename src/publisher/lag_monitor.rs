@@ -0,0 +1,129 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use kafka::client::{FetchOffset, GroupOffsetStorage, KafkaClient};
+use kafka::error::Error as KafkaError;
+
+use super::PublisherError;
+use crate::config::DeploymentConfig;
+use crate::metrics::Metrics;
+use crate::status::Status;
+
+/// Name of the gauge holding the lag of a consumer group on an export topic
+const CONSUMER_LAG_METRIC: &str = "kafka_consumer_group_lag";
+
+/// Periodically inspects the committed offsets of the configured consumer groups on the
+/// export topics and records how far behind the latest offsets they are, in the metrics
+/// and the status API.
+pub struct ConsumerLagMonitor {
+    running: Arc<AtomicBool>,
+    join_handle: thread::JoinHandle<()>,
+}
+
+impl ConsumerLagMonitor {
+    /// Starts the monitor, returns `None` if no consumer groups are configured.
+    pub fn start(
+        deployment_config: &DeploymentConfig,
+        metrics: Metrics,
+        status: Status,
+    ) -> Result<Option<Self>, PublisherError> {
+        let groups = deployment_config.consumer_lag_groups().to_vec();
+        if groups.is_empty() {
+            return Ok(None);
+        }
+        let hosts = vec![deployment_config.kafka_url().to_string()];
+        let topics = vec![deployment_config.kafka_topic().to_string()];
+        let interval = Duration::from_secs(deployment_config.consumer_lag_interval());
+        let security = match deployment_config.kafka_security() {
+            Some(security) => Some(security.security_config()?),
+            None => None,
+        };
+
+        let running = Arc::new(AtomicBool::new(true));
+        let thread_running = running.clone();
+        let join_handle = thread::Builder::new()
+            .name("ConsumerLagMonitor".into())
+            .spawn(move || {
+                let mut client = match security {
+                    Some(security) => KafkaClient::new_secure(hosts, security),
+                    None => KafkaClient::new(hosts),
+                };
+                client.set_group_offset_storage(GroupOffsetStorage::Kafka);
+                while thread_running.load(Ordering::SeqCst) {
+                    if let Err(err) = report_lag(&mut client, &groups, &topics, &metrics, &status) {
+                        warn!("Unable to inspect consumer group offsets: {}", err);
+                    }
+                    let started = Instant::now();
+                    while thread_running.load(Ordering::SeqCst) && started.elapsed() < interval {
+                        thread::sleep(Duration::from_secs(1));
+                    }
+                }
+            })
+            .map_err(|err| {
+                PublisherError::LagMonitorError(format!("Unable to start thread: {}", err))
+            })?;
+
+        Ok(Some(ConsumerLagMonitor {
+            running,
+            join_handle,
+        }))
+    }
+
+    pub fn shutdown(self) {
+        self.running.store(false, Ordering::SeqCst);
+        if self.join_handle.join().is_err() {
+            error!("Consumer lag monitor thread panicked");
+        }
+    }
+}
+
+fn report_lag(
+    client: &mut KafkaClient,
+    groups: &[String],
+    topics: &[String],
+    metrics: &Metrics,
+    status: &Status,
+) -> Result<(), KafkaError> {
+    client.load_metadata(topics)?;
+    for topic in topics {
+        let latest_offsets = client.fetch_topic_offsets(topic.as_str(), FetchOffset::Latest)?;
+        for group in groups {
+            let committed_offsets = client.fetch_group_topic_offsets(group, topic)?;
+            let lag: i64 = latest_offsets
+                .iter()
+                .map(|latest| {
+                    // A negative offset means the group has not committed for this partition
+                    let committed = committed_offsets
+                        .iter()
+                        .find(|committed| committed.partition == latest.partition)
+                        .map(|committed| committed.offset.max(0))
+                        .unwrap_or(0);
+                    (latest.offset - committed).max(0)
+                })
+                .sum();
+            debug!("Consumer group {} lag on {}: {}", group, topic, lag);
+            metrics.set_gauge(CONSUMER_LAG_METRIC, &[("group", group), ("topic", topic)], lag);
+            status.set_consumer_lag(group, topic, lag);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,43 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+/// Placeholder of the circuit id in the mirror topic
+const CIRCUIT_ID_PLACEHOLDER: &str = "{circuit_id}";
+
+/// Mirror of the contract state of each circuit on a compacted Kafka topic
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StateMirrorConfig {
+    /// Topic of a circuit's mirror, with a `{circuit_id}` placeholder
+    #[serde(default = "default_topic")]
+    pub topic: String,
+}
+
+/// default topic of a circuit's state mirror
+fn default_topic() -> String {
+    format!("{}-state", CIRCUIT_ID_PLACEHOLDER)
+}
+
+impl StateMirrorConfig {
+    /// Records are keyed by state address alone, so each circuit needs its own topic
+    pub fn is_per_circuit(&self) -> bool {
+        self.topic.contains(CIRCUIT_ID_PLACEHOLDER)
+    }
+
+    pub fn topic(&self, circuit_id: &str) -> String {
+        self.topic.replace(CIRCUIT_ID_PLACEHOLDER, circuit_id)
+    }
+}
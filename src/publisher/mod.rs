@@ -0,0 +1,845 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+mod avro;
+mod encryption;
+mod envelope;
+mod error;
+mod file_sink;
+mod filter;
+mod health;
+mod heartbeat;
+mod key;
+mod lag_monitor;
+mod mirror;
+mod nats;
+mod ops;
+mod partitioner;
+mod plugin;
+mod preferences;
+mod producer;
+mod quota;
+mod recent_events;
+mod router;
+mod security;
+mod serializer;
+mod sink;
+mod template;
+mod transform;
+mod wal;
+mod wal_replayer;
+mod webhook;
+
+pub use avro::schema as avro_schema;
+pub use envelope::{DualWriteConfig, ENVELOPE_VERSIONS};
+pub use error::PublisherError;
+pub use file_sink::FileSinkConfig;
+pub use filter::{validate_message_types, DATABASE_SINK};
+pub use heartbeat::Heartbeat;
+pub use key::KeyStrategy;
+pub use lag_monitor::ConsumerLagMonitor;
+pub use mirror::StateMirrorConfig;
+pub use ops::Lifecycle;
+pub use partitioner::PartitionStrategy;
+pub use plugin::PluginConfig;
+pub use producer::{AckLevel, CompressionCodec, KafkaProducerConfig};
+pub use recent_events::RecentEvents;
+pub use router::validate_topic_map;
+pub use security::KafkaSecurityConfig;
+pub use serializer::OutputFormat;
+pub use wal::WalStats;
+pub use wal_replayer::WalReplayer;
+pub use webhook::WebhookConfig;
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::Duration;
+
+use kafka::producer::{Producer, Record};
+use protobuf::Message as Msg;
+use serde_json::Value;
+
+use self::avro::AvroEncoder;
+use self::encryption::WalCipher;
+use self::envelope::{to_v2, EnvelopeBuilder, Sequencer};
+use self::file_sink::FileSink;
+use self::filter::{SinkFilter, KAFKA_SINK};
+use self::key::{state_key, RecordKeys};
+use self::quota::TopicQuotas;
+use self::partitioner::ConfiguredPartitioner;
+use self::serializer::{to_json, to_json_v2, JSON_TOPIC_SUFFIX};
+use self::nats::NatsSink;
+use self::webhook::WebhookSink;
+use self::plugin::PluginSink;
+use self::preferences::CircuitPreferences;
+use self::producer::KafkaProducerBuilder;
+use self::router::TopicRouter;
+use self::sink::Sink;
+use self::transform::WasmTransform;
+use self::health::{SinkHealth, SinkStatus};
+use self::wal::{Wal, WalEntry};
+use crate::alert::Alerter;
+use crate::application_metadata::ExportPreferences;
+use crate::config::DeploymentConfig;
+use crate::metrics::Metrics;
+use crate::proto::pubsub::{CircuitPayload, Message, Message_MessageType};
+
+/// Publishes serialized messages to the Kafka topic chosen by the `TopicRouter`.
+///
+/// By default all circuits share a single producer. When
+/// `kafka_producer_per_circuit` is enabled each circuit gets its own producer,
+/// so a broker throttling one circuit's partitions does not block the others.
+///
+/// If a WAL directory is configured, messages which cannot be delivered, or which exceed
+/// their topic's quota, are appended to the WAL and replayed later. While the WAL has pending entries new messages are queued
+/// behind them, so the delivery order is kept.
+///
+/// Messages are also handed to the other configured sinks: NATS JetStream, local files,
+/// an HTTP webhook and the sink plugins. If a WASM
+/// transform is configured, it is applied to each message before it reaches any sink.
+#[derive(Clone)]
+pub struct KafkaPublisher {
+    producer_builder: KafkaProducerBuilder,
+    router: TopicRouter,
+    preferences: CircuitPreferences,
+    record_keys: RecordKeys,
+    quotas: TopicQuotas,
+    per_circuit: bool,
+    kafka_enabled: bool,
+    output_format: OutputFormat,
+    avro: Option<Arc<AvroEncoder>>,
+    envelope_builder: EnvelopeBuilder,
+    sequencer: Sequencer,
+    envelope_version: u32,
+    dual_write: Option<DualWriteConfig>,
+    state_mirror: Option<StateMirrorConfig>,
+    ops_topic: Option<String>,
+    config_fingerprint: Arc<RwLock<String>>,
+    metrics: Metrics,
+    recent_events: RecentEvents,
+    producers: Arc<Mutex<HashMap<String, ProducerSlot>>>,
+    wal: Option<Wal>,
+    /// Held for reading by live sends and for writing while a WAL entry is replayed
+    wal_order: Arc<RwLock<()>>,
+    backlog_limits: BacklogLimits,
+    sinks: Arc<Vec<Box<dyn Sink>>>,
+    sink_health: SinkHealth,
+    sink_filter: SinkFilter,
+    transform: Option<Arc<WasmTransform>>,
+}
+
+#[derive(Clone)]
+struct BacklogLimits {
+    max_pending: Option<usize>,
+    max_pending_age: Option<u64>,
+    pause_intake: bool,
+}
+
+type ProducerSlot = Arc<Mutex<Option<Producer<ConfiguredPartitioner>>>>;
+
+/// Whether a message goes through the sinks, or straight to the WAL
+#[derive(Clone, Copy, PartialEq)]
+enum Delivery {
+    Live,
+    Deferred,
+}
+
+/// Key under which the producer shared by all circuits is stored
+const SHARED_PRODUCER: &str = "";
+
+/// Counter of the envelopes published, by envelope version
+const ENVELOPES_METRIC: &str = "exporter_envelopes_published_total";
+
+/// Counter of the tombstones written for deleted state addresses
+const TOMBSTONES_METRIC: &str = "exporter_tombstones_published_total";
+
+/// Counter of the records written to the state mirror topics, by operation
+const STATE_MIRROR_METRIC: &str = "exporter_state_mirror_records_total";
+
+impl KafkaPublisher {
+    pub fn new(
+        deployment_config: &DeploymentConfig,
+        recent_events: RecentEvents,
+        metrics: Metrics,
+    ) -> Result<Self, PublisherError> {
+        let wal_cipher = match deployment_config.wal_encryption() {
+            Some(wal_encryption) => {
+                let key = wal_encryption.key().map_err(|err| {
+                    PublisherError::EncryptionError(format!(
+                        "Unable to read the WAL encryption key: {}",
+                        err
+                    ))
+                })?;
+                Some(WalCipher::from_hex(&key)?)
+            }
+            None => None,
+        };
+        let wal = match deployment_config.wal_dir() {
+            Some(wal_dir) => Some(Wal::open(wal_dir, wal_cipher)?),
+            None => None,
+        };
+        let sink_health = SinkHealth::new();
+        let mut sinks: Vec<Box<dyn Sink>> = vec![];
+        if let (Some(nats_url), Some(nats_subject)) =
+            (deployment_config.nats_url(), deployment_config.nats_subject())
+        {
+            sinks.push(Box::new(NatsSink::connect(nats_url, nats_subject)?));
+        }
+        if let Some(file_sink) = deployment_config.file_sink() {
+            sinks.push(Box::new(FileSink::new(file_sink)?));
+        }
+        if let Some(webhook) = deployment_config.webhook() {
+            sinks.push(Box::new(WebhookSink::new(
+                webhook,
+                metrics.clone(),
+                sink_health.clone(),
+                Alerter::new(deployment_config.alert_webhook_url()),
+            )?));
+        }
+        for plugin in deployment_config.plugins() {
+            sinks.push(Box::new(PluginSink::load(plugin)?));
+        }
+        let avro = match deployment_config.schema_registry_url() {
+            Some(url) if deployment_config.output_format() == OutputFormat::Avro => {
+                Some(Arc::new(AvroEncoder::new(url)?))
+            }
+            _ => None,
+        };
+        let transform = match deployment_config.transform_wasm() {
+            Some(path) => Some(Arc::new(WasmTransform::load(path)?)),
+            None => None,
+        };
+        Ok(KafkaPublisher {
+            producer_builder: KafkaProducerBuilder::new(deployment_config),
+            router: TopicRouter::new(deployment_config),
+            preferences: CircuitPreferences::default(),
+            record_keys: RecordKeys::new(deployment_config.kafka_key()),
+            quotas: TopicQuotas::new(deployment_config.kafka_topic_quotas()),
+            per_circuit: deployment_config.kafka_producer_per_circuit(),
+            kafka_enabled: deployment_config.kafka_enabled(),
+            output_format: deployment_config.output_format(),
+            avro,
+            envelope_builder: EnvelopeBuilder::new(),
+            sequencer: Sequencer::new(),
+            envelope_version: deployment_config.envelope_version(),
+            dual_write: deployment_config.envelope_dual_write().cloned(),
+            state_mirror: deployment_config.state_mirror().cloned(),
+            ops_topic: deployment_config.ops_topic().map(String::from),
+            config_fingerprint: Arc::new(RwLock::new(deployment_config.fingerprint())),
+            metrics,
+            recent_events,
+            producers: Arc::new(Mutex::new(HashMap::new())),
+            wal,
+            wal_order: Arc::new(RwLock::new(())),
+            backlog_limits: BacklogLimits {
+                max_pending: deployment_config.wal_max_pending(),
+                max_pending_age: deployment_config.wal_max_pending_age(),
+                pause_intake: deployment_config.wal_pause_intake(),
+            },
+            sinks: Arc::new(sinks),
+            sink_health,
+            sink_filter: SinkFilter::new(deployment_config.sink_message_types()),
+            transform,
+        })
+    }
+
+    /// Wraps the message in an envelope of the given type and sends it to Kafka.
+    pub fn publish<M: Msg>(
+        &self,
+        circuit_id: &str,
+        message_type: Message_MessageType,
+        message: &M,
+    ) -> Result<(), PublisherError> {
+        let envelope = self.envelope_builder.build(message_type, message)?;
+        self.publish_envelope(circuit_id, envelope, Delivery::Live)
+    }
+
+    /// Publishes a message which is already serialized, in a new envelope of this run
+    pub fn republish(
+        &self,
+        circuit_id: &str,
+        message_type: Message_MessageType,
+        message: Vec<u8>,
+    ) -> Result<(), PublisherError> {
+        let envelope = self
+            .envelope_builder
+            .build_serialized(message_type, message);
+        self.publish_envelope(circuit_id, envelope, Delivery::Live)
+    }
+
+    /// Appends a message which is already serialized to the WAL, without handing it to
+    /// the other sinks, for a message whose delivery is stuck.
+    pub fn defer(
+        &self,
+        circuit_id: &str,
+        message_type: Message_MessageType,
+        message: Vec<u8>,
+    ) -> Result<(), PublisherError> {
+        let envelope = self
+            .envelope_builder
+            .build_serialized(message_type, message);
+        self.publish_envelope(circuit_id, envelope, Delivery::Deferred)
+    }
+
+    fn publish_envelope(
+        &self,
+        circuit_id: &str,
+        mut envelope: Message,
+        delivery: Delivery,
+    ) -> Result<(), PublisherError> {
+        if let Some(management_type) = self.router.management_type(circuit_id) {
+            envelope.set_circuit_management_type(management_type);
+        }
+        let mut envelope = match self.transform {
+            Some(ref transform) => {
+                let bytes = envelope
+                    .write_to_bytes()
+                    .map_err(PublisherError::SerializationError)?;
+                match transform.apply(bytes)? {
+                    Some(bytes) => protobuf::parse_from_bytes::<Message>(&bytes).map_err(|err| {
+                        PublisherError::TransformError(format!(
+                            "transform returned an invalid message: {}",
+                            err
+                        ))
+                    })?,
+                    None => {
+                        debug!("WASM transform dropped a message of circuit {}", circuit_id);
+                        return Ok(());
+                    }
+                }
+            }
+            None => envelope,
+        };
+        if !self.preferences.allows(circuit_id, envelope.get_field_type()) {
+            debug!(
+                "Circuit {} does not export {:?} messages",
+                circuit_id,
+                envelope.get_field_type()
+            );
+            return Ok(());
+        }
+        // Numbered once it is known to be exported, so that consumers only see gaps for
+        // messages which were lost
+        envelope.set_sequence(self.sequencer.next(circuit_id)?);
+        let bytes = envelope
+            .write_to_bytes()
+            .map_err(PublisherError::SerializationError)?;
+        let (envelope, bytes) = self.encrypt_payload(circuit_id, envelope, bytes)?;
+        // The type after the transform decides the topic and the sinks
+        let message_type = envelope.get_field_type();
+        let topic = self.router.topic(circuit_id, message_type);
+        let key = self.record_keys.key(circuit_id, &envelope);
+        let output_format = self.output_format(circuit_id);
+        let version = self.envelope_version;
+        let mut outputs = self
+            .encode(circuit_id, topic.clone(), &envelope, &bytes, version, output_format)?
+            .into_iter()
+            .map(|(topic, bytes)| (version, topic, bytes))
+            .collect::<Vec<_>>();
+        let dual_write = self
+            .dual_write
+            .as_ref()
+            .filter(|dual_write| dual_write.is_active());
+        // Avro records do not depend on the envelope version
+        if let Some(dual_write) = dual_write.filter(|_| output_format != OutputFormat::Avro) {
+            let dual_topic = format!("{}{}", topic, dual_write.topic_suffix);
+            let version = dual_write.version;
+            outputs.extend(
+                self.encode(circuit_id, dual_topic, &envelope, &bytes, version, output_format)?
+                    .into_iter()
+                    .map(|(topic, bytes)| (version, topic, bytes)),
+            );
+        }
+        for (version, topic, bytes) in outputs {
+            self.metrics
+                .incr_counter(ENVELOPES_METRIC, &[("version", &version.to_string())], 1);
+            if delivery == Delivery::Deferred {
+                if self.kafka_enabled && self.sink_allows(KAFKA_SINK, message_type) {
+                    self.append_to_wal(circuit_id, &topic, &key, bytes)?;
+                }
+                continue;
+            }
+            // A failing sink does not hold back the others
+            for sink in self.sinks.iter() {
+                if !self.sink_allows(sink.name(), message_type) {
+                    continue;
+                }
+                match sink.publish_envelope(circuit_id, &topic, &envelope, &bytes) {
+                    Ok(()) if sink.reports_health() => (),
+                    Ok(()) => self.sink_health.delivered(sink.name()),
+                    Err(err) => {
+                        error!("{}", err);
+                        self.sink_health.failed(sink.name(), &err.to_string());
+                    }
+                }
+            }
+            if self.kafka_enabled && self.sink_allows(KAFKA_SINK, message_type) {
+                self.send(circuit_id, &topic, &key, bytes)?;
+            }
+        }
+        self.recent_events.push(circuit_id, &envelope);
+        Ok(())
+    }
+
+    /// Writes a tombstone for a deleted state address: a Kafka record with the key of the
+    /// address's payloads and no value, so that compacted topics drop the address.
+    ///
+    /// Only the `state-key` record keys tell addresses apart, with the other strategies
+    /// nothing is written. The other sinks have no notion of tombstones.
+    pub fn publish_tombstone(&self, circuit_id: &str, address: &str) -> Result<(), PublisherError> {
+        let message_type = Message_MessageType::CIRCUIT_PAYLOAD;
+        if self.record_keys.strategy() != KeyStrategy::StateKey
+            || !self.kafka_enabled
+            || !self.sink_allows(KAFKA_SINK, message_type)
+            || !self.preferences.allows(circuit_id, message_type)
+        {
+            return Ok(());
+        }
+        let output_format = self.output_format(circuit_id);
+        let topic = self.router.topic(circuit_id, message_type);
+        let mut topics = vec![topic.clone()];
+        if let Some(dual_write) = self
+            .dual_write
+            .as_ref()
+            .filter(|dual_write| dual_write.is_active())
+            .filter(|_| output_format != OutputFormat::Avro)
+        {
+            topics.push(format!("{}{}", topic, dual_write.topic_suffix));
+        }
+        if output_format == OutputFormat::Both {
+            let json_topics = topics
+                .iter()
+                .map(|topic| format!("{}{}", topic, JSON_TOPIC_SUFFIX))
+                .collect::<Vec<_>>();
+            topics.extend(json_topics);
+        }
+        let key = state_key(circuit_id, address);
+        for topic in topics {
+            // The Kafka client writes an empty value as null
+            self.send(circuit_id, &topic, &key, vec![])?;
+            self.metrics.incr_counter(TOMBSTONES_METRIC, &[], 1);
+        }
+        Ok(())
+    }
+
+    /// Writes the value of a state address to its circuit's mirror topic, keyed by the
+    /// address, or a tombstone when the address was deleted. Compaction then leaves the
+    /// latest value of each address.
+    ///
+    /// Values are written as they are in the scabbard state, so circuits whose exports are
+    /// encrypted for a consumer are not mirrored.
+    pub fn mirror_state(
+        &self,
+        circuit_id: &str,
+        address: &str,
+        value: Option<&[u8]>,
+    ) -> Result<(), PublisherError> {
+        let state_mirror = match self.state_mirror {
+            Some(ref state_mirror) if self.kafka_enabled => state_mirror,
+            _ => return Ok(()),
+        };
+        if self.preferences.encryptor(circuit_id)?.is_some() {
+            debug!("Not mirroring the encrypted state of circuit {}", circuit_id);
+            return Ok(());
+        }
+        let operation = match value {
+            Some(_) => "upsert",
+            None => "tombstone",
+        };
+        let topic = state_mirror.topic(circuit_id);
+        self.send(circuit_id, &topic, address, value.unwrap_or_default().to_vec())?;
+        self.metrics
+            .incr_counter(STATE_MIRROR_METRIC, &[("operation", operation)], 1);
+        Ok(())
+    }
+
+    /// Publishes a lifecycle transition of the exporter to the ops topic, keyed by the run
+    /// so that a run's transitions stay in order. The other sinks are not sent them.
+    /// Failures are only logged, they do not hold back the exporter.
+    pub fn publish_lifecycle(&self, event: Lifecycle, circuit_id: Option<&str>, detail: Value) {
+        let topic = match self.ops_topic {
+            Some(ref topic) if self.kafka_enabled => topic,
+            _ => return,
+        };
+        let run_id = self.envelope_builder.run_id();
+        let record = ops::record(
+            event,
+            circuit_id,
+            run_id,
+            &self.config_fingerprint(),
+            detail,
+        );
+        if let Err(err) = self.send(circuit_id.unwrap_or_default(), topic, run_id, record) {
+            warn!("Unable to publish {:?} to the ops topic: {}", event, err);
+        }
+    }
+
+    /// Encodes an envelope in an output format and envelope version, returning the record
+    /// written to each topic. Avro records are the same for both versions, their schema
+    /// evolving through the registry instead.
+    fn encode(
+        &self,
+        circuit_id: &str,
+        topic: String,
+        envelope: &Message,
+        bytes: &[u8],
+        version: u32,
+        output_format: OutputFormat,
+    ) -> Result<Vec<(String, Vec<u8>)>, PublisherError> {
+        let v2 = if version == 2 {
+            Some(to_v2(circuit_id, envelope))
+        } else {
+            None
+        };
+        let protobuf = || match v2 {
+            Some(ref v2) => v2.write_to_bytes().map_err(PublisherError::SerializationError),
+            None => Ok(bytes.to_vec()),
+        };
+        let json = || match v2 {
+            Some(ref v2) => to_json_v2(v2),
+            None => to_json(envelope),
+        };
+        Ok(match output_format {
+            OutputFormat::Protobuf => vec![(topic, protobuf()?)],
+            OutputFormat::Json => vec![(topic, json()?)],
+            OutputFormat::Both => {
+                let json_topic = format!("{}{}", topic, JSON_TOPIC_SUFFIX);
+                vec![(topic, protobuf()?), (json_topic, json()?)]
+            }
+            OutputFormat::Avro => {
+                let avro = self.avro.as_ref().ok_or_else(|| {
+                    PublisherError::SchemaRegistryError("no schema registry configured".into())
+                })?;
+                let record = avro.encode(&topic, envelope)?;
+                vec![(topic, record)]
+            }
+        })
+    }
+
+    /// Encrypts the data of a payload for the consumer designated by its circuit
+    fn encrypt_payload(
+        &self,
+        circuit_id: &str,
+        mut envelope: Message,
+        bytes: Vec<u8>,
+    ) -> Result<(Message, Vec<u8>), PublisherError> {
+        if envelope.get_field_type() != Message_MessageType::CIRCUIT_PAYLOAD {
+            return Ok((envelope, bytes));
+        }
+        let encryptor = match self.preferences.encryptor(circuit_id)? {
+            Some(encryptor) => encryptor,
+            None => return Ok((envelope, bytes)),
+        };
+        let mut payload = protobuf::parse_from_bytes::<CircuitPayload>(envelope.get_message())
+            .map_err(PublisherError::SerializationError)?;
+        encryptor.encrypt(&mut payload)?;
+        envelope.set_message(
+            payload
+                .write_to_bytes()
+                .map_err(PublisherError::SerializationError)?,
+        );
+        let bytes = envelope
+            .write_to_bytes()
+            .map_err(PublisherError::SerializationError)?;
+        Ok((envelope, bytes))
+    }
+
+    /// The topic a message of the circuit is published to
+    pub fn topic(&self, circuit_id: &str, message_type: Message_MessageType) -> String {
+        self.router.topic(circuit_id, message_type)
+    }
+
+    /// The format of the circuit's messages, its export preferences taking precedence
+    pub fn output_format(&self, circuit_id: &str) -> OutputFormat {
+        self.preferences
+            .output_format(circuit_id)
+            .unwrap_or(self.output_format)
+    }
+
+    pub fn envelope_version(&self) -> u32 {
+        self.envelope_version
+    }
+
+    /// Records the splinterd node of the exporter, named in the envelopes published next
+    pub fn set_node_id(&self, node_id: &str) {
+        self.envelope_builder.set_node_id(node_id);
+    }
+
+    /// Records the scabbard service a circuit's state is read from, used for its record keys
+    pub fn set_service(&self, circuit_id: &str, service_id: &str) {
+        self.record_keys.set_service(circuit_id, service_id);
+    }
+
+    /// Returns whether the sink is allowed to receive messages of the type
+    pub fn sink_allows(&self, sink: &str, message_type: Message_MessageType) -> bool {
+        self.sink_filter.allows(sink, message_type)
+    }
+
+    /// Records the export preferences from a circuit's application metadata
+    pub fn set_export_preferences(&self, circuit_id: &str, preferences: &ExportPreferences) {
+        if let Some(ref topic) = preferences.topic {
+            self.router.set_circuit_topic(circuit_id, topic);
+        }
+        self.preferences.set(circuit_id, preferences);
+    }
+
+    /// Records the management type of a circuit, used to route its messages.
+    pub fn set_management_type(&self, circuit_id: &str, management_type: &str) {
+        self.router.set_management_type(circuit_id, management_type);
+    }
+
+    /// Applies the topic and sink message type settings of a reloaded deployment
+    /// configuration to the messages published from now on
+    pub fn reload(&self, deployment_config: &DeploymentConfig) {
+        self.router.reload(deployment_config);
+        self.sink_filter.reload(deployment_config.sink_message_types());
+        match self.config_fingerprint.write() {
+            Ok(mut fingerprint) => *fingerprint = deployment_config.fingerprint(),
+            Err(err) => error!("Unable to update configuration fingerprint: {}", err),
+        }
+    }
+
+    /// Returns the fingerprint of the configuration the messages are published with
+    pub fn config_fingerprint(&self) -> String {
+        match self.config_fingerprint.read() {
+            Ok(fingerprint) => fingerprint.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+
+    /// Returns the outcome of the deliveries to each sink
+    pub fn sink_health(&self) -> BTreeMap<String, SinkStatus> {
+        self.sink_health.sinks()
+    }
+
+    pub fn has_wal(&self) -> bool {
+        self.wal.is_some()
+    }
+
+    pub fn wal_stats(&self) -> Option<WalStats> {
+        self.wal.as_ref().map(Wal::stats)
+    }
+
+    /// Returns the reason the WAL backlog exceeds the configured limits, if it does.
+    pub fn backlog_exceeded(&self) -> Option<String> {
+        let stats = self.wal_stats()?;
+        if let Some(max_pending) = self.backlog_limits.max_pending {
+            if stats.pending > max_pending {
+                return Some(format!(
+                    "{} pending WAL entries exceed the limit of {}",
+                    stats.pending, max_pending
+                ));
+            }
+        }
+        match (self.backlog_limits.max_pending_age, stats.oldest_pending_age) {
+            (Some(max_age), Some(age)) if age > max_age => Some(format!(
+                "oldest pending WAL entry is {}s old, exceeding the limit of {}s",
+                age, max_age
+            )),
+            _ => None,
+        }
+    }
+
+    /// Blocks the calling thread while the WAL backlog exceeds its limits, if intake
+    /// pausing is enabled, so no further events are taken in until the backlog drains.
+    pub fn wait_for_backlog(&self) {
+        if !self.backlog_limits.pause_intake {
+            return;
+        }
+        let mut paused = false;
+        while let Some(reason) = self.backlog_exceeded() {
+            if !paused {
+                warn!("Pausing event intake: {}", reason);
+                paused = true;
+            }
+            thread::sleep(Duration::from_secs(1));
+        }
+        if paused {
+            info!("Resuming event intake");
+        }
+    }
+
+    /// Flushes the other sinks and closes the Kafka producers, as the exporter shuts down.
+    /// Messages published afterwards open new producers.
+    pub fn close(&self) {
+        for sink in self.sinks.iter() {
+            if let Err(err) = sink.flush() {
+                error!("Unable to flush {} sink: {}", sink.name(), err);
+            }
+        }
+        match self.producers.lock() {
+            Ok(mut producers) => producers.clear(),
+            Err(err) => error!("Unable to close Kafka producers: {}", err),
+        }
+    }
+
+    /// Syncs the WAL directory, if there is a WAL
+    pub fn sync_wal(&self) -> Result<(), PublisherError> {
+        match self.wal {
+            Some(ref wal) => wal.sync(),
+            None => Ok(()),
+        }
+    }
+
+    /// Delivers the pending WAL entries in order, returns how many were delivered.
+    pub fn replay_wal(&self) -> Result<usize, PublisherError> {
+        let wal = match self.wal {
+            Some(ref wal) => wal,
+            None => return Ok(0),
+        };
+        let mut replayed = 0;
+        for path in wal.pending_entries()? {
+            let _order = self
+                .wal_order
+                .write()
+                .map_err(|err| PublisherError::LockPoisoned(err.to_string()))?;
+            let entry = match wal.read(&path) {
+                Ok(entry) => entry,
+                Err(err) => {
+                    error!("Skipping unreadable WAL entry: {}", err);
+                    wal.quarantine(&path)?;
+                    continue;
+                }
+            };
+            // The rest of the entries wait for the next replay
+            if !self.quotas.try_take(&entry.topic, entry.payload.len()) {
+                debug!("Topic {} is over its quota, pausing WAL replay", entry.topic);
+                break;
+            }
+            self.deliver(&entry.circuit_id, &entry.topic, &entry.key, &entry.payload)?;
+            wal.remove(&path)?;
+            replayed += 1;
+        }
+        Ok(replayed)
+    }
+
+    /// Delivers the bytes, or appends them to the WAL if they cannot be delivered now.
+    fn send(
+        &self,
+        circuit_id: &str,
+        topic: &str,
+        key: &str,
+        bytes: Vec<u8>,
+    ) -> Result<(), PublisherError> {
+        self.producer_builder.check_size(topic, bytes.len())?;
+        let wal = match self.wal {
+            Some(ref wal) => wal,
+            None => {
+                self.quotas.take(topic, bytes.len());
+                return self.deliver(circuit_id, topic, key, &bytes);
+            }
+        };
+        let _order = self
+            .wal_order
+            .read()
+            .map_err(|err| PublisherError::LockPoisoned(err.to_string()))?;
+
+        if !wal.has_pending() {
+            if self.quotas.try_take(topic, bytes.len()) {
+                match self.deliver(circuit_id, topic, key, &bytes) {
+                    Ok(()) => return Ok(()),
+                    Err(err) => warn!("Appending message to the WAL: {}", err),
+                }
+            } else {
+                debug!("Topic {} is over its quota, appending message to the WAL", topic);
+            }
+        }
+        wal.append(&WalEntry {
+            circuit_id: circuit_id.to_string(),
+            topic: topic.to_string(),
+            key: key.to_string(),
+            payload: bytes,
+        })
+    }
+
+    /// Appends the bytes to the WAL without trying to deliver them first
+    fn append_to_wal(
+        &self,
+        circuit_id: &str,
+        topic: &str,
+        key: &str,
+        bytes: Vec<u8>,
+    ) -> Result<(), PublisherError> {
+        self.producer_builder.check_size(topic, bytes.len())?;
+        let wal = self
+            .wal
+            .as_ref()
+            .ok_or_else(|| PublisherError::WalError("no WAL directory configured".into()))?;
+        let _order = self
+            .wal_order
+            .read()
+            .map_err(|err| PublisherError::LockPoisoned(err.to_string()))?;
+        wal.append(&WalEntry {
+            circuit_id: circuit_id.to_string(),
+            topic: topic.to_string(),
+            key: key.to_string(),
+            payload: bytes,
+        })
+    }
+
+    /// Sends the bytes to the Kafka topic using the producer for the given circuit.
+    ///
+    /// The key, chosen by the `kafka_key` strategy, is used for partitioning and for
+    /// consumers.
+    fn deliver(
+        &self,
+        circuit_id: &str,
+        topic: &str,
+        key: &str,
+        bytes: &[u8],
+    ) -> Result<(), PublisherError> {
+        let slot = self.producer_slot(circuit_id)?;
+        let mut producer = slot
+            .lock()
+            .map_err(|err| PublisherError::LockPoisoned(err.to_string()))?;
+
+        let mut current = match producer.take() {
+            Some(current) => current,
+            None => self.producer_builder.build().map_err(|err| {
+                self.sink_health.failed(KAFKA_SINK, &err.to_string());
+                err
+            })?,
+        };
+
+        if let Err(err) = current.send(&Record::from_key_value(topic, key, bytes)) {
+            self.sink_health.failed(KAFKA_SINK, &err.to_string());
+            return Err(PublisherError::SendError(err));
+        }
+        self.sink_health.delivered(KAFKA_SINK);
+
+        // The producer is only put back on success so that a failed send reconnects
+        *producer = Some(current);
+        Ok(())
+    }
+
+    fn producer_slot(&self, circuit_id: &str) -> Result<ProducerSlot, PublisherError> {
+        let key = if self.per_circuit {
+            circuit_id
+        } else {
+            SHARED_PRODUCER
+        };
+        let mut producers = self
+            .producers
+            .lock()
+            .map_err(|err| PublisherError::LockPoisoned(err.to_string()))?;
+        Ok(producers
+            .entry(key.to_string())
+            .or_insert_with(|| Arc::new(Mutex::new(None)))
+            .clone())
+    }
+}
@@ -0,0 +1,67 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use std::sync::Mutex;
+
+use nats::jetstream::JetStream;
+
+use super::filter::NATS_SINK;
+use super::sink::Sink;
+use super::PublisherError;
+
+/// Publishes messages to a NATS subject, waiting for the JetStream acknowledgement.
+///
+/// The client reconnects on its own when the server goes away; publishes fail in the
+/// meantime and are logged by the publisher.
+pub struct NatsSink {
+    subject: String,
+    jetstream: Mutex<JetStream>,
+}
+
+impl NatsSink {
+    pub fn connect(url: &str, subject: &str) -> Result<Self, PublisherError> {
+        let connection = nats::Options::new()
+            .with_name("event-listener")
+            .max_reconnects(None)
+            .disconnect_callback(|| warn!("Disconnected from NATS, reconnecting"))
+            .reconnect_callback(|| info!("Reconnected to NATS"))
+            .connect(url)
+            .map_err(|err| PublisherError::NatsError(err.to_string()))?;
+        info!("Connected to NATS at {}", url);
+        Ok(NatsSink {
+            subject: subject.to_string(),
+            jetstream: Mutex::new(nats::jetstream::new(connection)),
+        })
+    }
+}
+
+impl Sink for NatsSink {
+    fn name(&self) -> &str {
+        NATS_SINK
+    }
+
+    fn publish(&self, _circuit_id: &str, _topic: &str, bytes: &[u8]) -> Result<(), PublisherError> {
+        let jetstream = self
+            .jetstream
+            .lock()
+            .map_err(|err| PublisherError::LockPoisoned(err.to_string()))?;
+        jetstream
+            .publish(&self.subject, bytes)
+            .map(|_ack| ())
+            .map_err(|err| PublisherError::NatsError(err.to_string()))
+    }
+}
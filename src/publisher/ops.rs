@@ -0,0 +1,69 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use serde_json::Value;
+
+use crate::time::now_millis;
+
+/// Transitions in the lifecycle of an exporter, published to the ops topic
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Lifecycle {
+    /// The subscriptions were opened and the exporter waits for events
+    Started,
+    /// A scabbard subscription was opened for a new circuit
+    Subscribed,
+    /// A scabbard subscription was reopened, after a restart or once it was revived
+    Resubscribed,
+    /// Shutdown was requested, the queued events are being delivered
+    Draining,
+    /// The sinks were flushed and the WAL synced
+    ShutdownComplete,
+}
+
+impl Lifecycle {
+    fn name(self) -> &'static str {
+        match self {
+            Lifecycle::Started => "started",
+            Lifecycle::Subscribed => "subscribed",
+            Lifecycle::Resubscribed => "resubscribed",
+            Lifecycle::Draining => "draining",
+            Lifecycle::ShutdownComplete => "shutdown_complete",
+        }
+    }
+}
+
+/// The JSON record of a lifecycle transition. The run identifies the exporter process as
+/// in the envelopes, the details depend on the transition.
+pub fn record(
+    event: Lifecycle,
+    circuit_id: Option<&str>,
+    run_id: &str,
+    config_fingerprint: &str,
+    detail: Value,
+) -> Vec<u8> {
+    json!({
+        "event": event.name(),
+        "circuit_id": circuit_id,
+        "run_id": run_id,
+        "exporter_version": env!("CARGO_PKG_VERSION"),
+        "config_fingerprint": config_fingerprint,
+        "at": now_millis(),
+        "detail": detail,
+    })
+    .to_string()
+    .into_bytes()
+}
@@ -0,0 +1,199 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use std::collections::HashMap;
+
+use kafka::client::ProduceMessage;
+use kafka::producer::{Partitioner, Topics};
+
+/// How messages are spread over the partitions of a topic
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum PartitionStrategy {
+    /// Hash the record key with murmur2, as the Java client does, so all messages with
+    /// the same key land on the same partition
+    Murmur2Key,
+    /// Cycle through the available partitions
+    RoundRobin,
+    /// Keep writing to one partition per topic for as long as it stays available
+    Sticky,
+}
+
+impl Default for PartitionStrategy {
+    fn default() -> Self {
+        PartitionStrategy::Murmur2Key
+    }
+}
+
+/// Assigns partitions following the configured `PartitionStrategy`.
+///
+/// Changes in the number of partitions of a topic are logged, since they move keys to
+/// other partitions when hashing them and break ordering across the change.
+pub struct ConfiguredPartitioner {
+    strategy: PartitionStrategy,
+    next: HashMap<String, usize>,
+    sticky: HashMap<String, i32>,
+    partition_counts: HashMap<String, u32>,
+}
+
+impl ConfiguredPartitioner {
+    pub fn new(strategy: PartitionStrategy) -> Self {
+        ConfiguredPartitioner {
+            strategy,
+            next: HashMap::new(),
+            sticky: HashMap::new(),
+            partition_counts: HashMap::new(),
+        }
+    }
+
+    fn check_partition_count(&mut self, topic: &str, count: u32) {
+        match self.partition_counts.insert(topic.to_string(), count) {
+            Some(previous) if previous != count => warn!(
+                "Partition count of topic {} changed from {} to {}, per-key ordering is \
+                 not guaranteed across the change",
+                topic, previous, count
+            ),
+            _ => (),
+        }
+    }
+}
+
+impl Partitioner for ConfiguredPartitioner {
+    fn partition(&mut self, topics: Topics, msg: &mut ProduceMessage) {
+        let partitions = match topics.partitions(msg.topic) {
+            Some(partitions) => partitions,
+            // The producer reports the unknown topic when sending
+            None => return,
+        };
+        self.check_partition_count(msg.topic, partitions.num_all());
+        let available = partitions.available_ids();
+
+        match self.strategy {
+            PartitionStrategy::Murmur2Key => match msg.key {
+                Some(key) if partitions.num_all() > 0 => {
+                    msg.partition = key_partition(key, partitions.num_all());
+                    return;
+                }
+                _ => (),
+            },
+            PartitionStrategy::Sticky => {
+                if let Some(partition) = self.sticky.get(msg.topic) {
+                    if available.contains(partition) {
+                        msg.partition = *partition;
+                        return;
+                    }
+                }
+            }
+            PartitionStrategy::RoundRobin => (),
+        }
+
+        if available.is_empty() {
+            return;
+        }
+        let next = self.next.entry(msg.topic.to_string()).or_insert(0);
+        msg.partition = available[*next % available.len()];
+        *next = next.wrapping_add(1);
+        if self.strategy == PartitionStrategy::Sticky {
+            self.sticky.insert(msg.topic.to_string(), msg.partition);
+        }
+    }
+}
+
+/// The partition the Java client's default partitioner picks for a key
+fn key_partition(key: &[u8], partition_count: u32) -> i32 {
+    ((murmur2(key) & 0x7fff_ffff) % partition_count) as i32
+}
+
+/// The murmur2 hash used by the Java client's default partitioner
+fn murmur2(data: &[u8]) -> u32 {
+    const SEED: u32 = 0x9747_b28c;
+    const M: u32 = 0x5bd1_e995;
+    const R: u32 = 24;
+
+    let mut h = SEED ^ data.len() as u32;
+    let mut chunks = data.chunks_exact(4);
+    for chunk in &mut chunks {
+        let mut k = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        k = k.wrapping_mul(M);
+        k ^= k >> R;
+        k = k.wrapping_mul(M);
+        h = h.wrapping_mul(M);
+        h ^= k;
+    }
+
+    let tail = chunks.remainder();
+    if tail.len() >= 3 {
+        h ^= u32::from(tail[2]) << 16;
+    }
+    if tail.len() >= 2 {
+        h ^= u32::from(tail[1]) << 8;
+    }
+    if !tail.is_empty() {
+        h ^= u32::from(tail[0]);
+        h = h.wrapping_mul(M);
+    }
+
+    h ^= h >> 13;
+    h = h.wrapping_mul(M);
+    h ^= h >> 15;
+    h
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The vectors of `UtilsTest.testMurmur2` in the Kafka Java client
+    #[test]
+    fn murmur2_matches_java_client() {
+        let cases: &[(&[u8], i32)] = &[
+            (b"21", -973_932_308),
+            (b"foobar", -790_332_482),
+            (b"a-little-bit-long-string", -985_981_536),
+            (b"a-little-bit-longer-string", -1_486_304_829),
+            (
+                b"lkjh234lh9fiuh90y23oiuhsafujhadof229phr9h19h89h8",
+                -58_897_971,
+            ),
+            (b"abc", 479_470_107),
+        ];
+        for (key, expected) in cases {
+            assert_eq!(murmur2(key) as i32, *expected, "key {:?}", key);
+        }
+    }
+
+    /// Keys of every length up to two 4-byte blocks, covering each tail length, with the
+    /// partitions the Java client picks among 6 and 12
+    #[test]
+    fn key_partition_matches_java_client() {
+        let cases: &[(&[u8], i32, i32, i32)] = &[
+            (b"", 275_646_681, 3, 9),
+            (b"a", -1_563_381_124, 4, 4),
+            (b"ab", 316_155_434, 2, 2),
+            (b"abc", 479_470_107, 3, 3),
+            (b"abcd", -1_323_649_548, 2, 8),
+            (b"abcde", 461_995_741, 1, 1),
+            (b"abcdef", 1_870_650_108, 0, 0),
+            (b"abcdefg", -346_467_175, 1, 1),
+        ];
+        for (key, hash, of_6, of_12) in cases {
+            assert_eq!(murmur2(key) as i32, *hash, "key {:?}", key);
+            assert_eq!(key_partition(key, 6), *of_6, "key {:?}", key);
+            assert_eq!(key_partition(key, 12), *of_12, "key {:?}", key);
+        }
+    }
+}
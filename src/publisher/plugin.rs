@@ -0,0 +1,163 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+//! Sinks loaded from dynamic libraries at startup.
+//!
+//! A plugin is a cdylib exporting the following C functions:
+//!
+//! ```c
+//! // Version of this interface the plugin implements, must be PLUGIN_ABI_VERSION
+//! uint32_t exporter_sink_abi_version(void);
+//! // Called once after loading with the plugin's configuration as a JSON string,
+//! // returns 0 on success
+//! int32_t exporter_sink_init(const char *config_json);
+//! // Called for every exported message with the serialized envelope, returns 0 on success
+//! int32_t exporter_sink_publish(const char *circuit_id, const char *topic,
+//!                               const uint8_t *data, size_t len);
+//! // Called once before the plugin is unloaded
+//! void exporter_sink_shutdown(void);
+//! ```
+//!
+//! Calls into a plugin are serialized, so plugins need not be thread-safe.
+
+use std::ffi::CString;
+use std::os::raw::c_char;
+use std::sync::Mutex;
+
+use libloading::Library;
+use serde_json::Value;
+
+use super::sink::Sink;
+use super::PublisherError;
+
+/// Version of the plugin interface implemented by this exporter
+pub const PLUGIN_ABI_VERSION: u32 = 1;
+
+type AbiVersionFn = unsafe extern "C" fn() -> u32;
+type InitFn = unsafe extern "C" fn(*const c_char) -> i32;
+type PublishFn = unsafe extern "C" fn(*const c_char, *const c_char, *const u8, usize) -> i32;
+type ShutdownFn = unsafe extern "C" fn();
+
+/// A sink plugin listed in the deployment configuration
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PluginConfig {
+    pub path: String,
+    /// Name of the sink in `sink_message_types`, the library path if not set
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub config: Value,
+}
+
+pub struct PluginSink {
+    name: String,
+    path: String,
+    publish_fn: PublishFn,
+    shutdown_fn: ShutdownFn,
+    lock: Mutex<()>,
+    // Kept last so the library is unloaded after the function pointers are dropped
+    _library: Library,
+}
+
+impl PluginSink {
+    pub fn load(plugin: &PluginConfig) -> Result<Self, PublisherError> {
+        let error = |msg: String| PublisherError::PluginError(format!("{}: {}", plugin.path, msg));
+
+        let library = Library::new(&plugin.path).map_err(|err| error(err.to_string()))?;
+        let (abi_version_fn, init_fn, publish_fn, shutdown_fn) = unsafe {
+            (
+                *library
+                    .get::<AbiVersionFn>(b"exporter_sink_abi_version\0")
+                    .map_err(|err| error(err.to_string()))?,
+                *library
+                    .get::<InitFn>(b"exporter_sink_init\0")
+                    .map_err(|err| error(err.to_string()))?,
+                *library
+                    .get::<PublishFn>(b"exporter_sink_publish\0")
+                    .map_err(|err| error(err.to_string()))?,
+                *library
+                    .get::<ShutdownFn>(b"exporter_sink_shutdown\0")
+                    .map_err(|err| error(err.to_string()))?,
+            )
+        };
+
+        let abi_version = unsafe { abi_version_fn() };
+        if abi_version != PLUGIN_ABI_VERSION {
+            return Err(error(format!(
+                "plugin implements interface version {}, expected {}",
+                abi_version, PLUGIN_ABI_VERSION
+            )));
+        }
+
+        let config =
+            CString::new(plugin.config.to_string()).map_err(|err| error(err.to_string()))?;
+        let status = unsafe { init_fn(config.as_ptr()) };
+        if status != 0 {
+            return Err(error(format!(
+                "initialization failed with status {}",
+                status
+            )));
+        }
+        info!("Loaded sink plugin {}", plugin.path);
+
+        Ok(PluginSink {
+            name: plugin.name.clone().unwrap_or_else(|| plugin.path.clone()),
+            path: plugin.path.clone(),
+            publish_fn,
+            shutdown_fn,
+            lock: Mutex::new(()),
+            _library: library,
+        })
+    }
+}
+
+impl Sink for PluginSink {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn publish(&self, circuit_id: &str, topic: &str, bytes: &[u8]) -> Result<(), PublisherError> {
+        let error = |msg: String| PublisherError::PluginError(format!("{}: {}", self.path, msg));
+        let circuit_id = CString::new(circuit_id).map_err(|err| error(err.to_string()))?;
+        let topic = CString::new(topic).map_err(|err| error(err.to_string()))?;
+
+        let _lock = self
+            .lock
+            .lock()
+            .map_err(|err| PublisherError::LockPoisoned(err.to_string()))?;
+        let status = unsafe {
+            (self.publish_fn)(
+                circuit_id.as_ptr(),
+                topic.as_ptr(),
+                bytes.as_ptr(),
+                bytes.len(),
+            )
+        };
+        if status != 0 {
+            return Err(error(format!("publish failed with status {}", status)));
+        }
+        Ok(())
+    }
+}
+
+impl Drop for PluginSink {
+    fn drop(&mut self) {
+        if let Ok(_lock) = self.lock.lock() {
+            unsafe { (self.shutdown_fn)() };
+        }
+    }
+}
@@ -0,0 +1,126 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+
+use protobuf::ProtobufEnum;
+
+use super::encryption::PayloadEncryptor;
+use super::serializer::OutputFormat;
+use super::PublisherError;
+use crate::application_metadata::ExportPreferences;
+use crate::proto::pubsub::Message_MessageType;
+
+/// Encoding, message types and payload encryption a circuit's application metadata
+/// asks for
+#[derive(Clone)]
+struct CircuitPreference {
+    output_format: Option<OutputFormat>,
+    message_types: Option<HashSet<String>>,
+    /// An invalid key is kept as its error, so the payloads are not exported in the clear
+    encryptor: Option<Result<Arc<PayloadEncryptor>, String>>,
+}
+
+/// The export preferences of the circuits with version 2 application metadata.
+///
+/// Their topics are kept by the `TopicRouter`.
+#[derive(Clone, Default)]
+pub struct CircuitPreferences {
+    preferences: Arc<RwLock<HashMap<String, CircuitPreference>>>,
+}
+
+impl CircuitPreferences {
+    pub fn set(&self, circuit_id: &str, preferences: &ExportPreferences) {
+        let message_types = preferences.message_types.as_ref().map(|names| {
+            let known = Message_MessageType::values()
+                .iter()
+                .map(|message_type| message_type.descriptor().name())
+                .collect::<HashSet<_>>();
+            for name in names.iter().filter(|name| !known.contains(name.as_str())) {
+                warn!(
+                    "Circuit {} asks for the unknown message type {}",
+                    circuit_id, name
+                );
+            }
+            names.iter().cloned().collect()
+        });
+        let encryptor = preferences.encryption_key.as_ref().map(|pem| {
+            PayloadEncryptor::from_pem(pem)
+                .map(Arc::new)
+                .map_err(|err| {
+                    error!("Circuit {} has an invalid encryption key: {}", circuit_id, err);
+                    err.to_string()
+                })
+        });
+        match self.preferences.write() {
+            Ok(mut circuit_preferences) => {
+                circuit_preferences.insert(
+                    circuit_id.to_string(),
+                    CircuitPreference {
+                        output_format: preferences.output_format,
+                        message_types,
+                        encryptor,
+                    },
+                );
+            }
+            Err(err) => error!("Unable to record circuit export preferences: {}", err),
+        }
+    }
+
+    /// Returns whether the circuit's members want messages of the type exported
+    pub fn allows(&self, circuit_id: &str, message_type: Message_MessageType) -> bool {
+        match self
+            .read(circuit_id)
+            .and_then(|preference| preference.message_types)
+        {
+            Some(message_types) => message_types.contains(message_type.descriptor().name()),
+            None => true,
+        }
+    }
+
+    /// Returns the encoding the circuit's members asked for, if any
+    pub fn output_format(&self, circuit_id: &str) -> Option<OutputFormat> {
+        self.read(circuit_id)
+            .and_then(|preference| preference.output_format)
+    }
+
+    /// Returns the encryptor of the circuit's payloads, if it designates a consumer
+    pub fn encryptor(
+        &self,
+        circuit_id: &str,
+    ) -> Result<Option<Arc<PayloadEncryptor>>, PublisherError> {
+        match self.read(circuit_id).and_then(|preference| preference.encryptor) {
+            Some(Ok(encryptor)) => Ok(Some(encryptor)),
+            Some(Err(err)) => Err(PublisherError::EncryptionError(format!(
+                "circuit {} has an invalid encryption key: {}",
+                circuit_id, err
+            ))),
+            None => Ok(None),
+        }
+    }
+
+    fn read(&self, circuit_id: &str) -> Option<CircuitPreference> {
+        match self.preferences.read() {
+            Ok(preferences) => preferences.get(circuit_id).cloned(),
+            Err(err) => {
+                error!("Unable to read circuit export preferences: {}", err);
+                None
+            }
+        }
+    }
+}
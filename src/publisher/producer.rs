@@ -0,0 +1,155 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use std::time::Duration;
+
+use kafka::client::Compression;
+use kafka::producer::{Producer, RequiredAcks};
+
+use super::error::PublisherError;
+use super::partitioner::{ConfiguredPartitioner, PartitionStrategy};
+use super::security::KafkaSecurityConfig;
+use crate::config::DeploymentConfig;
+
+/// How many brokers acknowledge a message before a send returns
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum AckLevel {
+    /// Do not wait for an acknowledgement, messages may be lost silently
+    None,
+    /// Wait for the partition leader
+    One,
+    /// Wait for all in-sync replicas
+    All,
+}
+
+impl Default for AckLevel {
+    fn default() -> Self {
+        AckLevel::One
+    }
+}
+
+impl From<AckLevel> for RequiredAcks {
+    fn from(acks: AckLevel) -> Self {
+        match acks {
+            AckLevel::None => RequiredAcks::None,
+            AckLevel::One => RequiredAcks::One,
+            AckLevel::All => RequiredAcks::All,
+        }
+    }
+}
+
+/// Compression applied to the message sets sent to Kafka
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum CompressionCodec {
+    None,
+    Gzip,
+    Snappy,
+}
+
+impl Default for CompressionCodec {
+    fn default() -> Self {
+        CompressionCodec::None
+    }
+}
+
+impl From<CompressionCodec> for Compression {
+    fn from(codec: CompressionCodec) -> Self {
+        match codec {
+            CompressionCodec::None => Compression::NONE,
+            CompressionCodec::Gzip => Compression::GZIP,
+            CompressionCodec::Snappy => Compression::SNAPPY,
+        }
+    }
+}
+
+/// Tuning of the Kafka producers, read from the `kafka_producer` section
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct KafkaProducerConfig {
+    #[serde(default)]
+    pub acks: AckLevel,
+    /// Milliseconds the brokers have to acknowledge a message
+    #[serde(default = "default_ack_timeout")]
+    pub ack_timeout: u64,
+    #[serde(default)]
+    pub compression: CompressionCodec,
+    /// Largest message in bytes written to Kafka, larger messages are rejected before
+    /// they reach the WAL since the brokers would refuse them on every replay
+    #[serde(default)]
+    pub max_message_size: Option<usize>,
+}
+
+impl Default for KafkaProducerConfig {
+    fn default() -> Self {
+        KafkaProducerConfig {
+            acks: AckLevel::default(),
+            ack_timeout: default_ack_timeout(),
+            compression: CompressionCodec::default(),
+            max_message_size: None,
+        }
+    }
+}
+
+fn default_ack_timeout() -> u64 {
+    5000
+}
+
+/// Creates the Kafka producers of the publisher with the configured tuning
+#[derive(Clone)]
+pub struct KafkaProducerBuilder {
+    kafka_url: String,
+    partition_strategy: PartitionStrategy,
+    config: KafkaProducerConfig,
+    security: Option<KafkaSecurityConfig>,
+}
+
+impl KafkaProducerBuilder {
+    pub fn new(deployment_config: &DeploymentConfig) -> Self {
+        KafkaProducerBuilder {
+            kafka_url: deployment_config.kafka_url().to_string(),
+            partition_strategy: deployment_config.kafka_partitioner(),
+            config: deployment_config.kafka_producer().clone(),
+            security: deployment_config.kafka_security().cloned(),
+        }
+    }
+
+    /// Fails if the message is larger than `max_message_size`
+    pub fn check_size(&self, topic: &str, size: usize) -> Result<(), PublisherError> {
+        match self.config.max_message_size {
+            Some(max) if size > max => Err(PublisherError::MessageTooLarge(format!(
+                "{} bytes for topic {}, the limit is {} bytes",
+                size, topic, max
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn build(&self) -> Result<Producer<ConfiguredPartitioner>, PublisherError> {
+        let mut builder = Producer::from_hosts(vec![self.kafka_url.clone()])
+            .with_ack_timeout(Duration::from_millis(self.config.ack_timeout))
+            .with_required_acks(self.config.acks.into())
+            .with_compression(self.config.compression.into())
+            .with_partitioner(ConfiguredPartitioner::new(self.partition_strategy));
+        if let Some(ref security) = self.security {
+            builder = builder.with_security(security.security_config()?);
+        }
+        builder
+            .create()
+            .map_err(PublisherError::ProducerCreationError)
+    }
+}
@@ -0,0 +1,116 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A token bucket refilled at `rate` bytes per second, holding at most a second's worth
+struct Bucket {
+    rate: f64,
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl Bucket {
+    fn new(rate: u64) -> Self {
+        Bucket {
+            rate: rate as f64,
+            tokens: rate as f64,
+            refilled_at: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.refilled_at);
+        let elapsed = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) / 1e9;
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+        self.refilled_at = now;
+    }
+
+    /// Takes the bytes if they fit. A message larger than the bucket is let through
+    /// once the bucket is full, leaving it in debt, so it is not held back forever.
+    fn try_take(&mut self, bytes: usize) -> bool {
+        self.refill();
+        let bytes = bytes as f64;
+        if self.tokens >= bytes.min(self.rate) {
+            self.tokens -= bytes;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Time until the bytes would fit
+    fn wait_time(&self, bytes: usize) -> Duration {
+        let missing = (bytes as f64).min(self.rate) - self.tokens;
+        Duration::from_millis((missing.max(0.0) / self.rate * 1000.0).ceil() as u64)
+    }
+}
+
+/// Throttles the bytes written to each topic with a configured quota, in bytes per
+/// second. Topics without a quota are not limited.
+#[derive(Clone)]
+pub struct TopicQuotas {
+    buckets: Arc<Mutex<HashMap<String, Bucket>>>,
+}
+
+impl TopicQuotas {
+    pub fn new(quotas: &BTreeMap<String, u64>) -> Self {
+        TopicQuotas {
+            buckets: Arc::new(Mutex::new(
+                quotas
+                    .iter()
+                    .filter(|(_, rate)| **rate > 0)
+                    .map(|(topic, rate)| (topic.clone(), Bucket::new(*rate)))
+                    .collect(),
+            )),
+        }
+    }
+
+    /// Takes the bytes from the topic's quota, returns false if the quota is exceeded
+    pub fn try_take(&self, topic: &str, bytes: usize) -> bool {
+        match self.buckets.lock() {
+            Ok(mut buckets) => buckets
+                .get_mut(topic)
+                .map_or(true, |bucket| bucket.try_take(bytes)),
+            Err(err) => {
+                error!("Unable to check topic quota: {}", err);
+                true
+            }
+        }
+    }
+
+    /// Blocks until the bytes fit in the topic's quota and takes them
+    pub fn take(&self, topic: &str, bytes: usize) {
+        loop {
+            let wait_time = match self.buckets.lock() {
+                Ok(mut buckets) => match buckets.get_mut(topic) {
+                    Some(bucket) if !bucket.try_take(bytes) => bucket.wait_time(bytes),
+                    _ => return,
+                },
+                Err(err) => {
+                    error!("Unable to check topic quota: {}", err);
+                    return;
+                }
+            };
+            thread::sleep(wait_time);
+        }
+    }
+}
@@ -0,0 +1,140 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use actix_web::web::Bytes;
+use futures::sync::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+
+use crate::event_handler::to_hex;
+use crate::proto::pubsub::Message;
+use crate::time::now_millis;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RecentEvent {
+    circuit_id: String,
+    message_type: String,
+    run_id: String,
+    published_at: u64,
+    message: String,
+}
+
+impl RecentEvent {
+    fn new(circuit_id: &str, envelope: &Message) -> Self {
+        RecentEvent {
+            circuit_id: circuit_id.to_string(),
+            message_type: format!("{:?}", envelope.get_field_type()),
+            run_id: envelope.get_run_id().to_string(),
+            published_at: now_millis(),
+            message: to_hex(envelope.get_message()),
+        }
+    }
+
+    pub fn circuit_id(&self) -> &str {
+        &self.circuit_id
+    }
+}
+
+struct Subscriber {
+    circuit_id: Option<String>,
+    sender: UnboundedSender<Bytes>,
+}
+
+/// A bounded buffer of the most recently exported envelopes, which can also be followed
+/// as a server-sent event stream.
+#[derive(Clone)]
+pub struct RecentEvents {
+    capacity: usize,
+    events: Arc<Mutex<VecDeque<RecentEvent>>>,
+    subscribers: Arc<Mutex<Vec<Subscriber>>>,
+}
+
+impl RecentEvents {
+    pub fn new(capacity: usize) -> Self {
+        RecentEvents {
+            capacity,
+            events: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+            subscribers: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    pub fn push(&self, circuit_id: &str, envelope: &Message) {
+        if self.capacity == 0 {
+            return;
+        }
+        let event = RecentEvent::new(circuit_id, envelope);
+
+        if let Ok(mut subscribers) = self.subscribers.lock() {
+            if !subscribers.is_empty() {
+                let data = serde_json::to_string(&event)
+                    .map(|json| Bytes::from(format!("data: {}\n\n", json)));
+                match data {
+                    // Subscribers whose stream has been closed are dropped
+                    Ok(data) => subscribers.retain(|subscriber| {
+                        let wants_event = match subscriber.circuit_id {
+                            Some(ref circuit_id) => circuit_id == event.circuit_id(),
+                            None => true,
+                        };
+                        !wants_event || subscriber.sender.unbounded_send(data.clone()).is_ok()
+                    }),
+                    Err(err) => error!("Unable to serialize recent event: {}", err),
+                }
+            }
+        }
+
+        match self.events.lock() {
+            Ok(mut events) => {
+                if events.len() == self.capacity {
+                    events.pop_front();
+                }
+                events.push_back(event);
+            }
+            Err(err) => error!("Unable to record recent event: {}", err),
+        }
+    }
+
+    /// Returns up to `limit` of the latest events, oldest first, optionally for one circuit
+    pub fn list(&self, circuit_id: Option<&str>, limit: usize) -> Vec<RecentEvent> {
+        let events = match self.events.lock() {
+            Ok(events) => events,
+            Err(err) => {
+                error!("Unable to read recent events: {}", err);
+                return Vec::new();
+            }
+        };
+        let mut matching = events
+            .iter()
+            .rev()
+            .filter(|event| circuit_id.map_or(true, |id| id == event.circuit_id()))
+            .take(limit)
+            .cloned()
+            .collect::<Vec<_>>();
+        matching.reverse();
+        matching
+    }
+
+    /// Registers a new stream subscriber, which receives every event pushed from now on
+    pub fn subscribe(&self, circuit_id: Option<String>) -> UnboundedReceiver<Bytes> {
+        let (sender, receiver) = unbounded();
+        match self.subscribers.lock() {
+            Ok(mut subscribers) => subscribers.push(Subscriber { circuit_id, sender }),
+            Err(err) => error!("Unable to register event stream subscriber: {}", err),
+        }
+        receiver
+    }
+}
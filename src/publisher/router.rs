@@ -0,0 +1,190 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, RwLock};
+
+use protobuf::ProtobufEnum;
+
+use crate::config::DeploymentConfig;
+use crate::error::ConfigurationError;
+use crate::proto::pubsub::Message_MessageType;
+
+/// Placeholder replaced by a circuit's management type in the topic template
+const MANAGEMENT_TYPE_PLACEHOLDER: &str = "{management_type}";
+/// Suffix of a `topic_map` entry matching every message type with the given prefix
+const WILDCARD: &str = "*";
+
+/// Decides which Kafka topic a message is written to.
+///
+/// Message types listed in `topic_map` go to their mapped topic, an exact type name taking
+/// precedence over a prefix such as `PROPOSAL_*`. Other messages go to the topic the
+/// circuit's application metadata asks for, if any, then to `kafka_topic` unless
+/// `kafka_topic_template` is set, in which case the topic is derived from the management
+/// type of the circuit the message belongs to.
+#[derive(Clone)]
+pub struct TopicRouter {
+    routes: Arc<RwLock<Routes>>,
+    management_types: Arc<RwLock<HashMap<String, String>>>,
+    circuit_topics: Arc<RwLock<HashMap<String, String>>>,
+}
+
+/// The topic settings of the deployment configuration, replaced on reload
+struct Routes {
+    default_topic: String,
+    template: Option<String>,
+    topic_map: BTreeMap<String, String>,
+}
+
+impl Routes {
+    fn new(deployment_config: &DeploymentConfig) -> Self {
+        Routes {
+            default_topic: deployment_config.kafka_topic().to_string(),
+            template: deployment_config.kafka_topic_template().map(ToOwned::to_owned),
+            topic_map: deployment_config.topic_map().clone(),
+        }
+    }
+
+    fn mapped_topic(&self, message_type: Message_MessageType) -> Option<&str> {
+        let name = message_type.descriptor().name();
+        if let Some(topic) = self.topic_map.get(name) {
+            return Some(topic);
+        }
+        self.topic_map
+            .iter()
+            .filter_map(|(pattern, topic)| {
+                let prefix = wildcard_prefix(pattern)?;
+                if name.starts_with(prefix) {
+                    Some((prefix.len(), topic))
+                } else {
+                    None
+                }
+            })
+            .max_by_key(|(len, _)| *len)
+            .map(|(_, topic)| topic.as_str())
+    }
+}
+
+impl TopicRouter {
+    pub fn new(deployment_config: &DeploymentConfig) -> Self {
+        TopicRouter {
+            routes: Arc::new(RwLock::new(Routes::new(deployment_config))),
+            management_types: Arc::new(RwLock::new(HashMap::new())),
+            circuit_topics: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// Replaces the topic settings, the messages published afterwards use the new ones
+    pub fn reload(&self, deployment_config: &DeploymentConfig) {
+        match self.routes.write() {
+            Ok(mut routes) => *routes = Routes::new(deployment_config),
+            Err(err) => error!("Unable to reload topic settings: {}", err),
+        }
+    }
+
+    pub fn set_management_type(&self, circuit_id: &str, management_type: &str) {
+        match self.management_types.write() {
+            Ok(mut management_types) => {
+                management_types.insert(circuit_id.to_string(), management_type.to_string());
+            }
+            Err(err) => error!("Unable to record circuit management type: {}", err),
+        }
+    }
+
+    /// Returns the management type of a circuit, if it was recorded
+    pub fn management_type(&self, circuit_id: &str) -> Option<String> {
+        match self.management_types.read() {
+            Ok(management_types) => management_types.get(circuit_id).cloned(),
+            Err(err) => {
+                error!("Unable to read circuit management types: {}", err);
+                None
+            }
+        }
+    }
+
+    /// Records the topic a circuit's members asked for in its application metadata
+    pub fn set_circuit_topic(&self, circuit_id: &str, topic: &str) {
+        match self.circuit_topics.write() {
+            Ok(mut circuit_topics) => {
+                circuit_topics.insert(circuit_id.to_string(), topic.to_string());
+            }
+            Err(err) => error!("Unable to record circuit topic: {}", err),
+        }
+    }
+
+    pub fn topic(&self, circuit_id: &str, message_type: Message_MessageType) -> String {
+        let routes = match self.routes.read() {
+            Ok(routes) => routes,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        if let Some(topic) = routes.mapped_topic(message_type) {
+            return topic.to_string();
+        }
+        match self.circuit_topics.read() {
+            Ok(circuit_topics) => {
+                if let Some(topic) = circuit_topics.get(circuit_id) {
+                    return topic.clone();
+                }
+            }
+            Err(err) => error!("Unable to read circuit topics: {}", err),
+        }
+        let template = match routes.template {
+            Some(ref template) => template,
+            None => return routes.default_topic.clone(),
+        };
+        match self.management_type(circuit_id) {
+            Some(management_type) => template.replace(MANAGEMENT_TYPE_PLACEHOLDER, &management_type),
+            None => routes.default_topic.clone(),
+        }
+    }
+}
+
+/// Checks that every `topic_map` entry matches a message type and names a topic
+pub fn validate_topic_map(topic_map: &BTreeMap<String, String>) -> Result<(), ConfigurationError> {
+    let known = Message_MessageType::values()
+        .iter()
+        .map(|message_type| message_type.descriptor().name())
+        .collect::<Vec<_>>();
+    for (pattern, topic) in topic_map {
+        let matches = match wildcard_prefix(pattern) {
+            Some(prefix) => known.iter().any(|name| name.starts_with(prefix)),
+            None => known.contains(&pattern.as_str()),
+        };
+        if !matches {
+            return Err(ConfigurationError::MissingValue(format!(
+                "topic_map: unknown message type {}",
+                pattern
+            )));
+        }
+        if topic.is_empty() {
+            return Err(ConfigurationError::MissingValue(format!(
+                "topic_map.{}",
+                pattern
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// The prefix of a wildcard `topic_map` entry, `None` for an exact type name
+fn wildcard_prefix(pattern: &str) -> Option<&str> {
+    if pattern.ends_with(WILDCARD) {
+        Some(&pattern[..pattern.len() - WILDCARD.len()])
+    } else {
+        None
+    }
+}
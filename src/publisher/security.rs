@@ -0,0 +1,74 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use kafka::client::SecurityConfig;
+use openssl::ssl::{SslConnector, SslFiletype, SslMethod};
+
+use super::PublisherError;
+
+/// TLS settings of the Kafka connections, read from the `kafka_security` section.
+///
+/// The same settings apply to the producers, the consumer lag monitor and the aggregator
+/// consumer.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct KafkaSecurityConfig {
+    /// PEM file of the CA certificates trusted for the brokers, the system trust store
+    /// when unset
+    #[serde(default)]
+    pub ca_cert: Option<String>,
+    /// PEM file of the client certificate chain presented to the brokers
+    #[serde(default)]
+    pub client_cert: Option<String>,
+    /// PEM file of the client certificate's private key
+    #[serde(default)]
+    pub client_key: Option<String>,
+    #[serde(default = "default_verify_hostname")]
+    pub verify_hostname: bool,
+}
+
+fn default_verify_hostname() -> bool {
+    true
+}
+
+impl KafkaSecurityConfig {
+    pub fn security_config(&self) -> Result<SecurityConfig, PublisherError> {
+        let tls_error =
+            |err: openssl::error::ErrorStack| PublisherError::KafkaSecurityError(err.to_string());
+        let mut builder = SslConnector::builder(SslMethod::tls()).map_err(tls_error)?;
+        if let Some(ref ca_cert) = self.ca_cert {
+            builder.set_ca_file(ca_cert).map_err(tls_error)?;
+        }
+        match (&self.client_cert, &self.client_key) {
+            (Some(client_cert), Some(client_key)) => {
+                builder
+                    .set_certificate_chain_file(client_cert)
+                    .map_err(tls_error)?;
+                builder
+                    .set_private_key_file(client_key, SslFiletype::PEM)
+                    .map_err(tls_error)?;
+                builder.check_private_key().map_err(tls_error)?;
+            }
+            (None, None) => (),
+            _ => {
+                return Err(PublisherError::KafkaSecurityError(
+                    "client_cert and client_key must be set together".to_string(),
+                ))
+            }
+        }
+        Ok(SecurityConfig::new(builder.build()).with_hostname_verification(self.verify_hostname))
+    }
+}
@@ -0,0 +1,341 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use openssl::base64;
+use protobuf::{Message as Msg, ProtobufEnum};
+use serde_json::Value;
+
+use super::PublisherError;
+use crate::proto::pubsub::{
+    Ballot, CircuitCreated, CircuitDefinition, CircuitMember, CircuitPayload,
+    CircuitPayloadDeleted, CircuitRemoved, CircuitService, ContractDeployFailed, Control,
+    EnvelopeV2, Message, Message_MessageType, ProposalAccept, ProposalReady, ProposalReject,
+    ProposalSubmit, ProposalVote,
+};
+
+/// Encoding of the messages handed to Kafka and the other sinks
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputFormat {
+    /// The protobuf envelope
+    Protobuf,
+    /// The JSON document built by `to_json`
+    Json,
+    /// The protobuf envelope, plus the JSON document on the topic suffixed with `.json`
+    Both,
+    /// Avro records in the Confluent wire format, see the `avro` module
+    Avro,
+}
+
+impl Default for OutputFormat {
+    fn default() -> Self {
+        OutputFormat::Protobuf
+    }
+}
+
+/// Suffix of the topic the JSON documents are written to with `OutputFormat::Both`
+pub const JSON_TOPIC_SUFFIX: &str = ".json";
+
+/// Converts an envelope into a JSON document.
+///
+/// The document holds the envelope fields with the decoded message under `message`.
+/// Field names are those of `pubsub.proto` and values follow the proto3 JSON mapping, as
+/// the generated JSON Schema does: enums are written by name, bytes in base64 and 64-bit
+/// integers as strings. Every field is present even when it holds its default value, so
+/// the shape of a document only depends on its type.
+pub fn to_json(envelope: &Message) -> Result<Vec<u8>, PublisherError> {
+    Ok(document(envelope)?.to_string().into_bytes())
+}
+
+/// The JSON document of an envelope, as written by `to_json`
+pub fn document(envelope: &Message) -> Result<Value, PublisherError> {
+    Ok(json!({
+        "type": envelope.get_field_type().descriptor().name(),
+        "message": message_document(envelope.get_field_type(), envelope.get_message())?,
+        "run_id": envelope.get_run_id(),
+        "run_started_at": uint64(envelope.get_run_started_at()),
+        "circuit_management_type": envelope.get_circuit_management_type(),
+        "sequence": uint64(envelope.get_sequence()),
+        "exporter_node_id": envelope.get_exporter_node_id(),
+        "exporter_version": envelope.get_exporter_version(),
+    }))
+}
+
+/// Converts a version 2 envelope into a JSON document, shaped like those of `to_json`
+/// with the additional envelope fields
+pub fn to_json_v2(envelope: &EnvelopeV2) -> Result<Vec<u8>, PublisherError> {
+    let document = json!({
+        "schema_version": envelope.get_schema_version(),
+        "type": envelope.get_field_type().descriptor().name(),
+        "circuit_id": envelope.get_circuit_id(),
+        "message": message_document(envelope.get_field_type(), envelope.get_message())?,
+        "run_id": envelope.get_run_id(),
+        "run_started_at": uint64(envelope.get_run_started_at()),
+        "published_at": uint64(envelope.get_published_at()),
+        "circuit_management_type": envelope.get_circuit_management_type(),
+        "sequence": uint64(envelope.get_sequence()),
+        "exporter_node_id": envelope.get_exporter_node_id(),
+        "exporter_version": envelope.get_exporter_version(),
+    });
+    Ok(document.to_string().into_bytes())
+}
+
+fn message_document(
+    message_type: Message_MessageType,
+    bytes: &[u8],
+) -> Result<Value, PublisherError> {
+    let message = match message_type {
+        Message_MessageType::PROPOSAL_SUBMIT => {
+            let submit = parse::<ProposalSubmit>(bytes)?;
+            json!({
+                "requester": submit.get_requester(),
+                "requester_node_id": submit.get_requester_node_id(),
+                "circuit_id": submit.get_circuit_id(),
+                "proposal_id": submit.get_proposal_id(),
+                "circuit": if submit.has_circuit() {
+                    definition_document(submit.get_circuit())
+                } else {
+                    Value::Null
+                },
+            })
+        }
+        Message_MessageType::PROPOSAL_VOTE => {
+            let vote = parse::<ProposalVote>(bytes)?;
+            json!({
+                "voter": vote.get_voter(),
+                "voter_node_id": vote.get_voter_node_id(),
+                "circuit_id": vote.get_circuit_id(),
+                "proposal_id": vote.get_proposal_id(),
+                "voter_display_name": vote.get_voter_display_name(),
+                "voter_node_metadata": vote.get_voter_node_metadata(),
+                "votes": ballots_document(vote.get_votes()),
+                "required_votes": vote.get_required_votes(),
+            })
+        }
+        Message_MessageType::PROPOSAL_ACCEPT => {
+            let accept = parse::<ProposalAccept>(bytes)?;
+            json!({
+                "voter": accept.get_voter(),
+                "voter_node_id": accept.get_voter_node_id(),
+                "circuit_id": accept.get_circuit_id(),
+                "alias": accept.get_alias(),
+                "comments": accept.get_comments(),
+                "proposal_id": accept.get_proposal_id(),
+                "voter_display_name": accept.get_voter_display_name(),
+                "voter_node_metadata": accept.get_voter_node_metadata(),
+                "votes": ballots_document(accept.get_votes()),
+                "required_votes": accept.get_required_votes(),
+            })
+        }
+        Message_MessageType::PROPOSAL_REJECT => {
+            let reject = parse::<ProposalReject>(bytes)?;
+            json!({
+                "voter": reject.get_voter(),
+                "voter_node_id": reject.get_voter_node_id(),
+                "circuit_id": reject.get_circuit_id(),
+                "alias": reject.get_alias(),
+                "comments": reject.get_comments(),
+                "proposal_id": reject.get_proposal_id(),
+                "voter_display_name": reject.get_voter_display_name(),
+                "voter_node_metadata": reject.get_voter_node_metadata(),
+                "votes": ballots_document(reject.get_votes()),
+                "required_votes": reject.get_required_votes(),
+            })
+        }
+        Message_MessageType::PROPOSAL_READY => {
+            let ready = parse::<ProposalReady>(bytes)?;
+            json!({
+                "requester": ready.get_requester(),
+                "requester_node_id": ready.get_requester_node_id(),
+                "circuit_id": ready.get_circuit_id(),
+                "proposal_id": ready.get_proposal_id(),
+            })
+        }
+        Message_MessageType::CIRCUIT_CREATED => {
+            let created = parse::<CircuitCreated>(bytes)?;
+            json!({
+                "requester": created.get_requester(),
+                "requester_node_id": created.get_requester_node_id(),
+                "circuit_id": created.get_circuit_id(),
+                "services": services_document(created.get_services()),
+                "members": members_document(created.get_members()),
+            })
+        }
+        Message_MessageType::CIRCUIT_PAYLOAD => {
+            let payload = parse::<CircuitPayload>(bytes)?;
+            json!({
+                "requester": payload.get_requester(),
+                "requester_node_id": payload.get_requester_node_id(),
+                "circuit_id": payload.get_circuit_id(),
+                "data": to_base64(payload.get_data()),
+                "key": payload.get_key(),
+                "contract_name": payload.get_contract_name(),
+                "decoded": serde_json::from_str::<Value>(payload.get_decoded())
+                    .unwrap_or(Value::Null),
+                "decode_error": payload.get_decode_error(),
+                "service_id": payload.get_service_id(),
+                "received_at": uint64(payload.get_received_at()),
+                "encoding": payload.get_encoding().descriptor().name(),
+                "sequence": uint64(payload.get_sequence()),
+                "origin": payload.get_origin().descriptor().name(),
+                "encryption": if payload.has_encryption() {
+                    let encryption = payload.get_encryption();
+                    json!({
+                        "wrapped_key": to_base64(encryption.get_wrapped_key()),
+                        "iv": to_base64(encryption.get_iv()),
+                        "tag": to_base64(encryption.get_tag()),
+                        "key_id": encryption.get_key_id(),
+                    })
+                } else {
+                    Value::Null
+                },
+            })
+        }
+        Message_MessageType::CONTROL => {
+            let control = parse::<Control>(bytes)?;
+            json!({
+                "command": control.get_command(),
+                "detail": control.get_detail(),
+                "effective_at": uint64(control.get_effective_at()),
+                "issued_at": uint64(control.get_issued_at()),
+            })
+        }
+        Message_MessageType::CIRCUIT_REMOVED => {
+            let removed = parse::<CircuitRemoved>(bytes)?;
+            json!({
+                "requester": removed.get_requester(),
+                "requester_node_id": removed.get_requester_node_id(),
+                "circuit_id": removed.get_circuit_id(),
+                "detected_at": uint64(removed.get_detected_at()),
+            })
+        }
+        Message_MessageType::CONTRACT_DEPLOY_FAILED => {
+            let failed = parse::<ContractDeployFailed>(bytes)?;
+            json!({
+                "circuit_id": failed.get_circuit_id(),
+                "service_id": failed.get_service_id(),
+                "contracts": failed
+                    .get_contracts()
+                    .iter()
+                    .map(|contract| {
+                        json!({
+                            "name": contract.get_name(),
+                            "version": contract.get_version(),
+                            "prefix": contract.get_prefix(),
+                        })
+                    })
+                    .collect::<Vec<_>>(),
+                "batch_id": failed.get_batch_id(),
+                "reason": failed.get_reason(),
+                "attempts": failed.get_attempts(),
+                "failed_at": uint64(failed.get_failed_at()),
+            })
+        }
+        Message_MessageType::CIRCUIT_PAYLOAD_DELETED => {
+            let deleted = parse::<CircuitPayloadDeleted>(bytes)?;
+            json!({
+                "requester": deleted.get_requester(),
+                "requester_node_id": deleted.get_requester_node_id(),
+                "circuit_id": deleted.get_circuit_id(),
+                "service_id": deleted.get_service_id(),
+                "key": deleted.get_key(),
+                "contract_name": deleted.get_contract_name(),
+                "detected_at": uint64(deleted.get_detected_at()),
+            })
+        }
+        Message_MessageType::TYPE_UNKNOWN => Value::String(to_base64(bytes)),
+    };
+    Ok(message)
+}
+
+fn definition_document(definition: &CircuitDefinition) -> Value {
+    json!({
+        "circuit_id": definition.get_circuit_id(),
+        "authorization_type": definition.get_authorization_type(),
+        "persistence": definition.get_persistence(),
+        "durability": definition.get_durability(),
+        "routes": definition.get_routes(),
+        "circuit_management_type": definition.get_circuit_management_type(),
+        "alias": definition.get_alias(),
+        "comments": definition.get_comments(),
+        "services": services_document(definition.get_services()),
+        "members": members_document(definition.get_members()),
+    })
+}
+
+fn services_document(services: &[CircuitService]) -> Value {
+    services
+        .iter()
+        .map(|service| {
+            json!({
+                "service_id": service.get_service_id(),
+                "service_type": service.get_service_type(),
+                "allowed_nodes": service.get_allowed_nodes(),
+                "arguments": service
+                    .get_arguments()
+                    .iter()
+                    .map(|argument| {
+                        json!({
+                            "key": argument.get_key(),
+                            "value": argument.get_value(),
+                        })
+                    })
+                    .collect::<Vec<_>>(),
+            })
+        })
+        .collect()
+}
+
+fn members_document(members: &[CircuitMember]) -> Value {
+    members
+        .iter()
+        .map(|member| {
+            json!({
+                "node_id": member.get_node_id(),
+                "endpoint": member.get_endpoint(),
+            })
+        })
+        .collect()
+}
+
+fn ballots_document(ballots: &[Ballot]) -> Value {
+    ballots
+        .iter()
+        .map(|ballot| {
+            json!({
+                "voter": ballot.get_voter(),
+                "voter_node_id": ballot.get_voter_node_id(),
+                "vote": ballot.get_vote().descriptor().name(),
+                "observed_at": uint64(ballot.get_observed_at()),
+            })
+        })
+        .collect()
+}
+
+/// Bytes are written in base64
+fn to_base64(bytes: &[u8]) -> String {
+    base64::encode_block(bytes)
+}
+
+/// 64-bit integers are written as strings, which JSON numbers cannot hold exactly
+fn uint64(value: u64) -> Value {
+    Value::String(value.to_string())
+}
+
+fn parse<M: Msg>(bytes: &[u8]) -> Result<M, PublisherError> {
+    protobuf::parse_from_bytes::<M>(bytes).map_err(PublisherError::SerializationError)
+}
@@ -0,0 +1,53 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use super::PublisherError;
+use crate::proto::pubsub::Message;
+
+/// A destination receiving every exported message next to Kafka
+pub trait Sink: Send + Sync {
+    /// Name under which the sink is listed in `sink_message_types`
+    fn name(&self) -> &str;
+
+    /// Delivers the serialized envelope of a message of the circuit. The topic is the
+    /// Kafka topic chosen for the message, for sinks which mirror it.
+    fn publish(&self, circuit_id: &str, topic: &str, bytes: &[u8]) -> Result<(), PublisherError>;
+
+    /// Delivers a message given its envelope next to the serialized bytes, for sinks
+    /// which render the message themselves. Defaults to `publish`.
+    fn publish_envelope(
+        &self,
+        circuit_id: &str,
+        topic: &str,
+        _envelope: &Message,
+        bytes: &[u8],
+    ) -> Result<(), PublisherError> {
+        self.publish(circuit_id, topic, bytes)
+    }
+
+    /// Persists anything buffered by the sink, called when the exporter shuts down
+    fn flush(&self) -> Result<(), PublisherError> {
+        Ok(())
+    }
+
+    /// Whether the sink reports its deliveries to the sink health itself, for sinks
+    /// which deliver after `publish` returns. Failures to accept a message are still
+    /// reported by the publisher.
+    fn reports_health(&self) -> bool {
+        false
+    }
+}
@@ -0,0 +1,116 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+//! Handlebars-style templates rendering a message's JSON document.
+//!
+//! Text is copied as is, except for the expressions between double braces:
+//!
+//! - `{{path}}` inserts the value at the dot separated path, a string without its quotes
+//!   and escaped so that it can sit inside a JSON string, any other value as JSON. A
+//!   missing value inserts nothing.
+//! - `{{json path}}` inserts the value as JSON, quotes included, or `null` when missing.
+//!
+//! Path segments are object keys or array indices, e.g. `message.members.0.node_id`.
+
+use serde_json::Value;
+
+use super::PublisherError;
+
+const OPEN: &str = "{{";
+const CLOSE: &str = "}}";
+const JSON_HELPER: &str = "json ";
+
+enum Segment {
+    Text(String),
+    Value { path: Vec<String>, json: bool },
+}
+
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+impl Template {
+    pub fn parse(source: &str) -> Result<Self, PublisherError> {
+        let mut segments = vec![];
+        let mut rest = source;
+        while let Some(start) = rest.find(OPEN) {
+            if start > 0 {
+                segments.push(Segment::Text(rest[..start].to_string()));
+            }
+            let expression = &rest[start + OPEN.len()..];
+            let end = expression.find(CLOSE).ok_or_else(|| {
+                PublisherError::WebhookError(format!(
+                    "unclosed expression in template at offset {}",
+                    source.len() - rest.len() + start
+                ))
+            })?;
+            segments.push(Self::expression(expression[..end].trim())?);
+            rest = &expression[end + CLOSE.len()..];
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Text(rest.to_string()));
+        }
+        Ok(Template { segments })
+    }
+
+    fn expression(expression: &str) -> Result<Segment, PublisherError> {
+        let (path, json) = if expression.starts_with(JSON_HELPER) {
+            (expression[JSON_HELPER.len()..].trim(), true)
+        } else {
+            (expression, false)
+        };
+        if path.is_empty() || path.contains(char::is_whitespace) {
+            return Err(PublisherError::WebhookError(format!(
+                "invalid template expression {{{{{}}}}}",
+                expression
+            )));
+        }
+        Ok(Segment::Value {
+            path: path.split('.').map(String::from).collect(),
+            json,
+        })
+    }
+
+    pub fn render(&self, document: &Value) -> String {
+        let mut output = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Text(text) => output.push_str(text),
+                Segment::Value { path, json } => match (lookup(document, path), json) {
+                    (Some(value), true) => output.push_str(&value.to_string()),
+                    (None, true) => output.push_str("null"),
+                    (Some(Value::String(string)), false) => {
+                        let quoted = Value::String(string.clone()).to_string();
+                        output.push_str(&quoted[1..quoted.len() - 1]);
+                    }
+                    (Some(value), false) => output.push_str(&value.to_string()),
+                    (None, false) => (),
+                },
+            }
+        }
+        output
+    }
+}
+
+fn lookup<'a>(document: &'a Value, path: &[String]) -> Option<&'a Value> {
+    path.iter()
+        .try_fold(document, |value, segment| match value {
+            Value::Object(map) => map.get(segment),
+            Value::Array(items) => segment.parse::<usize>().ok().and_then(|i| items.get(i)),
+            _ => None,
+        })
+}
@@ -0,0 +1,167 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+//! User-defined transforms run in a WASM sandbox.
+//!
+//! The module gets no imports, so it can only compute on the bytes it is given. It must
+//! export its `memory` and the following functions:
+//!
+//! ```text
+//! // Reserves len bytes in the module memory, returns their offset
+//! alloc(len: i32) -> i32
+//! // Transforms the serialized Message envelope stored at ptr. Returns a negative value
+//! // to drop the event, or the offset of the resulting envelope in the upper 32 bits and
+//! // its length in the lower 32 bits
+//! transform(ptr: i32, len: i32) -> i64
+//! ```
+
+use std::fs;
+use std::sync::mpsc::{channel, sync_channel, Sender};
+use std::sync::Mutex;
+use std::thread;
+
+use wasmi::{
+    ImportsBuilder, MemoryRef, Module, ModuleInstance, ModuleRef, NopExternals, RuntimeValue,
+};
+
+use super::PublisherError;
+
+type Request = (Vec<u8>, Sender<Result<Option<Vec<u8>>, PublisherError>>);
+
+/// Applies a WASM transform to each envelope.
+///
+/// The interpreter is not thread-safe, so the module runs on its own thread and the
+/// envelopes are passed to it over a channel.
+pub struct WasmTransform {
+    sender: Mutex<Sender<Request>>,
+}
+
+impl WasmTransform {
+    pub fn load(path: &str) -> Result<Self, PublisherError> {
+        let bytes = fs::read(path)
+            .map_err(|err| PublisherError::TransformError(format!("{}: {}", path, err)))?;
+        let (sender, receiver) = channel::<Request>();
+        let (loaded_sender, loaded_receiver) = sync_channel(1);
+
+        let path = path.to_string();
+        thread::Builder::new()
+            .name("WasmTransform".into())
+            .spawn(move || {
+                let instance = match Instance::new(&bytes) {
+                    Ok(instance) => {
+                        let _ = loaded_sender.send(Ok(()));
+                        instance
+                    }
+                    Err(err) => {
+                        let _ = loaded_sender.send(Err(err));
+                        return;
+                    }
+                };
+                // Ends once the publisher holding the sender is dropped
+                for (envelope, reply) in receiver {
+                    let _ = reply.send(instance.transform(&envelope));
+                }
+            })
+            .map_err(|err| PublisherError::TransformError(err.to_string()))?;
+
+        loaded_receiver
+            .recv()
+            .map_err(|err| PublisherError::TransformError(err.to_string()))??;
+        info!("Loaded WASM transform {}", path);
+
+        Ok(WasmTransform {
+            sender: Mutex::new(sender),
+        })
+    }
+
+    /// Returns the transformed envelope, or `None` if the transform dropped the event
+    pub fn apply(&self, envelope: Vec<u8>) -> Result<Option<Vec<u8>>, PublisherError> {
+        let (reply_sender, reply_receiver) = channel();
+        self.sender
+            .lock()
+            .map_err(|err| PublisherError::LockPoisoned(err.to_string()))?
+            .send((envelope, reply_sender))
+            .map_err(|err| PublisherError::TransformError(err.to_string()))?;
+        reply_receiver
+            .recv()
+            .map_err(|err| PublisherError::TransformError(err.to_string()))?
+    }
+}
+
+struct Instance {
+    module: ModuleRef,
+    memory: MemoryRef,
+}
+
+impl Instance {
+    fn new(bytes: &[u8]) -> Result<Self, PublisherError> {
+        let module = Module::from_buffer(bytes).map_err(transform_error)?;
+        let module = ModuleInstance::new(&module, &ImportsBuilder::default())
+            .map_err(transform_error)?
+            .assert_no_start();
+        let memory = module
+            .export_by_name("memory")
+            .and_then(|export| export.as_memory().cloned())
+            .ok_or_else(|| {
+                PublisherError::TransformError("module does not export its memory".into())
+            })?;
+        Ok(Instance { module, memory })
+    }
+
+    fn transform(&self, envelope: &[u8]) -> Result<Option<Vec<u8>>, PublisherError> {
+        let len = envelope.len() as i32;
+        let ptr = match self.invoke("alloc", &[RuntimeValue::I32(len)])? {
+            RuntimeValue::I32(ptr) => ptr,
+            other => return Err(unexpected_result("alloc", other)),
+        };
+        self.memory
+            .set(ptr as u32, envelope)
+            .map_err(transform_error)?;
+
+        let result = match self.invoke(
+            "transform",
+            &[RuntimeValue::I32(ptr), RuntimeValue::I32(len)],
+        )? {
+            RuntimeValue::I64(result) => result,
+            other => return Err(unexpected_result("transform", other)),
+        };
+        if result < 0 {
+            return Ok(None);
+        }
+        let output_ptr = (result >> 32) as u32;
+        let output_len = (result & 0xffff_ffff) as usize;
+        self.memory
+            .get(output_ptr, output_len)
+            .map(Some)
+            .map_err(transform_error)
+    }
+
+    fn invoke(&self, name: &str, args: &[RuntimeValue]) -> Result<RuntimeValue, PublisherError> {
+        self.module
+            .invoke_export(name, args, &mut NopExternals)
+            .map_err(transform_error)?
+            .ok_or_else(|| PublisherError::TransformError(format!("{} returned nothing", name)))
+    }
+}
+
+fn transform_error(err: wasmi::Error) -> PublisherError {
+    PublisherError::TransformError(err.to_string())
+}
+
+fn unexpected_result(name: &str, value: RuntimeValue) -> PublisherError {
+    PublisherError::TransformError(format!("{} returned {:?}", name, value))
+}
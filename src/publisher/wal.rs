@@ -0,0 +1,262 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use std::convert::TryInto;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use super::encryption::{sealed_key_id, WalCipher};
+use super::PublisherError;
+use crate::time::now_millis;
+
+/// Extension of the files holding pending entries
+const WAL_EXTENSION: &str = "wal";
+
+/// Length of the start of an entry which holds the id of the key it was sealed with
+const SEALED_HEADER_LEN: u64 = 9;
+
+/// First byte of the entries which carry a record key. Entries written before keys
+/// were configurable start with the length of the circuit id, which never begins with
+/// this byte, and are keyed by their circuit id.
+const KEYED_ENTRY_MARKER: u8 = 0xff;
+
+/// A message which could not be delivered yet
+pub struct WalEntry {
+    pub circuit_id: String,
+    pub topic: String,
+    pub key: String,
+    pub payload: Vec<u8>,
+}
+
+/// Number of pending entries and the age in seconds of the oldest one
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct WalStats {
+    pub pending: usize,
+    pub oldest_pending_age: Option<u64>,
+}
+
+/// A directory-backed write-ahead log of messages waiting to be published.
+///
+/// Each entry is stored in its own file named after the time it was appended, so that
+/// listing the directory in name order yields the entries in the order they were written.
+/// With a cipher the entries are written encrypted, while those written in the clear
+/// before encryption was enabled stay readable.
+#[derive(Clone)]
+pub struct Wal {
+    dir: PathBuf,
+    cipher: Option<WalCipher>,
+    sequence: Arc<AtomicU64>,
+    pending: Arc<AtomicUsize>,
+}
+
+impl Wal {
+    pub fn open(dir: &str, cipher: Option<WalCipher>) -> Result<Self, PublisherError> {
+        let dir = PathBuf::from(dir);
+        fs::create_dir_all(&dir).map_err(|err| wal_error(&dir, err))?;
+        let wal = Wal {
+            dir,
+            cipher,
+            sequence: Arc::new(AtomicU64::new(0)),
+            pending: Arc::new(AtomicUsize::new(0)),
+        };
+        let entries = wal.pending_entries()?;
+        if !entries.is_empty() {
+            info!("Found {} pending WAL entries", entries.len());
+        }
+        for path in &entries {
+            wal.check_key(path)?;
+        }
+        wal.pending.store(entries.len(), Ordering::SeqCst);
+        Ok(wal)
+    }
+
+    /// Fails if an entry was sealed with a key other than the configured one
+    fn check_key(&self, path: &Path) -> Result<(), PublisherError> {
+        let mut header = vec![];
+        fs::File::open(path)
+            .and_then(|file| file.take(SEALED_HEADER_LEN).read_to_end(&mut header))
+            .map_err(|err| wal_error(path, err))?;
+        let key_id = match sealed_key_id(&header) {
+            Some(key_id) => key_id,
+            None => return Ok(()),
+        };
+        match self.cipher {
+            Some(ref cipher) if cipher.key_id() == key_id => Ok(()),
+            Some(ref cipher) => Err(PublisherError::WalError(format!(
+                "{} is encrypted with key {}, not the configured key {}",
+                path.display(),
+                key_id,
+                cipher.key_id()
+            ))),
+            None => Err(PublisherError::WalError(format!(
+                "{} is encrypted with key {} but no wal_encryption is configured",
+                path.display(),
+                key_id
+            ))),
+        }
+    }
+
+    pub fn has_pending(&self) -> bool {
+        self.pending.load(Ordering::SeqCst) > 0
+    }
+
+    pub fn append(&self, entry: &WalEntry) -> Result<(), PublisherError> {
+        let name = format!(
+            "{:020}-{:010}",
+            now_millis(),
+            self.sequence.fetch_add(1, Ordering::SeqCst)
+        );
+        let tmp_path = self.dir.join(format!("{}.tmp", name));
+        let path = self.dir.join(format!("{}.{}", name, WAL_EXTENSION));
+
+        let bytes = match self.cipher {
+            Some(ref cipher) => cipher.seal(&encode_entry(entry))?,
+            None => encode_entry(entry),
+        };
+        let mut file = fs::File::create(&tmp_path).map_err(|err| wal_error(&tmp_path, err))?;
+        file.write_all(&bytes)
+            .and_then(|_| file.sync_all())
+            .map_err(|err| wal_error(&tmp_path, err))?;
+        // Renaming only once the entry is fully written keeps partial entries out of the log
+        fs::rename(&tmp_path, &path).map_err(|err| wal_error(&path, err))?;
+
+        self.pending.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Returns the paths of the pending entries, oldest first
+    pub fn pending_entries(&self) -> Result<Vec<PathBuf>, PublisherError> {
+        let mut entries = fs::read_dir(&self.dir)
+            .map_err(|err| wal_error(&self.dir, err))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().map_or(false, |ext| ext == WAL_EXTENSION))
+            .collect::<Vec<_>>();
+        entries.sort();
+        Ok(entries)
+    }
+
+    pub fn read(&self, path: &Path) -> Result<WalEntry, PublisherError> {
+        let mut bytes = fs::read(path).map_err(|err| wal_error(path, err))?;
+        if sealed_key_id(&bytes).is_some() {
+            bytes = self
+                .cipher
+                .as_ref()
+                .and_then(|cipher| cipher.open(&bytes))
+                .unwrap_or_default();
+        }
+        decode_entry(&bytes).ok_or_else(|| {
+            PublisherError::WalError(format!("Corrupted WAL entry {}", path.display()))
+        })
+    }
+
+    /// Moves an unreadable entry out of the log so that it is not retried forever
+    pub fn quarantine(&self, path: &Path) -> Result<(), PublisherError> {
+        let quarantined = path.with_extension("corrupt");
+        fs::rename(path, &quarantined).map_err(|err| wal_error(path, err))?;
+        self.pending.fetch_sub(1, Ordering::SeqCst);
+        Ok(())
+    }
+
+    pub fn remove(&self, path: &Path) -> Result<(), PublisherError> {
+        fs::remove_file(path).map_err(|err| wal_error(path, err))?;
+        self.pending.fetch_sub(1, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Syncs the directory, so that the entries added and removed survive a crash
+    pub fn sync(&self) -> Result<(), PublisherError> {
+        fs::File::open(&self.dir)
+            .and_then(|dir| dir.sync_all())
+            .map_err(|err| wal_error(&self.dir, err))
+    }
+
+    pub fn stats(&self) -> WalStats {
+        let oldest_pending_age = self
+            .pending_entries()
+            .ok()
+            .and_then(|entries| entries.into_iter().next())
+            .and_then(|path| appended_at(&path))
+            .map(|appended_at| now_millis().saturating_sub(appended_at) / 1000);
+        WalStats {
+            pending: self.pending.load(Ordering::SeqCst),
+            oldest_pending_age,
+        }
+    }
+}
+
+/// Extracts the append time in milliseconds from the name of an entry
+fn appended_at(path: &Path) -> Option<u64> {
+    path.file_stem()?.to_str()?.split('-').next()?.parse().ok()
+}
+
+fn wal_error(path: &Path, err: std::io::Error) -> PublisherError {
+    PublisherError::WalError(format!("{}: {}", path.display(), err))
+}
+
+/// Entries are stored as the marker, the length-prefixed circuit id, topic and key, and
+/// the payload
+fn encode_entry(entry: &WalEntry) -> Vec<u8> {
+    let mut bytes = vec![KEYED_ENTRY_MARKER];
+    for field in &[
+        entry.circuit_id.as_bytes(),
+        entry.topic.as_bytes(),
+        entry.key.as_bytes(),
+    ] {
+        bytes.extend_from_slice(&(field.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(field);
+    }
+    bytes.extend_from_slice(&entry.payload);
+    bytes
+}
+
+fn decode_entry(bytes: &[u8]) -> Option<WalEntry> {
+    if bytes.first() == Some(&KEYED_ENTRY_MARKER) {
+        let (circuit_id, rest) = decode_field(&bytes[1..])?;
+        let (topic, rest) = decode_field(rest)?;
+        let (key, payload) = decode_field(rest)?;
+        return Some(WalEntry {
+            circuit_id,
+            topic,
+            key,
+            payload: payload.to_vec(),
+        });
+    }
+    let (circuit_id, rest) = decode_field(bytes)?;
+    let (topic, payload) = decode_field(rest)?;
+    Some(WalEntry {
+        key: circuit_id.clone(),
+        circuit_id,
+        topic,
+        payload: payload.to_vec(),
+    })
+}
+
+fn decode_field(bytes: &[u8]) -> Option<(String, &[u8])> {
+    if bytes.len() < 4 {
+        return None;
+    }
+    let len = u32::from_be_bytes(bytes[..4].try_into().ok()?) as usize;
+    let rest = &bytes[4..];
+    if rest.len() < len {
+        return None;
+    }
+    let field = String::from_utf8(rest[..len].to_vec()).ok()?;
+    Some((field, &rest[len..]))
+}
@@ -0,0 +1,72 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::{KafkaPublisher, PublisherError};
+
+/// Periodically delivers the entries pending in the publisher's WAL
+pub struct WalReplayer {
+    running: Arc<AtomicBool>,
+    join_handle: thread::JoinHandle<()>,
+}
+
+impl WalReplayer {
+    /// Starts the replayer, returns `None` if the publisher has no WAL configured.
+    pub fn start(
+        publisher: KafkaPublisher,
+        interval: Duration,
+    ) -> Result<Option<Self>, PublisherError> {
+        if !publisher.has_wal() {
+            return Ok(None);
+        }
+
+        let running = Arc::new(AtomicBool::new(true));
+        let thread_running = running.clone();
+        let join_handle = thread::Builder::new()
+            .name("WalReplayer".into())
+            .spawn(move || {
+                while thread_running.load(Ordering::SeqCst) {
+                    match publisher.replay_wal() {
+                        Ok(0) => (),
+                        Ok(replayed) => info!("Replayed {} WAL entries", replayed),
+                        Err(err) => warn!("Unable to replay WAL: {}", err),
+                    }
+                    let started = Instant::now();
+                    while thread_running.load(Ordering::SeqCst) && started.elapsed() < interval {
+                        thread::sleep(Duration::from_millis(100));
+                    }
+                }
+            })
+            .map_err(|err| PublisherError::WalError(format!("Unable to start thread: {}", err)))?;
+
+        Ok(Some(WalReplayer {
+            running,
+            join_handle,
+        }))
+    }
+
+    pub fn shutdown(self) {
+        self.running.store(false, Ordering::SeqCst);
+        if self.join_handle.join().is_err() {
+            error!("WAL replayer thread panicked");
+        }
+    }
+}
@@ -0,0 +1,482 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crypto::hmac::Hmac;
+use crypto::mac::Mac;
+use crypto::sha2::Sha256;
+use futures::{Future, Stream};
+use hyper::{Body, Client, Request, StatusCode};
+use tokio::runtime::Runtime;
+use tokio::timer::Timeout;
+
+use super::filter::WEBHOOK_SINK;
+use super::health::SinkHealth;
+use super::serializer;
+use super::sink::Sink;
+use super::template::Template;
+use super::PublisherError;
+use crate::alert::Alerter;
+use crate::event_handler::to_hex;
+use crate::http::{self, Connector};
+use crate::metrics::Metrics;
+use crate::proto::pubsub::Message;
+
+/// Header carrying the HMAC-SHA256 of the request body, when a secret is configured
+const SIGNATURE_HEADER: &str = "X-Event-Listener-Signature";
+
+/// Gauge of the messages waiting in the queue of a webhook, by endpoint
+const QUEUE_DEPTH_METRIC: &str = "exporter_webhook_queue_depth";
+
+/// Counter of the messages dropped because the queue of a webhook was full, by endpoint
+const DROPPED_METRIC: &str = "exporter_webhook_dropped_total";
+
+/// Histogram of the seconds each request to a webhook took, by endpoint
+const LATENCY_METRIC: &str = "exporter_webhook_latency_seconds";
+
+/// Gauge set to 1 while a webhook is considered slow, by endpoint
+const SLOW_METRIC: &str = "exporter_webhook_slow";
+
+/// Body sent for each message
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum WebhookFormat {
+    /// The serialized envelope, as published to Kafka
+    Protobuf,
+    /// A JSON object with the circuit, the topic and the hex encoded envelope
+    Json,
+}
+
+impl Default for WebhookFormat {
+    fn default() -> Self {
+        WebhookFormat::Protobuf
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WebhookConfig {
+    pub url: String,
+    #[serde(default)]
+    pub format: WebhookFormat,
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+    #[serde(default = "default_base_backoff")]
+    pub base_backoff: u64,
+    #[serde(default = "default_max_backoff")]
+    pub max_backoff: u64,
+    /// Milliseconds a request may take before it is abandoned and retried
+    #[serde(default = "default_timeout")]
+    pub timeout: u64,
+    /// Number of messages waiting for delivery beyond which new ones are dropped
+    #[serde(default = "default_queue_capacity")]
+    pub queue_capacity: usize,
+    /// Milliseconds beyond which a request counts as slow
+    #[serde(default = "default_slow_threshold")]
+    pub slow_threshold: u64,
+    /// Number of consecutive slow requests after which the endpoint is reported as slow
+    #[serde(default = "default_slow_after")]
+    pub slow_after: u32,
+    #[serde(default)]
+    pub hmac_secret: Option<String>,
+    /// Template of the body, rendered from the message's JSON document instead of
+    /// sending `format`, see the `template` module
+    #[serde(default)]
+    pub template: Option<String>,
+}
+
+/// default number of retries after the first attempt
+fn default_max_retries() -> u32 {
+    5
+}
+
+/// default backoff in milliseconds before the first retry
+fn default_base_backoff() -> u64 {
+    500
+}
+
+/// default upper bound in milliseconds on the backoff between retries
+fn default_max_backoff() -> u64 {
+    30_000
+}
+
+/// default request timeout in milliseconds
+fn default_timeout() -> u64 {
+    10_000
+}
+
+/// default number of messages the queue holds
+fn default_queue_capacity() -> usize {
+    1000
+}
+
+/// default latency in milliseconds beyond which a request is slow
+fn default_slow_threshold() -> u64 {
+    2000
+}
+
+/// default number of consecutive slow requests before the endpoint is reported
+fn default_slow_after() -> u32 {
+    10
+}
+
+/// POSTs each message to an HTTP endpoint.
+///
+/// Messages are queued and delivered by a worker thread, so that a slow endpoint does not
+/// hold back Kafka and the other sinks. When the queue is full new messages are dropped
+/// and counted in `exporter_webhook_dropped_total`. The worker reports the outcome of
+/// each delivery to the sink health.
+///
+/// The latency of each request is recorded in `exporter_webhook_latency_seconds`. After
+/// `slow_after` consecutive requests slower than `slow_threshold` the endpoint is marked
+/// in `exporter_webhook_slow` and an alert is posted; the next fast request clears it.
+///
+/// Connection errors, timeouts, 429 and 5xx responses are retried with exponential
+/// backoff up to `max_retries` times; any other non-2xx response fails the message
+/// straight away.
+pub struct WebhookSink {
+    url: String,
+    format: WebhookFormat,
+    template: Option<Template>,
+    metrics: Metrics,
+    depth: Arc<AtomicUsize>,
+    /// Taken when the sink is flushed, which lets the worker drain the queue and stop
+    queue: Mutex<Option<SyncSender<Delivery>>>,
+    worker: Mutex<Option<JoinHandle<()>>>,
+}
+
+/// A body waiting for delivery
+struct Delivery {
+    body: Vec<u8>,
+    content_type: &'static str,
+}
+
+/// Delivers the queued bodies, one at a time
+struct WebhookWorker {
+    config: WebhookConfig,
+    client: Client<Connector>,
+    runtime: Runtime,
+    metrics: Metrics,
+    health: SinkHealth,
+    depth: Arc<AtomicUsize>,
+    alerter: Alerter,
+    /// Requests in a row that took longer than `slow_threshold`
+    consecutive_slow: u32,
+    slow: bool,
+}
+
+enum Attempt {
+    Delivered,
+    Retry(String),
+    Failed(String),
+}
+
+impl WebhookSink {
+    pub fn new(
+        config: &WebhookConfig,
+        metrics: Metrics,
+        health: SinkHealth,
+        alerter: Alerter,
+    ) -> Result<Self, PublisherError> {
+        config
+            .url
+            .parse::<hyper::Uri>()
+            .map_err(|err| PublisherError::WebhookError(format!("{}: {}", config.url, err)))?;
+        let runtime =
+            Runtime::new().map_err(|err| PublisherError::WebhookError(err.to_string()))?;
+        let client = http::client().map_err(|err| PublisherError::WebhookError(err.to_string()))?;
+        let template = match config.template {
+            Some(ref template) => Some(Template::parse(template)?),
+            None => None,
+        };
+        let depth = Arc::new(AtomicUsize::new(0));
+        let (sender, receiver) = mpsc::sync_channel(config.queue_capacity.max(1));
+        let worker = WebhookWorker {
+            config: config.clone(),
+            client,
+            runtime,
+            metrics: metrics.clone(),
+            health,
+            depth: depth.clone(),
+            alerter,
+            consecutive_slow: 0,
+            slow: false,
+        };
+        let worker = thread::Builder::new()
+            .name("WebhookSink".into())
+            .spawn(move || worker.run(receiver))
+            .map_err(|err| PublisherError::WebhookError(err.to_string()))?;
+        Ok(WebhookSink {
+            url: config.url.clone(),
+            format: config.format,
+            template,
+            metrics,
+            depth,
+            queue: Mutex::new(Some(sender)),
+            worker: Mutex::new(Some(worker)),
+        })
+    }
+
+    fn body(&self, circuit_id: &str, topic: &str, bytes: &[u8]) -> (Vec<u8>, &'static str) {
+        match self.format {
+            WebhookFormat::Protobuf => (bytes.to_vec(), "application/x-protobuf"),
+            WebhookFormat::Json => {
+                let body = json!({
+                    "circuit_id": circuit_id,
+                    "topic": topic,
+                    "message": to_hex(bytes),
+                });
+                (body.to_string().into_bytes(), "application/json")
+            }
+        }
+    }
+
+    /// The template rendered over the envelope's JSON document, with the circuit and
+    /// the topic added to it
+    fn render(
+        &self,
+        template: &Template,
+        circuit_id: &str,
+        topic: &str,
+        envelope: &Message,
+    ) -> Result<Vec<u8>, PublisherError> {
+        let mut document = serializer::document(envelope)?;
+        document["circuit_id"] = json!(circuit_id);
+        document["topic"] = json!(topic);
+        Ok(template.render(&document).into_bytes())
+    }
+
+    /// Queues a body for the worker, dropping it if the queue is full
+    fn enqueue(&self, body: Vec<u8>, content_type: &'static str) -> Result<(), PublisherError> {
+        let queue = self
+            .queue
+            .lock()
+            .map_err(|err| PublisherError::LockPoisoned(err.to_string()))?;
+        let sender = queue
+            .as_ref()
+            .ok_or_else(|| PublisherError::WebhookError("the webhook sink is closed".into()))?;
+        // Counted before sending, so that the worker never takes the depth below zero
+        let depth = self.depth.fetch_add(1, Ordering::SeqCst) + 1;
+        let delivery = Delivery { body, content_type };
+        match sender.try_send(delivery) {
+            Ok(()) => {
+                self.metrics.set_gauge(
+                    QUEUE_DEPTH_METRIC,
+                    &[("endpoint", &self.url)],
+                    depth as i64,
+                );
+                Ok(())
+            }
+            Err(err) => {
+                self.depth.fetch_sub(1, Ordering::SeqCst);
+                match err {
+                    TrySendError::Full(_) => {
+                        self.metrics
+                            .incr_counter(DROPPED_METRIC, &[("endpoint", &self.url)], 1);
+                        Err(PublisherError::WebhookError(format!(
+                            "the queue of {} is full, dropped the message",
+                            self.url
+                        )))
+                    }
+                    TrySendError::Disconnected(_) => Err(PublisherError::WebhookError(
+                        "the webhook worker has stopped".into(),
+                    )),
+                }
+            }
+        }
+    }
+}
+
+impl WebhookWorker {
+    fn run(mut self, receiver: Receiver<Delivery>) {
+        for delivery in receiver {
+            let depth = self.depth.fetch_sub(1, Ordering::SeqCst) - 1;
+            self.metrics.set_gauge(
+                QUEUE_DEPTH_METRIC,
+                &[("endpoint", &self.config.url)],
+                depth as i64,
+            );
+            match self.deliver(&delivery.body, delivery.content_type) {
+                Ok(()) => self.health.delivered(WEBHOOK_SINK),
+                Err(err) => {
+                    error!("{}", err);
+                    self.health.failed(WEBHOOK_SINK, &err.to_string());
+                }
+            }
+        }
+    }
+
+    fn signature(&self, body: &[u8]) -> Option<String> {
+        self.config.hmac_secret.as_ref().map(|secret| {
+            let mut hmac = Hmac::new(Sha256::new(), secret.as_bytes());
+            hmac.input(body);
+            format!("sha256={}", to_hex(hmac.result().code()))
+        })
+    }
+
+    fn attempt(&mut self, body: &[u8], content_type: &str, signature: Option<&str>) -> Attempt {
+        let mut builder = Request::post(self.config.url.as_str());
+        builder.header("Content-Type", content_type);
+        if let Some(signature) = signature {
+            builder.header(SIGNATURE_HEADER, signature);
+        }
+        let request = match builder.body(Body::from(body.to_vec())) {
+            Ok(request) => request,
+            Err(err) => return Attempt::Failed(err.to_string()),
+        };
+
+        let timeout = Duration::from_millis(self.config.timeout);
+        let response = self.client.request(request).and_then(|resp| {
+            let status = resp.status();
+            resp.into_body().concat2().map(move |_| status)
+        });
+        let started = Instant::now();
+        let result = self.runtime.block_on(Timeout::new(response, timeout));
+        self.record_latency(started.elapsed());
+        match result {
+            Ok(status) if status.is_success() => Attempt::Delivered,
+            Ok(status) if status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error() => {
+                Attempt::Retry(format!("webhook responded with status {}", status))
+            }
+            Ok(status) => Attempt::Failed(format!("webhook responded with status {}", status)),
+            Err(err) if err.is_elapsed() => {
+                Attempt::Retry(format!("webhook did not respond within {:?}", timeout))
+            }
+            Err(err) => match err.into_inner() {
+                Some(err) => Attempt::Retry(err.to_string()),
+                None => Attempt::Retry("the request timer failed".into()),
+            },
+        }
+    }
+
+    /// Observes the latency of a request and tracks whether the endpoint has turned slow
+    fn record_latency(&mut self, latency: Duration) {
+        let endpoint = [("endpoint", self.config.url.as_str())];
+        let seconds = latency.as_secs() as f64 + f64::from(latency.subsec_nanos()) / 1e9;
+        self.metrics.observe(LATENCY_METRIC, &endpoint, seconds);
+        if latency <= Duration::from_millis(self.config.slow_threshold) {
+            self.consecutive_slow = 0;
+            if self.slow {
+                info!("Webhook {} is responding in time again", self.config.url);
+                self.metrics.set_gauge(SLOW_METRIC, &endpoint, 0);
+                self.slow = false;
+            }
+            return;
+        }
+        self.consecutive_slow = self.consecutive_slow.saturating_add(1);
+        if !self.slow && self.consecutive_slow >= self.config.slow_after {
+            warn!(
+                "Webhook {} took longer than {}ms for {} requests in a row",
+                self.config.url, self.config.slow_threshold, self.consecutive_slow
+            );
+            self.metrics.set_gauge(SLOW_METRIC, &endpoint, 1);
+            self.alerter
+                .slow_webhook(&mut self.runtime, &self.config.url, latency);
+            self.slow = true;
+        }
+    }
+
+    fn backoff(&self, retry: u32) -> Duration {
+        let backoff = self
+            .config
+            .base_backoff
+            .checked_mul(2u64.saturating_pow(retry))
+            .unwrap_or(self.config.max_backoff)
+            .min(self.config.max_backoff);
+        Duration::from_millis(backoff)
+    }
+
+    /// POSTs the body, retrying as described on `WebhookSink`
+    fn deliver(&mut self, body: &[u8], content_type: &str) -> Result<(), PublisherError> {
+        let signature = self.signature(body);
+        let mut retry = 0;
+        loop {
+            match self.attempt(body, content_type, signature.as_ref().map(String::as_str)) {
+                Attempt::Delivered => return Ok(()),
+                Attempt::Failed(msg) => return Err(PublisherError::WebhookError(msg)),
+                Attempt::Retry(msg) if retry >= self.config.max_retries => {
+                    return Err(PublisherError::WebhookError(format!(
+                        "giving up after {} retries: {}",
+                        retry, msg
+                    )));
+                }
+                Attempt::Retry(msg) => {
+                    let delay = self.backoff(retry);
+                    warn!("Webhook delivery failed ({}), retrying in {:?}", msg, delay);
+                    thread::sleep(delay);
+                    retry += 1;
+                }
+            }
+        }
+    }
+}
+
+impl Sink for WebhookSink {
+    fn name(&self) -> &str {
+        WEBHOOK_SINK
+    }
+
+    fn publish(&self, circuit_id: &str, topic: &str, bytes: &[u8]) -> Result<(), PublisherError> {
+        let (body, content_type) = self.body(circuit_id, topic, bytes);
+        self.enqueue(body, content_type)
+    }
+
+    fn publish_envelope(
+        &self,
+        circuit_id: &str,
+        topic: &str,
+        envelope: &Message,
+        bytes: &[u8],
+    ) -> Result<(), PublisherError> {
+        match self.template {
+            Some(ref template) => {
+                let body = self.render(template, circuit_id, topic, envelope)?;
+                self.enqueue(body, "application/json")
+            }
+            None => self.publish(circuit_id, topic, bytes),
+        }
+    }
+
+    /// Stops taking messages and waits for the worker to deliver the queued ones
+    fn flush(&self) -> Result<(), PublisherError> {
+        self.queue
+            .lock()
+            .map_err(|err| PublisherError::LockPoisoned(err.to_string()))?
+            .take();
+        let worker = self
+            .worker
+            .lock()
+            .map_err(|err| PublisherError::LockPoisoned(err.to_string()))?
+            .take();
+        if let Some(worker) = worker {
+            if worker.join().is_err() {
+                return Err(PublisherError::WebhookError(
+                    "the webhook worker panicked".into(),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn reports_health(&self) -> bool {
+        true
+    }
+}
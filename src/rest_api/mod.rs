@@ -0,0 +1,161 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+mod error;
+mod routes;
+
+use std::sync::mpsc;
+use std::thread;
+
+use actix_web::{web, App, HttpServer};
+
+use crate::config::SharedConfig;
+use crate::event_handler::{Manifest, NamespacePauses, SnapshotTrigger};
+use crate::metrics::Metrics;
+use crate::proposal_cache::ProposalCache;
+use crate::publisher::{KafkaPublisher, RecentEvents};
+use crate::status::Status;
+
+pub use error::RestApiServerError;
+
+pub struct RestApiShutdownHandle {
+    do_shutdown: Box<dyn Fn() -> Result<(), RestApiServerError> + Send>,
+}
+
+impl RestApiShutdownHandle {
+    pub fn shutdown(&self) -> Result<(), RestApiServerError> {
+        (*self.do_shutdown)()
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn run(
+    bind_url: &str,
+    metrics: Metrics,
+    publisher: KafkaPublisher,
+    recent_events: RecentEvents,
+    proposal_cache: Option<ProposalCache>,
+    status: Status,
+    snapshot_trigger: SnapshotTrigger,
+    manifest: Manifest,
+    pauses: NamespacePauses,
+    config: SharedConfig,
+) -> Result<
+    (
+        RestApiShutdownHandle,
+        thread::JoinHandle<Result<(), RestApiServerError>>,
+    ),
+    RestApiServerError,
+> {
+    let bind_url = bind_url.to_owned();
+    let (tx, rx) = mpsc::channel();
+
+    let join_handle = thread::Builder::new()
+        .name("EventListenerRestApi".into())
+        .spawn(move || {
+            let sys = actix::System::new("EventListener-Rest-API");
+
+            let addr = HttpServer::new(move || {
+                App::new()
+                    .data(metrics.clone())
+                    .data(recent_events.clone())
+                    .data(status.clone())
+                    .data(publisher.clone())
+                    .data(snapshot_trigger.clone())
+                    .data(manifest.clone())
+                    .data(pauses.clone())
+                    .data(config.clone())
+                    .service(web::resource("/metrics").route(web::get().to(routes::fetch_metrics)))
+                    .service(web::resource("/status").route(web::get().to(routes::fetch_status)))
+                    .service(web::resource("/ready").route(web::get().to(routes::fetch_ready)))
+                    .service(
+                        web::resource("/manifest").route(web::get().to(routes::fetch_manifest)),
+                    )
+                    .service(
+                        web::resource("/control").route(web::post().to(routes::publish_control)),
+                    )
+                    .service(
+                        web::resource("/snapshot").route(web::post().to(routes::request_snapshot)),
+                    )
+                    .service(
+                        web::scope("/namespaces/{prefix}")
+                            .service(
+                                web::resource("/pause")
+                                    .route(web::post().to(routes::pause_namespace)),
+                            )
+                            .service(
+                                web::resource("/resume")
+                                    .route(web::post().to(routes::resume_namespace)),
+                            ),
+                    )
+                    .service(
+                        web::resource("/circuits/{circuit_id}/errors")
+                            .route(web::get().to(routes::list_circuit_errors)),
+                    )
+                    .service(
+                        web::scope("/events")
+                            .service(
+                                web::resource("/recent")
+                                    .route(web::get().to(routes::list_recent_events)),
+                            )
+                            .service(
+                                web::resource("/stream").route(web::get().to(routes::stream_events)),
+                            ),
+                    )
+                    .configure(|cfg| {
+                        if let Some(proposal_cache) = proposal_cache.clone() {
+                            cfg.data(proposal_cache)
+                                .service(
+                                    web::resource("/proposals")
+                                        .route(web::get().to(routes::list_proposals)),
+                                )
+                                .service(
+                                    web::resource("/circuits/{circuit_id}/votes")
+                                        .route(web::get().to(routes::list_circuit_votes)),
+                                );
+                        }
+                    })
+            })
+            .bind(bind_url)?
+            .disable_signals()
+            .system_exit()
+            .start();
+
+            tx.send(addr).map_err(|err| {
+                RestApiServerError::StartUpError(format!("Unable to send Server Addr: {}", err))
+            })?;
+            sys.run()?;
+
+            info!("Rest API terminating");
+
+            Ok(())
+        })?;
+
+    let addr = rx.recv().map_err(|err| {
+        RestApiServerError::StartUpError(format!("Unable to receive Server Addr: {}", err))
+    })?;
+
+    let do_shutdown = Box::new(move || {
+        debug!("Shutting down Rest API");
+        addr.stop(true);
+        debug!("Graceful signal sent to Rest API");
+
+        Ok(())
+    });
+
+    Ok((RestApiShutdownHandle { do_shutdown }, join_handle))
+}
@@ -0,0 +1,65 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use actix_web::{web, HttpResponse};
+
+use crate::proto::pubsub::{Control, Message_MessageType};
+use crate::publisher::KafkaPublisher;
+use crate::time::now_millis;
+
+#[derive(Deserialize)]
+pub struct ControlRequest {
+    command: String,
+    #[serde(default)]
+    detail: String,
+    effective_at: Option<u64>,
+}
+
+/// Publishes an operator-initiated control message to the export topic
+pub fn publish_control(
+    publisher: web::Data<KafkaPublisher>,
+    control_request: web::Json<ControlRequest>,
+) -> HttpResponse {
+    if control_request.command.is_empty() {
+        return HttpResponse::BadRequest().json(json!({
+            "message": "A control message requires a command"
+        }));
+    }
+
+    let issued_at = now_millis();
+    let mut control = Control::new();
+    control.set_command(control_request.command.clone());
+    control.set_detail(control_request.detail.clone());
+    control.set_effective_at(control_request.effective_at.unwrap_or(issued_at));
+    control.set_issued_at(issued_at);
+
+    // Control messages are not tied to a circuit
+    match publisher.publish("", Message_MessageType::CONTROL, &control) {
+        Ok(()) => {
+            info!("Published control message {}", control_request.command);
+            HttpResponse::Accepted().json(json!({
+                "message": "Control message published"
+            }))
+        }
+        Err(err) => {
+            error!("Failed to publish control message: {}", err);
+            HttpResponse::InternalServerError().json(json!({
+                "message": "Failed to publish control message"
+            }))
+        }
+    }
+}
@@ -0,0 +1,51 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use actix_web::{error, web, HttpResponse};
+use futures::Stream;
+
+use crate::publisher::RecentEvents;
+
+/// default number of events returned by the recent events endpoint
+const DEFAULT_LIMIT: usize = 20;
+
+#[derive(Deserialize)]
+pub struct EventsQuery {
+    circuit: Option<String>,
+    limit: Option<usize>,
+}
+
+pub fn list_recent_events(
+    recent_events: web::Data<RecentEvents>,
+    query: web::Query<EventsQuery>,
+) -> HttpResponse {
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT);
+    HttpResponse::Ok().json(recent_events.list(query.circuit.as_ref().map(String::as_str), limit))
+}
+
+pub fn stream_events(
+    recent_events: web::Data<RecentEvents>,
+    query: web::Query<EventsQuery>,
+) -> HttpResponse {
+    let stream = recent_events
+        .subscribe(query.circuit.clone())
+        .map_err(|_| error::ErrorInternalServerError("Event stream closed"));
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .header("Cache-Control", "no-cache")
+        .streaming(stream)
+}
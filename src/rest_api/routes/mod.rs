@@ -0,0 +1,36 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+mod control;
+mod events;
+mod manifest;
+mod metrics;
+mod namespaces;
+mod proposals;
+mod ready;
+mod snapshot;
+mod status;
+
+pub use control::*;
+pub use events::*;
+pub use manifest::*;
+pub use metrics::*;
+pub use namespaces::*;
+pub use proposals::*;
+pub use ready::*;
+pub use snapshot::*;
+pub use status::*;
@@ -0,0 +1,110 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use actix_web::{web, HttpResponse};
+
+use crate::config::SharedConfig;
+use crate::event_handler::{Manifest, NamespacePauses, SnapshotTrigger};
+
+/// Pauses the export of the state changes under a contract namespace
+pub fn pause_namespace(
+    pauses: web::Data<NamespacePauses>,
+    config: web::Data<SharedConfig>,
+    manifest: web::Data<Manifest>,
+    prefix: web::Path<String>,
+) -> HttpResponse {
+    if !is_prefix(&prefix) {
+        return invalid_prefix();
+    }
+    if !is_exported(&config, &manifest, &prefix) {
+        return unknown_namespace();
+    }
+    if pauses.pause(&prefix) {
+        info!("Paused the export of namespace {}", prefix);
+        HttpResponse::Ok().json(json!({
+            "message": "Namespace paused"
+        }))
+    } else {
+        HttpResponse::Conflict().json(json!({
+            "message": "The namespace is already paused"
+        }))
+    }
+}
+
+/// Resumes the export of a paused namespace, first exporting a snapshot of its state to
+/// catch up on the changes dropped while it was paused.
+///
+/// A paused namespace can be resumed even if it is no longer exported since, e.g. after
+/// a reload of `transaction_processors`, so that it does not stay listed as paused.
+pub fn resume_namespace(
+    pauses: web::Data<NamespacePauses>,
+    trigger: web::Data<SnapshotTrigger>,
+    config: web::Data<SharedConfig>,
+    manifest: web::Data<Manifest>,
+    prefix: web::Path<String>,
+) -> HttpResponse {
+    if !is_prefix(&prefix) {
+        return invalid_prefix();
+    }
+    if !pauses.is_paused(&prefix) && !is_exported(&config, &manifest, &prefix) {
+        return unknown_namespace();
+    }
+    if !pauses.resume(&prefix) {
+        return HttpResponse::Conflict().json(json!({
+            "message": "The namespace is not paused"
+        }));
+    }
+    info!("Resumed the export of namespace {}", prefix);
+    if trigger.catch_up(&prefix) {
+        HttpResponse::Accepted().json(json!({
+            "message": "Namespace resumed, catching up"
+        }))
+    } else {
+        HttpResponse::ServiceUnavailable().json(json!({
+            "message": "Namespace resumed, but the exporter is shutting down"
+        }))
+    }
+}
+
+/// Namespace prefixes are at least 6 hex digits
+fn is_prefix(prefix: &str) -> bool {
+    prefix.len() >= 6 && prefix.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Returns whether the prefix is that of a contract in `transaction_processors` or of one
+/// a subscribed circuit selected in its application metadata
+fn is_exported(config: &SharedConfig, manifest: &Manifest, prefix: &str) -> bool {
+    config
+        .current()
+        .deployment_config()
+        .transaction_processors()
+        .iter()
+        .any(|processor| processor.prefix == prefix)
+        || manifest.has_namespace(prefix)
+}
+
+fn unknown_namespace() -> HttpResponse {
+    HttpResponse::NotFound().json(json!({
+        "message": "No exported contract has this namespace prefix"
+    }))
+}
+
+fn invalid_prefix() -> HttpResponse {
+    HttpResponse::BadRequest().json(json!({
+        "message": "A namespace is named by its prefix of at least 6 hex digits"
+    }))
+}
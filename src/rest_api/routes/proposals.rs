@@ -0,0 +1,36 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use actix_web::{web, HttpResponse};
+
+use crate::proposal_cache::ProposalCache;
+
+pub fn list_proposals(proposal_cache: web::Data<ProposalCache>) -> HttpResponse {
+    HttpResponse::Ok().json(proposal_cache.list_proposals())
+}
+
+pub fn list_circuit_votes(
+    proposal_cache: web::Data<ProposalCache>,
+    circuit_id: web::Path<String>,
+) -> HttpResponse {
+    match proposal_cache.list_votes(&circuit_id) {
+        Some(votes) => HttpResponse::Ok().json(votes),
+        None => HttpResponse::NotFound().json(json!({
+            "message": format!("No proposal found for circuit {}", circuit_id)
+        })),
+    }
+}
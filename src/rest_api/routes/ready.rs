@@ -0,0 +1,50 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use actix_web::{web, HttpResponse};
+
+use crate::publisher::KafkaPublisher;
+use crate::status::Status;
+
+/// Reports not ready while the subscriptions reopened at startup are still catching up,
+/// or while the WAL backlog exceeds the configured limits
+pub fn fetch_ready(
+    publisher: web::Data<KafkaPublisher>,
+    status: web::Data<Status>,
+) -> HttpResponse {
+    let catching_up = status.catching_up_subscriptions();
+    let reason = if !catching_up.is_empty() {
+        Some(format!(
+            "{} subscriptions are catching up",
+            catching_up.len()
+        ))
+    } else {
+        publisher.backlog_exceeded()
+    };
+    match reason {
+        None => HttpResponse::Ok().json(json!({
+            "ready": true,
+            "wal": publisher.wal_stats(),
+        })),
+        Some(reason) => HttpResponse::ServiceUnavailable().json(json!({
+            "ready": false,
+            "reason": reason,
+            "catching_up": catching_up,
+            "wal": publisher.wal_stats(),
+        })),
+    }
+}
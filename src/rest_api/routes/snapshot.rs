@@ -0,0 +1,34 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use actix_web::{web, HttpResponse};
+
+use crate::event_handler::SnapshotTrigger;
+
+/// Requests an operator-initiated snapshot of every subscribed circuit
+pub fn request_snapshot(trigger: web::Data<SnapshotTrigger>) -> HttpResponse {
+    if trigger.request() {
+        info!("Snapshot requested through the REST API");
+        HttpResponse::Accepted().json(json!({
+            "message": "Snapshot requested"
+        }))
+    } else {
+        HttpResponse::ServiceUnavailable().json(json!({
+            "message": "The exporter is shutting down"
+        }))
+    }
+}
@@ -0,0 +1,44 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use actix_web::{web, HttpResponse};
+
+use crate::event_handler::NamespacePauses;
+use crate::publisher::KafkaPublisher;
+use crate::status::Status;
+
+pub fn fetch_status(
+    status: web::Data<Status>,
+    publisher: web::Data<KafkaPublisher>,
+    pauses: web::Data<NamespacePauses>,
+) -> HttpResponse {
+    HttpResponse::Ok().json(json!({
+        "subscriptions": status.subscriptions(),
+        "wal": publisher.wal_stats(),
+        "sinks": publisher.sink_health(),
+        "consumer_lag": status.consumer_lag(),
+        "paused_namespaces": pauses.paused(),
+        "config_fingerprint": publisher.config_fingerprint(),
+    }))
+}
+
+pub fn list_circuit_errors(
+    status: web::Data<Status>,
+    circuit_id: web::Path<String>,
+) -> HttpResponse {
+    HttpResponse::Ok().json(status.circuit_errors(&circuit_id))
+}
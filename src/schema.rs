@@ -0,0 +1,271 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+//! Describes the exported messages from the protobuf descriptors compiled into the binary,
+//! so the documentation always matches the messages actually produced.
+
+use std::fmt::Write;
+
+use protobuf::descriptor::{
+    DescriptorProto, EnumDescriptorProto, FieldDescriptorProto, FieldDescriptorProto_Label,
+    FieldDescriptorProto_Type, FileDescriptorProto,
+};
+use serde_json::Value;
+
+use crate::proto::pubsub;
+
+/// Serialization used for the exported messages
+const SERIALIZATION: &str = "protobuf";
+
+// Paths of the elements in a FileDescriptorProto, used to find their comments
+const MESSAGE_PATH: i32 = 4;
+const FIELD_PATH: i32 = 2;
+const NESTED_ENUM_PATH: i32 = 4;
+const ENUM_VALUE_PATH: i32 = 2;
+
+pub enum SchemaFormat {
+    Markdown,
+    Json,
+}
+
+pub fn describe_schema(format: SchemaFormat) -> String {
+    let schema = schema_document();
+    match format {
+        SchemaFormat::Json => {
+            serde_json::to_string_pretty(&schema).expect("Unable to serialize schema")
+        }
+        SchemaFormat::Markdown => markdown(&schema),
+    }
+}
+
+/// Returns the description printed by `describe-schema --format json`
+pub fn schema_document() -> Value {
+    schema(pubsub::file_descriptor_proto())
+}
+
+fn schema(file: &FileDescriptorProto) -> Value {
+    let message_names = file
+        .get_message_type()
+        .iter()
+        .map(DescriptorProto::get_name)
+        .collect::<Vec<_>>();
+    let messages = file
+        .get_message_type()
+        .iter()
+        .enumerate()
+        .map(|(index, message)| {
+            let path = vec![MESSAGE_PATH, index as i32];
+            describe_message(file, message, &path, &message_names)
+        })
+        .collect::<Vec<_>>();
+    json!({
+        "schema_version": env!("CARGO_PKG_VERSION"),
+        "serialization": SERIALIZATION,
+        "messages": messages,
+    })
+}
+
+fn describe_message(
+    file: &FileDescriptorProto,
+    message: &DescriptorProto,
+    path: &[i32],
+    message_names: &[&str],
+) -> Value {
+    let fields = message
+        .get_field()
+        .iter()
+        .enumerate()
+        .map(|(index, field)| {
+            json!({
+                "name": field.get_name(),
+                "number": field.get_number(),
+                "type": field_type(field),
+                "description": comment(file, &child_path(path, FIELD_PATH, index)),
+            })
+        })
+        .collect::<Vec<_>>();
+    let enums = message
+        .get_enum_type()
+        .iter()
+        .enumerate()
+        .map(|(index, enum_type)| {
+            describe_enum(
+                file,
+                enum_type,
+                &child_path(path, NESTED_ENUM_PATH, index),
+                message_names,
+            )
+        })
+        .collect::<Vec<_>>();
+    json!({
+        "name": message.get_name(),
+        "description": comment(file, path),
+        "fields": fields,
+        "enums": enums,
+    })
+}
+
+/// Enum values named after a message, such as `PROPOSAL_SUBMIT` for `ProposalSubmit`, are
+/// linked to that message as the payload they announce
+fn describe_enum(
+    file: &FileDescriptorProto,
+    enum_type: &EnumDescriptorProto,
+    path: &[i32],
+    message_names: &[&str],
+) -> Value {
+    let values = enum_type
+        .get_value()
+        .iter()
+        .enumerate()
+        .map(|(index, value)| {
+            let payload = message_names
+                .iter()
+                .find(|name| to_screaming_snake_case(name) == value.get_name());
+            json!({
+                "name": value.get_name(),
+                "number": value.get_number(),
+                "payload": payload,
+                "description": comment(file, &child_path(path, ENUM_VALUE_PATH, index)),
+            })
+        })
+        .collect::<Vec<_>>();
+    json!({
+        "name": enum_type.get_name(),
+        "description": comment(file, path),
+        "values": values,
+    })
+}
+
+fn child_path(path: &[i32], kind: i32, index: usize) -> Vec<i32> {
+    let mut child = path.to_vec();
+    child.push(kind);
+    child.push(index as i32);
+    child
+}
+
+/// Returns the comments of the element at the path, if the descriptor kept them
+fn comment(file: &FileDescriptorProto, path: &[i32]) -> String {
+    file.get_source_code_info()
+        .get_location()
+        .iter()
+        .find(|location| location.get_path() == path)
+        .map(|location| {
+            [
+                location.get_leading_comments(),
+                location.get_trailing_comments(),
+            ]
+            .iter()
+            .map(|comment| comment.trim())
+            .filter(|comment| !comment.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ")
+        })
+        .unwrap_or_default()
+}
+
+fn field_type(field: &FieldDescriptorProto) -> String {
+    let name = match field.get_field_type() {
+        FieldDescriptorProto_Type::TYPE_MESSAGE | FieldDescriptorProto_Type::TYPE_ENUM => {
+            field.get_type_name().trim_start_matches('.').to_string()
+        }
+        other => format!("{:?}", other)
+            .trim_start_matches("TYPE_")
+            .to_lowercase(),
+    };
+    match field.get_label() {
+        FieldDescriptorProto_Label::LABEL_REPEATED => format!("repeated {}", name),
+        _ => name,
+    }
+}
+
+fn to_screaming_snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    for (index, c) in name.chars().enumerate() {
+        if c.is_uppercase() && index > 0 {
+            snake.push('_');
+        }
+        snake.push(c.to_ascii_uppercase());
+    }
+    snake
+}
+
+fn markdown(schema: &Value) -> String {
+    let mut buf = String::new();
+    writeln!(&mut buf, "# Exported messages\n").expect("Unable to write to string");
+    writeln!(
+        &mut buf,
+        "Schema version {}, serialized with {}.",
+        text(&schema["schema_version"]),
+        text(&schema["serialization"])
+    )
+    .expect("Unable to write to string");
+
+    for message in schema["messages"].as_array().into_iter().flatten() {
+        writeln!(&mut buf, "\n## {}\n", text(&message["name"])).expect("Unable to write to string");
+        if !text(&message["description"]).is_empty() {
+            writeln!(&mut buf, "{}\n", text(&message["description"]))
+                .expect("Unable to write to string");
+        }
+        writeln!(
+            &mut buf,
+            "| Field | Number | Type | Description |\n|---|---|---|---|"
+        )
+        .expect("Unable to write to string");
+        for field in message["fields"].as_array().into_iter().flatten() {
+            writeln!(
+                &mut buf,
+                "| {} | {} | {} | {} |",
+                text(&field["name"]),
+                field["number"],
+                text(&field["type"]),
+                text(&field["description"])
+            )
+            .expect("Unable to write to string");
+        }
+
+        for enum_type in message["enums"].as_array().into_iter().flatten() {
+            writeln!(
+                &mut buf,
+                "\n### {}.{}\n",
+                text(&message["name"]),
+                text(&enum_type["name"])
+            )
+            .expect("Unable to write to string");
+            writeln!(
+                &mut buf,
+                "| Value | Number | Payload | Description |\n|---|---|---|---|"
+            )
+            .expect("Unable to write to string");
+            for value in enum_type["values"].as_array().into_iter().flatten() {
+                writeln!(
+                    &mut buf,
+                    "| {} | {} | {} | {} |",
+                    text(&value["name"]),
+                    value["number"],
+                    text(&value["payload"]),
+                    text(&value["description"])
+                )
+                .expect("Unable to write to string");
+            }
+        }
+    }
+    buf
+}
+
+fn text(value: &Value) -> &str {
+    value.as_str().unwrap_or("")
+}
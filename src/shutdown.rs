@@ -0,0 +1,84 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Gate in front of the event handlers, closed as the first stage of the shutdown.
+///
+/// Each event is processed while holding an `InFlight` guard, so that once the gate is
+/// closed the shutdown can wait for the events already taken in.
+#[derive(Clone, Default)]
+pub struct Intake {
+    stopped: Arc<AtomicBool>,
+    in_flight: Arc<AtomicUsize>,
+}
+
+/// Marks an event as being processed until dropped
+pub struct InFlight {
+    in_flight: Arc<AtomicUsize>,
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl Intake {
+    pub fn new() -> Self {
+        Intake::default()
+    }
+
+    /// Takes in an event, returns `None` once intake is stopped
+    pub fn enter(&self) -> Option<InFlight> {
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        let guard = InFlight {
+            in_flight: self.in_flight.clone(),
+        };
+        if self.is_stopped() {
+            return None;
+        }
+        Some(guard)
+    }
+
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::SeqCst)
+    }
+
+    /// Waits for the events being processed, returns how many are left if the timeout
+    /// elapses first
+    pub fn drain(&self, timeout: Duration) -> Result<(), usize> {
+        let started = Instant::now();
+        loop {
+            let in_flight = self.in_flight.load(Ordering::SeqCst);
+            if in_flight == 0 {
+                return Ok(());
+            }
+            if started.elapsed() >= timeout {
+                return Err(in_flight);
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+    }
+}
@@ -0,0 +1,101 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+//! The secp256k1 key signing the Sabre batches. Kept in a file, the exporter keeps its
+//! identity, and with it the scabbard admin permissions granted to it, across restarts.
+
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+use sawtooth_sdk::signing::secp256k1::Secp256k1PrivateKey;
+use sawtooth_sdk::signing::{create_context, PrivateKey};
+
+use crate::error::EventListenerError;
+
+/// Permission bits the key file must not grant to the group or to others
+const GROUP_OTHER_MODE: u32 = 0o077;
+/// Permissions of a generated key file
+const KEY_FILE_MODE: u32 = 0o600;
+
+/// Reads the hex encoded key from the file, generating and saving a new key if the file
+/// does not exist yet.
+///
+/// The file must be a regular file readable only by its owner.
+pub fn load_or_generate(path: &str) -> Result<String, EventListenerError> {
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(ref err) if err.kind() == ErrorKind::NotFound => return generate_into(path),
+        Err(err) => return Err(key_file_error(path, &err.to_string())),
+    };
+    if !metadata.file_type().is_file() {
+        return Err(key_file_error(path, "not a regular file"));
+    }
+    let mode = metadata.permissions().mode();
+    if mode & GROUP_OTHER_MODE != 0 {
+        return Err(key_file_error(
+            path,
+            &format!(
+                "permissions {:o} give access beyond its owner, expected 600",
+                mode & 0o777
+            ),
+        ));
+    }
+    let hex = fs::read_to_string(path).map_err(|err| key_file_error(path, &err.to_string()))?;
+    Ok(Secp256k1PrivateKey::from_hex(hex.trim())?.as_hex())
+}
+
+/// Generates a key which only lasts as long as the process
+pub fn generate() -> Result<String, EventListenerError> {
+    let context = create_context("secp256k1")?;
+    let private_key = context.new_random_private_key()?;
+    Ok(private_key.as_hex())
+}
+
+/// Returns the hex encoded public key of the hex encoded private key
+pub fn public_key(private_key: &str) -> Result<String, EventListenerError> {
+    let context = create_context("secp256k1")?;
+    let private_key = Secp256k1PrivateKey::from_hex(private_key)?;
+    Ok(context.get_public_key(&private_key)?.as_hex())
+}
+
+fn generate_into(path: &str) -> Result<String, EventListenerError> {
+    let context = create_context("secp256k1")?;
+    let private_key = context.new_random_private_key()?;
+    let public_key = context.get_public_key(&*private_key)?;
+
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(KEY_FILE_MODE)
+        .open(path)
+        .map_err(|err| key_file_error(path, &err.to_string()))?;
+    writeln!(file, "{}", private_key.as_hex())
+        .and_then(|_| file.sync_all())
+        .map_err(|err| key_file_error(path, &err.to_string()))?;
+
+    info!(
+        "Generated signing key {} with public key {}",
+        path,
+        public_key.as_hex()
+    );
+    Ok(private_key.as_hex())
+}
+
+fn key_file_error(path: &str, reason: &str) -> EventListenerError {
+    EventListenerError::KeyFileError(format!("{}: {}", path, reason))
+}
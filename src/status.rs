@@ -0,0 +1,285 @@
+/*
+ * Copyright 2019 Walmart Inc.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * -----------------------------------------------------------------------------
+ */
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use crate::time::now_millis;
+
+/// Returns the name under which the admin event subscription of a circuit management
+/// type is tracked
+pub fn admin_subscription(management_type: &str) -> String {
+    format!("admin::{}", management_type)
+}
+
+/// Number of error messages kept for each circuit
+const RECENT_CIRCUIT_ERRORS: usize = 10;
+
+/// Milliseconds a resubscribed stream has to stay quiet after connecting before the
+/// replayed events are considered delivered
+pub const CATCH_UP_QUIET_PERIOD: u64 = 5000;
+
+/// Returns the name under which a scabbard subscription is tracked
+pub fn scabbard_subscription(circuit_id: &str, service_id: &str) -> String {
+    format!("{}::{}", circuit_id, service_id)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SubscriptionState {
+    Connecting,
+    Connected,
+    Reconnecting,
+    Closed,
+}
+
+/// Connection and retry state of a single WebSocket subscription
+#[derive(Debug, Clone, Serialize)]
+pub struct SubscriptionStatus {
+    state: SubscriptionState,
+    /// number of consecutive failures since the subscription was last connected
+    attempts: u64,
+    last_error: Option<String>,
+    last_error_at: Option<u64>,
+    last_retry_at: Option<u64>,
+    last_connected_at: Option<u64>,
+    last_message_at: Option<u64>,
+    /// set while a subscription reopened at startup replays the events missed while the
+    /// exporter was down
+    catching_up: bool,
+}
+
+impl SubscriptionStatus {
+    pub fn last_connected_at(&self) -> Option<u64> {
+        self.last_connected_at
+    }
+
+    /// Splinterd replays the missed events right after a resubscription connects, so
+    /// the catch-up ends once the stream has been connected and quiet for a while
+    fn settle_catch_up(&mut self, now: u64) {
+        if !self.catching_up || self.state != SubscriptionState::Connected {
+            return;
+        }
+        let last_activity = self.last_connected_at.max(self.last_message_at);
+        if let Some(last_activity) = last_activity {
+            if now.saturating_sub(last_activity) >= CATCH_UP_QUIET_PERIOD {
+                self.catching_up = false;
+            }
+        }
+    }
+}
+
+impl Default for SubscriptionStatus {
+    fn default() -> Self {
+        SubscriptionStatus {
+            state: SubscriptionState::Connecting,
+            attempts: 0,
+            last_error: None,
+            last_error_at: None,
+            last_retry_at: None,
+            last_connected_at: None,
+            last_message_at: None,
+            catching_up: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CircuitError {
+    message: String,
+    at: u64,
+}
+
+/// Errors met while exporting the events of a single circuit
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CircuitErrors {
+    /// number of errors since the exporter started
+    count: u64,
+    /// the latest errors, oldest first
+    recent: VecDeque<CircuitError>,
+}
+
+/// Lag of a consumer group on an export topic, as last inspected
+#[derive(Debug, Clone, Serialize)]
+pub struct ConsumerLag {
+    group: String,
+    topic: String,
+    lag: i64,
+    at: u64,
+}
+
+/// Tracks the state of every subscription opened by the exporter, for the status API.
+///
+/// Failed connections are restarted immediately by the WebSocket error handlers, and
+/// reopened by the subscription supervisor once they exhaust their reconnect limit, so
+/// `last_retry_at` is the time of the most recent restart.
+#[derive(Clone, Default)]
+pub struct Status {
+    subscriptions: Arc<Mutex<BTreeMap<String, SubscriptionStatus>>>,
+    circuit_errors: Arc<Mutex<BTreeMap<String, CircuitErrors>>>,
+    consumer_lag: Arc<Mutex<BTreeMap<(String, String), ConsumerLag>>>,
+}
+
+impl Status {
+    pub fn new() -> Self {
+        Status::default()
+    }
+
+    pub fn connecting(&self, subscription: &str) {
+        self.update(subscription, |status| status.state = SubscriptionState::Connecting);
+    }
+
+    pub fn connected(&self, subscription: &str) {
+        self.update(subscription, |status| {
+            status.state = SubscriptionState::Connected;
+            status.attempts = 0;
+            status.last_connected_at = Some(now_millis());
+        });
+    }
+
+    /// Marks a subscription reopened at startup as replaying missed events
+    pub fn catching_up(&self, subscription: &str) {
+        self.update(subscription, |status| status.catching_up = true);
+    }
+
+    pub fn message_received(&self, subscription: &str) {
+        self.update(subscription, |status| {
+            status.last_message_at = Some(now_millis());
+        });
+    }
+
+    /// Records a failure, `retrying` indicates whether the connection is being restarted
+    pub fn failed(&self, subscription: &str, error: &str, retrying: bool) {
+        self.update(subscription, |status| {
+            let now = now_millis();
+            status.attempts += 1;
+            status.last_error = Some(error.to_string());
+            status.last_error_at = Some(now);
+            if retrying {
+                status.state = SubscriptionState::Reconnecting;
+                status.last_retry_at = Some(now);
+            } else {
+                status.state = SubscriptionState::Closed;
+            }
+        });
+    }
+
+    /// Forgets a subscription which was closed for good
+    pub fn remove(&self, subscription: &str) {
+        match self.subscriptions.lock() {
+            Ok(mut subscriptions) => {
+                subscriptions.remove(subscription);
+            }
+            Err(err) => error!("Unable to update subscription status: {}", err),
+        }
+    }
+
+    pub fn subscriptions(&self) -> BTreeMap<String, SubscriptionStatus> {
+        match self.subscriptions.lock() {
+            Ok(mut subscriptions) => {
+                let now = now_millis();
+                for status in subscriptions.values_mut() {
+                    status.settle_catch_up(now);
+                }
+                subscriptions.clone()
+            }
+            Err(err) => {
+                error!("Unable to read subscription status: {}", err);
+                BTreeMap::new()
+            }
+        }
+    }
+
+    /// Returns the subscriptions still replaying the events missed before startup
+    pub fn catching_up_subscriptions(&self) -> Vec<String> {
+        self.subscriptions()
+            .into_iter()
+            .filter(|(_, status)| status.catching_up)
+            .map(|(subscription, _)| subscription)
+            .collect()
+    }
+
+    /// Records an error met while exporting the events of a circuit
+    pub fn circuit_error(&self, circuit_id: &str, error: &str) {
+        match self.circuit_errors.lock() {
+            Ok(mut circuit_errors) => {
+                let errors = circuit_errors
+                    .entry(circuit_id.to_string())
+                    .or_insert_with(CircuitErrors::default);
+                errors.count += 1;
+                if errors.recent.len() == RECENT_CIRCUIT_ERRORS {
+                    errors.recent.pop_front();
+                }
+                errors.recent.push_back(CircuitError {
+                    message: error.to_string(),
+                    at: now_millis(),
+                });
+            }
+            Err(err) => error!("Unable to record circuit error: {}", err),
+        }
+    }
+
+    /// Returns the errors recorded for a circuit, empty if there were none
+    pub fn circuit_errors(&self, circuit_id: &str) -> CircuitErrors {
+        match self.circuit_errors.lock() {
+            Ok(circuit_errors) => circuit_errors.get(circuit_id).cloned().unwrap_or_default(),
+            Err(err) => {
+                error!("Unable to read circuit errors: {}", err);
+                CircuitErrors::default()
+            }
+        }
+    }
+
+    pub fn set_consumer_lag(&self, group: &str, topic: &str, lag: i64) {
+        match self.consumer_lag.lock() {
+            Ok(mut consumer_lag) => {
+                consumer_lag.insert(
+                    (group.to_string(), topic.to_string()),
+                    ConsumerLag {
+                        group: group.to_string(),
+                        topic: topic.to_string(),
+                        lag,
+                        at: now_millis(),
+                    },
+                );
+            }
+            Err(err) => error!("Unable to record consumer lag: {}", err),
+        }
+    }
+
+    pub fn consumer_lag(&self) -> Vec<ConsumerLag> {
+        match self.consumer_lag.lock() {
+            Ok(consumer_lag) => consumer_lag.values().cloned().collect(),
+            Err(err) => {
+                error!("Unable to read consumer lag: {}", err);
+                vec![]
+            }
+        }
+    }
+
+    fn update<F>(&self, subscription: &str, f: F)
+    where
+        F: FnOnce(&mut SubscriptionStatus),
+    {
+        match self.subscriptions.lock() {
+            Ok(mut subscriptions) => f(subscriptions
+                .entry(subscription.to_string())
+                .or_insert_with(SubscriptionStatus::default)),
+            Err(err) => error!("Unable to update subscription status: {}", err),
+        }
+    }
+}